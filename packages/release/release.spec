@@ -65,6 +65,7 @@ Source1049: prepare-local-fs.service
 Source1060: capture-kernel-dump.service
 Source1061: disable-kexec-load.service
 Source1062: load-crash-kernel.service
+Source1063: report-kernel-crash.service
 
 # systemd cgroups/slices
 Source1080: runtime.slice
@@ -143,8 +144,8 @@ install -p -m 0644 \
   %{S:1001} %{S:1002} %{S:1003} %{S:1004} %{S:1005} %{S:1006} %{S:1007} \
   %{S:1008} %{S:1009} %{S:1010} %{S:1011} %{S:1012} %{S:1013} %{S:1015} \
   %{S:1040} %{S:1041} %{S:1042} %{S:1043} %{S:1044} %{S:1045} %{S:1046} \
-  %{S:1047} %{S:1048} %{S:1049} %{S:1060} %{S:1061} %{S:1062} %{S:1080} \
-  %{S:1014} \
+  %{S:1047} %{S:1048} %{S:1049} %{S:1060} %{S:1061} %{S:1062} %{S:1063} \
+  %{S:1080} %{S:1014} \
   %{buildroot}%{_cross_unitdir}
 
 install -d %{buildroot}%{_cross_unitdir}/systemd-tmpfiles-setup.service.d
@@ -203,6 +204,7 @@ ln -s preconfigured.target %{buildroot}%{_cross_unitdir}/default.target
 %{_cross_unitdir}/disable-kexec-load.service
 %{_cross_unitdir}/capture-kernel-dump.service
 %{_cross_unitdir}/load-crash-kernel.service
+%{_cross_unitdir}/report-kernel-crash.service
 %{_cross_unitdir}/prepare-boot.service
 %{_cross_unitdir}/prepare-opt.service
 %{_cross_unitdir}/prepare-var.service
@@ -5,8 +5,9 @@ Current version: {{version}}
 ## apiclient binary
 
 The `apiclient` binary provides high-level methods to interact with the Bottlerocket API.
-There's a [set](#set-mode) subcommand for changing settings, an [update](#update-mode) subcommand for updating the host, and an [exec](#exec-mode) subcommand for running commands in host containers.
+There's a [set](#set-mode) subcommand for changing settings, [export and apply](#export-and-apply) subcommands for saving and restoring them, an [update](#update-mode) subcommand for updating the host, an [exec](#exec-mode) subcommand for running commands in host containers, a [watch](#watch-mode) subcommand for following settings changes, [history](#history-mode) and revert subcommands for undoing them, an [audit](#audit-mode) subcommand for seeing who changed what, a [containers](#containers-mode) subcommand for checking on host and bootstrap containers, a [schema](#schema-mode) subcommand for describing the settings, and a [validate](#validate-mode) subcommand for checking user data before launch.
 There's also a low-level [raw](#raw-mode) subcommand for direct interaction with the HTTP API.
+Output can be printed as JSON, YAML, TOML, or a table, and filtered with a query; see [output formats](#output-formats).
 
 It talks to the Bottlerocket socket by default.
 It can be pointed to another socket using `--socket-path`, for example for local testing.
@@ -27,6 +28,12 @@ Or, request some specific settings:
 apiclient get settings.motd settings.kernel.lockdown
 ```
 
+Settings marked sensitive, like secrets decrypted from user data by early-boot-config, are shown as `<redacted>`.
+To see their values:
+```shell
+apiclient get settings.kubernetes.bootstrap-token --show-sensitive
+```
+
 ### Set mode
 
 This allows you to change settings on the system.
@@ -75,6 +82,62 @@ You can use JSON form to set it:
 apiclient set --json '{"motd": "42"}'
 ```
 
+#### Conditional changes
+
+If more than one tool changes the same settings, one can overwrite another's change without noticing.
+To avoid that, read the current `ETag` of the settings, then pass it to `set` with `--if-match`:
+
+```shell
+apiclient -v raw -u /settings 2>&1 >/dev/null | grep etag
+apiclient set --if-match '"12"' motd="hi there"
+```
+
+If any of the settings you're changing were committed since the ETag was returned, the change is rejected with a `412 Precondition Failed` status and nothing is changed.
+Read the settings again and retry.
+The API only returns strong ETags, so weak ETags like `W/"12"` aren't accepted.
+
+#### Planning changes
+
+If you stage changes in a transaction before committing them, you can see what applying them would do:
+
+```shell
+apiclient raw -m PATCH -u '/settings?tx=FOO' -d '{"motd": "hi there"}'
+apiclient plan --tx FOO
+```
+
+This prints a unified diff of each configuration file that would be rewritten, and the restart commands that would run.
+Nothing is written or restarted.
+Without `--tx`, the API's "default" transaction is used.
+
+### Export and apply
+
+You can save the settings you've set on a host as a TOML document:
+
+```shell
+apiclient export > settings.toml
+```
+
+Only settings set by a user are included: those set through the API, or in user data at launch.
+Generated settings, like the region, settings added by migrations, and settings that still have their default values are left out.
+
+The document is in the same format as user data, and can be applied to this host or another one, from files, URIs, or stdin:
+
+```shell
+apiclient apply settings.toml
+apiclient apply https://example.com/settings.toml
+```
+
+By default, `apply` only adds or changes settings.
+To make a host's settings match the document, use `--prune`:
+
+```shell
+apiclient apply --prune settings.toml
+```
+
+Settings set by a user that aren't in the document are then reset to their defaults, or removed if they have no default.
+Generated settings and settings added by migrations are left alone.
+This happens in the same commit as the rest of the document, so if the commit fails, nothing is removed, and a revert restores everything at once.
+
 ### Update mode
 
 To start, you can check what updates are available:
@@ -84,6 +147,7 @@ apiclient update check
 ```
 
 This will show you the current state of the system along with any updates available in the repo; see the [updater README](../../updater/README.md#walkthrough) for details.
+If the last update failed its health check on first boot, and was rolled back, `update check` warns you and lists the checks that failed.
 
 Assuming you want to accept the chosen update, you can apply it:
 
@@ -106,6 +170,7 @@ apiclient update apply --check --reboot
 
 This will reboot the system.
 You should use this after updating if you didn't specify the `--reboot` flag.
+This works at any time; `settings.updates.maintenance.windows` only limit when an update can be activated.
 
 ```shell
 apiclient reboot
@@ -134,6 +199,168 @@ If that doesn't work for your use case, you can pass `-t`/`--tty` to specificall
 
 See the [exec documentation](../api-exec.md) for more detail on how this feature works.
 
+### Watch mode
+
+This mode prints settings changes as they happen, so you can react to them without polling.
+Every time a transaction is committed, the changed settings and their new values are printed as a single line of JSON.
+
+```shell
+apiclient watch
+```
+
+You can give prefixes to only see changes to matching settings:
+```shell
+apiclient watch settings.kubernetes settings.motd
+```
+
+Output looks like this:
+```text
+{"transaction":"apiclient-set-9vQ1xAb3","changes":{"settings.motd":"hi there"}}
+```
+
+Settings removed by a commit, for example by a revert or `apply --prune`, are listed under `removed`:
+```text
+{"transaction":"revert-12","changes":{},"removed":["settings.kubernetes.node-labels.team"]}
+```
+
+### History mode
+
+Each commit that changes settings is recorded in a history, so you can see what changed and when.
+The most recent 100 commits are kept.
+
+```shell
+apiclient history
+```
+
+Each entry has an ID, the name of the transaction, a timestamp, and the old and new values of each setting it changed.
+A `null` value means the setting wasn't set.
+
+If a change caused problems, you can revert it by ID:
+```shell
+apiclient revert 42
+```
+
+This restores every setting changed by entry 42, or by any later entry, to its value from before entry 42 was committed.
+The changes are applied to the system just like `apiclient set`, so configuration files are re-rendered and services restarted as needed.
+The revert itself is recorded in history, so it can also be reverted.
+
+### Output formats
+
+By default, subcommands print JSON, or a human-readable summary for `plan`.
+You can choose another format with `-o` or `--output`: `json`, `yaml`, `toml`, or `table`.
+The `table` format prints each setting on its own line with its full name, or a column per field for lists like `history`.
+
+```shell
+apiclient get settings.host-containers -o table
+```
+
+To print only part of the output, use `--query` with a path into the data.
+Keys are separated by dots, `[N]` selects an item from a list, and `*` selects every item or key, giving a list of the matches.
+Quote keys that contain dots.
+
+```shell
+apiclient get settings.kernel --query 'settings.kernel.sysctl."vm.max_map_count"'
+apiclient history --query '[*].id'
+apiclient get settings.host-containers --query 'settings.host-containers.*.enabled'
+```
+
+With `--output` or `--query`, the `set`, `apply`, and `revert` subcommands print the names of the settings they changed, and `raw` parses the response body as JSON.
+The output of `exec` is always passed through unchanged.
+
+With the `json`, `yaml`, or `toml` formats, errors are printed to stderr in the same format, so scripts can tell what went wrong:
+```json
+{
+  "error": {
+    "kind": "response",
+    "message": "Failed to change settings: ...",
+    "server_message": "Json deserialize error: ...",
+    "status": 400
+  }
+}
+```
+
+The `kind` is `response` if the API server returned an error, in which case `status` and `server_message` give its HTTP status and message; `transport` if the API server couldn't be reached; or `client` for other problems, like invalid input.
+
+### Audit mode
+
+The API server keeps an audit log of every request that changes the system: settings changes, commits, actions like reboots and updates, and `exec` commands, along with requests denied by the [access policy](../apiserver/README.md#access-policy).
+You can see who made each change, and when:
+
+```shell
+apiclient audit
+```
+
+Each entry has a timestamp, the caller's UID, GID, PID, and container, the request, the transaction and the settings it changed, and the result.
+Values of sensitive settings, like credentials and user data, are redacted.
+
+You can narrow down the entries:
+```shell
+apiclient audit --key settings.motd --since 2023-01-31T12:00:00Z
+apiclient audit --tx default --limit 10
+```
+
+### Containers mode
+
+This shows the state of each host container and bootstrap container configured in settings:
+
+```shell
+apiclient containers -o table
+```
+
+Each container's `state` is `pulling` while host-ctr fetches its image, then `running`, `exited`, or `failed`; containers that haven't started are `stopped`, or `disabled` if they're not enabled.
+The output also has the container's image source and digest, when its unit last started it, its exit code, and the reason it last failed.
+
+### Schema mode
+
+This prints a [JSON Schema](https://json-schema.org/) that describes the settings of the host's variant.
+It's generated from the API model, so it lists every setting, its type, and the constraints on its values, like the pattern of Kubernetes label keys or the range of percentages.
+
+```shell
+apiclient schema
+```
+
+You can use it with any JSON Schema validator to check settings before sending them to the API, or to generate tooling that stays in sync with the variant.
+To see the schema of a single setting, give its name:
+
+```shell
+apiclient schema kubernetes.node-labels
+```
+
+Names within maps, like `host-containers.admin`, select the schema shared by every entry in the map.
+
+### Validate mode
+
+This checks a user data file against the settings model, without needing a running host, so you can catch mistakes on a build workstation before launching instances.
+It uses the same model and validation as the API, so values like Kubernetes label keys are checked the same way they would be at boot.
+
+```shell
+apiclient validate user-data.toml
+```
+
+Each problem is printed with its location in the file, the setting it's about, and the kind of problem:
+
+```text
+user-data.toml:8:1: error[unknown-key]: settings.host-containers.admin.sourc: unknown field `sourc`, expected one of `source`, `enabled`, `superpowered`, `user-data`
+user-data.toml:3:1: error[invalid-value]: settings.kubernetes.max-pods: invalid type: string "lots", expected u32
+user-data.toml:3:1: warning[generated]: settings.kubernetes.max-pods: normally generated at boot by 'pluto max-pods'; the generator won't run, and the value from user data is used instead
+```
+
+Errors are syntax errors, a missing `settings` table, unknown settings, and values the model doesn't accept; if there are any, apiclient exits with a failure.
+Warnings are for settings that a setting generator would normally create at boot, and for top-level tables other than `settings`, which are ignored.
+With `--output`, the problems are printed as structured data instead.
+
+Each variant has its own model, and apiclient checks against the model of the variant it was built for.
+To check user data for another variant, give the variant with `--variant`, and the JSON Schema of its settings with `--schema`.
+You can get the schema with `apiclient schema` on a host running that variant:
+
+```shell
+apiclient schema > aws-ecs-1-schema.json
+apiclient validate --variant aws-ecs-1 --schema aws-ecs-1-schema.json user-data.toml
+```
+
+The schema describes most, but not all, of the checks the model makes, so a few invalid values, like malformed URLs, are only caught at boot.
+apiclient has the default settings of every variant built in, so it knows which settings are generated for any variant.
+
 ### Raw mode
 
 Raw mode lets you make HTTP requests to a UNIX socket.
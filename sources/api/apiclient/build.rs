@@ -1,3 +1,151 @@
-fn main() {
+/// This build script generates README.md from rustdoc, like our other crates, but also merges the
+/// default settings of every variant into a TOML file per variant, the same way storewolf does.
+/// 'apiclient validate' reads them to find out which settings are generated at boot, so it can
+/// check user data for any variant without a running host.
+use bottlerocket_variant::Variant;
+use merge_toml::merge_values;
+use snafu::ResultExt;
+use std::fs;
+use std::path::Path;
+use toml::{map::Map, Value};
+use walkdir::WalkDir;
+
+/// Each variant has a directory here, and stores its default settings in .toml files in its
+/// 'defaults.d' directory.  Entries are sorted by filename, and later entries take precedence.
+const VARIANTS_DIR: &str = "../../models/src";
+
+fn main() -> Result<()> {
     generate_readme::from_lib().unwrap();
+    generate_variant_defaults()?;
+
+    // Let 'apiclient validate' know which variant's model it was built with.
+    let variant = Variant::from_env().context(error::VariantSnafu)?;
+    println!("cargo:rustc-env=BUILD_VARIANT={}", variant);
+
+    // Reflect that we need to rerun if variant has changed to pick up the new model.
+    Variant::rerun_if_changed();
+
+    Ok(())
 }
+
+/// Merges each variant's default settings files into a single TOML value, serialized to a file in
+/// OUT_DIR, and generates the `VARIANT_DEFAULTS` list of them for 'apiclient validate'.
+fn generate_variant_defaults() -> Result<()> {
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR not set; are you not using cargo?");
+    let defaults_dir = Path::new(&out_dir).join("defaults");
+    fs::create_dir_all(&defaults_dir).context(error::FileSnafu {
+        op: "create",
+        path: &defaults_dir,
+    })?;
+
+    // Pick up new variants.
+    println!("cargo:rerun-if-changed={}", VARIANTS_DIR);
+    let entries = fs::read_dir(VARIANTS_DIR).context(error::FileSnafu {
+        op: "list",
+        path: VARIANTS_DIR,
+    })?;
+    let mut variants = Vec::new();
+    for entry in entries {
+        let entry = entry.context(error::FileSnafu {
+            op: "list",
+            path: VARIANTS_DIR,
+        })?;
+        let dir = entry.path().join("defaults.d");
+        if !dir.is_dir() {
+            continue;
+        }
+        let variant = entry.file_name().to_string_lossy().into_owned();
+        let data = toml::to_string(&merge_defaults(&dir)?).context(error::TomlSerializeSnafu)?;
+        let path = defaults_dir.join(format!("{}.toml", variant));
+        fs::write(&path, data).context(error::FileSnafu {
+            op: "write",
+            path: &path,
+        })?;
+        variants.push((variant, path));
+    }
+    variants.sort();
+
+    let mut code = String::from(
+        "/// The default settings of each variant, merged at build time, by variant name.\n\
+         const VARIANT_DEFAULTS: &[(&str, &str)] = &[\n",
+    );
+    for (variant, path) in variants {
+        code.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            variant,
+            path.display().to_string()
+        ));
+    }
+    code.push_str("];\n");
+    let path = Path::new(&out_dir).join("variant_defaults.rs");
+    fs::write(&path, code).context(error::FileSnafu { op: "write", path })?;
+
+    Ok(())
+}
+
+/// Merges the default settings files in the given 'defaults.d' directory into a single TOML value.
+fn merge_defaults(dir: &Path) -> Result<Value> {
+    let walker = WalkDir::new(dir)
+        .follow_links(true)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|e| e.file_name().to_string_lossy().ends_with(".toml"));
+
+    let mut defaults = Value::Table(Map::new());
+    for entry in walker {
+        let entry = entry.context(error::ListFilesSnafu { dir })?;
+        println!("cargo:rerun-if-changed={}", entry.path().display());
+
+        let data = fs::read_to_string(entry.path()).context(error::FileSnafu {
+            op: "read",
+            path: entry.path(),
+        })?;
+        let value =
+            toml::from_str(&data).context(error::TomlDeserializeSnafu { path: entry.path() })?;
+        merge_values(&mut defaults, &value).context(error::TomlMergeSnafu)?;
+    }
+    Ok(defaults)
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(super) enum Error {
+        #[snafu(display("Failed to {} {}: {}", op, path.display(), source))]
+        File {
+            op: String,
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to list files in {}: {}", dir.display(), source))]
+        ListFiles {
+            dir: PathBuf,
+            source: walkdir::Error,
+        },
+
+        #[snafu(display("{} is not valid TOML: {}", path.display(), source))]
+        TomlDeserialize {
+            path: PathBuf,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("Failed to merge TOML: {}", source))]
+        TomlMerge { source: merge_toml::Error },
+
+        #[snafu(display("Failed to serialize default settings: {}", source))]
+        TomlSerialize { source: toml::ser::Error },
+
+        #[snafu(display("Unable to determine variant: {}", source))]
+        Variant {
+            source: bottlerocket_variant::error::Error,
+        },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
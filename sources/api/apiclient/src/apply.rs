@@ -12,8 +12,30 @@ use std::path::Path;
 use tokio::io::AsyncReadExt;
 
 /// Reads settings in TOML or JSON format from files at the requested URIs (or from stdin, if given
-/// "-"), then commits them in a single transaction and applies them to the system.
-pub async fn apply<P>(socket_path: P, input_sources: Vec<String>) -> Result<()>
+/// "-"), then commits them in a single transaction and applies them to the system.  Returns the
+/// names of the settings that changed.
+pub async fn apply<P>(socket_path: P, input_sources: Vec<String>) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    apply_inputs(socket_path, input_sources, false).await
+}
+
+/// Works like `apply`, but also prunes settings set by the user that aren't in the inputs, so the
+/// host's settings match the inputs.  Pruned settings are reset to their defaults, or removed if
+/// they have no default.  Generated settings are left alone.
+pub async fn apply_and_prune<P>(socket_path: P, input_sources: Vec<String>) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    apply_inputs(socket_path, input_sources, true).await
+}
+
+async fn apply_inputs<P>(
+    socket_path: P,
+    input_sources: Vec<String>,
+    prune: bool,
+) -> Result<Vec<String>>
 where
     P: AsRef<Path>,
 {
@@ -57,13 +79,16 @@ where
     }
 
     // Commit the transaction and apply it to the system.
-    let uri = format!("/tx/commit_and_apply?tx={}", transaction);
+    let mut uri = format!("/tx/commit_and_apply?tx={}", transaction);
+    if prune {
+        uri.push_str("&prune=true");
+    }
     let method = "POST";
-    let (_status, _body) = crate::raw_request(&socket_path, &uri, method, None)
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
         .await
-        .context(error::CommitApplySnafu { uri })?;
+        .context(error::CommitApplySnafu { uri: &uri })?;
 
-    Ok(())
+    serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })
 }
 
 /// Retrieves the given source location and returns the result in a String.
@@ -232,6 +257,12 @@ mod error {
             source: reqwest::Error,
         },
 
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+
         #[snafu(display("Failed to read standard input: {}", source))]
         StdinRead { source: std::io::Error },
 
@@ -0,0 +1,76 @@
+//! The 'audit' module lets you see the API server's audit log: who changed settings, ran
+//! commands, or took actions through the API, and when, along with requests that were denied.
+
+use snafu::ResultExt;
+use std::path::Path;
+use url::form_urlencoded;
+
+/// Filters for the audit log entries to fetch.  Fields that aren't given don't filter anything.
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    /// Only entries at or after this time, given as an RFC 3339 timestamp.
+    pub since: Option<String>,
+    /// Only entries for this transaction.
+    pub transaction: Option<String>,
+    /// Only entries that affected settings starting with this prefix.
+    pub key: Option<String>,
+    /// Only this many of the most recent entries.
+    pub limit: Option<usize>,
+}
+
+/// Fetches the audit log entries matching the query, oldest first.
+pub async fn audit<P>(socket_path: P, query: &AuditQuery) -> Result<serde_json::Value>
+where
+    P: AsRef<Path>,
+{
+    let mut params = form_urlencoded::Serializer::new(String::new());
+    if let Some(since) = &query.since {
+        params.append_pair("since", since);
+    }
+    if let Some(transaction) = &query.transaction {
+        params.append_pair("tx", transaction);
+    }
+    if let Some(key) = &query.key {
+        params.append_pair("key", key);
+    }
+    if let Some(limit) = query.limit {
+        params.append_pair("limit", &limit.to_string());
+    }
+    let params = params.finish();
+
+    let uri = if params.is_empty() {
+        "/audit".to_string()
+    } else {
+        format!("/audit?{}", params)
+    };
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri: &uri, method })?;
+
+    serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
@@ -0,0 +1,45 @@
+//! The 'containers' module lets you see the state of the host containers and bootstrap containers
+//! configured in settings.
+
+use snafu::ResultExt;
+use std::path::Path;
+
+/// Fetches the state of each configured host container and bootstrap container: whether it's
+/// pulling, running, exited, or failed, along with its image digest, start time, exit code, and
+/// the reason it last failed.
+pub async fn containers<P>(socket_path: P) -> Result<serde_json::Value>
+where
+    P: AsRef<Path>,
+{
+    let uri = "/containers";
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+
+    serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
@@ -39,9 +39,8 @@ use tokio_tungstenite::tungstenite::{
     Error as WsError,
 };
 
-mod connect;
 mod terminal;
-use connect::websocket_connect;
+use crate::connect::{self, websocket_connect};
 use terminal::Terminal;
 
 /// To guard against stale connections, we send ping and pong messages through the channel
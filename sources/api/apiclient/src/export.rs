@@ -0,0 +1,46 @@
+//! The 'export' module gets the settings that were set by the user, leaving out settings that are
+//! generated, added by migrations, or have their default values.  The result is in the same form as the inputs to
+//! 'apply', so applying it to another host, or to this one with pruning, reproduces the settings.
+
+use snafu::ResultExt;
+use std::path::Path;
+
+/// Fetches the settings set by the user, returning them under a top-level "settings" key.
+pub async fn export<P>(socket_path: P) -> Result<serde_json::Value>
+where
+    P: AsRef<Path>,
+{
+    let uri = "/settings/export";
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+
+    let settings: serde_json::Value =
+        serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })?;
+    Ok(serde_json::json!({ "settings": settings }))
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
@@ -1,3 +1,5 @@
+use datastore::{Key, KeyType};
+use serde_json::Value;
 use snafu::{OptionExt, ResultExt};
 use std::path::Path;
 
@@ -45,6 +47,120 @@ where
     serde_json::from_str(&body).context(error::ResponseJsonSnafu { body })
 }
 
+/// Replaces the values of sensitive settings.
+const REDACTED: &str = "<redacted>";
+
+/// Where settings are found in a response from the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsLocation {
+    /// Settings are under a "settings" key, as in a response to "/" or to prefix queries.
+    Nested,
+    /// Settings are at the top level, as in a response to "/settings".
+    Root,
+}
+
+/// Returns where settings are found in the response to a GET of the given URI, or None if the
+/// response doesn't contain settings.
+pub fn settings_location(uri: &str) -> Option<SettingsLocation> {
+    let path = uri.split('?').next().unwrap_or_default();
+    match path.trim_end_matches('/') {
+        "" => Some(SettingsLocation::Nested),
+        "/settings" | "/tx" => Some(SettingsLocation::Root),
+        _ => None,
+    }
+}
+
+/// Replaces the values of settings the API has marked sensitive, like secrets decrypted from user
+/// data, with "<redacted>".
+pub async fn redact_sensitive<P>(
+    socket_path: P,
+    value: &mut Value,
+    settings_at: SettingsLocation,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let sensitive = get_uri(
+        &socket_path,
+        constants::API_SENSITIVE_SETTINGS_URI.to_string(),
+    )
+    .await?;
+    let names = sensitive
+        .as_object()
+        .context(error::SensitiveResponseSnafu)?
+        .keys();
+    for name in names {
+        let key = Key::new(KeyType::Data, name).context(error::SensitiveKeySnafu { name })?;
+        let segments = match settings_at {
+            SettingsLocation::Nested => &key.segments()[..],
+            // Sensitive settings are named starting with "settings".
+            SettingsLocation::Root => &key.segments()[1..],
+        };
+        redact_path(value, segments);
+    }
+    Ok(())
+}
+
+/// Replaces the value at the given path, if there's one, with "<redacted>".
+fn redact_path(value: &mut Value, segments: &[String]) {
+    let mut current = value;
+    for segment in segments {
+        match current.get_mut(segment) {
+            Some(inner) => current = inner,
+            None => return,
+        }
+    }
+    if !current.is_null() {
+        *current = Value::String(REDACTED.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_paths() {
+        let mut value = json!({
+            "settings": {
+                "kubernetes": {"bootstrap-token": "secret", "cluster-name": "c"},
+                "kernel": {"sysctl": {"vm.max_map_count": "1"}},
+            }
+        });
+        let segments = |name| Key::new(KeyType::Data, name).unwrap().segments().clone();
+        redact_path(&mut value, &segments("settings.kubernetes.bootstrap-token"));
+        redact_path(
+            &mut value,
+            &segments("settings.kernel.sysctl.\"vm.max_map_count\""),
+        );
+        redact_path(&mut value, &segments("settings.aws.credentials"));
+        assert_eq!(
+            value,
+            json!({
+                "settings": {
+                    "kubernetes": {"bootstrap-token": REDACTED, "cluster-name": "c"},
+                    "kernel": {"sysctl": {"vm.max_map_count": REDACTED}},
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(settings_location("/"), Some(SettingsLocation::Nested));
+        assert_eq!(
+            settings_location("/?prefix=settings.motd"),
+            Some(SettingsLocation::Nested)
+        );
+        assert_eq!(
+            settings_location("/settings?keys=settings.motd"),
+            Some(SettingsLocation::Root)
+        );
+        assert_eq!(settings_location("/os"), None);
+    }
+}
+
 mod error {
     use snafu::Snafu;
 
@@ -62,6 +178,15 @@ mod error {
             source: Box<crate::Error>,
         },
 
+        #[snafu(display("Invalid sensitive setting name '{}': {}", name, source))]
+        SensitiveKey {
+            name: String,
+            source: datastore::Error,
+        },
+
+        #[snafu(display("Sensitive settings response was not a JSON object"))]
+        SensitiveResponse,
+
         #[snafu(display("Response contained invalid JSON '{}' - {}", body, source))]
         ResponseJson {
             body: String,
@@ -0,0 +1,73 @@
+//! The 'history' module lets you see recently committed transactions and revert them.
+
+use log::info;
+use snafu::ResultExt;
+use std::path::Path;
+
+/// Fetches the history of committed transactions, oldest first, along with the old and new values
+/// of the settings each one changed.
+pub async fn history<P>(socket_path: P) -> Result<serde_json::Value>
+where
+    P: AsRef<Path>,
+{
+    let uri = "/tx/history";
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+
+    serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })
+}
+
+/// Restores settings to their state before the history entry with the given ID was committed,
+/// and applies the changes to the system.  Returns the names of the settings that changed.
+pub async fn revert<P>(socket_path: P, id: u64) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let uri = format!("/tx/revert?id={}", id);
+    let method = "POST";
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri: &uri, method })?;
+
+    let changed: Vec<String> =
+        serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })?;
+    if changed.is_empty() {
+        info!(
+            "Settings already match their state before history entry {}",
+            id
+        );
+    } else {
+        info!(
+            "Reverted {} setting(s): {}",
+            changed.len(),
+            changed.join(", ")
+        );
+    }
+    Ok(changed)
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
@@ -1,6 +1,8 @@
 //! The apiclient library provides high-level methods to interact with the Bottlerocket API.  See
-//! the documentation for submodules [`apply`], [`exec`], [`get`], [`reboot`], [`set`], and
-//! [`update`] for high-level helpers.
+//! the documentation for submodules [`apply`], [`audit`], [`containers`], [`exec`], [`export`],
+//! [`get`], [`history`], [`plan`], [`reboot`], [`schema`], [`set`], [`update`], and [`watch`] for
+//! high-level helpers, and [`output`] for formatting their results.  The [`validate`] submodule
+//! checks user data against the model offline, without an API to talk to.
 //!
 //! For more control, and to handle APIs without high-level wrappers, there are also 'raw' methods
 //! to query an HTTP API over a Unix-domain socket.
@@ -13,6 +15,7 @@
 // of hyper, but it lacks Unix-domain socket support:
 // https://github.com/seanmonstar/reqwest/issues/39
 
+use http::HeaderMap;
 use hyper::{body, header, Body, Client, Request};
 use hyper_unix_connector::{UnixClient, Uri};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
@@ -20,11 +23,22 @@ use snafu::{ensure, ResultExt};
 use std::path::Path;
 
 pub mod apply;
+pub mod audit;
+pub mod containers;
 pub mod exec;
+pub mod export;
 pub mod get;
+pub mod history;
+pub mod output;
+pub mod plan;
 pub mod reboot;
+pub mod schema;
 pub mod set;
 pub mod update;
+pub mod validate;
+pub mod watch;
+
+mod connect;
 
 mod error {
     use snafu::Snafu;
@@ -56,6 +70,17 @@ mod error {
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, error::Error>;
 
+impl Error {
+    /// If the server responded with an error status, returns the status and the body of the
+    /// response, which describes the error; returns None if we couldn't talk to the server.
+    pub fn status(&self) -> Option<(http::StatusCode, &str)> {
+        match self {
+            Error::ResponseStatus { code, body, .. } => Some((*code, body)),
+            _ => None,
+        }
+    }
+}
+
 /// Makes an HTTP request to a Unix-domain socket.
 ///
 /// The socket is specified as a path, for example "/tmp/api.sock".
@@ -107,6 +132,56 @@ pub async fn raw_request_unchecked<P, S1, S2>(
     method: S2,
     data: Option<String>,
 ) -> Result<(http::StatusCode, String)>
+where
+    P: AsRef<Path>,
+    S1: AsRef<str>,
+    S2: AsRef<str>,
+{
+    let (status, _headers, body) =
+        send_request(&socket_path, &uri, &method, HeaderMap::new(), data).await?;
+    Ok((status, body))
+}
+
+/// Works like raw_request, but sends the given headers along with the request, and returns the
+/// headers of the response along with its status code and body.  This is useful for APIs that use
+/// headers, for example conditional updates of settings using ETag and If-Match.
+pub async fn raw_request_with_headers<P, S1, S2>(
+    socket_path: P,
+    uri: S1,
+    method: S2,
+    headers: HeaderMap,
+    data: Option<String>,
+) -> Result<(http::StatusCode, HeaderMap, String)>
+where
+    P: AsRef<Path>,
+    S1: AsRef<str>,
+    S2: AsRef<str>,
+{
+    let (status, headers, body) = send_request(&socket_path, &uri, &method, headers, data).await?;
+
+    // Error if the response status is in not in the 2xx range.
+    ensure!(
+        status.is_success(),
+        error::ResponseStatusSnafu {
+            method: method.as_ref(),
+            code: status,
+            uri: uri.as_ref(),
+            body,
+        }
+    );
+
+    Ok((status, headers, body))
+}
+
+/// Sends an HTTP request over a Unix-domain socket, returning the status code, headers, and body
+/// of the response.
+async fn send_request<P, S1, S2>(
+    socket_path: P,
+    uri: S1,
+    method: S2,
+    headers: HeaderMap,
+    data: Option<String>,
+) -> Result<(http::StatusCode, HeaderMap, String)>
 where
     P: AsRef<Path>,
     S1: AsRef<str>,
@@ -124,12 +199,13 @@ where
     } else {
         Body::empty()
     };
-    let request = Request::builder()
+    let mut request = Request::builder()
         .method(method)
         .uri(&uri)
         .header(header::CONTENT_TYPE, "application/json")
         .body(request_data)
         .context(error::RequestSetupSnafu)?;
+    request.headers_mut().extend(headers);
 
     // Send request.
     let res = client
@@ -137,6 +213,7 @@ where
         .await
         .context(error::RequestSendSnafu)?;
     let status = res.status();
+    let headers = res.headers().clone();
 
     // Read streaming response body into a string.
     let body_bytes = body::to_bytes(res.into_body())
@@ -144,7 +221,7 @@ where
         .context(error::ResponseBodyReadSnafu)?;
     let body = String::from_utf8(body_bytes.to_vec()).context(error::NonUtf8ResponseSnafu)?;
 
-    Ok((status, body))
+    Ok((status, headers, body))
 }
 
 /// Generates a random ID, affectionately known as a 'rando'.
@@ -6,16 +6,23 @@
 // library calls based on the given flags, etc.)  The library modules contain the code for talking
 // to the API, which is intended to be reusable by other crates.
 
-use apiclient::{apply, exec, get, reboot, set, update};
+use apiclient::output::{self, Format, Query};
+use apiclient::{
+    apply, audit, containers, exec, export, get, history, plan, reboot, schema, set, update,
+    validate, watch,
+};
 use datastore::{serialize_scalar, Key, KeyType};
+use http::HeaderMap;
 use log::{info, log_enabled, trace, warn};
 use simplelog::{
     ColorChoice, ConfigBuilder as LogConfigBuilder, LevelFilter, TermLogger, TerminalMode,
 };
-use snafu::ResultExt;
+use snafu::{ensure, ResultExt};
 use std::collections::HashMap;
 use std::env;
 use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
 use std::process;
 use std::str::FromStr;
 use unindent::unindent;
@@ -27,6 +34,8 @@ const DEFAULT_METHOD: &str = "GET";
 struct Args {
     log_level: LevelFilter,
     socket_path: String,
+    output: Option<Format>,
+    query: Option<Query>,
 }
 
 impl Default for Args {
@@ -34,6 +43,8 @@ impl Default for Args {
         Self {
             log_level: LevelFilter::Info,
             socket_path: constants::API_SOCKET.to_string(),
+            output: None,
+            query: None,
         }
     }
 }
@@ -42,20 +53,40 @@ impl Default for Args {
 #[derive(Debug)]
 enum Subcommand {
     Apply(ApplyArgs),
+    Audit(AuditArgs),
+    Containers(ContainersArgs),
     Exec(ExecArgs),
+    Export(ExportArgs),
     Get(GetArgs),
+    History(HistoryArgs),
+    Plan(PlanArgs),
     Raw(RawArgs),
     Reboot(RebootArgs),
+    Revert(RevertArgs),
+    Schema(SchemaArgs),
     Set(SetArgs),
     Update(UpdateSubcommand),
+    Validate(ValidateArgs),
+    Watch(WatchArgs),
 }
 
 /// Stores user-supplied arguments for the 'apply' subcommand.
 #[derive(Debug)]
 struct ApplyArgs {
     input_sources: Vec<String>,
+    prune: bool,
 }
 
+/// Stores user-supplied arguments for the 'audit' subcommand.
+#[derive(Debug)]
+struct AuditArgs {
+    query: audit::AuditQuery,
+}
+
+/// Stores user-supplied arguments for the 'containers' subcommand.
+#[derive(Debug)]
+struct ContainersArgs {}
+
 /// Stores user-supplied arguments for the 'exec' subcommand.
 #[derive(Debug)]
 struct ExecArgs {
@@ -64,13 +95,34 @@ struct ExecArgs {
     tty: Option<bool>,
 }
 
+/// Stores user-supplied arguments for the 'export' subcommand.
+#[derive(Debug)]
+struct ExportArgs {}
+
 /// Stores user-supplied arguments for the 'get' subcommand.
 #[derive(Debug)]
-enum GetArgs {
+struct GetArgs {
+    target: GetTarget,
+    show_sensitive: bool,
+}
+
+/// What the 'get' subcommand fetches.
+#[derive(Debug)]
+enum GetTarget {
     Prefixes(Vec<String>),
     Uri(String),
 }
 
+/// Stores user-supplied arguments for the 'history' subcommand.
+#[derive(Debug)]
+struct HistoryArgs {}
+
+/// Stores user-supplied arguments for the 'plan' subcommand.
+#[derive(Debug)]
+struct PlanArgs {
+    transaction: Option<String>,
+}
+
 /// Stores user-supplied arguments for the 'raw' subcommand.
 #[derive(Debug)]
 struct RawArgs {
@@ -83,9 +135,28 @@ struct RawArgs {
 #[derive(Debug)]
 struct RebootArgs {}
 
+/// Stores user-supplied arguments for the 'revert' subcommand.
+#[derive(Debug)]
+struct RevertArgs {
+    id: u64,
+}
+
+/// Stores user-supplied arguments for the 'schema' subcommand.
+#[derive(Debug)]
+struct SchemaArgs {
+    setting: Option<String>,
+}
+
 /// Stores user-supplied arguments for the 'set' subcommand.
 #[derive(Debug)]
-enum SetArgs {
+struct SetArgs {
+    input: SetInput,
+    if_match: Option<String>,
+}
+
+/// Stores the settings the user wants to change with the 'set' subcommand.
+#[derive(Debug)]
+enum SetInput {
     Simple(HashMap<Key, String>),
     Json(serde_json::Value),
 }
@@ -113,6 +184,20 @@ struct UpdateApplyArgs {
 #[derive(Debug)]
 struct UpdateCancelArgs {}
 
+/// Stores user-supplied arguments for the 'validate' subcommand.
+#[derive(Debug)]
+struct ValidateArgs {
+    input_source: String,
+    variant: Option<String>,
+    schema: Option<String>,
+}
+
+/// Stores user-supplied arguments for the 'watch' subcommand.
+#[derive(Debug)]
+struct WatchArgs {
+    prefixes: Vec<String>,
+}
+
 /// Informs the user about proper usage of the program and exits.
 fn usage() -> ! {
     let msg = &format!(
@@ -122,7 +207,24 @@ fn usage() -> ! {
             -s, --socket-path PATH     Override the server socket path.  Default: {socket}
             --log-level                Desired amount of output; trace|debug|info|warn|error
             -v, --verbose              Sets log level to 'debug'.  This prints extra info,
-                                       like HTTP status code to stderr in 'raw' mode.
+                                       like HTTP status code and headers to stderr in 'raw'
+                                       mode.
+            -o, --output FORMAT        Print results as json|yaml|toml|table.  Default: json,
+                                       toml for 'export', or the usual human-readable output
+                                       for 'plan', 'set', 'apply', 'revert', and 'raw'.  With json, yaml, or toml,
+                                       errors are also printed to stderr in that format, with
+                                       the kind of error ("response" for an error status from
+                                       the server, "transport" if the server couldn't be
+                                       reached, or "client"), the HTTP status, and the server's
+                                       error message.
+            --query QUERY              Print only part of the results, selected by a path like
+                                       settings.motd, history[0].id, or
+                                       settings.host-containers.*.enabled.  Quote keys that
+                                       contain dots: 'settings.kernel.sysctl."vm.swappiness"'
+
+                                       'set', 'apply', and 'revert' print the names of the
+                                       settings they changed if --output or --query is given.
+                                       'exec' output is passed through unchanged.
 
         Subcommands:
             raw                        Makes an HTTP request and prints the response on stdout.
@@ -130,12 +232,23 @@ fn usage() -> ! {
             apply                      Applies settings from TOML/JSON files at given URIs,
                                        or from stdin.
             get                        Retrieve and print settings.
+            audit                      Prints the audit log of changes made through the API.
+            containers                 Prints the state of host and bootstrap containers.
+            export                     Prints settings set by the user as a TOML document
+                                       that 'apply' accepts.
             set                        Changes settings and applies them to the system.
+            plan                       Shows what applying pending settings would change.
+            history                    Prints recently committed settings changes.
+            revert ID                  Reverts settings changes back to a history entry.
+            schema                     Prints the JSON Schema of the settings.
+            validate                   Checks user data against the settings model, without
+                                       needing a running host.
             update check               Prints information about available updates.
             update apply               Applies available updates.
             update cancel              Deactivates an applied update.
             reboot                     Reboots the host.
             exec                       Execute a command in a host container.
+            watch                      Prints settings changes as they're committed.
 
         raw options:
             -u, --uri URI              Required; URI to request from the server, e.g. /tx
@@ -146,6 +259,24 @@ fn usage() -> ! {
             [ URI ...]                 The list of URIs to TOML or JSON settings files that you
                                        want to apply to the system.  If no URI is specified, or
                                        if "-" is given, reads from stdin.
+            --prune                    Also prune settings set by the user that aren't in the
+                                       inputs, so settings match the inputs: they're reset to
+                                       their defaults, or removed if they have none.  Generated
+                                       settings are left alone.
+
+        export options:
+            None.
+
+        audit options:
+            --since TIME               Only print entries at or after this RFC 3339 time, like
+                                       2023-01-31T12:00:00Z.
+            --tx TRANSACTION           Only print entries for this transaction.
+            --key PREFIX               Only print entries that changed settings starting with
+                                       this prefix.  The "settings." prefix is optional.
+            --limit N                  Only print the N most recent matching entries.
+
+        containers options:
+            None.  Use '-o table' for a summary with a row per container.
 
         reboot options:
             None.
@@ -157,6 +288,9 @@ fn usage() -> ! {
 
                                        If neither prefixes nor URI are specified, get will show
                                        settings and OS info.
+            --show-sensitive           Print the values of settings marked sensitive, like
+                                       secrets decrypted from user data.  By default they're
+                                       replaced with "<redacted>".
 
         set options:
             KEY=VALUE [KEY=VALUE ...]  The settings you want to set.  For example:
@@ -168,6 +302,43 @@ fn usage() -> ! {
                                        which can simplify setting multiple values, and is necessary
                                        for some numeric settings.  For example:
                                           -j '{{"kernel": {{"sysctl": {{"vm.max_map_count": "262144"}}}}}}'
+            --if-match ETAG            Only change settings if none of them have changed since
+                                       the ETag was returned by GET /settings.  You can see the
+                                       ETag with: apiclient -v raw -u /settings
+
+        plan options:
+            --tx TRANSACTION           The pending transaction to plan.  Default: the
+                                       'default' transaction, used by the API when no
+                                       transaction is given.
+
+        history options:
+            None.
+
+        revert options:
+            ID                         Required; the ID of the history entry to revert, as
+                                       shown by 'history'.  Settings changed by that entry
+                                       and any later entries are restored to their values
+                                       from before that entry, and applied to the system.
+
+        schema options:
+            [ SETTING ]                Only print the schema of this setting, like
+                                       kubernetes.node-labels.  The "settings." prefix is
+                                       optional.
+
+        validate options:
+            [ FILE ]                   The TOML user data file to check.  If no file is given,
+                                       or if "-" is given, reads from stdin.
+            --variant VARIANT          The variant the user data is for.  Default: the variant
+                                       apiclient was built for.
+            --schema FILE              The JSON Schema of the variant's settings, as printed by
+                                       'apiclient schema' on a host of that variant.  Each
+                                       variant has its own model, and apiclient only has the
+                                       model of the variant it was built for, so this is needed
+                                       for other variants.
+
+                                       Problems are printed as FILE:LINE:COLUMN, and errors
+                                       cause a non-zero exit.  Settings that a setting generator
+                                       would create at boot are reported as warnings.
 
         update check options:
             None.
@@ -185,7 +356,12 @@ fn usage() -> ! {
 
             TARGET                     Required; the name of the container in which to run the command.
             COMMAND                    Required; the command to run.
-            [ ARG ...]                 Any desired arguments to the command."#,
+            [ ARG ...]                 Any desired arguments to the command.
+
+        watch options:
+            [ PREFIX [PREFIX ...] ]    Only print changes to settings starting with these
+                                       prefixes.  The "settings." prefix is optional.  If no
+                                       prefixes are given, all changes are printed."#,
         socket = constants::API_SOCKET,
         method = DEFAULT_METHOD,
     );
@@ -226,6 +402,23 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
 
             "-v" | "--verbose" => global_args.log_level = LevelFilter::Debug,
 
+            "-o" | "--output" => {
+                let format_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to -o | --output"));
+                global_args.output = Some(
+                    Format::from_str(&format_str).unwrap_or_else(|e| usage_msg(e.to_string())),
+                );
+            }
+
+            "--query" => {
+                let query_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to --query"));
+                global_args.query =
+                    Some(Query::from_str(&query_str).unwrap_or_else(|e| usage_msg(e.to_string())));
+            }
+
             "-s" | "--socket-path" => {
                 global_args.socket_path = iter
                     .next()
@@ -233,7 +426,8 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
             }
 
             // Subcommands
-            "raw" | "apply" | "exec" | "get" | "reboot" | "set" | "update"
+            "raw" | "apply" | "audit" | "containers" | "exec" | "export" | "get" | "history"
+            | "plan" | "reboot" | "revert" | "schema" | "set" | "update" | "validate" | "watch"
                 if subcommand.is_none() && !arg.starts_with('-') =>
             {
                 subcommand = Some(arg)
@@ -248,11 +442,20 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
         // Default subcommand is 'raw'
         None | Some("raw") => (global_args, parse_raw_args(subcommand_args)),
         Some("apply") => (global_args, parse_apply_args(subcommand_args)),
+        Some("audit") => (global_args, parse_audit_args(subcommand_args)),
+        Some("containers") => (global_args, parse_containers_args(subcommand_args)),
         Some("exec") => (global_args, parse_exec_args(subcommand_args)),
+        Some("export") => (global_args, parse_export_args(subcommand_args)),
         Some("get") => (global_args, parse_get_args(subcommand_args)),
+        Some("history") => (global_args, parse_history_args(subcommand_args)),
+        Some("plan") => (global_args, parse_plan_args(subcommand_args)),
         Some("reboot") => (global_args, parse_reboot_args(subcommand_args)),
+        Some("revert") => (global_args, parse_revert_args(subcommand_args)),
+        Some("schema") => (global_args, parse_schema_args(subcommand_args)),
         Some("set") => (global_args, parse_set_args(subcommand_args)),
         Some("update") => (global_args, parse_update_args(subcommand_args)),
+        Some("validate") => (global_args, parse_validate_args(subcommand_args)),
+        Some("watch") => (global_args, parse_watch_args(subcommand_args)),
         _ => usage_msg("Missing or unknown subcommand"),
     }
 }
@@ -302,13 +505,16 @@ fn parse_raw_args(args: Vec<String>) -> Subcommand {
 /// Parses arguments for the 'apply' subcommand.
 fn parse_apply_args(args: Vec<String>) -> Subcommand {
     let mut input_sources = Vec::new();
+    let mut prune = false;
 
     for arg in args.into_iter() {
         match arg {
+            x if x == "--prune" => prune = true,
+
             // Allow "-" for stdin, but we have no other parameters.
-            x if x.starts_with('-') && x != "-" => {
-                usage_msg("apiclient apply takes no parameters, just a list of URIs.")
-            }
+            x if x.starts_with('-') && x != "-" => usage_msg(
+                "apiclient apply takes no parameters other than --prune, just a list of URIs.",
+            ),
 
             x => input_sources.push(x),
         }
@@ -319,7 +525,64 @@ fn parse_apply_args(args: Vec<String>) -> Subcommand {
         input_sources.push("-".to_string());
     }
 
-    Subcommand::Apply(ApplyArgs { input_sources })
+    Subcommand::Apply(ApplyArgs {
+        input_sources,
+        prune,
+    })
+}
+
+/// Parses arguments for the 'audit' subcommand.
+fn parse_audit_args(args: Vec<String>) -> Subcommand {
+    let mut query = audit::AuditQuery::default();
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "--since" => {
+                query.since = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --since")),
+                )
+            }
+
+            "--tx" => {
+                query.transaction = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --tx")),
+                )
+            }
+
+            "--key" => {
+                query.key = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --key")),
+                )
+            }
+
+            "--limit" => {
+                let limit_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to --limit"));
+                query.limit = Some(
+                    limit_str
+                        .parse()
+                        .unwrap_or_else(|_| usage_msg(format!("Invalid limit '{}'", limit_str))),
+                )
+            }
+
+            x => usage_msg(format!("Unknown argument '{}'", x)),
+        }
+    }
+
+    Subcommand::Audit(AuditArgs { query })
+}
+
+/// Parses arguments for the 'containers' subcommand.
+fn parse_containers_args(args: Vec<String>) -> Subcommand {
+    if !args.is_empty() {
+        usage_msg(format!("Unknown arguments: {}", args.join(", ")));
+    }
+    Subcommand::Containers(ContainersArgs {})
 }
 
 /// Parses arguments for the 'exec' subcommand.
@@ -362,13 +625,24 @@ fn parse_exec_args(args: Vec<String>) -> Subcommand {
     })
 }
 
+/// Parses arguments for the 'export' subcommand.
+fn parse_export_args(args: Vec<String>) -> Subcommand {
+    if !args.is_empty() {
+        usage_msg(format!("Unknown arguments: {}", args.join(", ")));
+    }
+    Subcommand::Export(ExportArgs {})
+}
+
 /// Parses arguments for the 'get' subcommand.
 fn parse_get_args(args: Vec<String>) -> Subcommand {
     let mut prefixes = vec![];
     let mut uri = None;
+    let mut show_sensitive = false;
 
     for arg in args.into_iter() {
         match &arg {
+            x if x == "--show-sensitive" => show_sensitive = true,
+
             x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
 
             x if x.starts_with('/') => {
@@ -382,23 +656,21 @@ fn parse_get_args(args: Vec<String>) -> Subcommand {
         }
     }
 
-    if let Some(uri) = uri {
+    let target = if let Some(uri) = uri {
         if !prefixes.is_empty() {
             usage_msg("You can specify prefixes or a URI, but not both.");
         }
-        Subcommand::Get(GetArgs::Uri(uri))
+        GetTarget::Uri(uri)
     } else if !prefixes.is_empty() {
-        if uri.is_some() {
-            usage_msg("You can specify prefixes or a URI, but not both.");
-        }
-        Subcommand::Get(GetArgs::Prefixes(prefixes))
+        GetTarget::Prefixes(prefixes)
     } else {
         // A reasonable default is showing OS info and settings.
-        Subcommand::Get(GetArgs::Prefixes(vec![
-            "os.".to_string(),
-            "settings.".to_string(),
-        ]))
-    }
+        GetTarget::Prefixes(vec!["os.".to_string(), "settings.".to_string()])
+    };
+    Subcommand::Get(GetArgs {
+        target,
+        show_sensitive,
+    })
 }
 
 /// Parses arguments for the 'reboot' subcommand.
@@ -409,6 +681,76 @@ fn parse_reboot_args(args: Vec<String>) -> Subcommand {
     Subcommand::Reboot(RebootArgs {})
 }
 
+/// Parses arguments for the 'history' subcommand.
+fn parse_history_args(args: Vec<String>) -> Subcommand {
+    if !args.is_empty() {
+        usage_msg(format!("Unknown arguments: {}", args.join(", ")));
+    }
+    Subcommand::History(HistoryArgs {})
+}
+
+/// Parses arguments for the 'plan' subcommand.
+fn parse_plan_args(args: Vec<String>) -> Subcommand {
+    let mut transaction = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "--tx" => {
+                transaction = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --tx")),
+                )
+            }
+
+            x => usage_msg(format!("Unknown argument '{}'", x)),
+        }
+    }
+
+    Subcommand::Plan(PlanArgs { transaction })
+}
+
+/// Parses arguments for the 'revert' subcommand.
+fn parse_revert_args(args: Vec<String>) -> Subcommand {
+    let mut id = None;
+
+    for arg in args.into_iter() {
+        match &arg {
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+
+            x if id.is_none() => {
+                id = Some(
+                    x.parse()
+                        .unwrap_or_else(|_| usage_msg(format!("Invalid history ID '{}'", x))),
+                )
+            }
+
+            _ => usage_msg("Only one history ID may be given to 'revert'"),
+        }
+    }
+
+    Subcommand::Revert(RevertArgs {
+        id: id.unwrap_or_else(|| usage_msg("Must specify a history ID to revert")),
+    })
+}
+
+/// Parses arguments for the 'schema' subcommand.
+fn parse_schema_args(args: Vec<String>) -> Subcommand {
+    let mut setting = None;
+
+    for arg in args.into_iter() {
+        match &arg {
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+
+            _ if setting.is_none() => setting = Some(arg),
+
+            _ => usage_msg("Only one setting may be given to 'schema'"),
+        }
+    }
+
+    Subcommand::Schema(SchemaArgs { setting })
+}
+
 /// Parses arguments for the 'set' subcommand.
 // Note: the API doesn't allow setting non-settings keys, e.g. services, configuration-files, and
 // metadata.  If we allow it in the future, we should revisit this 'set' parsing code and decide
@@ -417,6 +759,7 @@ fn parse_reboot_args(args: Vec<String>) -> Subcommand {
 fn parse_set_args(args: Vec<String>) -> Subcommand {
     let mut simple = HashMap::new();
     let mut json = None;
+    let mut if_match = None;
 
     let mut iter = args.into_iter();
     while let Some(arg) = iter.next() {
@@ -456,6 +799,13 @@ fn parse_set_args(args: Vec<String>) -> Subcommand {
                 json = Some(input_map.into());
             }
 
+            "--if-match" => {
+                if_match = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --if-match")),
+                )
+            }
+
             x if x.contains('=') => {
                 let (raw_key, value) = x.split_once('=').unwrap();
 
@@ -479,15 +829,17 @@ fn parse_set_args(args: Vec<String>) -> Subcommand {
         }
     }
 
-    if json.is_some() && !simple.is_empty() {
+    let input = if json.is_some() && !simple.is_empty() {
         usage_msg("Cannot specify key=value pairs and --json settings with 'set'");
     } else if let Some(json) = json {
-        Subcommand::Set(SetArgs::Json(json))
+        SetInput::Json(json)
     } else if !simple.is_empty() {
-        Subcommand::Set(SetArgs::Simple(simple))
+        SetInput::Simple(simple)
     } else {
         usage_msg("Must specify key=value settings or --json settings with 'set'");
-    }
+    };
+
+    Subcommand::Set(SetArgs { input, if_match })
 }
 
 /// Parses the desired subcommand of 'update'.
@@ -550,6 +902,61 @@ fn parse_update_cancel_args(args: Vec<String>) -> UpdateSubcommand {
     UpdateSubcommand::Cancel(UpdateCancelArgs {})
 }
 
+/// Parses arguments for the 'watch' subcommand.
+fn parse_watch_args(args: Vec<String>) -> Subcommand {
+    let mut prefixes = vec![];
+
+    for arg in args.into_iter() {
+        match &arg {
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+
+            // All other arguments are settings prefixes to watch.
+            _ => prefixes.push(arg),
+        }
+    }
+
+    Subcommand::Watch(WatchArgs { prefixes })
+}
+
+/// Parses arguments for the 'validate' subcommand.
+fn parse_validate_args(args: Vec<String>) -> Subcommand {
+    let mut input_source = None;
+    let mut variant = None;
+    let mut schema = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "--variant" => {
+                variant = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --variant")),
+                )
+            }
+
+            "--schema" => {
+                schema = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --schema")),
+                )
+            }
+
+            // Allow "-" for stdin.
+            x if x.starts_with('-') && x != "-" => usage_msg(format!("Unknown argument '{}'", x)),
+
+            _ if input_source.is_none() => input_source = Some(arg),
+
+            _ => usage_msg("Only one file may be given to 'validate'"),
+        }
+    }
+
+    Subcommand::Validate(ValidateArgs {
+        input_source: input_source.unwrap_or_else(|| "-".to_string()),
+        variant,
+        schema,
+    })
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 // Helpers
 
@@ -559,9 +966,10 @@ async fn check(args: &Args) -> Result<String> {
     let output = update::check(&args.socket_path)
         .await
         .context(error::UpdateCheckSnafu)?;
+    update::warn_failed_health_check(&output);
 
     match serde_json::from_str::<serde_json::Value>(&output) {
-        Ok(value) => println!("{:#}", value),
+        Ok(value) => print_value(args, &value)?,
         Err(e) => {
             warn!("Unable to deserialize response (invalid JSON?): {}", e);
             println!("{}", output);
@@ -571,6 +979,58 @@ async fn check(args: &Args) -> Result<String> {
     Ok(output)
 }
 
+/// Whether the user asked for structured output rather than the default for the subcommand.
+fn wants_output(args: &Args) -> bool {
+    args.output.is_some() || args.query.is_some()
+}
+
+/// Prints the part of the value selected by the user's query, if any, in the user's requested
+/// format, or pretty JSON by default.
+fn print_value(args: &Args, value: &serde_json::Value) -> Result<()> {
+    print_value_as(args, value, Format::Json)
+}
+
+/// Works like `print_value`, but uses the given format if the user didn't request one.
+fn print_value_as(args: &Args, value: &serde_json::Value, default: Format) -> Result<()> {
+    let selected = match &args.query {
+        Some(query) => query.select(value).context(error::OutputSnafu)?,
+        None => value.clone(),
+    };
+    let formatted =
+        output::format(&selected, args.output.unwrap_or(default)).context(error::OutputSnafu)?;
+    print!("{}", formatted);
+    Ok(())
+}
+
+/// Prints the names of changed settings, if the user asked for output.
+fn print_changed(args: &Args, changed: Vec<String>) -> Result<()> {
+    if wants_output(args) {
+        print_value(args, &serde_json::Value::from(changed))?;
+    }
+    Ok(())
+}
+
+/// Prints the diffs of configuration files, then the commands that would restart services.
+fn print_plan(plan: &plan::Plan) {
+    if plan.files.is_empty() {
+        println!("No configuration files would change.");
+    }
+    for diff in plan.files.values() {
+        print!("{}", diff);
+    }
+
+    if plan.restart_commands.is_empty() {
+        println!("\nNo services would be restarted.");
+    } else {
+        println!("\nRestart commands that would run:");
+        for (service, commands) in &plan.restart_commands {
+            for command in commands {
+                println!("  {}: {}", service, command);
+            }
+        }
+    }
+}
+
 /// We want the key=val form of 'set' to be as simple as possible; we don't want users to have to
 /// annotate or structure their input too much just to tell us the data type, but unfortunately
 /// knowledge of the data type is required to deserialize with the current datastore ser/de code.
@@ -610,8 +1070,7 @@ fn massage_set_input(input_map: HashMap<Key, String>) -> Result<HashMap<Key, Str
 // Main dispatch
 
 /// Main entry point, dispatches subcommands.
-async fn run() -> Result<()> {
-    let (args, subcommand) = parse_args(env::args());
+async fn run(args: &Args, subcommand: Subcommand) -> Result<()> {
     trace!("Parsed args for subcommand {:?}: {:?}", subcommand, args);
 
     // We use TerminalMode::Stderr because apiclient users expect server response data on stdout.
@@ -627,28 +1086,57 @@ async fn run() -> Result<()> {
 
     match subcommand {
         Subcommand::Raw(raw) => {
-            let (status, body) =
-                apiclient::raw_request(&args.socket_path, &raw.uri, &raw.method, raw.data)
-                    .await
-                    .context(error::RequestSnafu {
-                        uri: &raw.uri,
-                        method: &raw.method,
-                    })?;
+            let (status, headers, body) = apiclient::raw_request_with_headers(
+                &args.socket_path,
+                &raw.uri,
+                &raw.method,
+                HeaderMap::new(),
+                raw.data,
+            )
+            .await
+            .context(error::RequestSnafu {
+                uri: &raw.uri,
+                method: &raw.method,
+            })?;
 
             // In raw mode, the user is expecting only the server response on stdout, so we more
             // carefully control other output and only write it to stderr.
             if log_enabled!(log::Level::Debug) {
                 eprintln!("{}", status);
+                for (name, value) in &headers {
+                    eprintln!("{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+                }
             }
-            if !body.is_empty() {
+            if wants_output(args) {
+                let value = serde_json::from_str(&body).context(error::RawJsonSnafu)?;
+                print_value(args, &value)?;
+            } else if !body.is_empty() {
                 println!("{}", body);
             }
         }
 
         Subcommand::Apply(apply) => {
-            apply::apply(&args.socket_path, apply.input_sources)
+            let changed = if apply.prune {
+                apply::apply_and_prune(&args.socket_path, apply.input_sources).await
+            } else {
+                apply::apply(&args.socket_path, apply.input_sources).await
+            }
+            .context(error::ApplySnafu)?;
+            print_changed(args, changed)?;
+        }
+
+        Subcommand::Audit(audit) => {
+            let value = audit::audit(&args.socket_path, &audit.query)
+                .await
+                .context(error::AuditSnafu)?;
+            print_value(args, &value)?;
+        }
+
+        Subcommand::Containers(_containers) => {
+            let value = containers::containers(&args.socket_path)
                 .await
-                .context(error::ApplySnafu)?;
+                .context(error::ContainersSnafu)?;
+            print_value(args, &value)?;
         }
 
         Subcommand::Exec(exec) => {
@@ -657,15 +1145,50 @@ async fn run() -> Result<()> {
                 .context(error::ExecSnafu)?;
         }
 
+        Subcommand::Export(_export) => {
+            let value = export::export(&args.socket_path)
+                .await
+                .context(error::ExportSnafu)?;
+            print_value_as(args, &value, Format::Toml)?;
+        }
+
         Subcommand::Get(get) => {
-            let result = match get {
-                GetArgs::Uri(uri) => get::get_uri(&args.socket_path, uri).await,
-                GetArgs::Prefixes(prefixes) => get::get_prefixes(&args.socket_path, prefixes).await,
+            let (result, settings_at) = match get.target {
+                GetTarget::Uri(uri) => {
+                    let settings_at = get::settings_location(&uri);
+                    (get::get_uri(&args.socket_path, uri).await, settings_at)
+                }
+                GetTarget::Prefixes(prefixes) => (
+                    get::get_prefixes(&args.socket_path, prefixes).await,
+                    Some(get::SettingsLocation::Nested),
+                ),
             };
-            let value = result.context(error::GetSnafu)?;
-            let pretty =
-                serde_json::to_string_pretty(&value).expect("JSON Value already validated as JSON");
-            println!("{}", pretty);
+            let mut value = result.context(error::GetSnafu)?;
+            if let (false, Some(settings_at)) = (get.show_sensitive, settings_at) {
+                get::redact_sensitive(&args.socket_path, &mut value, settings_at)
+                    .await
+                    .context(error::GetSnafu)?;
+            }
+            print_value(args, &value)?;
+        }
+
+        Subcommand::History(_history) => {
+            let value = history::history(&args.socket_path)
+                .await
+                .context(error::HistorySnafu)?;
+            print_value(args, &value)?;
+        }
+
+        Subcommand::Plan(plan) => {
+            let plan = plan::plan(&args.socket_path, plan.transaction.as_deref())
+                .await
+                .context(error::PlanSnafu)?;
+            if wants_output(args) {
+                let value = serde_json::to_value(&plan).context(error::SerializeSnafu)?;
+                print_value(args, &value)?;
+            } else {
+                print_plan(&plan);
+            }
         }
 
         Subcommand::Reboot(_reboot) => {
@@ -674,9 +1197,23 @@ async fn run() -> Result<()> {
                 .context(error::RebootSnafu)?;
         }
 
+        Subcommand::Revert(revert) => {
+            let changed = history::revert(&args.socket_path, revert.id)
+                .await
+                .context(error::RevertSnafu)?;
+            print_changed(args, changed)?;
+        }
+
+        Subcommand::Schema(schema) => {
+            let value = schema::schema(&args.socket_path, schema.setting.as_deref())
+                .await
+                .context(error::SchemaSnafu)?;
+            print_value(args, &value)?;
+        }
+
         Subcommand::Set(set) => {
-            let settings = match set {
-                SetArgs::Simple(input_map) => {
+            let settings = match set.input {
+                SetInput::Simple(input_map) => {
                     // For key=val, we need some type information to deserialize into a Settings.
                     trace!("Original key=value input: {:#?}", input_map);
                     let massaged_map = massage_set_input(input_map)?;
@@ -687,26 +1224,29 @@ async fn run() -> Result<()> {
                     datastore::deserialization::from_map(&massaged_map)
                         .context(error::DeserializeMapSnafu)?
                 }
-                SetArgs::Json(json) => {
+                SetInput::Json(json) => {
                     // No processing to do on JSON input; the format determines the types.  serde
                     // can turn a Value into the nested Settings structure itself.
                     serde_json::from_value(json).context(error::DeserializeJsonSnafu)?
                 }
             };
 
-            set::set(&args.socket_path, &settings)
-                .await
-                .context(error::SetSnafu)?;
+            let changed = match set.if_match {
+                Some(etag) => set::set_if_match(&args.socket_path, &settings, &etag).await,
+                None => set::set(&args.socket_path, &settings).await,
+            }
+            .context(error::SetSnafu)?;
+            print_changed(args, changed)?;
         }
 
         Subcommand::Update(subcommand) => match subcommand {
             UpdateSubcommand::Check(_check) => {
-                check(&args).await?;
+                check(args).await?;
             }
 
             UpdateSubcommand::Apply(apply) => {
                 if apply.check {
-                    let output = check(&args).await?;
+                    let output = check(args).await?;
                     // Exit early if no update is required, either because none is available or one
                     // is already applied and ready.
                     if !update::required(&output) {
@@ -730,11 +1270,100 @@ async fn run() -> Result<()> {
             }
 
             UpdateSubcommand::Cancel(_cancel) => {
-                update::cancel(&args.socket_path)
+                let status = update::cancel(&args.socket_path)
                     .await
                     .context(error::UpdateCancelSnafu)?;
+                if wants_output(args) {
+                    let value = serde_json::from_str(&status).context(error::RawJsonSnafu)?;
+                    print_value(args, &value)?;
+                }
             }
         },
+
+        Subcommand::Validate(validate) => {
+            let (name, input) = if validate.input_source == "-" {
+                let mut input = String::new();
+                io::stdin()
+                    .read_to_string(&mut input)
+                    .context(error::ValidateInputSnafu { name: "stdin" })?;
+                ("<stdin>", input)
+            } else {
+                let input = fs::read_to_string(&validate.input_source).context(
+                    error::ValidateInputSnafu {
+                        name: &validate.input_source,
+                    },
+                )?;
+                (validate.input_source.as_str(), input)
+            };
+
+            let schema = match &validate.schema {
+                Some(path) => {
+                    let schema = fs::read_to_string(path)
+                        .context(error::ValidateSchemaReadSnafu { path })?;
+                    let schema: serde_json::Value = serde_json::from_str(&schema)
+                        .context(error::ValidateSchemaJsonSnafu { path })?;
+                    Some(schema)
+                }
+                None => None,
+            };
+            let report =
+                validate::validate(&input, validate.variant.as_deref(), schema.as_ref())
+                    .context(error::ValidateSnafu)?;
+            if wants_output(args) {
+                let value = serde_json::to_value(&report).context(error::SerializeSnafu)?;
+                print_value(args, &value)?;
+            } else {
+                for problem in &report.problems {
+                    match (problem.line, problem.column) {
+                        (Some(line), Some(column)) => {
+                            println!("{}:{}:{}: {}", name, line, column, problem)
+                        }
+                        _ => println!("{}: {}", name, problem),
+                    }
+                }
+            }
+
+            let errors = report.errors();
+            ensure!(
+                errors == 0,
+                error::InvalidUserDataSnafu {
+                    name,
+                    variant: &report.variant,
+                    errors,
+                }
+            );
+            info!(
+                "{} is valid for {}, with {} warning(s)",
+                name,
+                report.variant,
+                report.warnings()
+            );
+        }
+
+        Subcommand::Watch(watch) => {
+            // Print one event per line so the output is easy to consume from scripts.
+            // Other formats print each event as a separate document.
+            watch::watch(&args.socket_path, watch.prefixes, |event| {
+                let value = serde_json::to_value(event).expect("event already serialized once");
+                let selected = match &args.query {
+                    Some(query) => match query.select(&value) {
+                        Ok(selected) => selected,
+                        // Not every event has to match.
+                        Err(_) => return,
+                    },
+                    None => value,
+                };
+                match args.output {
+                    None | Some(Format::Json) => println!("{}", selected),
+                    Some(format) => match output::format(&selected, format) {
+                        Ok(formatted) => print!("{}", formatted),
+                        Err(e) => warn!("{}", e),
+                    },
+                }
+            })
+            .await
+            .context(error::WatchSnafu)?;
+        }
     }
 
     Ok(())
@@ -745,14 +1374,28 @@ async fn run() -> Result<()> {
 // https://github.com/shepmaster/snafu/issues/110
 #[tokio::main]
 async fn main() {
-    if let Err(e) = run().await {
-        eprintln!("{}", e);
+    let (args, subcommand) = parse_args(env::args());
+    if let Err(e) = run(&args, subcommand).await {
+        // Print errors in the requested format so scripts can tell what went wrong.
+        match args.output {
+            Some(format) if format.is_structured() => {
+                let value = output::error_value(&e);
+                match output::format(&value, format) {
+                    Ok(formatted) => eprint!("{}", formatted),
+                    Err(_) => eprintln!("{}", value),
+                }
+            }
+            _ => eprintln!("{}", e),
+        }
         process::exit(1);
     }
 }
 
 mod error {
-    use apiclient::{apply, exec, get, reboot, set, update};
+    use apiclient::{
+        apply, audit, containers, exec, export, get, history, plan, reboot, schema, set, update,
+        validate, watch,
+    };
     use snafu::Snafu;
 
     #[derive(Debug, Snafu)]
@@ -761,6 +1404,12 @@ mod error {
         #[snafu(display("Failed to apply settings: {}", source))]
         Apply { source: apply::Error },
 
+        #[snafu(display("Failed to get audit log: {}", source))]
+        Audit { source: audit::Error },
+
+        #[snafu(display("Failed to get container status: {}", source))]
+        Containers { source: containers::Error },
+
         #[snafu(display("Unable to deserialize input JSON into model: {}", source))]
         DeserializeJson { source: serde_json::Error },
 
@@ -775,12 +1424,37 @@ mod error {
         #[snafu(display("Failed to exec: {}", source))]
         Exec { source: exec::Error },
 
+        #[snafu(display("Failed to export settings: {}", source))]
+        Export { source: export::Error },
+
         #[snafu(display("Failed to get settings: {}", source))]
         Get { source: get::Error },
 
+        #[snafu(display("Failed to get settings history: {}", source))]
+        History { source: history::Error },
+
+        #[snafu(display("{} is not valid user data for {}: {} error(s)", name, variant, errors))]
+        InvalidUserData {
+            name: String,
+            variant: String,
+            errors: usize,
+        },
+
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
+        #[snafu(display("Unable to format output: {}", source))]
+        Output { source: apiclient::output::Error },
+
+        #[snafu(display("Failed to plan settings changes: {}", source))]
+        Plan { source: plan::Error },
+
+        #[snafu(display(
+            "Response was not valid JSON, needed for --output and --query: {}",
+            source
+        ))]
+        RawJson { source: serde_json::Error },
+
         #[snafu(display("Failed to reboot: {}", source))]
         Reboot { source: reboot::Error },
 
@@ -792,6 +1466,12 @@ mod error {
             source: Box<apiclient::Error>,
         },
 
+        #[snafu(display("Failed to revert settings: {}", source))]
+        Revert { source: history::Error },
+
+        #[snafu(display("Failed to get settings schema: {}", source))]
+        Schema { source: schema::Error },
+
         #[snafu(display("Unable to serialize data: {}", source))]
         Serialize { source: serde_json::Error },
 
@@ -806,6 +1486,30 @@ mod error {
 
         #[snafu(display("Failed to check for updates: {}", source))]
         UpdateCheck { source: update::Error },
+
+        #[snafu(display("Failed to validate user data: {}", source))]
+        Validate { source: validate::Error },
+
+        #[snafu(display("Failed to read user data from {}: {}", name, source))]
+        ValidateInput {
+            name: String,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to read settings schema from {}: {}", path, source))]
+        ValidateSchemaRead {
+            path: String,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Settings schema in {} is not valid JSON: {}", path, source))]
+        ValidateSchemaJson {
+            path: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to watch settings: {}", source))]
+        Watch { source: watch::Error },
     }
 }
 type Result<T> = std::result::Result<T, error::Error>;
@@ -0,0 +1,448 @@
+//! The 'output' module formats API responses for printing, optionally selecting part of them with
+//! a query, so scripts can consume them without extra tools.
+//!
+//! Queries are a small subset of JSONPath.  They're a series of keys separated by dots, like
+//! `settings.motd`, with a few additions:
+//! * Keys containing dots can be quoted, like `settings.kernel.sysctl."vm.max_map_count"`.
+//! * `[N]` selects the Nth item of a list, like `[0].id`.
+//! * `*` or `[*]` selects every item of a list or object; the result is then a list of matches.
+//! * A leading `$` or `.` is allowed and ignored.
+
+use serde_json::{Map, Value};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// The formats we can print data in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    Table,
+}
+
+impl Format {
+    /// Whether the format is meant for programs rather than people; errors are printed in
+    /// structured formats too.
+    pub fn is_structured(self) -> bool {
+        self != Format::Table
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "table" => Ok(Format::Table),
+            _ => error::UnknownFormatSnafu { input }.fail(),
+        }
+    }
+}
+
+/// Formats the given value, returning a string ready to print, with a trailing newline.
+pub fn format(value: &Value, format: Format) -> Result<String> {
+    let mut output = match format {
+        Format::Json => serde_json::to_string_pretty(value).context(error::JsonSnafu)?,
+        Format::Yaml => serde_yaml::to_string(value).context(error::YamlSnafu)?,
+        Format::Toml => {
+            // TOML documents are tables; use a query to select one if the data isn't an object.
+            ensure!(value.is_object(), error::TomlNotTableSnafu);
+            let value = toml::Value::try_from(value).context(error::TomlSnafu)?;
+            toml::to_string_pretty(&value).context(error::TomlSnafu)?
+        }
+        Format::Table => table(value),
+    };
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Renders a value as aligned columns.  A list of objects becomes a row per object, with a column
+/// per key; anything else becomes a row per scalar, keyed by its path in the data.
+fn table(value: &Value) -> String {
+    let mut rows = Vec::new();
+    match value {
+        Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object) => {
+            let mut columns: Vec<&String> = Vec::new();
+            for item in items.iter().filter_map(Value::as_object) {
+                for key in item.keys() {
+                    if !columns.contains(&key) {
+                        columns.push(key);
+                    }
+                }
+            }
+            rows.push(columns.iter().map(|c| c.to_uppercase()).collect());
+            for item in items {
+                rows.push(
+                    columns
+                        .iter()
+                        .map(|c| item.get(c.as_str()).map(cell).unwrap_or_default())
+                        .collect(),
+                );
+            }
+        }
+        Value::Array(_) | Value::Object(_) => {
+            let mut flat = BTreeMap::new();
+            flatten("", value, &mut flat);
+            rows.extend(flat.into_iter().map(|(path, value)| vec![path, value]));
+        }
+        scalar => rows.push(vec![cell(scalar)]),
+    }
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|c| c.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut output = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            if i + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                let _ = write!(line, "{:width$}  ", cell, width = widths[i]);
+            }
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+/// Adds each scalar in the value to `flat`, keyed by its path, like `settings.motd` or `[0].id`.
+fn flatten(path: &str, value: &Value, flat: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                // Quote keys with dots, like 'apiclient set' accepts.
+                let key = if key.contains('.') {
+                    format!("\"{}\"", key)
+                } else {
+                    key.clone()
+                };
+                let path = if path.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten(&path, value, flat);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", path, i), value, flat);
+            }
+        }
+        _ => {
+            flat.insert(path.to_string(), cell(value));
+        }
+    }
+}
+
+/// Renders a value for a table cell; strings are shown without quotes.
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// A parsed query that selects part of a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    input: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let bad = |msg: &'static str| error::QueryParseSnafu { input, msg }.fail();
+        let mut segments = Vec::new();
+        let mut rest = input.trim();
+        rest = rest.strip_prefix('$').unwrap_or(rest);
+        let mut first = true;
+        while !rest.is_empty() {
+            if let Some(bracketed) = rest.strip_prefix('[') {
+                let (inner, after) = match bracketed.split_once(']') {
+                    Some(split) => split,
+                    None => return bad("unclosed '['"),
+                };
+                let segment = if inner == "*" {
+                    Segment::Wildcard
+                } else if let Some(key) = inner
+                    .strip_prefix('"')
+                    .and_then(|inner| inner.strip_suffix('"'))
+                {
+                    Segment::Key(key.to_string())
+                } else if let Ok(index) = inner.parse() {
+                    Segment::Index(index)
+                } else {
+                    return bad("expected a number, '*', or a quoted key in brackets");
+                };
+                segments.push(segment);
+                rest = after;
+            } else {
+                match rest.strip_prefix('.') {
+                    Some(after) => rest = after,
+                    None if first => {}
+                    None => return bad("expected '.' or '[' between keys"),
+                }
+                if let Some(quoted) = rest.strip_prefix('"') {
+                    let (key, after) = match quoted.split_once('"') {
+                        Some(split) => split,
+                        None => return bad("unclosed '\"'"),
+                    };
+                    segments.push(Segment::Key(key.to_string()));
+                    rest = after;
+                } else {
+                    let end = rest.find(|c| c == '.' || c == '[').unwrap_or(rest.len());
+                    let (key, after) = rest.split_at(end);
+                    match key {
+                        // A lone leading dot means the whole value.
+                        "" if first && after.is_empty() => {}
+                        "" if first && after.starts_with('[') => {}
+                        "" => return bad("empty key"),
+                        "*" => segments.push(Segment::Wildcard),
+                        key => segments.push(Segment::Key(key.to_string())),
+                    }
+                    rest = after;
+                }
+            }
+            first = false;
+        }
+        Ok(Query {
+            input: input.to_string(),
+            segments,
+        })
+    }
+}
+
+impl Query {
+    /// Selects the part of the value matched by the query.  If the query has a wildcard, returns
+    /// a list of every match, which may be empty; otherwise, returns the single match.
+    pub fn select(&self, value: &Value) -> Result<Value> {
+        let mut matches = vec![value];
+        let mut wildcard = false;
+        for segment in &self.segments {
+            matches = matches
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (segment, value) {
+                        (Segment::Key(key), Value::Object(map)) => {
+                            map.get(key).into_iter().collect()
+                        }
+                        (Segment::Index(index), Value::Array(items)) => {
+                            items.get(*index).into_iter().collect()
+                        }
+                        (Segment::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (Segment::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+            wildcard |= *segment == Segment::Wildcard;
+        }
+
+        if wildcard {
+            Ok(Value::Array(matches.into_iter().cloned().collect()))
+        } else {
+            matches
+                .pop()
+                .cloned()
+                .context(error::QueryNoMatchSnafu { query: &self.input })
+        }
+    }
+}
+
+/// Builds the structured form of an error, for printing in a structured format.  `kind` is
+/// "response" if the server responded with an error status, which is included along with the
+/// server's message; "transport" if we couldn't talk to the server; or "client" for other errors,
+/// like invalid input.
+pub fn error_value(error: &(dyn std::error::Error + 'static)) -> Value {
+    let mut fields = Map::new();
+    let mut kind = "client";
+    let mut source = Some(error);
+    while let Some(e) = source {
+        let api_error = e
+            .downcast_ref::<crate::Error>()
+            .or_else(|| e.downcast_ref::<Box<crate::Error>>().map(AsRef::as_ref));
+        if let Some(api_error) = api_error {
+            match api_error.status() {
+                Some((status, body)) => {
+                    kind = "response";
+                    fields.insert("status".to_string(), status.as_u16().into());
+                    fields.insert("server_message".to_string(), body.into());
+                }
+                None => kind = "transport",
+            }
+            break;
+        }
+        source = e.source();
+    }
+    fields.insert("kind".to_string(), kind.into());
+    fields.insert("message".to_string(), error.to_string().into());
+
+    let mut value = Map::new();
+    value.insert("error".to_string(), Value::Object(fields));
+    Value::Object(value)
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Unable to format output as JSON: {}", source))]
+        Json { source: serde_json::Error },
+
+        #[snafu(display("Query '{}' matched nothing", query))]
+        QueryNoMatch { query: String },
+
+        #[snafu(display("Invalid query '{}': {}", input, msg))]
+        QueryParse { input: String, msg: &'static str },
+
+        #[snafu(display("Unable to format output as TOML: {}", source))]
+        Toml { source: toml::ser::Error },
+
+        #[snafu(display("TOML output must be an object; use --query to select one"))]
+        TomlNotTable,
+
+        #[snafu(display("Unknown output format '{}', expected json|yaml|toml|table", input))]
+        UnknownFormat { input: String },
+
+        #[snafu(display("Unable to format output as YAML: {}", source))]
+        Yaml { source: serde_yaml::Error },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn select(query: &str, value: &Value) -> Value {
+        Query::from_str(query).unwrap().select(value).unwrap()
+    }
+
+    #[test]
+    fn queries() {
+        let value = json!({
+            "settings": {
+                "motd": "hi",
+                "kernel": {"sysctl": {"vm.max_map_count": "262144"}},
+                "host-containers": {
+                    "admin": {"enabled": false},
+                    "control": {"enabled": true}
+                }
+            },
+            "history": [{"id": 1}, {"id": 2}]
+        });
+        assert_eq!(select("settings.motd", &value), json!("hi"));
+        assert_eq!(select("$.settings.motd", &value), json!("hi"));
+        assert_eq!(select(".settings.motd", &value), json!("hi"));
+        assert_eq!(select(".", &value), value);
+        assert_eq!(
+            select("settings.kernel.sysctl.\"vm.max_map_count\"", &value),
+            json!("262144")
+        );
+        assert_eq!(
+            select("settings.kernel.sysctl[\"vm.max_map_count\"]", &value),
+            json!("262144")
+        );
+        assert_eq!(
+            select("settings.host-containers.*.enabled", &value),
+            json!([false, true])
+        );
+        assert_eq!(select("history[1].id", &value), json!(2));
+        assert_eq!(select("history[*].id", &value), json!([1, 2]));
+        assert_eq!(select("[0]", &json!(["a"])), json!("a"));
+        assert_eq!(select(".[0]", &json!(["a"])), json!("a"));
+
+        Query::from_str("settings.missing")
+            .unwrap()
+            .select(&value)
+            .unwrap_err();
+        for bad in [
+            "settings..motd",
+            "history[",
+            "history[x]",
+            "settings.[0]",
+            "\"open",
+        ] {
+            Query::from_str(bad).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let value = json!({"motd": "hi", "kernel": {"lockdown": "none"}});
+        assert_eq!(
+            format(&value, Format::Toml).unwrap(),
+            "motd = 'hi'\n\n[kernel]\nlockdown = 'none'\n"
+        );
+        assert_eq!(
+            format(&value, Format::Yaml).unwrap(),
+            "kernel:\n  lockdown: none\nmotd: hi\n"
+        );
+        assert_eq!(
+            format(&value, Format::Table).unwrap(),
+            "kernel.lockdown  none\nmotd             hi\n"
+        );
+        assert_eq!(
+            format(
+                &json!([{"id": 1, "keys": ["a"]}, {"id": 12}]),
+                Format::Table
+            )
+            .unwrap(),
+            "ID  KEYS\n1   [\"a\"]\n12\n"
+        );
+        format(&json!("hi"), Format::Toml).unwrap_err();
+    }
+
+    #[test]
+    fn errors() {
+        let error = crate::Error::ResponseStatus {
+            method: "PATCH".to_string(),
+            code: http::StatusCode::BAD_REQUEST,
+            uri: "/settings".to_string(),
+            body: "Unable to match your input".to_string(),
+        };
+        let value = error_value(&error);
+        assert_eq!(value["error"]["kind"], "response");
+        assert_eq!(value["error"]["status"], 400);
+        assert_eq!(
+            value["error"]["server_message"],
+            "Unable to match your input"
+        );
+
+        let value = error_value(&Error::TomlNotTable);
+        assert_eq!(value["error"]["kind"], "client");
+        assert!(value["error"].get("status").is_none());
+    }
+}
@@ -0,0 +1,58 @@
+//! The 'plan' module lets you see what committing and applying a pending transaction would do,
+//! without changing anything.
+
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What committing and applying a pending transaction would change on the system.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Plan {
+    /// Map of configuration file path to a unified diff of its current and new contents.  Files
+    /// that wouldn't change aren't included.
+    pub files: BTreeMap<String, String>,
+    /// Map of affected service name to the restart commands that would run for it.
+    pub restart_commands: BTreeMap<String, Vec<String>>,
+}
+
+/// Requests the plan for the given pending transaction, or the default transaction if None.
+pub async fn plan<P>(socket_path: P, transaction: Option<&str>) -> Result<Plan>
+where
+    P: AsRef<Path>,
+{
+    let uri = match transaction {
+        Some(transaction) => format!("/tx/plan?tx={}", transaction),
+        None => "/tx/plan".to_string(),
+    };
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri: &uri, method })?;
+
+    serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Response from '{}' was not a valid plan: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
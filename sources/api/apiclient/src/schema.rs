@@ -0,0 +1,133 @@
+//! The 'schema' module lets you fetch the JSON Schema that describes the settings of the host's
+//! variant, so you can check input before sending it to the API.
+
+use datastore::{Key, KeyType};
+use serde_json::Value;
+use snafu::{OptionExt, ResultExt};
+use std::path::Path;
+
+/// Fetches the settings schema.  If a setting name is given, like `settings.kubernetes` or
+/// `kubernetes.node-labels`, only the schema of that setting is returned.
+pub async fn schema<P>(socket_path: P, setting: Option<&str>) -> Result<Value>
+where
+    P: AsRef<Path>,
+{
+    let uri = "/schema";
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+
+    let schema = serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })?;
+    match setting {
+        Some(setting) => select(schema, setting),
+        None => Ok(schema),
+    }
+}
+
+/// Finds the schema of the given setting within the settings schema.  Names within maps, like
+/// the name of a host container, select the schema shared by all the map's values.
+fn select(mut schema: Value, setting: &str) -> Result<Value> {
+    let key = Key::new(KeyType::Data, setting).context(error::InvalidKeySnafu { setting })?;
+    let mut segments = key.segments().as_slice();
+    // The "settings." prefix is optional.
+    if segments.first().map(String::as_str) == Some("settings") {
+        segments = &segments[1..];
+    }
+
+    for segment in segments {
+        schema = match schema
+            .get_mut("properties")
+            .and_then(|p| p.get_mut(segment))
+        {
+            Some(property) => property.take(),
+            None => schema
+                .get_mut("additionalProperties")
+                .filter(|values| values.is_object())
+                .map(Value::take)
+                .context(error::UnknownSettingSnafu { setting })?,
+        };
+    }
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn settings_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "motd": { "type": "string" },
+                "host-containers": {
+                    "type": "object",
+                    "additionalProperties": {
+                        "type": "object",
+                        "properties": { "enabled": { "type": "boolean" } },
+                        "additionalProperties": false,
+                    },
+                },
+            },
+            "additionalProperties": false,
+        })
+    }
+
+    #[test]
+    fn select_setting() {
+        let expected = json!({ "type": "string" });
+        assert_eq!(
+            select(settings_schema(), "settings.motd").unwrap(),
+            expected
+        );
+        assert_eq!(select(settings_schema(), "motd").unwrap(), expected);
+    }
+
+    #[test]
+    fn select_map_value() {
+        assert_eq!(
+            select(settings_schema(), "host-containers.admin.enabled").unwrap(),
+            json!({ "type": "boolean" })
+        );
+    }
+
+    #[test]
+    fn select_unknown() {
+        assert!(select(settings_schema(), "settings.nope").is_err());
+        assert!(select(settings_schema(), "motd.nope").is_err());
+    }
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Invalid setting name '{}': {}", setting, source))]
+        InvalidKey {
+            setting: String,
+            source: datastore::Error,
+        },
+
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("No setting '{}' in the schema", setting))]
+        UnknownSetting { setting: String },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
@@ -1,34 +1,104 @@
 use crate::rando;
-use snafu::ResultExt;
+use http::{header, HeaderMap, HeaderValue};
+use log::warn;
+use snafu::{ensure, ResultExt};
 use std::path::Path;
 
 /// Changes the requested settings through the API, then commits and applies the transaction
 /// containing those changes.  The given Settings only has to be populated (i.e. Option::Some) with
 /// the settings you want to change.  If you're deserializing a request from a user, for example,
 /// the created Settings will only have the requested keys populated.
-pub async fn set<P>(socket_path: P, settings: &model::Settings) -> Result<()>
+///
+/// Returns the names of the settings that changed.
+pub async fn set<P>(socket_path: P, settings: &model::Settings) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    set_with_headers(socket_path, settings, HeaderMap::new()).await
+}
+
+/// Works like `set`, but only changes the settings if none of them have changed since the given
+/// ETag was returned by the API from a GET of /settings.  If any have changed, the server rejects
+/// the change with a 412 (Precondition Failed) status, and nothing is committed.
+pub async fn set_if_match<P>(
+    socket_path: P,
+    settings: &model::Settings,
+    etag: &str,
+) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let value = if_match_value(etag)?;
+    let mut headers = HeaderMap::new();
+    headers.insert(header::IF_MATCH, value);
+
+    set_with_headers(socket_path, settings, headers).await
+}
+
+/// Builds the If-Match header value for the given ETag.  The API only returns strong ETags, and
+/// only compares against them, so weak ETags are rejected here rather than by the server.
+fn if_match_value(etag: &str) -> Result<HeaderValue> {
+    let etag = etag.trim();
+    ensure!(!etag.starts_with("W/"), error::WeakEtagSnafu { etag });
+    // Accept the ETag with or without quotes, since shells make them awkward to pass along.
+    let etag = if etag.starts_with('"') {
+        etag.to_string()
+    } else {
+        format!("\"{}\"", etag)
+    };
+    HeaderValue::from_str(&etag).context(error::InvalidEtagSnafu { etag: &etag })
+}
+
+async fn set_with_headers<P>(
+    socket_path: P,
+    settings: &model::Settings,
+    headers: HeaderMap,
+) -> Result<Vec<String>>
 where
     P: AsRef<Path>,
 {
     // We use a specific transaction ID so we don't commit any other changes that may be pending.
     let transaction = format!("apiclient-set-{}", rando());
 
+    let result = set_in_transaction(&socket_path, &transaction, settings, headers).await;
+    if result.is_err() {
+        // Don't leave the changes pending, for example after an If-Match conflict; nothing else
+        // will commit or clean up our transaction.
+        let uri = format!("/tx?tx={}", transaction);
+        if let Err(e) = crate::raw_request(&socket_path, &uri, "DELETE", None).await {
+            warn!("Failed to delete transaction '{}': {}", transaction, e);
+        }
+    }
+    result
+}
+
+/// Sends the settings changes to the server in the given transaction, then commits and applies it.
+async fn set_in_transaction<P>(
+    socket_path: P,
+    transaction: &str,
+    settings: &model::Settings,
+    headers: HeaderMap,
+) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
     // Send the settings changes to the server.
     let uri = format!("/settings?tx={}", transaction);
     let method = "PATCH";
     let request_body = serde_json::to_string(&settings).context(error::SerializeSnafu)?;
-    let (_status, _body) = crate::raw_request(&socket_path, &uri, method, Some(request_body))
-        .await
-        .context(error::RequestSnafu { uri, method })?;
+    let (_status, _headers, _body) =
+        crate::raw_request_with_headers(&socket_path, &uri, method, headers, Some(request_body))
+            .await
+            .context(error::RequestSnafu { uri, method })?;
 
     // Commit the transaction and apply it to the system.
     let uri = format!("/tx/commit_and_apply?tx={}", transaction);
     let method = "POST";
-    let (_status, _body) = crate::raw_request(&socket_path, &uri, method, None)
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
         .await
-        .context(error::RequestSnafu { uri, method })?;
+        .context(error::RequestSnafu { uri: &uri, method })?;
 
-    Ok(())
+    serde_json::from_str(&body).context(error::ResponseJsonSnafu { uri })
 }
 
 mod error {
@@ -37,6 +107,18 @@ mod error {
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
     pub enum Error {
+        #[snafu(display("Invalid ETag '{}': {}", etag, source))]
+        InvalidEtag {
+            etag: String,
+            source: http::header::InvalidHeaderValue,
+        },
+
+        #[snafu(display(
+            "Weak ETag '{}' can't be used with If-Match; use the ETag from a GET of /settings",
+            etag
+        ))]
+        WeakEtag { etag: String },
+
         #[snafu(display("Unable to serialize data: {}", source))]
         Serialize { source: serde_json::Error },
 
@@ -47,7 +129,29 @@ mod error {
             #[snafu(source(from(crate::Error, Box::new)))]
             source: Box<crate::Error>,
         },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
     }
 }
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn if_match_values() {
+        assert_eq!(if_match_value("42").unwrap(), "\"42\"");
+        assert_eq!(if_match_value("\"42\"").unwrap(), "\"42\"");
+        assert_eq!(if_match_value(" 42\n").unwrap(), "\"42\"");
+        assert!(matches!(
+            if_match_value("W/\"42\""),
+            Err(Error::WeakEtag { .. })
+        ));
+    }
+}
@@ -69,6 +69,34 @@ pub fn required(check_output: &str) -> bool {
     }
 }
 
+/// Warns the user if the last update failed its health check on first boot, as shown in the
+/// output of check(), since the host may have rolled back to the previous version.
+pub fn warn_failed_health_check(check_output: &str) {
+    let response: serde_json::Value = match serde_json::from_str(check_output) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+    let health_check = &response["boot_health_check"];
+    if health_check["healthy"].as_bool() != Some(false) {
+        return;
+    }
+
+    let version = health_check["version"].as_str().unwrap_or("unknown");
+    if health_check["rolled_back"].as_bool() == Some(true) {
+        warn!(
+            "Update to {} failed its health check and was rolled back.",
+            version
+        );
+    } else {
+        warn!("Update to {} failed its health check.", version);
+    }
+    for failure in health_check["failures"].as_array().into_iter().flatten() {
+        if let Some(failure) = failure.as_str() {
+            warn!("  {}", failure);
+        }
+    }
+}
+
 /// Applies the update shown as selected in the output of check(), and makes it active.
 pub async fn apply<P>(socket_path: P) -> Result<()>
 where
@@ -0,0 +1,760 @@
+//! The 'validate' module checks user data against the settings model without a running host, so
+//! you can catch mistakes on a build workstation before launching instances.
+//!
+//! User data for the variant apiclient was built for is deserialized into its model, with all of
+//! the same validation the API does.  Each variant has its own model, so user data for another
+//! variant is checked against that variant's settings schema instead, as printed by 'apiclient
+//! schema' on a host of that variant.  Each problem is reported with the setting it's about and
+//! where that setting is in the input.  Settings that are normally created by a setting generator
+//! at boot are reported as warnings, because a value from user data means the generator won't run;
+//! the default settings of every variant are built in, so generators are known for any variant.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use snafu::{ensure, OptionExt};
+use std::collections::HashMap;
+use std::fmt;
+use toml::value::Table;
+
+mod schema;
+
+/// The variant whose model apiclient was built with.
+pub const BUILD_VARIANT: &str = env!("BUILD_VARIANT");
+
+// Defines VARIANT_DEFAULTS.
+include!(concat!(env!("OUT_DIR"), "/variant_defaults.rs"));
+
+/// Returns the names of the variants apiclient knows the default settings of.
+pub fn variants() -> impl Iterator<Item = &'static str> {
+    VARIANT_DEFAULTS.iter().map(|(variant, _)| *variant)
+}
+
+/// The results of validating user data.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub variant: String,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.problems
+            .iter()
+            .filter(|p| p.severity == severity)
+            .count()
+    }
+}
+
+/// A problem found in user data.  Line and column are 1-based, and point to the setting's key, or
+/// the nearest table that contains it.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    pub kind: ProblemKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: ", self.severity, self.kind)?;
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProblemKind {
+    /// The input isn't valid TOML.
+    Syntax,
+    /// There's no 'settings' table.
+    MissingSettings,
+    /// A setting that isn't in the model.
+    UnknownKey,
+    /// A value the model doesn't accept.
+    InvalidValue,
+    /// A top-level table other than 'settings', which is ignored at boot.
+    Ignored,
+    /// A setting that's normally created by a setting generator.
+    Generated,
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ProblemKind::Syntax => "syntax",
+            ProblemKind::MissingSettings => "missing-settings",
+            ProblemKind::UnknownKey => "unknown-key",
+            ProblemKind::InvalidValue => "invalid-value",
+            ProblemKind::Ignored => "ignored",
+            ProblemKind::Generated => "generated",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// What settings in user data are checked against.
+enum Model<'a> {
+    /// The model apiclient was built with.
+    Built,
+    /// The JSON Schema of a variant's settings.
+    Schema(&'a Value),
+}
+
+/// Validates TOML user data for the given variant, or the variant apiclient was built for.  If the
+/// settings schema of the variant is given, the settings are checked against it; otherwise, the
+/// variant must be the one apiclient was built for, and they're checked against its model.
+pub fn validate(input: &str, variant: Option<&str>, schema: Option<&Value>) -> Result<Report> {
+    let variant = variant.unwrap_or(BUILD_VARIANT);
+    let defaults = VARIANT_DEFAULTS
+        .iter()
+        .find(|(name, _)| *name == variant)
+        .map(|(_, defaults)| *defaults)
+        .context(error::UnknownVariantSnafu { variant })?;
+    let model = match schema {
+        Some(schema) => Model::Schema(schema),
+        None => {
+            ensure!(
+                variant == BUILD_VARIANT,
+                error::SchemaRequiredSnafu {
+                    requested: variant,
+                    built: BUILD_VARIANT,
+                }
+            );
+            Model::Built
+        }
+    };
+
+    let defaults: Table = toml::from_str(defaults).expect("default settings are valid TOML");
+    let generators = setting_generators(&defaults);
+    Ok(Report {
+        variant: variant.to_string(),
+        problems: check(input, &model, &generators),
+    })
+}
+
+/// Finds the settings that have setting generators in the given defaults, along with the
+/// generator commands.
+fn setting_generators(defaults: &Table) -> Vec<(Vec<String>, String)> {
+    fn walk(path: &mut Vec<String>, table: &Table, found: &mut Vec<(Vec<String>, String)>) {
+        for (name, value) in table {
+            match value {
+                toml::Value::String(command) if name == "setting-generator" => {
+                    found.push((path.clone(), command.clone()))
+                }
+                toml::Value::Table(inner) => {
+                    path.push(name.clone());
+                    walk(path, inner, found);
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    if let Some(toml::Value::Table(metadata)) = defaults.get("metadata") {
+        walk(&mut Vec::new(), metadata, &mut found);
+    }
+    found.retain(|(path, _)| path.first().map(String::as_str) == Some("settings"));
+    found
+}
+
+/// Checks user data against the model, given the settings that have setting generators.
+fn check(input: &str, model: &Model, generators: &[(Vec<String>, String)]) -> Vec<Problem> {
+    let locator = Locator::new(input);
+    let mut problems = Vec::new();
+
+    let mut user_data: Table = match toml::from_str(input) {
+        Ok(user_data) => user_data,
+        Err(e) => {
+            let (line, column) = match e.line_col() {
+                Some((line, column)) => (Some(line + 1), Some(column + 1)),
+                None => (None, None),
+            };
+            // The location is reported separately, so drop it from the message.
+            let message = e.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(index) if line.is_some() => message[..index].to_string(),
+                _ => message,
+            };
+            problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::Syntax,
+                key: None,
+                line,
+                column,
+                message,
+            });
+            return problems;
+        }
+    };
+
+    let settings = user_data.remove("settings");
+    for name in user_data.keys() {
+        problems.push(locator.problem(
+            Severity::Warning,
+            ProblemKind::Ignored,
+            &[name.clone()],
+            "only 'settings' is read from user data; this is ignored".to_string(),
+        ));
+    }
+
+    let settings = match settings {
+        Some(settings) => settings,
+        None => {
+            problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::MissingSettings,
+                key: None,
+                line: None,
+                column: None,
+                message: "user data has no 'settings' table".to_string(),
+            });
+            return problems;
+        }
+    };
+
+    // Convert to JSON like the API would see it; TOML values always convert.
+    let settings = Value::deserialize(settings).expect("TOML value converts to JSON");
+
+    match model {
+        Model::Built => {
+            if let Err(error) = deserialize_at(&[], settings.clone()) {
+                find_problems(&locator, &mut Vec::new(), &settings, error, &mut problems);
+            }
+        }
+        Model::Schema(schema) => {
+            for mismatch in schema::check(schema, &settings) {
+                let mut path = vec!["settings".to_string()];
+                path.extend(mismatch.path);
+                problems.push(locator.problem(
+                    Severity::Error,
+                    mismatch.kind,
+                    &path,
+                    mismatch.message,
+                ));
+            }
+        }
+    }
+
+    // Sundog only runs a setting generator if the setting doesn't have a value yet, so a value
+    // from user data takes the place of the generated one.
+    let mut leaves = Vec::new();
+    find_leaves(&mut vec!["settings".to_string()], &settings, &mut leaves);
+    for (path, command) in generators {
+        if leaves.iter().any(|leaf| leaf.starts_with(path)) {
+            problems.push(locator.problem(
+                Severity::Warning,
+                ProblemKind::Generated,
+                path,
+                format!(
+                    "normally generated at boot by '{}'; the generator won't run, and the value \
+                     from user data is used instead",
+                    command
+                ),
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Deserializes a value into the model as if it were the only setting given, at the given path
+/// under 'settings'.
+fn deserialize_at(path: &[String], value: Value) -> std::result::Result<(), String> {
+    let settings = path.iter().rev().fold(value, |value, segment| {
+        Value::Object(std::iter::once((segment.clone(), value)).collect())
+    });
+    model::Settings::deserialize(&settings)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Errors that come from checking part of a struct on its own, rather than the user's input.
+fn is_missing_field(error: &str) -> bool {
+    error.starts_with("missing field")
+}
+
+/// Narrows down where the given deserialization error comes from by checking the value's children
+/// on their own, so each problem is reported against the most specific setting.
+fn find_problems(
+    locator: &Locator,
+    path: &mut Vec<String>,
+    value: &Value,
+    error: String,
+    problems: &mut Vec<Problem>,
+) {
+    if let Value::Object(map) = value {
+        // If the table itself isn't accepted, for example because its name is unknown, report it
+        // once rather than once per value inside it.
+        let table_error = deserialize_at(path, json!({})).err();
+        match table_error {
+            Some(error) if !is_missing_field(&error) => {
+                problems.push(locator.setting_problem(path, error));
+                return;
+            }
+            _ => {}
+        }
+
+        let found = problems.len();
+        for (name, child) in map {
+            path.push(name.clone());
+            match deserialize_at(path, child.clone()) {
+                Err(error) if !is_missing_field(&error) => {
+                    find_problems(locator, path, child, error, problems)
+                }
+                _ => {}
+            }
+            path.pop();
+        }
+        if problems.len() > found {
+            return;
+        }
+    }
+
+    problems.push(locator.setting_problem(path, error));
+}
+
+/// Finds the paths of all values that aren't tables.
+fn find_leaves(path: &mut Vec<String>, value: &Value, leaves: &mut Vec<Vec<String>>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (name, child) in map {
+                path.push(name.clone());
+                find_leaves(path, child, leaves);
+                path.pop();
+            }
+        }
+        _ => leaves.push(path.clone()),
+    }
+}
+
+/// Formats a key path the way it would be written in TOML, quoting segments where needed.
+fn key_name(path: &[String]) -> String {
+    path.iter()
+        .map(|segment| {
+            if !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                segment.clone()
+            } else {
+                format!("{:?}", segment)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Finds where keys are defined in TOML source.  The TOML parser doesn't tell us, so this
+/// understands just enough of the format to find table headers and keys at the start of lines.
+struct Locator {
+    positions: HashMap<Vec<String>, (usize, usize)>,
+}
+
+impl Locator {
+    fn new(input: &str) -> Self {
+        let mut positions = HashMap::new();
+        let mut table = Vec::new();
+        // The closing delimiter of a multi-line string we're in, if any.
+        let mut multiline: Option<&str> = None;
+
+        for (index, line) in input.lines().enumerate() {
+            if let Some(delimiter) = multiline {
+                if line.contains(delimiter) {
+                    multiline = None;
+                }
+                continue;
+            }
+
+            let trimmed = line.trim_start();
+            let (header, rest) = if let Some(rest) = trimmed.strip_prefix("[[") {
+                (true, rest)
+            } else if let Some(rest) = trimmed.strip_prefix('[') {
+                (true, rest)
+            } else {
+                (false, trimmed)
+            };
+            let start = line.len() - rest.trim_start().len();
+            let (keys, after) = match parse_key(rest) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+
+            let path = if header {
+                if !after.starts_with(']') {
+                    continue;
+                }
+                table = keys;
+                table.clone()
+            } else {
+                let value = match after.strip_prefix('=') {
+                    Some(value) => value.trim_start(),
+                    None => continue,
+                };
+                for delimiter in &["\"\"\"", "'''"] {
+                    if let Some(string) = value.strip_prefix(delimiter) {
+                        if !string.contains(delimiter) {
+                            multiline = Some(delimiter);
+                        }
+                    }
+                }
+                table.iter().chain(keys.iter()).cloned().collect()
+            };
+
+            let column = line[..start].chars().count() + 1;
+            for length in 1..=path.len() {
+                positions
+                    .entry(path[..length].to_vec())
+                    .or_insert((index + 1, column));
+            }
+        }
+
+        Self { positions }
+    }
+
+    /// Returns the position of the key, or of the nearest table containing it.
+    fn locate(&self, path: &[String]) -> Option<(usize, usize)> {
+        (1..=path.len())
+            .rev()
+            .find_map(|length| self.positions.get(&path[..length]).copied())
+    }
+
+    fn problem(
+        &self,
+        severity: Severity,
+        kind: ProblemKind,
+        path: &[String],
+        message: String,
+    ) -> Problem {
+        let position = self.locate(path);
+        Problem {
+            severity,
+            kind,
+            key: Some(key_name(path)),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            message,
+        }
+    }
+
+    /// Makes a problem from a deserialization error for the setting at the given path under
+    /// 'settings'.
+    fn setting_problem(&self, path: &[String], error: String) -> Problem {
+        let kind = if error.starts_with("unknown field") {
+            ProblemKind::UnknownKey
+        } else {
+            ProblemKind::InvalidValue
+        };
+        let mut full_path = vec!["settings".to_string()];
+        full_path.extend_from_slice(path);
+        self.problem(Severity::Error, kind, &full_path, error)
+    }
+}
+
+/// Parses a possibly dotted and quoted TOML key at the start of the input, returning its segments
+/// and the rest of the input.
+fn parse_key(input: &str) -> Option<(Vec<String>, &str)> {
+    let mut segments = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        let (segment, after) = if let Some(quoted) = rest.strip_prefix('"') {
+            parse_basic_string(quoted)?
+        } else if let Some(quoted) = rest.strip_prefix('\'') {
+            let end = quoted.find('\'')?;
+            (quoted[..end].to_string(), &quoted[end + 1..])
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            (rest[..end].to_string(), &rest[end..])
+        };
+        segments.push(segment);
+
+        rest = after.trim_start();
+        match rest.strip_prefix('.') {
+            Some(after_dot) => rest = after_dot,
+            None => return Some((segments, rest)),
+        }
+    }
+}
+
+/// Parses the rest of a TOML basic string, after the opening quote.
+fn parse_basic_string(input: &str) -> Option<(String, &str)> {
+    let mut parsed = String::new();
+    let mut chars = input.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((parsed, &input[index + 1..])),
+            '\\' => {
+                let escaped = match chars.next()?.1 {
+                    'b' => '\u{8}',
+                    't' => '\t',
+                    'n' => '\n',
+                    'f' => '\u{c}',
+                    'r' => '\r',
+                    'u' => parse_unicode(&mut chars, 4)?,
+                    'U' => parse_unicode(&mut chars, 8)?,
+                    other => other,
+                };
+                parsed.push(escaped);
+            }
+            c => parsed.push(c),
+        }
+    }
+    None
+}
+
+fn parse_unicode(chars: &mut std::str::CharIndices, digits: usize) -> Option<char> {
+    let hex: String = chars.take(digits).map(|(_, c)| c).collect();
+    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generators() -> Vec<(Vec<String>, String)> {
+        let defaults = toml::from_str(
+            r#"
+            [metadata.settings.kubernetes]
+            max-pods.setting-generator = "pluto max-pods"
+            affected-services = ["kubernetes"]
+            "#,
+        )
+        .unwrap();
+        setting_generators(&defaults)
+    }
+
+    fn problems(input: &str) -> Vec<Problem> {
+        check(input, &Model::Built, &generators())
+    }
+
+    /// Checks against the schema of the model apiclient was built with, which should find the
+    /// same problems as the model itself.
+    fn schema_problems(input: &str) -> Vec<Problem> {
+        let schema = model::schema::settings();
+        check(input, &Model::Schema(&schema), &generators())
+    }
+
+    #[test]
+    fn valid() {
+        let input = r#"
+            [settings]
+            host-containers.admin.enabled = true
+            kernel.sysctl."vm.max_map_count" = "262144"
+        "#;
+        assert!(problems(input).is_empty());
+    }
+
+    #[test]
+    fn syntax_error() {
+        let found = problems("[settings]\nmotd = \n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, ProblemKind::Syntax);
+        assert_eq!(found[0].line, Some(2));
+        assert!(!found[0].message.contains("at line"));
+    }
+
+    #[test]
+    fn missing_settings() {
+        let found = problems("[other]\na = 1\n");
+        let kinds: Vec<_> = found.iter().map(|p| p.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ProblemKind::Ignored, ProblemKind::MissingSettings]
+        );
+        assert_eq!(found[0].key.as_deref(), Some("other"));
+        assert_eq!((found[0].line, found[0].column), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let input = "[settings]\nmotd = \"hi\"\n\n[settings.nope]\na = 1\nb = 2\n\n[settings.kernel]\n  lockdwn = \"none\"\n";
+        let schema_found = schema_problems(input);
+        assert_eq!(schema_found.len(), 2);
+        assert!(schema_found
+            .iter()
+            .all(|p| p.kind == ProblemKind::UnknownKey));
+
+        let found = problems(input);
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|p| p.kind == ProblemKind::UnknownKey));
+
+        let nope = found
+            .iter()
+            .find(|p| p.key.as_deref() == Some("settings.nope"))
+            .unwrap();
+        assert_eq!((nope.line, nope.column), (Some(4), Some(2)));
+
+        let lockdown = found
+            .iter()
+            .find(|p| p.key.as_deref() == Some("settings.kernel.lockdwn"))
+            .unwrap();
+        assert_eq!((lockdown.line, lockdown.column), (Some(9), Some(3)));
+    }
+
+    #[test]
+    fn invalid_values() {
+        let input = r#"[settings]
+kernel.lockdown = "sometimes"
+host-containers."bad name!".enabled = true
+kubernetes.node-labels."example.com/ok" = "fine"
+kubernetes.node-labels."-bad" = "value"
+"#;
+        let mut schema_keys: Vec<_> = schema_problems(input)
+            .into_iter()
+            .map(|p| p.key.unwrap())
+            .collect();
+        schema_keys.sort();
+        assert_eq!(
+            schema_keys,
+            vec![
+                "settings.host-containers.\"bad name!\"",
+                "settings.kernel.lockdown",
+                "settings.kubernetes.node-labels.-bad",
+            ]
+        );
+
+        let found = problems(input);
+        let keys: Vec<_> = found.iter().map(|p| p.key.clone().unwrap()).collect();
+        assert_eq!(found.len(), 3, "{:?}", keys);
+        assert!(found.iter().all(|p| p.kind == ProblemKind::InvalidValue));
+        assert!(keys.contains(&"settings.kernel.lockdown".to_string()));
+        assert!(keys.contains(&"settings.host-containers.\"bad name!\"".to_string()));
+        assert!(keys.contains(&"settings.kubernetes.node-labels.-bad".to_string()));
+
+        let lockdown = found
+            .iter()
+            .find(|p| p.key.as_deref() == Some("settings.kernel.lockdown"))
+            .unwrap();
+        assert_eq!((lockdown.line, lockdown.column), (Some(2), Some(1)));
+    }
+
+    #[test]
+    fn generated_settings() {
+        let input = r#"
+            [settings.kubernetes]
+            max-pods = 29
+            cluster-name = "example"
+        "#;
+        let found = problems(input);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, ProblemKind::Generated);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert_eq!(
+            found[0].key.as_deref(),
+            Some("settings.kubernetes.max-pods")
+        );
+        assert_eq!(found[0].line, Some(3));
+        assert!(found[0].message.contains("pluto max-pods"));
+    }
+
+    #[test]
+    fn other_variants() {
+        assert!(super::variants().any(|variant| variant == BUILD_VARIANT));
+
+        let input = "[settings]\nmotd = \"hi\"\n";
+        assert!(validate(input, None, None).unwrap().problems.is_empty());
+        assert!(matches!(
+            validate(input, Some("no-such-variant"), None),
+            Err(Error::UnknownVariant { .. })
+        ));
+
+        // Another variant's model isn't built in, so its schema is needed.
+        if let Some(other) = super::variants().find(|variant| *variant != BUILD_VARIANT) {
+            assert!(matches!(
+                validate(input, Some(other), None),
+                Err(Error::SchemaRequired { .. })
+            ));
+            let schema = json!({
+                "type": "object",
+                "properties": { "motd": { "type": "string" } },
+                "additionalProperties": false,
+            });
+            let report = validate("[settings]\nmotd = 1\n", Some(other), Some(&schema)).unwrap();
+            assert_eq!(report.variant, other);
+            assert_eq!(report.errors(), 1);
+            assert_eq!(report.problems[0].key.as_deref(), Some("settings.motd"));
+        }
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let input = "[settings]\nmotd = \"\"\"\nnope = 1\n\"\"\"\n";
+        assert!(problems(input).is_empty());
+
+        let locator = Locator::new("[settings]\nmotd = '''\n[settings.x]\n'''\n");
+        assert!(locator
+            .positions
+            .get(&vec!["settings".to_string(), "x".to_string()])
+            .is_none());
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let (segments, rest) = parse_key(r#"a."b.c" . 'd"e' . "A" = 1"#).unwrap();
+        assert_eq!(segments, vec!["a", "b.c", "d\"e", "A"]);
+        assert_eq!(rest, "= 1");
+        assert_eq!(key_name(&["a".to_string(), "b.c".to_string()]), "a.\"b.c\"");
+    }
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display(
+            "apiclient was built for variant '{}', not '{}'; give the settings schema of '{}' with --schema, from 'apiclient schema' on a host of that variant",
+            built,
+            requested,
+            requested
+        ))]
+        SchemaRequired { requested: String, built: String },
+
+        #[snafu(display("Unknown variant '{}'", variant))]
+        UnknownVariant { variant: String },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
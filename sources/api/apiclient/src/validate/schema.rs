@@ -0,0 +1,481 @@
+//! Checks settings against the JSON Schema of a variant's settings, as printed by 'apiclient
+//! schema', so user data can be validated for variants other than the one apiclient was built for.
+//!
+//! Only the keywords used in settings schemas are understood.  Like the schema itself, this can't
+//! make every check the model makes, like parsing URLs, so the API remains the final authority.
+
+use super::ProblemKind;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// A value that doesn't match the schema.  The path is relative to the value that was checked.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Mismatch {
+    pub(super) path: Vec<String>,
+    pub(super) kind: ProblemKind,
+    pub(super) message: String,
+}
+
+/// Checks the value against the schema, returning every mismatch found.  Fields listed as
+/// required aren't checked, because user data is merged with the variant's defaults, which may
+/// provide them.
+pub(super) fn check(schema: &Value, value: &Value) -> Vec<Mismatch> {
+    let mut found = Vec::new();
+    check_at(schema, value, &mut Vec::new(), &mut found);
+    found
+}
+
+fn matches(schema: &Value, value: &Value) -> bool {
+    check(schema, value).is_empty()
+}
+
+fn check_at(schema: &Value, value: &Value, path: &mut Vec<String>, found: &mut Vec<Mismatch>) {
+    let schema = match schema {
+        Value::Bool(false) => {
+            found.push(invalid(path, "no value is accepted here".to_string()));
+            return;
+        }
+        Value::Object(schema) => schema,
+        // 'true', or anything else we don't understand, accepts everything.
+        _ => return,
+    };
+
+    if schema.get("readOnly") == Some(&Value::Bool(true)) {
+        found.push(invalid(path, "is generated, and can't be set".to_string()));
+        return;
+    }
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        if !has_type(value, expected) {
+            let message = format!("invalid type: {}, expected {}", describe(value), expected);
+            found.push(invalid(path, message));
+            return;
+        }
+    }
+    if let Some(accepted) = schema.get("enum").and_then(Value::as_array) {
+        if !accepted.contains(value) {
+            let accepted: Vec<String> = accepted.iter().map(Value::to_string).collect();
+            let message = format!(
+                "invalid value: {}, expected one of {}",
+                value,
+                accepted.join(", ")
+            );
+            found.push(invalid(path, message));
+            return;
+        }
+    }
+
+    let before = found.len();
+    match value {
+        Value::String(string) => check_string(schema, string, path, found),
+        Value::Number(_) => check_number(schema, value, path, found),
+        Value::Array(items) => check_array(schema, items, path, found),
+        Value::Object(map) => check_object(schema, map, path, found),
+        _ => {}
+    }
+    if found.len() > before {
+        return;
+    }
+
+    if let Some(alternatives) = schema.get("anyOf").and_then(Value::as_array) {
+        if !alternatives.iter().any(|schema| matches(schema, value)) {
+            found.push(invalid(path, alternatives_message(alternatives, value)));
+            return;
+        }
+    }
+    if let Some(alternatives) = schema.get("oneOf").and_then(Value::as_array) {
+        let matched = alternatives
+            .iter()
+            .filter(|schema| matches(schema, value))
+            .count();
+        if matched != 1 {
+            found.push(invalid(path, alternatives_message(alternatives, value)));
+            return;
+        }
+    }
+    if let Some(not) = schema.get("not") {
+        if matches(not, value) {
+            let message = match not.get("pattern").and_then(Value::as_str) {
+                Some(pattern) => format!(
+                    "invalid value: {} may not match the pattern '{}'",
+                    value, pattern
+                ),
+                None => format!("invalid value: {}", value),
+            };
+            found.push(invalid(path, message));
+        }
+    }
+}
+
+fn check_string(
+    schema: &Map<String, Value>,
+    string: &str,
+    path: &[String],
+    found: &mut Vec<Mismatch>,
+) {
+    let length = string.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+        if length < min {
+            let message = format!(
+                "invalid value: {:?} is shorter than {} characters",
+                string, min
+            );
+            found.push(invalid(path, message));
+        }
+    }
+    if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+        if length > max {
+            let message = format!(
+                "invalid value: {:?} is longer than {} characters",
+                string, max
+            );
+            found.push(invalid(path, message));
+        }
+    }
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        // Patterns the regex crate can't compile, like those with lookaround, are left to the API.
+        if let Ok(regex) = Regex::new(&rust_pattern(pattern)) {
+            if !regex.is_match(string) {
+                let message = format!(
+                    "invalid value: {:?} doesn't match the pattern '{}'",
+                    string, pattern
+                );
+                found.push(invalid(path, message));
+            }
+        }
+    }
+    if let Some(format) = schema.get("format").and_then(Value::as_str) {
+        if !has_format(string, format) {
+            let message = format!("invalid value: {:?} isn't a valid {}", string, format);
+            found.push(invalid(path, message));
+        }
+    }
+}
+
+fn check_number(
+    schema: &Map<String, Value>,
+    value: &Value,
+    path: &[String],
+    found: &mut Vec<Mismatch>,
+) {
+    let number = match value.as_f64() {
+        Some(number) => number,
+        None => return,
+    };
+    if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+        if number < min {
+            found.push(invalid(
+                path,
+                format!("invalid value: {} is less than {}", value, min),
+            ));
+        }
+    }
+    if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+        if number > max {
+            found.push(invalid(
+                path,
+                format!("invalid value: {} is greater than {}", value, max),
+            ));
+        }
+    }
+}
+
+fn check_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &[String],
+    found: &mut Vec<Mismatch>,
+) {
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if (items.len() as u64) < min {
+            let message = format!(
+                "invalid length {}, expected at least {} items",
+                items.len(),
+                min
+            );
+            found.push(invalid(path, message));
+            return;
+        }
+    }
+
+    let prefix = schema
+        .get("prefixItems")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    for (index, item) in items.iter().enumerate() {
+        let item_schema = match prefix.get(index) {
+            Some(item_schema) => item_schema,
+            None => match schema.get("items") {
+                Some(Value::Bool(false)) => {
+                    let message = format!(
+                        "invalid length {}, expected {} items",
+                        items.len(),
+                        prefix.len()
+                    );
+                    found.push(invalid(path, message));
+                    return;
+                }
+                Some(item_schema) => item_schema,
+                None => continue,
+            },
+        };
+        // Array items don't have keys of their own, so problems are reported against the array.
+        for mismatch in check(item_schema, item) {
+            let mut item_path = path.to_vec();
+            item_path.extend(mismatch.path);
+            found.push(Mismatch {
+                path: item_path,
+                kind: mismatch.kind,
+                message: format!("item {}: {}", index + 1, mismatch.message),
+            });
+        }
+    }
+}
+
+fn check_object(
+    schema: &Map<String, Value>,
+    map: &Map<String, Value>,
+    path: &mut Vec<String>,
+    found: &mut Vec<Mismatch>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, child) in map {
+        path.push(name.clone());
+        if let Some(names) = schema.get("propertyNames") {
+            let name_value = Value::String(name.clone());
+            if !matches(names, &name_value) {
+                let message = match check(names, &name_value).into_iter().next() {
+                    Some(mismatch) => mismatch.message.replacen("invalid value", "invalid name", 1),
+                    None => format!("invalid name {:?}", name),
+                };
+                found.push(invalid(path, message));
+                path.pop();
+                continue;
+            }
+        }
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(child_schema) => check_at(child_schema, child, path, found),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    let message = match properties {
+                        Some(properties) if !properties.is_empty() => {
+                            let expected: Vec<String> = properties
+                                .keys()
+                                .map(|name| format!("`{}`", name))
+                                .collect();
+                            format!(
+                                "unknown field `{}`, expected one of {}",
+                                name,
+                                expected.join(", ")
+                            )
+                        }
+                        _ => format!("unknown field `{}`, there are no fields", name),
+                    };
+                    found.push(Mismatch {
+                        path: path.clone(),
+                        kind: ProblemKind::UnknownKey,
+                        message,
+                    });
+                }
+                Some(child_schema) => check_at(child_schema, child, path, found),
+                None => {}
+            },
+        }
+        path.pop();
+    }
+}
+
+fn invalid(path: &[String], message: String) -> Mismatch {
+    Mismatch {
+        path: path.to_vec(),
+        kind: ProblemKind::InvalidValue,
+        message,
+    }
+}
+
+/// Describes why a value matched none, or more than one, of the alternatives in 'anyOf' or
+/// 'oneOf'.  If only one alternative has the value's type, its problem is the most useful.
+fn alternatives_message(alternatives: &[Value], value: &Value) -> String {
+    let mut typed = alternatives.iter().filter(|schema| {
+        schema
+            .get("type")
+            .and_then(Value::as_str)
+            .map_or(true, |expected| has_type(value, expected))
+    });
+    match (typed.next(), typed.next()) {
+        (Some(schema), None) => match check(schema, value).into_iter().next() {
+            Some(mismatch) => mismatch.message,
+            None => format!("invalid value: {} matches more than one accepted form", value),
+        },
+        _ => format!("invalid value: {} doesn't match an accepted form", value),
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Describes a value's type and value the way deserialization errors from the model do.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => format!("boolean `{}`", b),
+        Value::Number(n) if n.is_f64() => format!("floating point `{}`", n),
+        Value::Number(n) => format!("integer `{}`", n),
+        Value::String(s) => format!("string {:?}", s),
+        Value::Array(_) => "sequence".to_string(),
+        Value::Object(_) => "map".to_string(),
+    }
+}
+
+/// Checks the formats used in settings schemas.  Other formats, like 'uri-reference', are left to
+/// the API.
+fn has_format(string: &str, format: &str) -> bool {
+    match format {
+        "ipv4" => string.parse::<Ipv4Addr>().is_ok(),
+        "ipv6" => string.parse::<Ipv6Addr>().is_ok(),
+        "hostname" => is_hostname(string),
+        _ => true,
+    }
+}
+
+/// Checks for a hostname as described in RFC 1123: dot-separated labels of letters, digits, and
+/// hyphens, where labels don't start or end with a hyphen.
+fn is_hostname(string: &str) -> bool {
+    let name = string.strip_suffix('.').unwrap_or(string);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Schemas use ECMA-262 patterns; the only syntax in settings schemas that the regex crate spells
+/// differently is named groups.
+fn rust_pattern(pattern: &str) -> String {
+    let mut converted = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(index) = rest.find("(?<") {
+        converted.push_str(&rest[..index]);
+        let after = &rest[index + 3..];
+        // "(?<=" and "(?<!" are lookbehind, which we leave for the regex crate to reject.
+        if after.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            converted.push_str("(?P<");
+        } else {
+            converted.push_str("(?<");
+        }
+        rest = after;
+    }
+    converted.push_str(rest);
+    converted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string", "pattern": "^(?<first>[a-z]+)$", "maxLength": 5 },
+                "mode": { "type": "string", "enum": ["on", "off"] },
+                "count": { "type": "integer", "minimum": 0, "maximum": 10 },
+                "address": { "type": "string", "anyOf": [{ "format": "ipv4" }, { "format": "ipv6" }] },
+                "labels": {
+                    "type": "object",
+                    "propertyNames": { "type": "string", "pattern": "^[a-z]+$" },
+                    "additionalProperties": { "type": "string" },
+                },
+                "servers": { "type": "array", "items": { "type": "string", "format": "hostname" } },
+                "pair": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "string" }, { "type": "integer" }],
+                    "items": false,
+                    "minItems": 2,
+                },
+                "os": { "type": "object", "readOnly": true },
+            },
+        })
+    }
+
+    #[test]
+    fn accepts_valid() {
+        let value = json!({
+            "name": "abc",
+            "mode": "on",
+            "count": 3,
+            "address": "::1",
+            "labels": {"a": "b"},
+            "servers": ["example.com"],
+            "pair": ["a", 1],
+        });
+        assert_eq!(check(&schema(), &value), vec![]);
+    }
+
+    #[test]
+    fn finds_mismatches() {
+        let value = json!({
+            "name": "abcdefg1",
+            "mode": "maybe",
+            "count": 11,
+            "address": "nope",
+            "labels": {"Bad": "b", "ok": 1},
+            "servers": ["-bad.example.com"],
+            "pair": ["a", 1, 2],
+            "os": {},
+            "unknown": true,
+        });
+        let found = check(&schema(), &value);
+        let mut paths: Vec<String> = found.iter().map(|m| m.path.join(".")).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "address",
+                "count",
+                "labels.Bad",
+                "labels.ok",
+                "mode",
+                // Too long, and doesn't match the pattern.
+                "name",
+                "name",
+                "os",
+                "pair",
+                "servers",
+                "unknown",
+            ]
+        );
+        for mismatch in &found {
+            let expected = if mismatch.path == ["unknown"] {
+                ProblemKind::UnknownKey
+            } else {
+                ProblemKind::InvalidValue
+            };
+            assert_eq!(mismatch.kind, expected, "{:?}", mismatch);
+        }
+        let servers = found.iter().find(|m| m.path == ["servers"]).unwrap();
+        assert!(servers.message.starts_with("item 1: "));
+    }
+
+    #[test]
+    fn converts_patterns() {
+        assert_eq!(rust_pattern("^(?<a>x)(?<=y)$"), "^(?P<a>x)(?<=y)$");
+        assert!(Regex::new(&rust_pattern("^(?<key>[a-z]+)$")).is_ok());
+    }
+}
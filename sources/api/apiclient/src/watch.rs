@@ -0,0 +1,83 @@
+//! The 'watch' module lets you subscribe to settings changes through the apiserver.  A WebSocket
+//! is opened to the server, and the server sends an event every time a transaction is committed,
+//! listing the changed keys and their new values.
+
+use crate::connect::websocket_connect;
+use futures::StreamExt;
+use log::debug;
+use model::watch::SettingsEvent;
+use snafu::ResultExt;
+use std::path::Path;
+use tokio_tungstenite::tungstenite::{protocol::frame::coding::CloseCode, Message};
+
+/// Connects to the server and calls `on_event` with each SettingsEvent the server sends, until the
+/// server closes the connection.  If `prefixes` is not empty, the server only sends changes to keys
+/// starting with one of the given prefixes; the "settings." prefix is optional.
+pub async fn watch<P, F>(socket_path: P, prefixes: Vec<String>, mut on_event: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(SettingsEvent),
+{
+    let path = if prefixes.is_empty() {
+        "/watch".to_string()
+    } else {
+        format!("/watch?prefix={}", prefixes.join(","))
+    };
+
+    let mut ws_stream = websocket_connect(socket_path, &path)
+        .await
+        .context(error::ConnectSnafu)?;
+
+    // The server sends pings as a heartbeat; tungstenite queues the matching pong for us and
+    // sends it as we continue reading, so we only have to handle events and Close.
+    while let Some(msg) = ws_stream.next().await {
+        match msg.context(error::ReadSnafu)? {
+            Message::Text(text) => {
+                let event = serde_json::from_str(&text).context(error::DeserializeSnafu)?;
+                on_event(event);
+            }
+
+            Message::Close(Some(frame)) if frame.code != CloseCode::Normal => {
+                return error::CloseSnafu {
+                    code: u16::from(frame.code),
+                    reason: frame.reason.to_string(),
+                }
+                .fail();
+            }
+
+            Message::Close(_) => {
+                debug!("Server closed watch connection");
+                break;
+            }
+
+            Message::Ping(_) | Message::Pong(_) | Message::Binary(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+mod error {
+    use crate::connect;
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Server closed connection with code {}: {}", code, reason))]
+        Close { code: u16, reason: String },
+
+        #[snafu(display("Failed to connect to watch endpoint: {}", source))]
+        Connect { source: connect::Error },
+
+        #[snafu(display("Failed to deserialize event from server: {}", source))]
+        Deserialize { source: serde_json::Error },
+
+        #[snafu(display("Failed to read message from server: {}", source))]
+        Read {
+            source: tokio_tungstenite::tungstenite::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
@@ -19,6 +19,11 @@ const DEFAULT_BIND_PATH: &str = "/run/api.sock";
 /// By default, when the user requests that we run a process via /exec, we run the process through
 /// this containerd socket.
 const DEFAULT_EXEC_SOCKET: &str = "/run/host-containerd/containerd.sock";
+/// By default, this is where we look for the access policy; if it doesn't exist, every caller has
+/// full access.
+const DEFAULT_POLICY_PATH: &str = "/var/lib/bottlerocket/api/policy.toml";
+/// By default, this is where we record requests denied by the access policy.
+const DEFAULT_AUDIT_LOG_PATH: &str = "/var/log/api/audit.log";
 
 type Result<T> = std::result::Result<T, error::Error>;
 
@@ -46,6 +51,8 @@ struct Args {
     socket_gid: Option<Gid>,
     socket_path: String,
     exec_socket_path: String,
+    policy_path: String,
+    audit_log_path: String,
 }
 
 /// Informs the user about proper usage of the program and exits.
@@ -57,12 +64,20 @@ fn usage() -> ! {
             [ --socket-path PATH ]
             [ --socket-gid GROUP_ID ]
             [ --exec-socket-path PATH ]
+            [ --policy-path PATH ]
+            [ --audit-log-path PATH ]
             [ --no-color ]
             [ --log-level trace|debug|info|warn|error ]
 
     --socket-path defaults to {}
-    --exec-socket-path (for apiclient exec) defaults to {}",
-        program_name, DEFAULT_BIND_PATH, DEFAULT_EXEC_SOCKET
+    --exec-socket-path (for apiclient exec) defaults to {}
+    --policy-path defaults to {}
+    --audit-log-path defaults to {}",
+        program_name,
+        DEFAULT_BIND_PATH,
+        DEFAULT_EXEC_SOCKET,
+        DEFAULT_POLICY_PATH,
+        DEFAULT_AUDIT_LOG_PATH
     );
     process::exit(2);
 }
@@ -80,6 +95,8 @@ fn parse_args(args: env::Args) -> Args {
     let mut socket_gid = None;
     let mut socket_path = None;
     let mut exec_socket_path = None;
+    let mut policy_path = None;
+    let mut audit_log_path = None;
 
     let mut iter = args.skip(1);
     while let Some(arg) = iter.next() {
@@ -127,6 +144,20 @@ fn parse_args(args: env::Args) -> Args {
                     }))
             }
 
+            "--policy-path" => {
+                policy_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --policy-path")),
+                )
+            }
+
+            "--audit-log-path" => {
+                audit_log_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --audit-log-path")),
+                )
+            }
+
             _ => usage(),
         }
     }
@@ -137,6 +168,8 @@ fn parse_args(args: env::Args) -> Args {
         log_level: log_level.unwrap_or(LevelFilter::Info),
         socket_path: socket_path.unwrap_or_else(|| DEFAULT_BIND_PATH.to_string()),
         exec_socket_path: exec_socket_path.unwrap_or_else(|| DEFAULT_EXEC_SOCKET.to_string()),
+        policy_path: policy_path.unwrap_or_else(|| DEFAULT_POLICY_PATH.to_string()),
+        audit_log_path: audit_log_path.unwrap_or_else(|| DEFAULT_AUDIT_LOG_PATH.to_string()),
     }
 }
 
@@ -174,6 +207,8 @@ async fn run() -> Result<()> {
         threads,
         args.socket_gid,
         args.exec_socket_path,
+        args.policy_path,
+        args.audit_log_path,
     )
     .await
     .context(error::ServerSnafu)
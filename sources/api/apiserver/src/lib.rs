@@ -5,7 +5,7 @@ This library provides an API server intended for use in an OS that is primarily
 It's intended to be the primary way to read and modify OS settings, to update services based on those settings, and more generally to learn about and change the state of the system.
 
 The server listens to HTTP requests on a Unix-domain socket.
-There is no built-in authentication - local access to the socket should be limited to processes and containers that should be able to configure the system.
+Local access to the socket should be limited to processes and containers that should be able to configure the system; an [access policy](#access-policy) can further limit what each of them can do.
 Remote access should only be allowed through an authenticated control channel such as SSH or SSM.
 
 # Design
@@ -21,10 +21,27 @@ You can also PATCH changes to the `/settings` endpoint.
 Settings are stored as a pending transaction until a commit API is called.
 Pending settings can be retrieved from `/tx` to see what will change.
 
+Responses from `GET /settings` include an `ETag` header holding the current generation of the data store, which increases with every commit that changes a value.
+If several clients may change the same settings, send that value back in an `If-Match` header when you PATCH.
+The PATCH is rejected with `412 Precondition Failed` if any of the settings you're changing were committed since you read them.
+The check is made again when the transaction is committed, so a commit from another client in between is rejected the same way rather than overwritten.
+
+To see what applying a transaction would do before committing it, GET `/tx/plan`.
+It returns a unified diff of each configuration file that would be rewritten, and the restart commands of each service that would be restarted, without changing anything.
+
 Upon making a `/tx/commit` POST call, the pending transaction is made live.
 Upon making an `/tx/apply` POST call, an external settings applier tool is called to apply the changes to the system and restart services as necessary.
 There's also `/tx/commit_and_apply` to do both, which is the most common case.
 
+Clients that need to react to settings changes can open a WebSocket at `/watch` instead of polling.
+An event is sent every time a transaction is committed, listing the changed keys and their new values.
+You can pass a `prefix` parameter, a comma-separated list of key prefixes, to only receive changes to matching keys.
+
+Each commit that changes a value is recorded in a bounded history, which you can see at `/tx/history`.
+Each entry has an ID, the transaction name, a timestamp, and the old and new value of each changed key.
+Values of sensitive keys, the same ones redacted in the audit log, are recorded as `<redacted>`.
+A `POST` to `/tx/revert?id=ID` restores every key changed by that entry, or by any later entry, to the value it had before that entry was committed, then applies the changes.
+
 If you don't specify a transaction, the "default" transaction is used, so you usually don't have to think about it.
 If you want to group changes into transactions yourself, you can add a `tx` parameter to the APIs mentioned above.
 For example, if you want the name "FOO", you can `PATCH` to `/settings?tx=FOO` and `POST` to `/tx/commit_and_apply?tx=FOO`.
@@ -32,6 +49,65 @@ For example, if you want the name "FOO", you can `PATCH` to `/settings?tx=FOO` a
 Requests are directed by `server::router`.
 `server::controller` maps requests into our data model.
 
+## Access policy
+
+By default, any process that can open the socket has full access to the API.
+To limit that, write an access policy to `/var/lib/bottlerocket/api/policy.toml`.
+It's read again whenever it changes, so no restart is needed.
+
+When a client connects, the server reads its UID, GID, and PID from the socket, and uses the PID to find the host container it's running in, if any.
+The policy maps these callers to roles, and each role lists the API paths, HTTP methods, and settings its callers may use:
+
+```toml
+default-role = "reader"
+
+[roles.reader]
+routes = ["/settings", "/os", "/updates/status"]
+methods = ["GET"]
+hidden-settings = ["settings.kubernetes.bootstrap-token"]
+
+[roles.ntp]
+readable-settings = ["settings.ntp"]
+writable-settings = ["settings.ntp"]
+
+[roles.admin]
+
+[[callers]]
+container = "admin"
+role = "admin"
+
+[[callers]]
+uid = 1000
+role = "ntp"
+```
+
+Each entry in `routes` also allows the paths beneath it.
+Requests to `/exec` open a WebSocket with GET, but they run commands, so they're checked against `methods` as POST requests; a role limited to GET can't use them.
+Settings are given as prefixes; `readable-settings` and `writable-settings` limit the settings a role can read and change, and `hidden-settings` are left out of responses even if they're readable.
+A field that isn't given doesn't restrict anything, so the "admin" role above allows everything.
+
+Callers are compared to each `[[callers]]` entry in order, using whichever of `uid`, `gid`, and `container` the entry gives, and the first match decides the role.
+Callers that match no entry get the `default-role`, or are denied if there isn't one.
+Processes run as root by systemd services on the host, in a `system.slice/UNIT.service` cgroup, always have full access, because the system services that configure the host depend on it.
+If the policy file is invalid, only those processes have access.
+Callers that aren't in a host container or a host service, like those in Kubernetes pods, only get the `default-role`, whatever their UID.
+
+Settings a caller can't read are left out of responses, history, and `/watch` events.
+Requests that aren't allowed get a `403 Forbidden` response, including PATCHes that change settings the caller can't change.
+Planning, reverting, and pruning can involve any setting, so they require access to all settings.
+Denied requests are recorded in the [audit log](#audit-log).
+
+## Audit log
+
+Requests that change the system are recorded in an audit log at `/var/log/api/audit.log`, one line of JSON each.
+This includes PATCHes to `/settings`, POSTs and DELETEs under `/tx`, `/actions`, and the container and command of each `/exec` request, along with any request denied by the access policy.
+Each entry has a timestamp, the caller's UID, GID, PID, and container, its role, the request method and path, the transaction and the settings it changed with their new values, and the result.
+Values of sensitive settings, like credentials and user data, are replaced with `<redacted>`.
+
+The log is rotated when it reaches 4 MiB, and the four most recent rotated logs are kept as `audit.log.1` through `audit.log.4`.
+You can GET entries from `/audit`, filtered by `since` (an RFC 3339 time), `tx`, `key` (a settings prefix), and `limit` (the number of most recent entries).
+Settings the caller can't read are left out.
+
 ## Model
 
 The API is driven by a data model (similar to a schema) defined in Rust.
@@ -42,6 +118,10 @@ The data model describes system settings, services using those settings, and con
 It also has a more general structure for metadata.
 Metadata entries can be stored for any data field in the model.
 
+You can GET a [JSON Schema](https://json-schema.org/) description of the settings from `/schema`.
+It's generated from the model, so it lists every setting of the running variant with its type and the constraints on its values, like patterns, allowed values, and ranges.
+Clients can use it to check input before sending it.
+
 ## Data store
 
 Data from the model is stored in a key/value data store.
@@ -57,7 +137,8 @@ For more detail, see [datastore](../datastore).
 # Current limitations
 
 * Data store locking is coarse; read requests can happen in parallel, but a write request will block everything else.
-* There's no support for rolling back commits.
+* Only the most recent commits are kept in history, so older ones can't be reverted.
+* Earlier values of sensitive keys aren't kept in history, so commits that changed them can't be reverted.
 * There are no metrics.
 
 # Example usage
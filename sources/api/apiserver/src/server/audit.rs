@@ -0,0 +1,674 @@
+//! The 'audit' module records every API request that changes the system, and every request denied
+//! by the access policy, so the owner of the host can see who changed what, and when.  Each entry
+//! is a line of JSON appended to the audit log file, which is rotated when it gets large.
+//!
+//! Entries hold the caller's credentials and role, the request, the transaction and the settings
+//! it affected, if any, and the result.  Values of sensitive settings, like credentials, are
+//! redacted.
+
+use super::controller;
+use super::error::{self, Result};
+use super::policy::{Access, Caller};
+use super::SharedData;
+use actix_web::body::BoxBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::Method;
+use actix_web::{web, HttpMessage, HttpRequest};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use snafu::ResultExt;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::{self, Arc};
+
+/// The audit log is rotated when it would grow beyond this size, in bytes.
+const MAX_LOG_SIZE: u64 = 4 * 1024 * 1024;
+/// The number of rotated audit logs we keep, named like "audit.log.1", with 1 the most recent.
+const ROTATED_LOGS: u32 = 4;
+
+/// Settings whose values are left out of the audit log.  Each also covers the settings beneath it.
+const SENSITIVE_SETTINGS: &[&str] = &[
+    "settings.aws.credentials",
+    "settings.container-registry.credentials",
+    "settings.kubernetes.bootstrap-token",
+    // Can contain a username:password component
+    "settings.network.https-proxy",
+];
+/// Settings whose names end with any of these have their values left out of the audit log.
+const SENSITIVE_SUFFIXES: &[&str] = &[".user-data"];
+/// Replaces the values of sensitive settings.
+const REDACTED: &str = "<redacted>";
+
+/// The outcome of an audited request.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Ok,
+    Denied,
+    Error,
+}
+
+/// A single audit log entry.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    timestamp: DateTime<Utc>,
+    caller: Option<&'a Caller>,
+    role: Option<&'a str>,
+    method: &'a str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction: Option<&'a str>,
+    /// The settings affected by the request, and their new values; null means removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keys: Option<BTreeMap<String, Value>>,
+    /// For exec requests, the container and command the client asked to run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<&'a [String]>,
+    result: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+}
+
+impl<'a> Entry<'a> {
+    fn new(method: &'a str, path: &'a str, result: Outcome) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            caller: None,
+            role: None,
+            method,
+            path,
+            transaction: None,
+            keys: None,
+            target: None,
+            command: None,
+            result,
+            status: None,
+            reason: None,
+        }
+    }
+}
+
+/// The details of a request that only its handler knows; handlers add these to the request with
+/// note_changes so the audit middleware can include them.
+#[derive(Debug, Clone)]
+struct Changes {
+    transaction: String,
+    keys: BTreeMap<String, Value>,
+}
+
+/// Records the transaction and the settings a request changed, for its audit log entry.  Keys with
+/// no value in the map were removed.
+pub(crate) fn note_changes(
+    req: &HttpRequest,
+    transaction: &str,
+    keys: BTreeMap<String, Option<Value>>,
+) {
+    req.extensions_mut().insert(Changes {
+        transaction: transaction.to_string(),
+        keys: keys
+            .into_iter()
+            .map(|(key, value)| (key, value.unwrap_or(Value::Null)))
+            .collect(),
+    });
+}
+
+/// Returns whether the value of the given setting must be left out of records like the audit log
+/// and settings history, either because it's known to hold secrets or because it's been marked
+/// sensitive in the datastore.
+pub(crate) fn should_redact(key: &str, marked_sensitive: bool) -> bool {
+    marked_sensitive
+        || SENSITIVE_SETTINGS.iter().any(|prefix| {
+            key == *prefix
+                || key
+                    .strip_prefix(prefix)
+                    .map_or(false, |rest| rest.starts_with('.'))
+        })
+        || SENSITIVE_SUFFIXES
+            .iter()
+            .any(|suffix| key.ends_with(suffix))
+}
+
+/// Returns the value to record for the given setting, redacting it if it's sensitive.
+fn redact(key: &str, value: Value, marked_sensitive: bool) -> Value {
+    if should_redact(key, marked_sensitive) && !value.is_null() {
+        Value::String(REDACTED.to_string())
+    } else {
+        value
+    }
+}
+
+/// Returns whether a request changes the system, and so should be audited.  Exec requests are
+/// audited by the exec module once the client says what it wants to run.
+fn is_mutating(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Request middleware run after the handler; records mutating requests, and requests denied by
+/// handlers checking access to settings.
+pub(crate) fn record_response(
+    res: actix_web::Result<ServiceResponse<BoxBody>>,
+) -> actix_web::Result<ServiceResponse<BoxBody>> {
+    let res = match res {
+        Ok(res) => res,
+        Err(e) => return Err(e),
+    };
+    let req = res.request();
+    let error = res
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<error::Error>());
+    let denied = matches!(error, Some(error::Error::Forbidden { .. }));
+    if !denied && !is_mutating(req.method()) {
+        return Ok(res);
+    }
+    let data = match req.app_data::<web::Data<SharedData>>() {
+        Some(data) => data,
+        None => return Ok(res),
+    };
+
+    let caller = req.conn_data::<Caller>();
+    let access = req.extensions().get::<Access>().cloned();
+    let role = access.as_ref().and_then(Access::role_name);
+    let reason = error.map(|e| e.to_string());
+    if denied {
+        data.audit.record_denied(
+            caller,
+            role,
+            req.method(),
+            req.path(),
+            reason.as_deref().unwrap_or_default(),
+        );
+        return Ok(res);
+    }
+
+    let status = res.status();
+    let result = if status.is_success() || status.is_informational() {
+        Outcome::Ok
+    } else {
+        Outcome::Error
+    };
+    let changes = req.extensions().get::<Changes>().cloned();
+    // If the handler failed before it could say which transaction it used, we can still tell from
+    // the query string for transaction-related requests.
+    let query_transaction = if req.path().starts_with("/settings") || req.path().starts_with("/tx")
+    {
+        let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).ok();
+        Some(
+            query
+                .and_then(|q| q.get("tx").cloned())
+                .unwrap_or_else(|| "default".to_string()),
+        )
+    } else {
+        None
+    };
+
+    let mut entry = Entry::new(req.method().as_str(), req.path(), result);
+    entry.caller = caller;
+    entry.role = role;
+    entry.status = Some(status.as_u16());
+    entry.reason = reason.as_deref();
+    match &changes {
+        Some(changes) => {
+            entry.transaction = Some(&changes.transaction);
+            let datastore = data.ds.read().ok();
+            entry.keys = Some(
+                changes
+                    .keys
+                    .iter()
+                    .map(|(key, value)| {
+                        let marked = datastore
+                            .as_ref()
+                            .map_or(false, |ds| controller::is_sensitive(&**ds, key));
+                        (key.clone(), redact(key, value.clone(), marked))
+                    })
+                    .collect(),
+            );
+        }
+        None => entry.transaction = query_transaction.as_deref(),
+    }
+    data.audit.write(&entry);
+
+    Ok(res)
+}
+
+/// Records an exec request once the client has said what it wants to run; see the exec module.
+#[derive(Debug)]
+pub(crate) struct ExecAudit {
+    log: Arc<AuditLog>,
+    caller: Option<Caller>,
+    role: Option<String>,
+}
+
+impl ExecAudit {
+    pub(crate) fn new(req: &HttpRequest, log: Arc<AuditLog>) -> Self {
+        Self {
+            log,
+            caller: req.conn_data::<Caller>().cloned(),
+            role: req
+                .extensions()
+                .get::<Access>()
+                .and_then(|access| access.role_name().map(str::to_string)),
+        }
+    }
+
+    /// Records the container and command the client asked to run, and whether it was started.
+    pub(crate) fn record(&self, target: &str, command: &[OsString], error: Option<&str>) {
+        let command: Vec<String> = command
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let result = if error.is_some() {
+            Outcome::Error
+        } else {
+            Outcome::Ok
+        };
+        let mut entry = Entry::new(Method::GET.as_str(), "/exec", result);
+        entry.caller = self.caller.as_ref();
+        entry.role = self.role.as_deref();
+        entry.target = Some(target);
+        entry.command = Some(&command);
+        entry.reason = error;
+        self.log.write(&entry);
+    }
+}
+
+/// Filters for reading the audit log.
+#[derive(Debug, Default)]
+pub(crate) struct AuditQuery {
+    /// Only entries at or after this time.
+    pub(crate) since: Option<DateTime<Utc>>,
+    /// Only entries for this transaction.
+    pub(crate) transaction: Option<String>,
+    /// Only entries that affected settings starting with this prefix.
+    pub(crate) key: Option<String>,
+    /// Only this many of the most recent entries.
+    pub(crate) limit: Option<usize>,
+}
+
+/// Appends entries to the audit log file, opening it on first use, and rotating it when it gets
+/// large.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    file: sync::Mutex<Option<File>>,
+}
+
+impl AuditLog {
+    pub(crate) fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            file: sync::Mutex::new(None),
+        }
+    }
+
+    /// Records a request that was denied.  Failing to write the audit log shouldn't change the
+    /// response to the request, so failures are logged rather than returned.
+    pub(crate) fn record_denied(
+        &self,
+        caller: Option<&Caller>,
+        role: Option<&str>,
+        method: &Method,
+        path: &str,
+        reason: &str,
+    ) {
+        warn!(
+            "Denied {} {} to {:?} with role {:?}: {}",
+            method, path, caller, role, reason
+        );
+        let mut entry = Entry::new(method.as_str(), path, Outcome::Denied);
+        entry.caller = caller;
+        entry.role = role;
+        entry.status = Some(403);
+        entry.reason = Some(reason);
+        self.write(&entry);
+    }
+
+    /// Returns the entries matching the query from the audit log and its rotated copies, oldest
+    /// first.  Settings the caller can't read are left out of the entries.  The logs can be large,
+    /// so this should be called on a blocking thread; writers only wait while the logs are opened.
+    pub(crate) fn read(&self, query: &AuditQuery, access: &Access) -> Result<Vec<Value>> {
+        let mut entries = Vec::new();
+        for (path, file, length) in self.open_for_read()? {
+            for line in BufReader::new(file.take(length)).lines() {
+                let line = line.context(error::AuditReadSnafu { path: &path })?;
+                let mut entry: Value = match serde_json::from_str(&line) {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        warn!(
+                            "Skipping invalid audit log line in '{}': {}",
+                            path.display(),
+                            e
+                        );
+                        continue;
+                    }
+                };
+                if let Some(Value::Object(keys)) = entry.get_mut("keys") {
+                    keys.retain(|key, _value| access.can_read(key));
+                }
+                if query_matches(query, &entry) {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        if let Some(limit) = query.limit {
+            let skip = entries.len().saturating_sub(limit);
+            entries.drain(..skip);
+        }
+        Ok(entries)
+    }
+
+    /// Opens the audit log and its rotated copies, oldest first, returning each with its length.
+    /// We hold the lock only while opening them, so the logs aren't rotated or written partway;
+    /// the open files can then be read up to those lengths without the lock, even if the logs are
+    /// rotated or written in the meantime.
+    fn open_for_read(&self) -> Result<Vec<(PathBuf, File, u64)>> {
+        let _file = self.lock();
+        let mut files = Vec::new();
+        for path in (1..=ROTATED_LOGS)
+            .rev()
+            .map(|n| self.rotated_path(n))
+            .chain(std::iter::once(self.path.clone()))
+        {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context(error::AuditReadSnafu { path }),
+            };
+            let length = file
+                .metadata()
+                .context(error::AuditReadSnafu { path: &path })?
+                .len();
+            files.push((path, file, length));
+        }
+        Ok(files)
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Unable to serialize audit log entry: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+
+        let mut file = self.lock();
+        if let Some(f) = file.as_ref() {
+            let size = f.metadata().map(|m| m.len()).unwrap_or_default();
+            if size + line.len() as u64 > MAX_LOG_SIZE {
+                *file = None;
+                self.rotate();
+            }
+        }
+        if file.is_none() {
+            *file = self.open();
+        }
+        if let Some(f) = file.as_mut() {
+            if let Err(e) = f.write_all(line.as_bytes()) {
+                warn!("Unable to write audit log '{}': {}", self.path.display(), e);
+                // Reopen the file next time, in case it was removed.
+                *file = None;
+            }
+        }
+    }
+
+    // A panic while holding the lock can at worst leave a partial line, which readers skip, so we
+    // ignore poisoning.
+    fn lock(&self) -> sync::MutexGuard<'_, Option<File>> {
+        self.file
+            .lock()
+            .unwrap_or_else(sync::PoisonError::into_inner)
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Shifts each rotated log to the next number, dropping the oldest, and moves the current log
+    /// to ".1".
+    fn rotate(&self) {
+        info!("Rotating audit log '{}'", self.path.display());
+        for n in (1..ROTATED_LOGS).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                if let Err(e) = fs::rename(&from, self.rotated_path(n + 1)) {
+                    warn!("Unable to rotate audit log '{}': {}", from.display(), e);
+                }
+            }
+        }
+        if let Err(e) = fs::rename(&self.path, self.rotated_path(1)) {
+            warn!(
+                "Unable to rotate audit log '{}': {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    fn open(&self) -> Option<File> {
+        if let Some(parent) = self.path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                warn!(
+                    "Unable to create audit log directory '{}': {}",
+                    parent.display(),
+                    e
+                );
+                return None;
+            }
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&self.path)
+            .map_err(|e| warn!("Unable to open audit log '{}': {}", self.path.display(), e))
+            .ok()
+    }
+}
+
+/// Returns whether a parsed audit log entry matches the query.
+fn query_matches(query: &AuditQuery, entry: &Value) -> bool {
+    if let Some(since) = query.since {
+        let timestamp = entry
+            .get("timestamp")
+            .and_then(Value::as_str)
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        if timestamp.map_or(true, |t| t < since) {
+            return false;
+        }
+    }
+    if let Some(transaction) = &query.transaction {
+        if entry.get("transaction").and_then(Value::as_str) != Some(transaction.as_str()) {
+            return false;
+        }
+    }
+    if let Some(prefix) = &query.key {
+        let keys = entry.get("keys").and_then(Value::as_object);
+        if !keys.map_or(false, |keys| {
+            keys.keys().any(|k| k.starts_with(prefix.as_str()))
+        }) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::btreemap;
+    use serde_json::json;
+
+    fn log() -> (tempfile::TempDir, AuditLog) {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.log"));
+        (dir, log)
+    }
+
+    fn write_change(log: &AuditLog, transaction: &str, keys: BTreeMap<String, Value>) {
+        let mut entry = Entry::new("POST", "/tx/commit_and_apply", Outcome::Ok);
+        entry.transaction = Some(transaction);
+        entry.keys = Some(
+            keys.into_iter()
+                .map(|(key, value)| {
+                    let value = redact(&key, value, false);
+                    (key, value)
+                })
+                .collect(),
+        );
+        log.write(&entry);
+    }
+
+    #[test]
+    fn redacts_sensitive_settings() {
+        assert_eq!(
+            redact(
+                "settings.kubernetes.bootstrap-token",
+                json!("secret"),
+                false
+            ),
+            json!(REDACTED)
+        );
+        assert_eq!(
+            redact(
+                "settings.host-containers.admin.user-data",
+                json!("c2VjcmV0"),
+                false
+            ),
+            json!(REDACTED)
+        );
+        assert_eq!(
+            redact(
+                "settings.container-registry.credentials",
+                json!([{"password": "x"}]),
+                false
+            ),
+            json!(REDACTED)
+        );
+        assert_eq!(redact("settings.motd", json!("hi"), false), json!("hi"));
+        assert_eq!(redact("settings.motd", json!("hi"), true), json!(REDACTED));
+        assert_eq!(
+            redact("settings.kubernetes.bootstrap-token", Value::Null, false),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn write_and_query() {
+        let (_dir, log) = log();
+        write_change(
+            &log,
+            "a",
+            btreemap! {"settings.motd".to_string() => json!("hi")},
+        );
+        let since = Utc::now();
+        write_change(
+            &log,
+            "b",
+            btreemap! {
+                "settings.ntp.time-servers".to_string() => json!(["https://example.com"]),
+                "settings.kubernetes.bootstrap-token".to_string() => json!("secret"),
+            },
+        );
+        log.record_denied(None, Some("reader"), &Method::PATCH, "/settings", "no");
+
+        let all = log
+            .read(&AuditQuery::default(), &Access::default())
+            .unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(
+            all[1]["keys"]["settings.kubernetes.bootstrap-token"],
+            json!(REDACTED)
+        );
+        assert_eq!(all[2]["result"], json!("denied"));
+
+        let query = AuditQuery {
+            since: Some(since),
+            ..Default::default()
+        };
+        assert_eq!(log.read(&query, &Access::default()).unwrap().len(), 2);
+
+        let query = AuditQuery {
+            transaction: Some("a".to_string()),
+            ..Default::default()
+        };
+        let entries = log.read(&query, &Access::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["keys"]["settings.motd"], json!("hi"));
+
+        let query = AuditQuery {
+            key: Some("settings.ntp".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(log.read(&query, &Access::default()).unwrap().len(), 1);
+
+        let query = AuditQuery {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let entries = log.read(&query, &Access::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["path"], json!("/settings"));
+    }
+
+    #[test]
+    fn reads_without_holding_lock() {
+        let (_dir, log) = log();
+        write_change(
+            &log,
+            "a",
+            btreemap! {"settings.motd".to_string() => json!("hi")},
+        );
+        let files = log.open_for_read().unwrap();
+
+        // Writing doesn't wait for readers, and readers only see what was there when they opened
+        // the logs.
+        write_change(
+            &log,
+            "b",
+            btreemap! {"settings.motd".to_string() => json!("bye")},
+        );
+        let lines: Vec<String> = files
+            .into_iter()
+            .flat_map(|(_path, file, length)| BufReader::new(file.take(length)).lines())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("\"a\""));
+    }
+
+    #[test]
+    fn rotates() {
+        let (_dir, log) = log();
+        let big = "x".repeat(MAX_LOG_SIZE as usize / 3);
+        for _ in 0..4 {
+            write_change(
+                &log,
+                "a",
+                btreemap! {"settings.motd".to_string() => json!(big)},
+            );
+        }
+        assert!(log.rotated_path(1).exists());
+        assert!(!log.rotated_path(2).exists());
+
+        // Entries are still read in order across the rotated logs.
+        let entries = log
+            .read(&AuditQuery::default(), &Access::default())
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+    }
+}
@@ -2,22 +2,35 @@
 //! controller in the MVC model.
 
 use bottlerocket_release::BottlerocketRelease;
+use chrono::{DateTime, Utc};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
 use std::process::{Command, Stdio};
 
+use crate::server::audit;
 use crate::server::error::{self, Result};
 use actix_web::HttpResponse;
 use datastore::deserialization::{from_map, from_map_with_prefix};
 use datastore::serialization::to_pairs;
-use datastore::{deserialize_scalar, Committed, DataStore, Key, KeyType, ScalarError, Value};
-use model::{ConfigurationFiles, Services, Settings};
+use datastore::{
+    deserialize_scalar, history, Committed, DataStore, Key, KeyType, ScalarError, Value,
+};
+use model::{ConfigurationFiles, Services, Settings, UpdateMaintenance};
 use num::FromPrimitive;
 use std::os::unix::process::ExitStatusExt;
 use thar_be_updates::error::TbuErrorStatus;
 
+/// The metadata key storewolf uses to record the default value of each setting.
+const DEFAULT_VALUE_METADATA: &str = "default-value";
+
+/// The metadata key that marks settings the user set, as opposed to settings from defaults,
+/// setting generators, or migrations.  It's recorded when a commit sets a setting, and removed
+/// when the setting is removed or returns to its default.
+const USER_SET_METADATA: &str = "user-set";
+
 /// List the open transactions from the data store.
 pub(crate) fn list_transactions<D>(datastore: &D) -> Result<HashSet<String>>
 where
@@ -297,6 +310,42 @@ pub(crate) fn get_settings_keys<D: DataStore>(
     Ok(settings)
 }
 
+/// Gets the values of the settings in the given Settings, in a map keyed by the dotted key name.
+pub(crate) fn settings_values(settings: &Settings) -> Result<BTreeMap<String, Option<Value>>> {
+    let pairs =
+        to_pairs(settings).context(error::DataStoreSerializationSnafu { given: "Settings" })?;
+    let mut result = BTreeMap::new();
+    for (key, value_str) in pairs {
+        let value: Value = deserialize_scalar::<_, ScalarError>(&value_str)
+            .context(error::InvalidValueSnafu { key: key.name() })?;
+        result.insert(key.name().to_string(), Some(value));
+    }
+    Ok(result)
+}
+
+/// Gets the values of the given data keys, deserialized from their datastore form, in a map keyed
+/// by the dotted key name.  Keys that aren't populated are skipped.
+pub(crate) fn get_values<D: DataStore>(
+    datastore: &D,
+    keys: &HashSet<Key>,
+    committed: &Committed,
+) -> Result<BTreeMap<String, Value>> {
+    let mut result = BTreeMap::new();
+    for key in keys {
+        let value_str = match datastore
+            .get_key(key, committed)
+            .context(error::DataStoreSnafu { op: "get_key" })?
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let value: Value = deserialize_scalar::<_, ScalarError>(&value_str)
+            .context(error::InvalidValueSnafu { key: key.name() })?;
+        result.insert(key.name().to_string(), value);
+    }
+    Ok(result)
+}
+
 /// Build a collection of Service items with the given names using data from the datastore.
 pub(crate) fn get_services_names<D: DataStore>(
     datastore: &D,
@@ -360,14 +409,23 @@ where
 }
 
 /// Given a Settings, takes any Some values and updates them in the datastore.
+///
+/// If `expected_generation` is given, the write is rejected if any of the keys being set was
+/// changed by a commit since that datastore generation, so clients can detect lost updates.
 pub(crate) fn set_settings<D: DataStore>(
     datastore: &mut D,
     settings: &Settings,
     transaction: &str,
+    expected_generation: Option<u64>,
 ) -> Result<()> {
     trace!("Serializing Settings to write to data store");
     let pairs =
         to_pairs(settings).context(error::DataStoreSerializationSnafu { given: "Settings" })?;
+
+    if let Some(generation) = expected_generation {
+        check_unchanged_since(datastore, &pairs.keys().cloned().collect(), generation)?;
+    }
+
     let pending = Committed::Pending {
         tx: transaction.into(),
     };
@@ -376,6 +434,30 @@ pub(crate) fn set_settings<D: DataStore>(
         .context(error::DataStoreSnafu { op: "set_keys" })
 }
 
+/// Fails with PreconditionFailed if any of the given keys was changed by a commit since the given
+/// datastore generation.
+fn check_unchanged_since<D: DataStore>(
+    datastore: &D,
+    keys: &HashSet<Key>,
+    generation: u64,
+) -> Result<()> {
+    let changed = datastore
+        .changed_since(keys, generation)
+        .context(error::DataStoreSnafu {
+            op: "changed_since",
+        })?;
+    if !changed.is_empty() {
+        let mut keys: Vec<_> = changed.iter().map(|key| key.name().as_str()).collect();
+        keys.sort_unstable();
+        return error::PreconditionFailedSnafu {
+            generation,
+            keys: keys.join(", "),
+        }
+        .fail();
+    }
+    Ok(())
+}
+
 // This is not as nice as get_settings, which uses Serializer/Deserializer to properly use the
 // data model and check types.
 /// Gets the value of a metadata key for the requested list of data keys.
@@ -443,14 +525,429 @@ pub(crate) fn get_metadata_for_all_data_keys<D: DataStore, S: AsRef<str>>(
     Ok(result)
 }
 
-/// Makes live any pending settings in the datastore, returning the changed keys.
-pub(crate) fn commit_transaction<D>(datastore: &mut D, transaction: &str) -> Result<HashSet<Key>>
+/// Metadata marking a setting as sensitive, so its value is redacted by default.
+const SENSITIVE_METADATA: &str = "sensitive";
+
+/// Marks the given settings sensitive, along with any settings beneath them.
+pub(crate) fn set_sensitive<D, S>(datastore: &mut D, data_key_strs: &[S]) -> Result<()>
+where
+    D: DataStore,
+    S: AsRef<str>,
+{
+    let md_key = Key::new(KeyType::Meta, SENSITIVE_METADATA).context(error::NewKeySnafu {
+        key_type: "meta",
+        name: SENSITIVE_METADATA,
+    })?;
+    for data_key_str in data_key_strs {
+        let data_key_str = data_key_str.as_ref();
+        let data_key = Key::new(KeyType::Data, data_key_str).context(error::NewKeySnafu {
+            key_type: "data",
+            name: data_key_str,
+        })?;
+        ensure!(
+            data_key.starts_with_segments(&["settings"]),
+            error::InvalidInputSnafu {
+                input: "keys",
+                value: data_key_str,
+            }
+        );
+        datastore
+            .set_metadata(&md_key, &data_key, "true")
+            .context(error::DataStoreSnafu { op: "set_metadata" })?;
+    }
+    Ok(())
+}
+
+/// Gets all settings marked sensitive.
+pub(crate) fn get_sensitive<D: DataStore>(datastore: &D) -> Result<HashMap<String, Value>> {
+    get_metadata_for_all_data_keys(datastore, SENSITIVE_METADATA)
+}
+
+/// Returns whether the given setting, or one above it, is marked sensitive.
+pub(crate) fn is_sensitive<D: DataStore>(datastore: &D, data_key_str: &str) -> bool {
+    let (md_key, data_key) = match (
+        Key::new(KeyType::Meta, SENSITIVE_METADATA),
+        Key::new(KeyType::Data, data_key_str),
+    ) {
+        (Ok(md_key), Ok(data_key)) => (md_key, data_key),
+        _ => return false,
+    };
+    matches!(datastore.get_metadata(&md_key, &data_key), Ok(Some(value)) if value == "true")
+}
+
+/// Makes live any pending settings in the datastore, returning the changed keys.  Fails without
+/// changing anything if the settings would be inconsistent, like bootstrap containers ordered in a
+/// cycle.
+///
+/// If `expected_generation` is given, from an If-Match header when the settings were set, the
+/// commit is rejected if any of the pending keys was changed by another commit since that
+/// generation.  The caller must hold the datastore write lock from this check through the commit.
+pub(crate) fn commit_transaction<D>(
+    datastore: &mut D,
+    transaction: &str,
+    expected_generation: Option<u64>,
+) -> Result<HashSet<Key>>
+where
+    D: DataStore,
+{
+    commit_transaction_removing(datastore, transaction, expected_generation, &HashSet::new())
+}
+
+/// Commits the given transaction like commit_transaction, and removes the given live settings in
+/// the same commit, unless the transaction sets them; see prune_transaction.  Either everything is
+/// committed, and recorded in one history entry, or nothing is.
+pub(crate) fn commit_transaction_removing<D>(
+    datastore: &mut D,
+    transaction: &str,
+    expected_generation: Option<u64>,
+    remove: &HashSet<Key>,
+) -> Result<HashSet<Key>>
+where
+    D: DataStore,
+{
+    check_bootstrap_container_order(datastore, transaction)?;
+    let pending = Committed::Pending {
+        tx: transaction.into(),
+    };
+    let pending_settings = datastore
+        .get_prefix("settings.", &pending)
+        .context(error::DataStoreSnafu { op: "get_prefix" })?;
+    if let Some(generation) = expected_generation {
+        check_unchanged_since(
+            datastore,
+            &pending_settings.keys().cloned().collect(),
+            generation,
+        )?;
+    }
+    let sensitive = history_sensitive(datastore)?;
+    let changes = datastore
+        .commit_transaction_removing(transaction, remove, &sensitive)
+        .context(error::DataStoreSnafu { op: "commit" })?;
+
+    // The commit already succeeded, so we don't fail it; the settings just won't be recognized as
+    // user-set until they're set again.
+    if let Err(e) = record_user_set(datastore, transaction, &pending_settings, remove) {
+        error!(
+            "Unable to record which settings were set by the user: {}",
+            e
+        );
+    }
+    Ok(changes)
+}
+
+/// Records which of the settings committed by the given transaction were set by the user, and
+/// forgets the removed settings.  Settings set to their default value aren't user-set.  Settings
+/// with a setting generator are left alone in the launch transaction, where sundog sets them;
+/// other settings there come from user data.
+fn record_user_set<D: DataStore>(
+    datastore: &mut D,
+    transaction: &str,
+    committed: &HashMap<Key, String>,
+    removed: &HashSet<Key>,
+) -> Result<()> {
+    let user_set = Key::new(KeyType::Meta, USER_SET_METADATA).context(error::NewKeySnafu {
+        key_type: "meta",
+        name: USER_SET_METADATA,
+    })?;
+    let defaults = get_settings_defaults(datastore)?;
+    let generated = datastore
+        .get_metadata_prefix("settings.", &Some("setting-generator"))
+        .context(error::DataStoreSnafu {
+            op: "get_metadata_prefix",
+        })?;
+
+    for (key, value) in committed {
+        if defaults.get(key) == Some(value) {
+            datastore.unset_metadata(&user_set, key)
+        } else if transaction == constants::LAUNCH_TRANSACTION && generated.contains_key(key) {
+            continue;
+        } else {
+            datastore.set_metadata(&user_set, key, "true")
+        }
+        .context(error::DataStoreSnafu { op: "set_metadata" })?;
+    }
+    for key in removed.difference(&committed.keys().cloned().collect()) {
+        datastore
+            .unset_metadata(&user_set, key)
+            .context(error::DataStoreSnafu {
+                op: "unset_metadata",
+            })?;
+    }
+    Ok(())
+}
+
+/// Returns a check for whether a setting's values must be redacted in history, the same as in the
+/// audit log: settings known to hold secrets, and settings marked sensitive, along with the
+/// settings beneath them.
+fn history_sensitive<D: DataStore>(datastore: &D) -> Result<impl Fn(&str) -> bool> {
+    let marked: Vec<String> = get_sensitive(datastore)?.into_keys().collect();
+    Ok(move |key: &str| {
+        let marked_sensitive = marked.iter().any(|marked| {
+            key == marked
+                || key
+                    .strip_prefix(marked.as_str())
+                    .map_or(false, |rest| rest.starts_with('.'))
+        });
+        audit::should_redact(key, marked_sensitive)
+    })
+}
+
+/// Checks that the bootstrap containers' `after` dependencies, as they'd be after committing the
+/// given transaction, don't form a cycle.  systemd would break the cycle by skipping one of the
+/// containers' ordering, so the containers would silently run in an order nobody asked for.
+fn check_bootstrap_container_order<D>(datastore: &D, transaction: &str) -> Result<()>
 where
     D: DataStore,
 {
+    let prefix = "settings.bootstrap-containers";
+    let pending = Committed::Pending {
+        tx: transaction.into(),
+    };
+    let pending_settings: Option<Settings> = get_prefix(datastore, &pending, prefix, None)?;
+    let pending_containers = match pending_settings.and_then(|s| s.bootstrap_containers) {
+        Some(containers) => containers,
+        // Nothing changes the order, and the live settings were checked when committed.
+        None => return Ok(()),
+    };
+    let live_settings: Option<Settings> = get_prefix(datastore, &Committed::Live, prefix, None)?;
+    let live_containers = live_settings
+        .and_then(|s| s.bootstrap_containers)
+        .unwrap_or_default();
+
+    // Pending dependencies replace the live ones, since each list is a single setting.
+    let mut after: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for containers in [live_containers, pending_containers] {
+        for (name, container) in containers {
+            let entry = after.entry(name.to_string()).or_default();
+            if let Some(deps) = container.after {
+                *entry = deps.iter().map(|dep| dep.to_string()).collect();
+            }
+        }
+    }
+
+    if let Some(cycle) = find_dependency_cycle(&after) {
+        return error::BootstrapContainerCycleSnafu {
+            cycle: cycle.join(" -> "),
+        }
+        .fail();
+    }
+    Ok(())
+}
+
+/// Returns the names around a cycle in the given dependencies, starting and ending with the same
+/// name, if there is a cycle.  Dependencies on names that aren't in the map are ignored.
+fn find_dependency_cycle(after: &BTreeMap<String, Vec<String>>) -> Option<Vec<String>> {
+    fn visit<'a>(
+        name: &'a str,
+        after: &'a BTreeMap<String, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|visiting| *visiting == name) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|n| n.to_string()).collect();
+            cycle.push(name.to_string());
+            return Some(cycle);
+        }
+        if done.contains(name) {
+            return None;
+        }
+
+        path.push(name);
+        for dep in after.get(name).into_iter().flatten() {
+            if after.contains_key(dep) {
+                if let Some(cycle) = visit(dep, after, path, done) {
+                    return Some(cycle);
+                }
+            }
+        }
+        path.pop();
+        done.insert(name);
+        None
+    }
+
+    let mut done = HashSet::new();
+    after
+        .keys()
+        .find_map(|name| visit(name, after, &mut Vec::new(), &mut done))
+}
+
+/// A history entry from the datastore, with values deserialized from their datastore form so
+/// they're readable in API responses.
+#[derive(Debug, Serialize)]
+pub(crate) struct HistoryRecord {
+    id: u64,
+    transaction: String,
+    timestamp: DateTime<Utc>,
+    pub(crate) changes: BTreeMap<String, ValueChange>,
+}
+
+/// The value of a key before and after a commit; None (null) means the key wasn't populated.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct ValueChange {
+    old: Option<Value>,
+    new: Option<Value>,
+}
+
+/// Returns the history of committed transactions recorded by the datastore, oldest first.
+pub(crate) fn get_history<D: DataStore>(datastore: &D) -> Result<Vec<HistoryRecord>> {
+    let entries = datastore
+        .history()
+        .context(error::DataStoreSnafu { op: "history" })?;
+
+    let deserialize = |key: &str, value: Option<String>| -> Result<Option<Value>> {
+        value
+            .map(|v| deserialize_scalar::<_, ScalarError>(&v))
+            .transpose()
+            .context(error::InvalidValueSnafu { key })
+    };
+
+    let mut records = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let mut changes = BTreeMap::new();
+        for (key, change) in entry.changes {
+            let value_change = ValueChange {
+                old: deserialize(&key, change.old)?,
+                new: deserialize(&key, change.new)?,
+            };
+            changes.insert(key, value_change);
+        }
+        records.push(HistoryRecord {
+            id: entry.id,
+            transaction: entry.transaction,
+            timestamp: entry.timestamp,
+            changes,
+        });
+    }
+    Ok(records)
+}
+
+/// Restores the live settings to their state before the given history entry was committed,
+/// returning the changed keys.  The caller should apply the changes, as with a commit.
+pub(crate) fn revert_transaction<D: DataStore>(datastore: &mut D, id: u64) -> Result<HashSet<Key>> {
+    let entries = datastore
+        .history()
+        .context(error::DataStoreSnafu { op: "history" })?;
+    ensure!(
+        entries.iter().any(|entry| entry.id == id),
+        error::HistoryEntryNotFoundSnafu { id }
+    );
+    let redacted = history::redacted_before(&entries, id);
+    ensure!(
+        redacted.is_empty(),
+        error::HistoryEntryRedactedSnafu {
+            id,
+            keys: redacted.into_iter().collect::<Vec<_>>().join(", "),
+        }
+    );
+
+    let sensitive = history_sensitive(datastore)?;
+    let changed = datastore
+        .revert_history(id, &sensitive)
+        .context(error::DataStoreSnafu { op: "revert" })?;
+
+    // Settings the revert removed or returned to their defaults are no longer user-set.  Restored
+    // values may have come from anywhere, so the revert doesn't mark settings user-set itself.
+    let defaults = get_settings_defaults(datastore)?;
+    let user_set = Key::new(KeyType::Meta, USER_SET_METADATA).context(error::NewKeySnafu {
+        key_type: "meta",
+        name: USER_SET_METADATA,
+    })?;
+    for key in &changed {
+        let value = datastore
+            .get_key(key, &Committed::Live)
+            .context(error::DataStoreSnafu { op: "get_key" })?;
+        if value.is_none() || value.as_ref() == defaults.get(key) {
+            if let Err(e) = datastore.unset_metadata(&user_set, key) {
+                error!("Unable to record that {} isn't set by the user: {}", key, e);
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Returns the default value of each setting, in datastore form, as recorded by storewolf.
+fn get_settings_defaults<D: DataStore>(datastore: &D) -> Result<HashMap<Key, String>> {
+    let metadata = datastore
+        .get_metadata_prefix("settings.", &Some(DEFAULT_VALUE_METADATA))
+        .context(error::DataStoreSnafu {
+            op: "get_metadata_prefix",
+        })?;
+    Ok(metadata
+        .into_iter()
+        .filter_map(|(data_key, mut metadata)| {
+            metadata
+                .drain()
+                .next()
+                .map(|(_meta_key, value)| (data_key, value))
+        })
+        .collect())
+}
+
+/// Returns the live settings that were set by the user, in datastore form.  Those are settings
+/// with the user-set mark; see record_user_set.  Settings from defaults, setting generators, and
+/// migrations aren't included, even if they have no recorded default.
+fn get_user_settings_pairs<D: DataStore>(datastore: &D) -> Result<HashMap<Key, String>> {
+    let user_set = datastore
+        .get_metadata_prefix("settings.", &Some(USER_SET_METADATA))
+        .context(error::DataStoreSnafu {
+            op: "get_metadata_prefix",
+        })?;
+    let mut pairs = datastore
+        .get_prefix("settings.", &Committed::Live)
+        .context(error::DataStoreSnafu { op: "get_prefix" })?;
+    pairs.retain(|key, _| user_set.contains_key(key));
+    Ok(pairs)
+}
+
+/// Build a Settings containing only the settings that were set by the user, leaving out those
+/// that are generated, from migrations, or have their default values.
+pub(crate) fn get_user_settings<D: DataStore>(datastore: &D) -> Result<Settings> {
+    let pairs = get_user_settings_pairs(datastore)?;
+    from_map(&pairs).context(error::DeserializationSnafu {
+        given: "user settings",
+    })
+}
+
+/// Prepares to commit the given transaction so that it describes all user-set settings, rather
+/// than just the ones it changes.  User-set settings that aren't in the transaction are reset to
+/// their defaults in the transaction.  Those with no default have to be removed from the live data
+/// store instead; they're returned, and the caller should pass them to
+/// commit_transaction_removing, so they're removed in the same commit as the transaction.  Nothing
+/// live is changed here, so if the commit fails, no settings are lost.
+pub(crate) fn prune_transaction<D: DataStore>(
+    datastore: &mut D,
+    transaction: &str,
+) -> Result<HashSet<Key>> {
+    let pending = Committed::Pending {
+        tx: transaction.into(),
+    };
+    let pending_keys = datastore
+        .list_populated_keys("settings.", &pending)
+        .context(error::DataStoreSnafu {
+            op: "list_populated_keys",
+        })?;
+    let defaults = get_settings_defaults(datastore)?;
+
+    let mut reset = HashMap::new();
+    let mut remove = HashSet::new();
+    for key in get_user_settings_pairs(datastore)?.into_keys() {
+        if pending_keys.contains(&key) {
+            continue;
+        }
+        match defaults.get(&key) {
+            Some(default) => {
+                reset.insert(key, default.clone());
+            }
+            None => {
+                remove.insert(key);
+            }
+        }
+    }
+
     datastore
-        .commit_transaction(transaction)
-        .context(error::DataStoreSnafu { op: "commit" })
+        .set_keys(&reset, &pending)
+        .context(error::DataStoreSnafu { op: "set_keys" })?;
+    Ok(remove)
 }
 
 /// Launches the config applier to make appropriate changes to the system based on any settings
@@ -532,6 +1029,49 @@ where
     Ok(())
 }
 
+/// Asks the config applier what committing and applying the given pending transaction would do,
+/// without changing anything.  Returns its description of the changes: a unified diff of each
+/// configuration file that would change, and the restart commands of each affected service.
+///
+/// The config applier calls back to the API to learn about the transaction, so this must not be
+/// called while holding the datastore lock, or from a thread that needs to serve those requests.
+pub(crate) fn plan_transaction(transaction: &str) -> Result<Value> {
+    debug!(
+        "Launching thar-be-settings to plan transaction '{}'",
+        transaction
+    );
+    let output = Command::new("/usr/bin/thar-be-settings")
+        .arg("--plan")
+        .arg(transaction)
+        .output()
+        .context(error::ConfigApplierStartSnafu)?;
+    ensure!(
+        output.status.success(),
+        error::ConfigApplierPlanSnafu {
+            code: output
+                .status
+                .code()
+                .map(|i| i.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+
+    serde_json::from_slice(&output.stdout).context(error::ConfigApplierPlanOutputSnafu)
+}
+
+/// Returns the live update maintenance settings, which limit when updates are activated and the
+/// host rebooted.
+pub(crate) fn get_update_maintenance<D: DataStore>(
+    datastore: &D,
+) -> Result<Option<UpdateMaintenance>> {
+    let settings =
+        get_settings_prefix(datastore, "settings.updates.maintenance", &Committed::Live)?;
+    Ok(settings
+        .and_then(|settings| settings.updates)
+        .and_then(|updates| updates.maintenance))
+}
+
 /// Dispatches an update command via `thar-be-updates`
 pub(crate) fn dispatch_update_command(args: &[&str]) -> Result<HttpResponse> {
     let status = Command::new("/usr/bin/thar-be-updates")
@@ -551,6 +1091,7 @@ pub(crate) fn dispatch_update_command(args: &[&str]) -> Result<HttpResponse> {
         Some(TbuErrorStatus::DisallowCommand) => error::Error::DisallowCommand,
         Some(TbuErrorStatus::UpdateDoesNotExist) => error::Error::UpdateDoesNotExist,
         Some(TbuErrorStatus::NoStagedImage) => error::Error::NoStagedImage,
+        Some(TbuErrorStatus::OutsideMaintenanceWindow) => error::Error::OutsideMaintenanceWindow,
         // other errors
         _ => error::Error::UpdateError,
     };
@@ -582,6 +1123,266 @@ mod test {
         assert_eq!(settings.motd, Some("json string".try_into().unwrap()));
     }
 
+    #[test]
+    fn get_values_works() {
+        let mut ds = MemoryDataStore::new();
+        let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+        let hostname = Key::new(KeyType::Data, "settings.hostname").unwrap();
+        ds.set_key(&motd, "\"json string\"", &Committed::Live)
+            .unwrap();
+
+        // Unpopulated keys are skipped
+        let values = get_values(&ds, &hashset!(motd, hostname), &Committed::Live).unwrap();
+        assert_eq!(
+            values,
+            BTreeMap::from([("settings.motd".to_string(), "json string".into())])
+        );
+    }
+
+    #[test]
+    fn revert_transaction_works() {
+        let mut ds = MemoryDataStore::new();
+        let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+        ds.set_key(&motd, "\"old\"", &Committed::Live).unwrap();
+        let pending = Committed::Pending { tx: "tx".into() };
+        ds.set_key(&motd, "\"new\"", &pending).unwrap();
+        commit_transaction(&mut ds, "tx", None).unwrap();
+
+        let history = get_history(&ds).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].transaction, "tx");
+        assert_eq!(
+            history[0].changes["settings.motd"],
+            ValueChange {
+                old: Some("old".into()),
+                new: Some("new".into()),
+            }
+        );
+
+        let changed = revert_transaction(&mut ds, history[0].id).unwrap();
+        assert_eq!(changed, hashset!(motd.clone()));
+        assert_eq!(
+            ds.get_key(&motd, &Committed::Live).unwrap(),
+            Some("\"old\"".to_string())
+        );
+
+        // The revert shows up in history, and unknown IDs are rejected.
+        assert_eq!(get_history(&ds).unwrap().len(), 2);
+        assert!(matches!(
+            revert_transaction(&mut ds, 42),
+            Err(error::Error::HistoryEntryNotFound { id: 42 })
+        ));
+    }
+
+    #[test]
+    fn history_redacts_sensitive_settings() {
+        let mut ds = MemoryDataStore::new();
+        let token = Key::new(KeyType::Data, "settings.kubernetes.bootstrap-token").unwrap();
+        let decrypted = Key::new(KeyType::Data, "settings.decrypted.value").unwrap();
+        set_sensitive(&mut ds, &["settings.decrypted"]).unwrap();
+        let pending = Committed::Pending { tx: "tx".into() };
+        ds.set_key(&token, "\"abcdef.0123456789abcdef\"", &pending)
+            .unwrap();
+        ds.set_key(&decrypted, "\"secret\"", &pending).unwrap();
+        commit_transaction(&mut ds, "tx", None).unwrap();
+
+        let history = get_history(&ds).unwrap();
+        for key in [&token, &decrypted] {
+            assert_eq!(
+                history[0].changes[key.name()],
+                ValueChange {
+                    old: None,
+                    new: Some("<redacted>".into()),
+                }
+            );
+        }
+
+        // Once a secret is changed, its earlier value can't be restored, so the revert is refused.
+        let pending = Committed::Pending { tx: "tx".into() };
+        ds.set_key(&decrypted, "\"rotated\"", &pending).unwrap();
+        commit_transaction(&mut ds, "tx", None).unwrap();
+        let history = get_history(&ds).unwrap();
+        assert!(matches!(
+            revert_transaction(&mut ds, history[1].id),
+            Err(error::Error::HistoryEntryRedacted { .. })
+        ));
+        assert_eq!(get_history(&ds).unwrap().len(), 2);
+
+        // The secrets weren't set before the first commit, so reverting it removes them.
+        let changed = revert_transaction(&mut ds, history[0].id).unwrap();
+        assert_eq!(changed, hashset!(token, decrypted));
+    }
+
+    #[test]
+    fn prune_transaction_works() {
+        let mut ds = MemoryDataStore::new();
+        let key = |name| Key::new(KeyType::Data, name).unwrap();
+        let default_value = Key::new(KeyType::Meta, DEFAULT_VALUE_METADATA).unwrap();
+        let generator = Key::new(KeyType::Meta, "setting-generator").unwrap();
+
+        // An unchanged default, a generated setting, and one added by a migration, with no
+        // default.
+        ds.set_metadata(&default_value, &key("settings.motd"), "\"default\"")
+            .unwrap();
+        ds.set_key(
+            &key("settings.network.hostname"),
+            "\"default\"",
+            &Committed::Live,
+        )
+        .unwrap();
+        ds.set_metadata(
+            &default_value,
+            &key("settings.network.hostname"),
+            "\"default\"",
+        )
+        .unwrap();
+        ds.set_key(
+            &key("settings.kernel.lockdown"),
+            "\"none\"",
+            &Committed::Live,
+        )
+        .unwrap();
+        ds.set_metadata(&generator, &key("settings.kernel.lockdown"), "\"true\"")
+            .unwrap();
+        ds.set_key(
+            &key("settings.updates.version-lock"),
+            "\"latest\"",
+            &Committed::Live,
+        )
+        .unwrap();
+
+        // A changed default and two settings with no default, set by the user.
+        let user = Committed::Pending { tx: "user".into() };
+        ds.set_key(&key("settings.motd"), "\"changed\"", &user)
+            .unwrap();
+        ds.set_key(
+            &key("settings.ntp.time-servers"),
+            "[\"https://a.example.com\"]",
+            &user,
+        )
+        .unwrap();
+        ds.set_key(&key("settings.updates.ignore-waves"), "true", &user)
+            .unwrap();
+        commit_transaction(&mut ds, "user", None).unwrap();
+
+        let settings = get_user_settings(&ds).unwrap();
+        assert_eq!(settings.motd, Some("changed".try_into().unwrap()));
+        assert!(settings.network.is_none());
+        assert!(settings.kernel.is_none());
+        assert!(settings.ntp.is_some());
+        let updates = settings.updates.unwrap();
+        assert!(updates.ignore_waves.is_some());
+        assert!(updates.version_lock.is_none());
+
+        // The transaction keeps one user setting; the other is removed, and the changed default
+        // is reset.
+        let pending = Committed::Pending { tx: "tx".into() };
+        ds.set_key(
+            &key("settings.ntp.time-servers"),
+            "[\"https://b.example.com\"]",
+            &pending,
+        )
+        .unwrap();
+        let remove = prune_transaction(&mut ds, "tx").unwrap();
+        assert_eq!(remove, hashset!(key("settings.updates.ignore-waves")));
+        assert_eq!(
+            ds.get_key(&key("settings.motd"), &pending).unwrap(),
+            Some("\"default\"".to_string())
+        );
+        // Nothing is removed until the commit.
+        assert_eq!(get_history(&ds).unwrap().len(), 1);
+        assert!(get_user_settings(&ds).unwrap().updates.is_some());
+
+        let changes = commit_transaction_removing(&mut ds, "tx", None, &remove).unwrap();
+        assert!(changes.contains(&key("settings.updates.ignore-waves")));
+        let history = get_history(&ds).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].transaction, "tx");
+        let settings = get_user_settings(&ds).unwrap();
+        assert!(settings.motd.is_none());
+        assert!(settings.updates.is_none());
+        assert!(settings.ntp.is_some());
+
+        // Settings that weren't set by the user survive pruning.
+        for name in [
+            "settings.network.hostname",
+            "settings.kernel.lockdown",
+            "settings.updates.version-lock",
+        ] {
+            assert!(ds.get_key(&key(name), &Committed::Live).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn launch_transaction_marks_user_data() {
+        let mut ds = MemoryDataStore::new();
+        let key = |name| Key::new(KeyType::Data, name).unwrap();
+        let default_value = Key::new(KeyType::Meta, DEFAULT_VALUE_METADATA).unwrap();
+        let generator = Key::new(KeyType::Meta, "setting-generator").unwrap();
+        ds.set_metadata(&default_value, &key("settings.motd"), "\"default\"")
+            .unwrap();
+        ds.set_metadata(&generator, &key("settings.kernel.lockdown"), "\"true\"")
+            .unwrap();
+
+        // At launch, the transaction holds defaults, generated settings, and user data.
+        let launch = Committed::Pending {
+            tx: constants::LAUNCH_TRANSACTION.into(),
+        };
+        ds.set_key(&key("settings.motd"), "\"default\"", &launch)
+            .unwrap();
+        ds.set_key(&key("settings.kernel.lockdown"), "\"none\"", &launch)
+            .unwrap();
+        ds.set_key(&key("settings.updates.ignore-waves"), "true", &launch)
+            .unwrap();
+        commit_transaction(&mut ds, constants::LAUNCH_TRANSACTION, None).unwrap();
+
+        let settings = get_user_settings(&ds).unwrap();
+        assert!(settings.motd.is_none());
+        assert!(settings.kernel.is_none());
+        assert!(settings.updates.is_some());
+
+        // Once the user sets a generated setting, it's theirs.
+        let user = Committed::Pending { tx: "user".into() };
+        ds.set_key(&key("settings.kernel.lockdown"), "\"integrity\"", &user)
+            .unwrap();
+        commit_transaction(&mut ds, "user", None).unwrap();
+        assert!(get_user_settings(&ds).unwrap().kernel.is_some());
+    }
+
+    #[test]
+    fn failed_prune_commit_removes_nothing() {
+        let mut ds = MemoryDataStore::new();
+        let key = |name| Key::new(KeyType::Data, name).unwrap();
+        let user = Committed::Pending { tx: "user".into() };
+        ds.set_key(&key("settings.updates.ignore-waves"), "true", &user)
+            .unwrap();
+        commit_transaction(&mut ds, "user", None).unwrap();
+        let generation = ds.generation().unwrap();
+
+        // Another commit changes a setting in the transaction after it was read.
+        let theirs = Committed::Pending {
+            tx: "theirs".into(),
+        };
+        ds.set_key(&key("settings.motd"), "\"theirs\"", &theirs)
+            .unwrap();
+        commit_transaction(&mut ds, "theirs", None).unwrap();
+
+        let mine = Committed::Pending { tx: "mine".into() };
+        ds.set_key(&key("settings.motd"), "\"mine\"", &mine)
+            .unwrap();
+        let remove = prune_transaction(&mut ds, "mine").unwrap();
+        assert_eq!(remove, hashset!(key("settings.updates.ignore-waves")));
+        commit_transaction_removing(&mut ds, "mine", Some(generation), &remove).unwrap_err();
+
+        // The setting that would have been pruned is still there.
+        assert_eq!(
+            ds.get_key(&key("settings.updates.ignore-waves"), &Committed::Live)
+                .unwrap(),
+            Some("true".to_string())
+        );
+        assert_eq!(get_history(&ds).unwrap().len(), 2);
+    }
+
     #[test]
     fn get_settings_prefix_works() {
         let mut ds = MemoryDataStore::new();
@@ -762,7 +1563,7 @@ mod test {
         let mut ds = MemoryDataStore::new();
         let tx = "test transaction";
         let pending = Committed::Pending { tx: tx.into() };
-        set_settings(&mut ds, &settings, tx).unwrap();
+        set_settings(&mut ds, &settings, tx, None).unwrap();
 
         // Retrieve directly
         let key = Key::new(KeyType::Data, "settings.motd").unwrap();
@@ -772,6 +1573,65 @@ mod test {
         );
     }
 
+    #[test]
+    fn set_settings_checks_generation() {
+        let settings = model::Settings {
+            motd: Some("tz".try_into().unwrap()),
+            ..Default::default()
+        };
+        let mut ds = MemoryDataStore::new();
+
+        // Nothing has been committed yet, so generation 0 is current.
+        set_settings(&mut ds, &settings, "tx", Some(0)).unwrap();
+        commit_transaction(&mut ds, "tx", None).unwrap();
+        let generation = ds.generation().unwrap();
+
+        // The key changed since generation 0, so an update based on it is rejected...
+        assert!(matches!(
+            set_settings(&mut ds, &settings, "tx", Some(0)),
+            Err(error::Error::PreconditionFailed { generation: 0, .. })
+        ));
+        // ...but an update based on the current generation is fine.
+        set_settings(&mut ds, &settings, "tx", Some(generation)).unwrap();
+    }
+
+    #[test]
+    fn commit_checks_generation() {
+        let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+        let settings = |motd: &str| model::Settings {
+            motd: Some(motd.try_into().unwrap()),
+            ..Default::default()
+        };
+        let mut ds = MemoryDataStore::new();
+        set_settings(&mut ds, &settings("first"), "first", None).unwrap();
+        commit_transaction(&mut ds, "first", None).unwrap();
+        let generation = ds.generation().unwrap();
+
+        // A client sets motd based on the current generation, and passes the check...
+        set_settings(&mut ds, &settings("mine"), "mine", Some(generation)).unwrap();
+        // ...but another client commits a change to motd before the first client commits.
+        set_settings(&mut ds, &settings("theirs"), "theirs", None).unwrap();
+        commit_transaction(&mut ds, "theirs", None).unwrap();
+
+        // The first client's commit is rejected rather than overwriting the other change.
+        assert!(matches!(
+            commit_transaction(&mut ds, "mine", Some(generation)),
+            Err(error::Error::PreconditionFailed { .. })
+        ));
+        assert_eq!(
+            ds.get_key(&motd, &Committed::Live).unwrap(),
+            Some("\"theirs\"".to_string())
+        );
+
+        // Based on the new generation, the commit goes through.
+        let generation = ds.generation().unwrap();
+        commit_transaction(&mut ds, "mine", Some(generation)).unwrap();
+        assert_eq!(
+            ds.get_key(&motd, &Committed::Live).unwrap(),
+            Some("\"mine\"".to_string())
+        );
+    }
+
     #[test]
     fn get_metadata_keys_works() {
         let mut ds = MemoryDataStore::new();
@@ -819,6 +1679,22 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn sensitive_settings() {
+        let mut ds = MemoryDataStore::new();
+        set_sensitive(&mut ds, &["settings.aws.credentials"]).unwrap();
+
+        assert!(is_sensitive(&ds, "settings.aws.credentials"));
+        assert!(is_sensitive(&ds, "settings.aws.credentials.inner"));
+        assert!(!is_sensitive(&ds, "settings.aws.region"));
+        assert_eq!(
+            get_sensitive(&ds).unwrap(),
+            hashmap!("settings.aws.credentials".to_string() => true.into())
+        );
+        // Only settings can be marked sensitive
+        set_sensitive(&mut ds, &["os.arch"]).unwrap_err();
+    }
+
     #[test]
     fn commit_works() {
         // Set directly with data store
@@ -839,7 +1715,7 @@ mod test {
         get_settings(&ds, &Committed::Live).unwrap_err();
 
         // Commit, pending -> live
-        commit_transaction(&mut ds, tx).unwrap();
+        commit_transaction(&mut ds, tx, None).unwrap();
 
         // No more pending settings
         get_settings(&ds, &pending).unwrap_err();
@@ -847,4 +1723,123 @@ mod test {
         let settings = get_settings(&ds, &Committed::Live).unwrap();
         assert_eq!(settings.motd, Some("json string".try_into().unwrap()));
     }
+
+    #[test]
+    fn dependency_cycles() {
+        let after = |deps: &[(&str, &[&str])]| -> BTreeMap<String, Vec<String>> {
+            deps.iter()
+                .map(|(name, deps)| {
+                    (
+                        name.to_string(),
+                        deps.iter().map(|dep| dep.to_string()).collect(),
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            find_dependency_cycle(&after(&[
+                ("disk", &[]),
+                ("register", &["disk", "missing"]),
+                ("tune", &["disk", "register"]),
+            ])),
+            None
+        );
+        assert_eq!(
+            find_dependency_cycle(&after(&[("self", &["self"])])),
+            Some(vec!["self".to_string(), "self".to_string()])
+        );
+        assert_eq!(
+            find_dependency_cycle(&after(&[
+                ("a", &["b"]),
+                ("b", &["c"]),
+                ("c", &["a"]),
+                ("d", &["a"]),
+            ])),
+            Some(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn commit_rejects_bootstrap_container_cycle() {
+        let mut ds = MemoryDataStore::new();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+        let set = |ds: &mut MemoryDataStore, name: &str, value: &str, committed: &Committed| {
+            ds.set_key(&Key::new(KeyType::Data, name).unwrap(), value, committed)
+                .unwrap();
+        };
+        set(
+            &mut ds,
+            "settings.bootstrap-containers.disk.after",
+            "[\"register\"]",
+            &Committed::Live,
+        );
+        set(
+            &mut ds,
+            "settings.bootstrap-containers.register.source",
+            "\"example.com/register\"",
+            &Committed::Live,
+        );
+
+        // Live dependencies count toward a cycle made by the transaction.
+        set(
+            &mut ds,
+            "settings.bootstrap-containers.register.after",
+            "[\"disk\"]",
+            &pending,
+        );
+        commit_transaction(&mut ds, tx, None).unwrap_err();
+
+        // Replacing the live dependency breaks the cycle.
+        set(
+            &mut ds,
+            "settings.bootstrap-containers.disk.after",
+            "[]",
+            &pending,
+        );
+        commit_transaction(&mut ds, tx, None).unwrap();
+    }
+
+    #[test]
+    fn get_update_maintenance_works() {
+        let mut ds = MemoryDataStore::new();
+        assert!(get_update_maintenance(&ds).unwrap().is_none());
+
+        ds.set_key(
+            &Key::new(KeyType::Data, "settings.updates.maintenance.windows").unwrap(),
+            "[\"Mon-Fri 02:00-05:00\"]",
+            &Committed::Live,
+        )
+        .unwrap();
+        ds.set_key(
+            &Key::new(KeyType::Data, "settings.updates.maintenance.timezone").unwrap(),
+            "\"America/New_York\"",
+            &Committed::Live,
+        )
+        .unwrap();
+        // Pending settings aren't in effect yet.
+        ds.set_key(
+            &Key::new(KeyType::Data, "settings.updates.maintenance.timezone").unwrap(),
+            "\"Europe/Paris\"",
+            &Committed::Pending {
+                tx: "test".to_string(),
+            },
+        )
+        .unwrap();
+
+        let maintenance = get_update_maintenance(&ds).unwrap().unwrap();
+        let windows = maintenance.windows.unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].as_ref(), "Mon-Fri 02:00-05:00");
+        assert_eq!(
+            maintenance.timezone.as_ref().map(AsRef::as_ref),
+            Some("America/New_York")
+        );
+    }
 }
@@ -40,6 +40,12 @@ pub enum Error {
     #[snafu(display("Input '{}' cannot be empty", input))]
     EmptyInput { input: String },
 
+    #[snafu(display("Input '{}' is not valid: {}", input, value))]
+    InvalidInput { input: String, value: String },
+
+    #[snafu(display("Conflict: settings changed since generation {}: {}", generation, keys))]
+    PreconditionFailed { generation: u64, keys: String },
+
     #[snafu(display("Another thread poisoned the data store lock by panicking"))]
     DataStoreLock,
 
@@ -55,9 +61,53 @@ pub enum Error {
     #[snafu(display("Tried to commit with no pending changes"))]
     CommitWithNoPending,
 
+    #[snafu(display(
+        "Bootstrap containers can't be ordered, their 'after' settings form a cycle: {}",
+        cycle
+    ))]
+    BootstrapContainerCycle { cycle: String },
+
     #[snafu(display("Unable to get OS release data: {}", source))]
     ReleaseData { source: bottlerocket_release::Error },
 
+    #[snafu(display("No kernel crash has been reported"))]
+    NoCrashReport,
+
+    #[snafu(display("Unable to read kernel crash report '{}': {}", path, source))]
+    CrashReportRead { path: String, source: io::Error },
+
+    #[snafu(display("Unable to parse kernel crash report '{}': {}", path, source))]
+    CrashReportParse {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+    // Access policy errors
+    #[snafu(display("Forbidden: {}", reason))]
+    Forbidden { reason: String },
+
+    #[snafu(display("Unable to read access policy '{}': {}", path.display(), source))]
+    PolicyRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Invalid access policy '{}': {}", path.display(), source))]
+    PolicyParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[snafu(display("Access policy '{}' refers to undefined role '{}'", path.display(), role))]
+    PolicyRole { path: PathBuf, role: String },
+
+    #[snafu(display("Unable to read audit log '{}': {}", path.display(), source))]
+    AuditRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Unable to read audit log in the background: {}", source))]
+    AuditReadBlocking {
+        source: actix_web::error::BlockingError,
+    },
+
     // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
     // Controller errors
@@ -70,6 +120,16 @@ pub enum Error {
     #[snafu(display("Listed key '{}' not found on disk", key))]
     ListedKeyNotPresent { key: String },
 
+    #[snafu(display("No transaction with ID {} in history", id))]
+    HistoryEntryNotFound { id: u64 },
+
+    #[snafu(display(
+        "Can't revert transaction {}; earlier values of sensitive settings aren't kept: {}",
+        id,
+        keys
+    ))]
+    HistoryEntryRedacted { id: u64, keys: String },
+
     #[snafu(display("Data store error during {}: {}", op, source))]
     DataStore {
         op: String,
@@ -109,6 +169,12 @@ pub enum Error {
         source: serde_json::Error,
     },
 
+    #[snafu(display("Data key '{}' has a value that is not valid JSON: {}", key, source))]
+    InvalidValue {
+        key: String,
+        source: serde_json::Error,
+    },
+
     #[snafu(display("Config applier was unable to fork child, returned {}", code))]
     ConfigApplierFork { code: String },
 
@@ -127,6 +193,31 @@ pub enum Error {
     #[snafu(display("Unable to send input to config applier: {}", source))]
     ConfigApplierWrite { source: io::Error },
 
+    #[snafu(display(
+        "Config applier failed to plan changes, exit code {}: {}",
+        code,
+        stderr
+    ))]
+    ConfigApplierPlan { code: String, stderr: String },
+
+    #[snafu(display("Config applier returned an invalid plan: {}", source))]
+    ConfigApplierPlanOutput { source: serde_json::Error },
+
+    #[snafu(display("Unable to run config applier in the background: {}", source))]
+    ConfigApplierBlocking {
+        source: actix_web::error::BlockingError,
+    },
+
+    #[snafu(display("Unable to get container status: {}", source))]
+    ContainerStatus {
+        source: host_containers::status::Error,
+    },
+
+    #[snafu(display("Unable to get container status in the background: {}", source))]
+    ContainerStatusBlocking {
+        source: actix_web::error::BlockingError,
+    },
+
     #[snafu(display("Unable to start shutdown: {}", source))]
     Shutdown { source: io::Error },
 
@@ -157,6 +248,9 @@ pub enum Error {
     #[snafu(display("Update action not allowed according to update state"))]
     DisallowCommand,
 
+    #[snafu(display("Update can't be activated outside of a maintenance window"))]
+    OutsideMaintenanceWindow,
+
     #[snafu(display("Update dispatcher failed"))]
     UpdateError,
 
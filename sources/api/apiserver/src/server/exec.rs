@@ -10,6 +10,7 @@
 // the WebSocket actors and the child, it's not async either - it uses standard threads and
 // channels.  See its docs for more detail.
 
+use super::audit::ExecAudit;
 use actix::prelude::{Actor, ActorContext, AsyncContext, Handler, StreamHandler};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws::{self, Message};
@@ -19,6 +20,7 @@ use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::mpsc::TrySendError;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 mod child;
@@ -67,7 +69,12 @@ pub(crate) async fn ws_exec(
         r.path()
     );
 
-    ws::start(WsExec::new(data.exec_socket_path.clone()), &r, stream)
+    let audit = ExecAudit::new(&r, Arc::clone(&data.audit));
+    ws::start(
+        WsExec::new(data.exec_socket_path.clone(), audit),
+        &r,
+        stream,
+    )
 }
 
 /// WsExec is an actor that represents the WebSocket connection to the client.  All messages to and
@@ -89,14 +96,18 @@ pub(crate) struct WsExec {
     /// This represents the path to the containerd socket that we use to spawn the requested
     /// process in a container namespace.
     exec_socket_path: PathBuf,
+
+    /// This records the command the client asks us to run in the audit log.
+    audit: ExecAudit,
 }
 
 impl WsExec {
-    fn new(exec_socket_path: PathBuf) -> Self {
+    fn new(exec_socket_path: PathBuf, audit: ExecAudit) -> Self {
         Self {
             heartbeat: Instant::now(),
             child_handles: None,
             exec_socket_path,
+            audit,
         }
     }
 
@@ -228,9 +239,15 @@ impl StreamHandler<Result<Message, ws::ProtocolError>> for WsExec {
                                init.target,
                                init.command,
                                init.tty.is_some());
+                        let target = init.target.clone();
+                        let command = init.command.clone();
                         // Spawn the process, getting back handles that let us interact with it.
+                        let spawned =
+                            ChildHandles::new(init, &self.exec_socket_path, ctx.address());
+                        let spawn_error = spawned.as_ref().err().map(|e| e.to_string());
+                        self.audit.record(&target, &command, spawn_error.as_deref());
                         let child_handles = ok_or_stop!(
-                            ChildHandles::new(init, &self.exec_socket_path, ctx.address()),
+                            spawned,
                             ctx,
                             "failed to spawn process",
                             ws::CloseCode::Error
@@ -0,0 +1,200 @@
+//! The metrics module counts the requests the server handles, how long they take, and the
+//! transactions committed to the data store, so metricdog can export them.  The totals are kept in
+//! memory, so they start over when the server restarts.  They're returned by GET /metrics.
+
+use super::SharedData;
+use actix_web::body::BoxBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::web;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The upper bounds, in seconds, of the buckets we count request durations in.
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The route we count requests under if they don't match any route, so that arbitrary paths don't
+/// each get their own totals.
+pub(crate) const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Running totals for the server.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    totals: Mutex<Totals>,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    /// Request totals by method and route.
+    requests: BTreeMap<(String, String), RouteTotals>,
+    commits: u64,
+    committed_keys: u64,
+}
+
+#[derive(Debug)]
+struct RouteTotals {
+    /// Responses by status code.
+    responses: BTreeMap<u16, u64>,
+    /// Requests counted in each bucket of DURATION_BUCKETS; each bucket includes the shorter ones.
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    count: u64,
+}
+
+impl Default for RouteTotals {
+    fn default() -> Self {
+        Self {
+            responses: BTreeMap::new(),
+            duration_buckets: [0; DURATION_BUCKETS.len()],
+            duration_sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Metrics {
+    /// Counts a request to the given route pattern, like "/tx/{id}", rather than its full path.
+    pub(crate) fn record_request(&self, method: &str, route: &str, status: u16, took: Duration) {
+        // A panic while the lock was held can't leave the totals inconsistent enough to matter.
+        let mut totals = self.totals.lock().unwrap_or_else(|e| e.into_inner());
+        let route_totals = totals
+            .requests
+            .entry((method.to_string(), route.to_string()))
+            .or_default();
+        *route_totals.responses.entry(status).or_default() += 1;
+        let seconds = took.as_secs_f64();
+        for (bound, count) in DURATION_BUCKETS
+            .iter()
+            .zip(route_totals.duration_buckets.iter_mut())
+        {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        route_totals.duration_sum += seconds;
+        route_totals.count += 1;
+    }
+
+    /// Counts a commit of the given number of changed keys.
+    pub(crate) fn record_commit(&self, changed_keys: usize) {
+        let mut totals = self.totals.lock().unwrap_or_else(|e| e.into_inner());
+        totals.commits += 1;
+        totals.committed_keys += changed_keys as u64;
+    }
+
+    /// Returns the current totals.
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let totals = self.totals.lock().unwrap_or_else(|e| e.into_inner());
+        let requests = totals
+            .requests
+            .iter()
+            .map(|((method, route), route_totals)| RouteMetrics {
+                method: method.clone(),
+                route: route.clone(),
+                responses: route_totals.responses.clone(),
+                duration_seconds: Histogram {
+                    buckets: DURATION_BUCKETS
+                        .iter()
+                        .zip(route_totals.duration_buckets.iter())
+                        .map(|(le, count)| Bucket {
+                            le: *le,
+                            count: *count,
+                        })
+                        .collect(),
+                    sum: route_totals.duration_sum,
+                    count: route_totals.count,
+                },
+            })
+            .collect();
+        MetricsSnapshot {
+            requests,
+            commits: totals.commits,
+            committed_keys: totals.committed_keys,
+        }
+    }
+}
+
+/// Counts a response to a request that started at the given time.  Meant to be mapped over the
+/// response future in a wrap_fn, so the count includes requests denied by the access policy.
+/// Requests that fail before a response is built aren't counted.
+pub(crate) fn record_response(
+    started: Instant,
+    res: actix_web::Result<ServiceResponse<BoxBody>>,
+) -> actix_web::Result<ServiceResponse<BoxBody>> {
+    let res = res?;
+    let req = res.request();
+    if let Some(data) = req.app_data::<web::Data<SharedData>>() {
+        data.metrics.record_request(
+            req.method().as_str(),
+            req.match_pattern().as_deref().unwrap_or(UNMATCHED_ROUTE),
+            res.status().as_u16(),
+            started.elapsed(),
+        );
+    }
+    Ok(res)
+}
+
+/// The server's totals, as returned by GET /metrics.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct MetricsSnapshot {
+    requests: Vec<RouteMetrics>,
+    commits: u64,
+    committed_keys: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RouteMetrics {
+    method: String,
+    route: String,
+    responses: BTreeMap<u16, u64>,
+    duration_seconds: Histogram,
+}
+
+#[derive(Debug, Serialize)]
+struct Histogram {
+    /// Cumulative counts of requests that took at most `le` seconds.
+    buckets: Vec<Bucket>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Bucket {
+    le: f64,
+    count: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn totals() {
+        let metrics = Metrics::default();
+        metrics.record_request("GET", "/settings", 200, Duration::from_millis(3));
+        metrics.record_request("GET", "/settings", 200, Duration::from_millis(30));
+        metrics.record_request("GET", "/settings", 403, Duration::from_secs(20));
+        metrics.record_commit(2);
+        metrics.record_commit(1);
+
+        let snapshot = serde_json::to_value(metrics.snapshot()).unwrap();
+        assert_eq!(snapshot["commits"], json!(2));
+        assert_eq!(snapshot["committed-keys"], json!(3));
+
+        let route = &snapshot["requests"][0];
+        assert_eq!(route["method"], json!("GET"));
+        assert_eq!(route["route"], json!("/settings"));
+        assert_eq!(route["responses"], json!({"200": 2, "403": 1}));
+        let histogram = &route["duration-seconds"];
+        assert_eq!(histogram["count"], json!(3));
+        assert_eq!(histogram["buckets"][0], json!({"le": 0.005, "count": 1}));
+        assert_eq!(histogram["buckets"][3], json!({"le": 0.05, "count": 2}));
+        assert_eq!(histogram["buckets"][10], json!({"le": 10.0, "count": 2}));
+    }
+}
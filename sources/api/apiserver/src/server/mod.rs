@@ -1,32 +1,44 @@
 //! The server module owns the API surface.  It interfaces with the datastore through the
 //! server::controller module.
 
+mod audit;
 mod controller;
 mod error;
 mod exec;
+mod metrics;
+mod policy;
+mod watch;
 
 pub use error::Error;
 
 use actix_web::{
-    body::BoxBody, error::ResponseError, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+    body::BoxBody, dev::Service, error::ResponseError, http::header, web, App, HttpRequest,
+    HttpResponse, HttpServer, Responder,
 };
-use datastore::{Committed, FilesystemDataStore, Key, Value};
+use chrono::{DateTime, Utc};
+use corndog::drift::DriftReport;
+use datastore::{Committed, DataStore, FilesystemDataStore, Key, Value};
 use error::Result;
 use fs2::FileExt;
+use futures::future::{ready, Either, FutureExt};
 use http::StatusCode;
-use log::info;
+use log::{info, warn};
 use model::{ConfigurationFiles, Model, Services, Settings};
 use nix::unistd::{chown, Gid};
+use policy::Access;
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::fs::{set_permissions, File, Permissions};
+use std::fs::{self, set_permissions, File, Permissions};
+use std::io;
 use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
 use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync;
-use thar_be_updates::status::{UpdateStatus, UPDATE_LOCKFILE};
+use std::sync::{self, Arc};
+use std::time::Instant;
+use thar_be_updates::status::{maintenance_windows, UpdateStatus, UPDATE_LOCKFILE};
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
@@ -49,22 +61,42 @@ fn notify_unix_socket_ready() -> Result<()> {
     Ok(())
 }
 
+/// Binds the API socket, replacing any socket left behind by a previous run.  We bind it
+/// ourselves, rather than using HttpServer::bind_uds, because only listen_uds supports on_connect.
+fn bind_socket(socket_path: &Path) -> Result<UnixListener> {
+    match fs::remove_file(socket_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context(error::BindSocketSnafu { path: socket_path }),
+    }
+    UnixListener::bind(socket_path).context(error::BindSocketSnafu { path: socket_path })
+}
+
 // Router
 
 /// This is the primary interface of the module.  It defines the server and application that actix
 /// spawns for requests.  It creates a shared datastore handle that can be used by handler methods
 /// to interface with the controller.
-pub async fn serve<P1, P2, P3>(
+///
+/// Callers are authorized according to the access policy at policy_path, if it exists; see the
+/// policy module.  Requests that change the system, and denied requests, are recorded in the audit
+/// log at audit_log_path; see the audit module.
+#[allow(clippy::too_many_arguments)]
+pub async fn serve<P1, P2, P3, P4, P5>(
     socket_path: P1,
     datastore_path: P2,
     threads: usize,
     socket_gid: Option<Gid>,
     exec_socket_path: P3,
+    policy_path: P4,
+    audit_log_path: P5,
 ) -> Result<()>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
     P3: Into<PathBuf>,
+    P4: Into<PathBuf>,
+    P5: Into<PathBuf>,
 {
     // SharedData gives us a convenient way to make data available to handler methods when it
     // doesn't come from the request itself.  It's easier than the ownership tricks required to
@@ -72,6 +104,13 @@ where
     let shared_data = web::Data::new(SharedData {
         ds: sync::RwLock::new(FilesystemDataStore::new(datastore_path)),
         exec_socket_path: exec_socket_path.into(),
+        watchers: watch::Watchers::default(),
+        policy: policy::PolicyFile::new(policy_path),
+        audit: Arc::new(audit::AuditLog::new(audit_log_path)),
+        metrics: metrics::Metrics::default(),
+        expected_generations: sync::Mutex::new(HashMap::new()),
+        sysctl_path_prefix: corndog::SYSCTL_PATH_PREFIX.into(),
+        lockdown_path: corndog::LOCKDOWN_PATH.into(),
     });
 
     let http_server = HttpServer::new(move || {
@@ -79,17 +118,33 @@ where
             // This makes the data store available to API methods merely by having a Data
             // parameter.
             .app_data(shared_data.clone())
+            // Check that the caller may make the request before it reaches a handler, and record
+            // requests that change the system, and any denials, in the audit log.
+            .wrap_fn(|req, srv| match policy::authorize(&req) {
+                Ok(()) => Either::Left(srv.call(req).map(audit::record_response)),
+                Err(denied) => Either::Right(ready(Ok(denied))),
+            })
+            // Count requests by route and response status, and time them, for metricdog.
+            .wrap_fn(|req, srv| {
+                let started = Instant::now();
+                srv.call(req)
+                    .map(move |res| metrics::record_response(started, res))
+            })
             // Retrieve the full API model; not all data is writable, so we only support GET.
             .route("/", web::get().to(get_model))
             .service(
                 web::scope("/settings")
                     .route("", web::get().to(get_settings))
-                    .route("", web::patch().to(patch_settings)),
+                    .route("", web::patch().to(patch_settings))
+                    .route("/export", web::get().to(get_exported_settings)),
             )
             .service(
                 // Transaction support
                 web::scope("/tx")
                     .route("/list", web::get().to(get_transaction_list))
+                    .route("/plan", web::get().to(plan_transaction))
+                    .route("/history", web::get().to(get_transaction_history))
+                    .route("/revert", web::post().to(revert_transaction))
                     .route("", web::get().to(get_transaction))
                     .route("", web::delete().to(delete_transaction))
                     .route("/commit", web::post().to(commit_transaction))
@@ -99,11 +154,17 @@ where
                         web::post().to(commit_transaction_and_apply),
                     ),
             )
-            .service(web::scope("/os").route("", web::get().to(get_os_info)))
+            .service(
+                web::scope("/os")
+                    .route("", web::get().to(get_os_info))
+                    .route("/crash-report", web::get().to(get_crash_report)),
+            )
             .service(
                 web::scope("/metadata")
                     .route("/affected-services", web::get().to(get_affected_services))
                     .route("/setting-generators", web::get().to(get_setting_generators))
+                    .route("/sensitive", web::get().to(get_sensitive_settings))
+                    .route("/sensitive", web::post().to(set_sensitive_settings))
                     .route("/templates", web::get().to(get_templates)),
             )
             .service(web::scope("/services").route("", web::get().to(get_services)))
@@ -121,9 +182,17 @@ where
             )
             .service(web::scope("/updates").route("/status", web::get().to(get_update_status)))
             .service(web::resource("/exec").route(web::get().to(exec::ws_exec)))
+            .service(web::resource("/watch").route(web::get().to(watch::ws_watch)))
+            .service(web::resource("/audit").route(web::get().to(get_audit_log)))
+            .service(web::resource("/schema").route(web::get().to(get_schema)))
+            .service(web::scope("/kernel").route("/drift", web::get().to(get_kernel_drift)))
+            .service(web::resource("/containers").route(web::get().to(get_containers)))
+            .service(web::resource("/metrics").route(web::get().to(get_metrics)))
     })
+    // Read the credentials of each caller when it connects, for the access policy.
+    .on_connect(policy::Caller::on_connect)
     .workers(threads)
-    .bind_uds(socket_path.as_ref())
+    .listen_uds(bind_socket(socket_path.as_ref())?)
     .context(error::BindSocketSnafu {
         path: socket_path.as_ref(),
     })?;
@@ -155,6 +224,7 @@ where
 /// BottlerocketRelease whose fields aren't optional.  (Its other users depend on those fields.)
 async fn get_model(
     query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
     data: web::Data<SharedData>,
 ) -> Result<ModelResponse> {
     // When we query settings, services, etc., we query differently if the user gave a prefix - it
@@ -163,13 +233,14 @@ async fn get_model(
     // whereas without prefix matching, we should always have some data to return.  The logic is
     // fairly different, so we branch early.
     if let Some(prefix) = query.get("prefix") {
-        return get_model_prefix(data, prefix).await;
+        return get_model_prefix(data, &access, prefix).await;
     }
 
     let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
 
     // Fetch all the data and build a Model.
-    let settings = Some(controller::get_settings(&*datastore, &Committed::Live)?);
+    let settings =
+        Some(access.filter_settings(controller::get_settings(&*datastore, &Committed::Live)?)?);
     let services = Some(controller::get_services(&*datastore)?);
     let configuration_files = Some(controller::get_configuration_files(&*datastore)?);
     let os = Some(controller::get_os_info()?);
@@ -188,7 +259,11 @@ async fn get_model(
 }
 
 /// Helper for get_model that handles the case of matching a user-specified prefix.
-async fn get_model_prefix(data: web::Data<SharedData>, prefix: &str) -> Result<ModelResponse> {
+async fn get_model_prefix(
+    data: web::Data<SharedData>,
+    access: &Access,
+    prefix: &str,
+) -> Result<ModelResponse> {
     if prefix.is_empty() {
         return error::EmptyInputSnafu { input: "prefix" }.fail();
     }
@@ -199,7 +274,9 @@ async fn get_model_prefix(data: web::Data<SharedData>, prefix: &str) -> Result<M
     // Note that we don't add a prefix (for example "settings.") to the given prefix before passing
     // it to _prefix methods, like we do in get_settings, because here we're fetching the whole
     // model, not just settings.
-    let settings = controller::get_settings_prefix(&*datastore, prefix, &Committed::Live)?;
+    let settings = controller::get_settings_prefix(&*datastore, prefix, &Committed::Live)?
+        .map(|settings| access.filter_settings(settings))
+        .transpose()?;
     let services = controller::get_services_prefix(&*datastore, prefix)?;
     let configuration_files = controller::get_configuration_files_prefix(&*datastore, prefix)?;
 
@@ -240,9 +317,13 @@ async fn get_model_prefix(data: web::Data<SharedData>, prefix: &str) -> Result<M
 /// parameters, return the subset of matching settings.
 async fn get_settings(
     query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
     data: web::Data<SharedData>,
-) -> Result<SettingsResponse> {
+) -> Result<GenerationSettingsResponse> {
     let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+    let generation = datastore
+        .generation()
+        .context(error::DataStoreSnafu { op: "generation" })?;
 
     let settings = if let Some(keys_str) = query.get("keys") {
         let keys = comma_separated("keys", keys_str)?;
@@ -262,22 +343,46 @@ async fn get_settings(
     } else {
         controller::get_settings(&*datastore, &Committed::Live)
     }?;
+    let settings = access.filter_settings(settings)?;
 
-    Ok(SettingsResponse(settings))
+    Ok(GenerationSettingsResponse(settings, generation))
 }
 
-/// Apply the requested settings to the pending data store
+/// Apply the requested settings to the pending data store.  If the request has an If-Match header
+/// with an ETag from GET /settings, the write is rejected if any of the given settings changed
+/// since.  The generation is kept with the transaction and checked again when it's committed, so
+/// a commit from another client in between isn't overwritten.
 async fn patch_settings(
+    req: HttpRequest,
     settings: web::Json<Settings>,
     query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
     data: web::Data<SharedData>,
 ) -> Result<HttpResponse> {
+    access.check_write(&settings)?;
     let transaction = transaction_name(&query);
+    let expected_generation = if_match_generation(&req)?;
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
-    controller::set_settings(&mut *datastore, &settings, transaction)?;
+    controller::set_settings(&mut *datastore, &settings, transaction, expected_generation)?;
+    if let Some(generation) = expected_generation {
+        data.expect_generation(transaction, generation);
+    }
+    audit::note_changes(&req, transaction, controller::settings_values(&settings)?);
     Ok(HttpResponse::NoContent().finish()) // 204
 }
 
+/// Return the live settings that were set by the user, leaving out settings that are generated,
+/// added by migrations, or have their default values, so they can be applied to make another host
+/// match.
+async fn get_exported_settings(
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<SettingsResponse> {
+    let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+    let settings = controller::get_user_settings(&*datastore)?;
+    Ok(SettingsResponse(access.filter_settings(settings)?))
+}
+
 async fn get_transaction_list(data: web::Data<SharedData>) -> Result<TransactionListResponse> {
     let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
     let data = controller::list_transactions(&*datastore)?;
@@ -287,40 +392,122 @@ async fn get_transaction_list(data: web::Data<SharedData>) -> Result<Transaction
 /// Get any pending settings in the given transaction, or the "default" transaction if unspecified.
 async fn get_transaction(
     query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
     data: web::Data<SharedData>,
 ) -> Result<SettingsResponse> {
     let transaction = transaction_name(&query);
     let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
     let data = controller::get_transaction(&*datastore, transaction)?;
-    Ok(SettingsResponse(data))
+    Ok(SettingsResponse(access.filter_settings(data)?))
 }
 
 /// Delete the given transaction, or the "default" transaction if unspecified.
 async fn delete_transaction(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
     let deleted = controller::delete_transaction(&mut *datastore, transaction)?;
+    data.forget_expected_generation(transaction);
+    let keys = deleted
+        .iter()
+        .map(|k| (k.name().to_string(), None))
+        .collect();
+    audit::note_changes(&req, transaction, keys);
     Ok(ChangedKeysResponse(deleted))
 }
 
 /// Save settings changes from the given transaction, or the "default" transaction if unspecified,
 /// to the live data store.  Returns the list of changed keys.
 async fn commit_transaction(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
 
-    let changes = controller::commit_transaction(&mut *datastore, transaction)?;
+    let changes = commit_with_prune(&data, &mut *datastore, transaction, &query, &access)?;
 
     if changes.is_empty() {
         return error::CommitWithNoPendingSnafu.fail();
     }
 
+    note_committed(&req, &*datastore, transaction, &changes);
+    data.metrics.record_commit(changes.len());
+    notify_watchers(&data, &*datastore, transaction, &changes);
+
+    Ok(ChangedKeysResponse(changes))
+}
+
+/// Describes what committing and applying the given transaction would do, without changing
+/// anything: a unified diff of each configuration file that would change, and the restart commands
+/// of each affected service.  Configuration files can contain any setting, so only callers that
+/// can read all settings may see a plan.
+async fn plan_transaction(
+    query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
+) -> Result<PlanResponse> {
+    access.check_read_all("plan a transaction")?;
+    let transaction = transaction_name(&query).to_string();
+    // The config applier asks us about the transaction while we wait for it, so we wait on a
+    // blocking thread rather than blocking the server from answering.
+    let plan = web::block(move || controller::plan_transaction(&transaction))
+        .await
+        .context(error::ConfigApplierBlockingSnafu)??;
+    Ok(PlanResponse(plan))
+}
+
+/// Returns the history of committed transactions, oldest first, leaving out changes to settings
+/// the caller can't read.
+async fn get_transaction_history(
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<HistoryResponse> {
+    let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+    let mut history = controller::get_history(&*datastore)?;
+    if !access.can_read_all() {
+        for record in history.iter_mut() {
+            record.changes.retain(|key, _change| access.can_read(key));
+        }
+    }
+    Ok(HistoryResponse(history))
+}
+
+/// Restores settings to their state before the transaction with the given history 'id' was
+/// committed, then applies the changes, as with commit_and_apply.  Returns the list of changed
+/// keys.  Any setting could be changed, so only callers that can change all settings may revert.
+async fn revert_transaction(
+    req: HttpRequest,
+    query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<ChangedKeysResponse> {
+    access.check_write_all("revert")?;
+    let id_str = query
+        .get("id")
+        .context(error::MissingInputSnafu { input: "id" })?;
+    let id = id_str.parse().ok().context(error::InvalidInputSnafu {
+        input: "id",
+        value: id_str,
+    })?;
+    let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
+
+    let changes = controller::revert_transaction(&mut *datastore, id)?;
+
+    let transaction = format!("revert-{}", id);
+    note_committed(&req, &*datastore, &transaction, &changes);
+    if !changes.is_empty() {
+        data.metrics.record_commit(changes.len());
+        notify_watchers(&data, &*datastore, &transaction, &changes);
+
+        let key_names = changes.iter().map(|k| k.name()).collect();
+        controller::apply_changes(Some(&key_names))?;
+    }
+
     Ok(ChangedKeysResponse(changes))
 }
 
@@ -341,18 +528,24 @@ async fn apply_changes(query: web::Query<HashMap<String, String>>) -> Result<Htt
 /// perform both a commit and an apply.  Commits the given transaction, or the "default"
 /// transaction if unspecified.
 async fn commit_transaction_and_apply(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
 
-    let changes = controller::commit_transaction(&mut *datastore, transaction)?;
+    let changes = commit_with_prune(&data, &mut *datastore, transaction, &query, &access)?;
 
     if changes.is_empty() {
         return error::CommitWithNoPendingSnafu.fail();
     }
 
+    note_committed(&req, &*datastore, transaction, &changes);
+    data.metrics.record_commit(changes.len());
+    notify_watchers(&data, &*datastore, transaction, &changes);
+
     let key_names = changes.iter().map(|k| k.name()).collect();
     controller::apply_changes(Some(&key_names))?;
 
@@ -384,6 +577,19 @@ async fn get_os_info(
     Ok(BottlerocketReleaseResponse(os))
 }
 
+/// Returns the report of the latest kernel crash, written by prairiedog after the host rebooted
+/// from the crash.
+async fn get_crash_report() -> Result<CrashReportResponse> {
+    let path = constants::KERNEL_CRASH_REPORT;
+    let report = match fs::read(path) {
+        Ok(report) => report,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return error::NoCrashReportSnafu.fail(),
+        Err(e) => return Err(e).context(error::CrashReportReadSnafu { path }),
+    };
+    let report = serde_json::from_slice(&report).context(error::CrashReportParseSnafu { path })?;
+    Ok(CrashReportResponse(report))
+}
+
 /// Get the affected services for a list of data keys
 async fn get_affected_services(
     query: web::Query<HashMap<String, String>>,
@@ -408,6 +614,26 @@ async fn get_setting_generators(data: web::Data<SharedData>) -> Result<MetadataR
     Ok(MetadataResponse(resp))
 }
 
+/// Get all settings that are marked sensitive, like settings decrypted from user data
+async fn get_sensitive_settings(data: web::Data<SharedData>) -> Result<MetadataResponse> {
+    let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+    let resp = controller::get_sensitive(&*datastore)?;
+    Ok(MetadataResponse(resp))
+}
+
+/// Mark the settings named in the request body, a JSON list, as sensitive.  Clients like apiclient
+/// and logdog redact the values of sensitive settings by default.
+async fn set_sensitive_settings(
+    keys: web::Json<Vec<String>>,
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<HttpResponse> {
+    access.check_write_names(keys.iter())?;
+    let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
+    controller::set_sensitive(&mut *datastore, &keys)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 /// Get the template metadata for a list of data keys
 async fn get_templates(
     query: web::Query<HashMap<String, String>>,
@@ -485,14 +711,31 @@ async fn get_configuration_files(
 }
 
 /// Get the update status from 'thar-be-updates'
-async fn get_update_status() -> Result<UpdateStatusResponse> {
+async fn get_update_status(data: web::Data<SharedData>) -> Result<UpdateStatusResponse> {
     let lockfile = File::create(UPDATE_LOCKFILE).context(error::UpdateLockOpenSnafu)?;
     lockfile
         .try_lock_shared()
         .context(error::UpdateShareLockSnafu)?;
     let result = thar_be_updates::status::get_update_status(&lockfile);
     match result {
-        Ok(update_status) => Ok(UpdateStatusResponse(update_status)),
+        Ok(mut update_status) => {
+            // updog records its progress as it writes an update, so it's read here to show how far
+            // a 'prepare' that's still running has gotten.
+            if let Err(e) = update_status.update_staging_progress() {
+                warn!("Unable to get update progress: {}", e);
+            }
+            // The next maintenance window depends on the current time, so it's computed here
+            // rather than when 'thar-be-updates' last ran.
+            let maintenance = {
+                let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+                controller::get_update_maintenance(&*datastore)?
+            };
+            match maintenance_windows(maintenance.as_ref()) {
+                Ok(windows) => update_status.update_maintenance_window(&windows),
+                Err(e) => warn!("Unable to determine maintenance windows: {}", e),
+            }
+            Ok(UpdateStatusResponse(update_status))
+        }
         Err(e) => match e {
             thar_be_updates::error::Error::NoStatusFile { .. } => {
                 error::UninitializedUpdateStatusSnafu.fail()
@@ -502,6 +745,128 @@ async fn get_update_status() -> Result<UpdateStatusResponse> {
     }
 }
 
+/// Returns a JSON Schema document describing this variant's settings, including the validation
+/// constraints of each setting's type, so clients can check input before sending it.
+async fn get_schema() -> SchemaResponse {
+    SchemaResponse(model::schema::settings())
+}
+
+/// Compares the kernel settings in effect against the live settings, reporting any that have
+/// drifted, for example a sysctl changed by a privileged container.  This only reports; `corndog
+/// verify --restore` restores the configured values.  Only settings the caller can read are
+/// compared, so the report doesn't reveal hidden values.
+async fn get_kernel_drift(
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<KernelDriftResponse> {
+    let settings = {
+        let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+        controller::get_settings_prefix(&*datastore, "settings.kernel", &Committed::Live)?
+    };
+    let settings = match settings {
+        Some(settings) => Some(access.filter_settings(settings)?),
+        None => None,
+    };
+    let report = match settings.and_then(|settings| settings.kernel) {
+        Some(kernel) => {
+            corndog::drift::check_under(&kernel, &data.sysctl_path_prefix, &data.lockdown_path)
+        }
+        None => DriftReport::default(),
+    };
+    Ok(KernelDriftResponse(report))
+}
+
+/// Returns the state of each configured host container and bootstrap container, leaving out
+/// containers whose settings the caller can't read.
+async fn get_containers(
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<ContainersResponse> {
+    let mut host_containers = HashMap::new();
+    let mut bootstrap_containers = HashMap::new();
+    {
+        let datastore = data.ds.read().ok().context(error::DataStoreLockSnafu)?;
+        for prefix in ["settings.host-containers", "settings.bootstrap-containers"] {
+            let settings =
+                match controller::get_settings_prefix(&*datastore, prefix, &Committed::Live)? {
+                    Some(settings) => access.filter_settings(settings)?,
+                    None => continue,
+                };
+            host_containers.extend(settings.host_containers.unwrap_or_default());
+            bootstrap_containers.extend(settings.bootstrap_containers.unwrap_or_default());
+        }
+    }
+    // Asking systemd and host-ctr about each container runs commands, so we wait on a blocking
+    // thread rather than blocking the server from answering.
+    let statuses = web::block(move || {
+        host_containers::status::statuses(&host_containers, &bootstrap_containers)
+    })
+    .await
+    .context(error::ContainerStatusBlockingSnafu)?
+    .context(error::ContainerStatusSnafu)?;
+    Ok(ContainersResponse(statuses))
+}
+
+/// Returns the number of requests the server has handled, by route and response status, how long
+/// they took, and the number of commits to the data store, since the server started.  metricdog
+/// exports these as metrics.
+async fn get_metrics(data: web::Data<SharedData>) -> MetricsResponse {
+    MetricsResponse(data.metrics.snapshot())
+}
+
+/// Returns entries from the audit log, oldest first.  Entries can be filtered by time with 'since',
+/// an RFC 3339 timestamp; by transaction with 'tx'; by affected settings with 'key', a settings
+/// prefix; and to the most recent entries with 'limit'.
+async fn get_audit_log(
+    query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
+    data: web::Data<SharedData>,
+) -> Result<AuditResponse> {
+    let since = query
+        .get("since")
+        .map(|since_str| {
+            DateTime::parse_from_rfc3339(since_str)
+                .map(|since| since.with_timezone(&Utc))
+                .ok()
+                .context(error::InvalidInputSnafu {
+                    input: "since",
+                    value: since_str,
+                })
+        })
+        .transpose()?;
+    let limit = query
+        .get("limit")
+        .map(|limit_str| {
+            limit_str.parse().ok().context(error::InvalidInputSnafu {
+                input: "limit",
+                value: limit_str,
+            })
+        })
+        .transpose()?;
+    // As elsewhere, the settings prefix is implied, so we add it if it wasn't given.
+    let key = query.get("key").map(|key| {
+        if key.starts_with("settings") {
+            key.to_string()
+        } else {
+            format!("settings.{}", key)
+        }
+    });
+
+    let audit_query = audit::AuditQuery {
+        since,
+        transaction: query.get("tx").cloned(),
+        key,
+        limit,
+    };
+    // The audit logs can be large, so we read them on a blocking thread rather than blocking the
+    // server from answering.
+    let access = access.into_inner();
+    let entries = web::block(move || data.audit.read(&audit_query, &access))
+        .await
+        .context(error::AuditReadBlockingSnafu)??;
+    Ok(AuditResponse(entries))
+}
+
 /// Refreshes the list of updates and checks if an update is available matching the configured version lock
 async fn refresh_updates() -> Result<HttpResponse> {
     controller::dispatch_update_command(&["refresh"])
@@ -522,7 +887,8 @@ async fn deactivate_update() -> Result<HttpResponse> {
     controller::dispatch_update_command(&["deactivate"])
 }
 
-/// Reboots the machine
+/// Reboots the machine.  Maintenance windows only limit when updates are activated, so an update
+/// is only applied by a reboot if it was activated in a window.
 async fn reboot() -> Result<HttpResponse> {
     debug!("Rebooting now");
     let output = Command::new("/sbin/shutdown")
@@ -554,6 +920,112 @@ fn comma_separated<'a>(key_name: &'static str, input: &'a str) -> Result<HashSet
     Ok(input.split(',').collect())
 }
 
+/// Tells any clients watching for settings changes about the keys changed in a commit.  Keys that
+/// no longer have a value, for example after a revert or prune, are sent as removed.  The commit
+/// has already succeeded by the time this is called, so failures are logged rather than returned.
+fn notify_watchers<D: DataStore>(
+    data: &SharedData,
+    datastore: &D,
+    transaction: &str,
+    changes: &HashSet<Key>,
+) {
+    if data.watchers.is_empty() {
+        return;
+    }
+    match watch::settings_event(datastore, transaction, changes) {
+        Ok(event) => data.watchers.notify(event),
+        Err(e) => warn!("Unable to notify watchers of changed settings: {}", e),
+    }
+}
+
+/// Records the keys changed by a commit, and their new values, for the request's audit log entry.
+/// The commit has already succeeded by the time this is called, so failures to read the values
+/// are logged, and the keys are recorded without them.
+fn note_committed<D: DataStore>(
+    req: &HttpRequest,
+    datastore: &D,
+    transaction: &str,
+    changes: &HashSet<Key>,
+) {
+    let mut values =
+        controller::get_values(datastore, changes, &Committed::Live).unwrap_or_else(|e| {
+            warn!("Unable to read changed settings for audit log: {}", e);
+            BTreeMap::new()
+        });
+    let keys = changes
+        .iter()
+        .map(|key| (key.name().to_string(), values.remove(key.name())))
+        .collect();
+    audit::note_changes(req, transaction, keys);
+}
+
+/// Returns the datastore generation given in the request's If-Match header, if any.  We only
+/// accept a single strong ETag, as returned by GET /settings; "*" matches any generation, so it's
+/// treated the same as no header.
+fn if_match_generation(req: &HttpRequest) -> Result<Option<u64>> {
+    let value = match req.headers().get(header::IF_MATCH) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let etag = value
+        .to_str()
+        .ok()
+        .context(error::InvalidInputSnafu {
+            input: "If-Match",
+            value: String::from_utf8_lossy(value.as_bytes()),
+        })?
+        .trim();
+    if etag == "*" {
+        return Ok(None);
+    }
+
+    etag.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .and_then(|s| s.parse().ok())
+        .map(Some)
+        .context(error::InvalidInputSnafu {
+            input: "If-Match",
+            value: etag,
+        })
+}
+
+/// Commits the given transaction.  If the 'prune' query parameter is true, user-set settings that
+/// aren't in the transaction are pruned in the same commit; see controller::prune_transaction.
+/// Returns the changed keys.  Pruning can remove any setting, so only callers that can change all
+/// settings may prune.  If settings were set in the transaction with If-Match, the commit is
+/// rejected if they've changed since.
+fn commit_with_prune<D: DataStore>(
+    data: &SharedData,
+    datastore: &mut D,
+    transaction: &str,
+    query: &web::Query<HashMap<String, String>>,
+    access: &Access,
+) -> Result<HashSet<Key>> {
+    let prune = match query.get("prune") {
+        Some(prune_str) => prune_str.parse().ok().context(error::InvalidInputSnafu {
+            input: "prune",
+            value: prune_str,
+        })?,
+        None => false,
+    };
+
+    let expected_generation = data.expected_generation(transaction);
+    let changes = if prune {
+        access.check_write_all("prune")?;
+        let remove = controller::prune_transaction(datastore, transaction)?;
+        controller::commit_transaction_removing(
+            datastore,
+            transaction,
+            expected_generation,
+            &remove,
+        )?
+    } else {
+        controller::commit_transaction(datastore, transaction, expected_generation)?
+    };
+    data.forget_expected_generation(transaction);
+    Ok(changes)
+}
+
 fn transaction_name(query: &web::Query<HashMap<String, String>>) -> &str {
     if let Some(name_str) = query.get("tx") {
         name_str
@@ -571,17 +1043,24 @@ impl ResponseError for error::Error {
             // 400 Bad Request
             MissingInput { .. } => StatusCode::BAD_REQUEST,
             EmptyInput { .. } => StatusCode::BAD_REQUEST,
+            InvalidInput { .. } => StatusCode::BAD_REQUEST,
             NewKey { .. } => StatusCode::BAD_REQUEST,
 
+            // 403 Forbidden
+            Forbidden { .. } => StatusCode::FORBIDDEN,
+
             // 404 Not Found
             MissingData { .. } => StatusCode::NOT_FOUND,
             ListKeys { .. } => StatusCode::NOT_FOUND,
             UpdateDoesNotExist { .. } => StatusCode::NOT_FOUND,
             NoStagedImage { .. } => StatusCode::NOT_FOUND,
             UninitializedUpdateStatus { .. } => StatusCode::NOT_FOUND,
+            HistoryEntryNotFound { .. } => StatusCode::NOT_FOUND,
+            NoCrashReport => StatusCode::NOT_FOUND,
 
             // 422 Unprocessable Entity
             CommitWithNoPending => StatusCode::UNPROCESSABLE_ENTITY,
+            BootstrapContainerCycle { .. } => StatusCode::UNPROCESSABLE_ENTITY,
 
             // 423 Locked
             UpdateShareLock { .. } => StatusCode::LOCKED,
@@ -589,6 +1068,11 @@ impl ResponseError for error::Error {
 
             // 409 Conflict
             DisallowCommand { .. } => StatusCode::CONFLICT,
+            OutsideMaintenanceWindow => StatusCode::CONFLICT,
+            HistoryEntryRedacted { .. } => StatusCode::CONFLICT,
+
+            // 412 Precondition Failed
+            PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
 
             // 500 Internal Server Error
             DataStoreLock => StatusCode::INTERNAL_SERVER_ERROR,
@@ -601,16 +1085,29 @@ impl ResponseError for error::Error {
             DataStoreSerialization { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             CommandSerialization { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             InvalidMetadata { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            InvalidValue { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierFork { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierStart { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierStdin {} => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierWait { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierWrite { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ConfigApplierPlan { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ConfigApplierPlanOutput { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ConfigApplierBlocking { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ContainerStatus { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ContainerStatusBlocking { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditReadBlocking { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             SystemdNotify { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             SystemdNotifyStatus {} => StatusCode::INTERNAL_SERVER_ERROR,
             SetPermissions { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             SetGroup { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ReleaseData { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            CrashReportRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            CrashReportParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            PolicyRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            PolicyParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            PolicyRole { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Shutdown { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Reboot { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateDispatcher { .. } => StatusCode::INTERNAL_SERVER_ERROR,
@@ -629,6 +1126,62 @@ impl ResponseError for error::Error {
 pub(crate) struct SharedData {
     ds: sync::RwLock<FilesystemDataStore>,
     exec_socket_path: PathBuf,
+    watchers: watch::Watchers,
+    policy: policy::PolicyFile,
+    audit: Arc<audit::AuditLog>,
+    metrics: metrics::Metrics,
+    /// The datastore generation each pending transaction's settings were based on, from If-Match
+    /// headers, so commits can be checked; see commit_with_prune.
+    expected_generations: sync::Mutex<HashMap<String, u64>>,
+    /// Where the kernel's sysctls and lockdown mode are read, for drift reports.
+    sysctl_path_prefix: PathBuf,
+    lockdown_path: PathBuf,
+}
+
+impl SharedData {
+    /// Shared data for tests, keeping the datastore, policy, audit log, and kernel settings in the
+    /// given directory.
+    #[cfg(test)]
+    pub(crate) fn for_test(dir: &Path) -> Self {
+        Self {
+            ds: sync::RwLock::new(FilesystemDataStore::new(dir.join("datastore"))),
+            exec_socket_path: dir.join("exec.sock"),
+            watchers: watch::Watchers::default(),
+            policy: policy::PolicyFile::new(dir.join("policy.toml")),
+            audit: Arc::new(audit::AuditLog::new(dir.join("audit.log"))),
+            metrics: metrics::Metrics::default(),
+            expected_generations: sync::Mutex::new(HashMap::new()),
+            sysctl_path_prefix: dir.join("sys"),
+            lockdown_path: dir.join("lockdown"),
+        }
+    }
+
+    /// Records that settings in the transaction were based on the given generation.  If settings
+    /// were set based on several generations, we keep the oldest, to check all of them.
+    fn expect_generation(&self, transaction: &str, generation: u64) {
+        self.lock_expected_generations()
+            .entry(transaction.to_string())
+            .and_modify(|expected| *expected = (*expected).min(generation))
+            .or_insert(generation);
+    }
+
+    /// Returns the generation the transaction's settings were based on, if any.
+    fn expected_generation(&self, transaction: &str) -> Option<u64> {
+        self.lock_expected_generations().get(transaction).copied()
+    }
+
+    /// Forgets the generation of a transaction that was committed or deleted.
+    fn forget_expected_generation(&self, transaction: &str) {
+        self.lock_expected_generations().remove(transaction);
+    }
+
+    // The map is only ever updated whole, so a panic can't leave it in a bad state; we ignore
+    // poisoning.
+    fn lock_expected_generations(&self) -> sync::MutexGuard<'_, HashMap<String, u64>> {
+        self.expected_generations
+            .lock()
+            .unwrap_or_else(sync::PoisonError::into_inner)
+    }
 }
 
 /// Helper macro for implementing the actix-web Responder trait for a type.
@@ -668,6 +1221,29 @@ impl_responder_for!(ModelResponse, self, self.0);
 struct SettingsResponse(Settings);
 impl_responder_for!(SettingsResponse, self, self.0);
 
+/// This lets us respond with live Settings along with the datastore generation they were read at,
+/// as an ETag header, so clients can send it back in If-Match to make conditional updates.
+struct GenerationSettingsResponse(Settings, u64);
+impl Responder for GenerationSettingsResponse {
+    type Body = BoxBody;
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        let mut response = SettingsResponse(self.0).respond_to(req);
+        if response.status().is_success() {
+            let etag = header::EntityTag::new_strong(self.1.to_string());
+            match etag.to_string().parse() {
+                Ok(value) => {
+                    response.headers_mut().insert(header::ETAG, value);
+                }
+                Err(e) => warn!(
+                    "Unable to build ETag header for generation {}: {}",
+                    self.1, e
+                ),
+            }
+        }
+        response
+    }
+}
+
 /// This lets us respond from our handler methods with a release (or Result<release>), where
 /// "release" is a serde_json::Value corresponding to the BottlerocketRelease struct.
 ///
@@ -702,3 +1278,114 @@ impl_responder_for!(ChangedKeysResponse, self, self.0);
 
 struct TransactionListResponse(HashSet<String>);
 impl_responder_for!(TransactionListResponse, self, self.0);
+
+/// This lets us respond from our handler methods with the config applier's plan for a transaction
+struct PlanResponse(Value);
+impl_responder_for!(PlanResponse, self, self.0);
+
+/// This lets us respond from our handler methods with the transaction history
+struct HistoryResponse(Vec<controller::HistoryRecord>);
+impl_responder_for!(HistoryResponse, self, self.0);
+
+/// This lets us respond from our handler methods with audit log entries
+struct AuditResponse(Vec<Value>);
+impl_responder_for!(AuditResponse, self, self.0);
+
+/// This lets us respond from our handler methods with the settings schema
+struct SchemaResponse(Value);
+impl_responder_for!(SchemaResponse, self, self.0);
+
+/// This lets us respond from our handler methods with a kernel crash report
+struct CrashReportResponse(Value);
+impl_responder_for!(CrashReportResponse, self, self.0);
+
+/// This lets us respond from our handler methods with a kernel settings drift report
+struct KernelDriftResponse(DriftReport);
+impl_responder_for!(KernelDriftResponse, self, self.0);
+
+/// This lets us respond from our handler methods with the state of each container
+struct ContainersResponse(Vec<host_containers::status::ContainerStatus>);
+impl_responder_for!(ContainersResponse, self, self.0);
+
+/// This lets us respond from our handler methods with the server's request and commit totals
+struct MetricsResponse(metrics::MetricsSnapshot);
+impl_responder_for!(MetricsResponse, self, self.0);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test;
+    use maplit::hashmap;
+    use model::modeled_types::{Lockdown, SysctlKey};
+    use model::KernelSettings;
+    use std::convert::TryFrom;
+
+    /// Requests the drift report through the access policy, as a caller we know nothing about.
+    async fn kernel_drift(data: web::Data<SharedData>) -> DriftReport {
+        let app = test::init_service(
+            App::new()
+                .app_data(data)
+                .wrap_fn(|req, srv| match policy::authorize(&req) {
+                    Ok(()) => Either::Left(srv.call(req)),
+                    Err(denied) => Either::Right(ready(Ok(denied))),
+                })
+                .route("/kernel/drift", web::get().to(get_kernel_drift)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/kernel/drift").to_request();
+        test::call_and_read_body_json(&app, req).await
+    }
+
+    #[actix_rt::test]
+    async fn kernel_drift_is_filtered() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::create_dir_all(dir.join("datastore/live")).unwrap();
+        fs::create_dir_all(dir.join("sys/vm")).unwrap();
+        fs::write(dir.join("sys/vm/swappiness"), "60\n").unwrap();
+        fs::write(dir.join("lockdown"), "[none] integrity confidentiality\n").unwrap();
+
+        let data = web::Data::new(SharedData::for_test(dir));
+        let settings = Settings {
+            kernel: Some(KernelSettings {
+                sysctl: Some(hashmap! {
+                    SysctlKey::try_from("vm.swappiness").unwrap() => "10".to_string(),
+                }),
+                lockdown: Some(Lockdown::try_from("integrity").unwrap()),
+                modules: None,
+            }),
+            ..Default::default()
+        };
+        {
+            let mut datastore = data.ds.write().unwrap();
+            controller::set_settings(&mut *datastore, &settings, "tx", None).unwrap();
+            controller::commit_transaction(&mut *datastore, "tx", None).unwrap();
+        }
+
+        // Without a policy, every setting is compared.
+        let report = kernel_drift(data.clone()).await;
+        assert_eq!(report.checked, 2);
+        let drift: Vec<_> = report
+            .drift
+            .iter()
+            .map(|drift| (drift.name.as_str(), drift.actual.as_str()))
+            .collect();
+        assert_eq!(drift, [("vm.swappiness", "60"), ("lockdown", "none")]);
+
+        // A caller that can't read the lockdown setting doesn't learn about it.
+        fs::write(
+            dir.join("policy.toml"),
+            r#"
+                default-role = "reader"
+
+                [roles.reader]
+                hidden-settings = ["kernel.lockdown"]
+            "#,
+        )
+        .unwrap();
+        let report = kernel_drift(data).await;
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.drift.len(), 1);
+        assert_eq!(report.drift[0].name, "vm.swappiness");
+    }
+}
@@ -0,0 +1,787 @@
+//! The 'policy' module decides what each caller of the API is allowed to do.
+//!
+//! When a client connects to the API socket, we read its credentials (UID, GID, and PID) from the
+//! socket with SO_PEERCRED, and use the PID to find the host container it's running in, if any.
+//! A policy file maps callers to roles, and each role lists the routes, HTTP methods, and settings
+//! its callers may use.
+//!
+//! An example policy:
+//!
+//! ```toml
+//! default-role = "reader"
+//!
+//! [roles.reader]
+//! routes = ["/settings", "/os", "/updates/status"]
+//! methods = ["GET"]
+//! hidden-settings = ["settings.kubernetes.bootstrap-token"]
+//!
+//! [roles.admin]
+//!
+//! [[callers]]
+//! container = "admin"
+//! role = "admin"
+//! ```
+//!
+//! A role field that isn't given doesn't restrict anything, so the "admin" role above allows
+//! everything.  Callers are matched in order against the `uid`, `gid`, and `container` given in
+//! each `[[callers]]` entry, and the first match decides the role; callers that match no entry get
+//! the `default-role`, or are denied if there isn't one.
+//!
+//! Processes run as root by systemd services on the host, rather than in a container, always
+//! have full access; the system services that configure the host depend on it.  Callers we can't
+//! place, for example in Kubernetes pods, only get the `default-role`, since `[[callers]]` entries
+//! are written with host processes and host containers in mind.  If there's no policy file, every
+//! caller has full access, and if the policy file is invalid, only the host root processes do.
+//!
+//! Requests to `/exec` use GET to open a WebSocket, but they run commands in host containers, so
+//! they're treated as POST requests when checking a role's `methods`; a read-only role can't
+//! use them.
+
+use super::error::{self, Result};
+use super::SharedData;
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::{http::Method, web, HttpMessage, ResponseError};
+use datastore::deserialization::from_map;
+use datastore::serialization::to_pairs;
+use datastore::Key;
+use log::{debug, error, warn};
+use model::Settings;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{self, Arc};
+use std::time::SystemTime;
+
+/// The route for running commands in host containers, which is checked as a POST request whatever
+/// its method; see Access::allows_route.
+const EXEC_ROUTE: &str = "/exec";
+
+/// The identity of the process on the other end of an API connection.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Caller {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) pid: Option<i32>,
+    /// The name of the host container the caller is running in, if any.
+    pub(crate) container: Option<String>,
+    /// Whether the caller is a host process run by a systemd service, outside any container.
+    pub(crate) host: bool,
+}
+
+impl Caller {
+    /// Called by actix for each new connection; reads the peer's credentials from the socket and
+    /// stores them as connection data, so the request middleware can find them.
+    pub(crate) fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+        let stream = match connection.downcast_ref::<actix_web::rt::net::UnixStream>() {
+            Some(stream) => stream,
+            None => return,
+        };
+        match stream.peer_cred() {
+            Ok(cred) => {
+                let origin = cred.pid().map_or(Origin::Unknown, origin_of);
+                let caller = Caller {
+                    uid: cred.uid(),
+                    gid: cred.gid(),
+                    pid: cred.pid(),
+                    host: origin == Origin::Host,
+                    container: match origin {
+                        Origin::Container(name) => Some(name),
+                        Origin::Host | Origin::Unknown => None,
+                    },
+                };
+                debug!("Accepted connection from {:?}", caller);
+                data.insert(caller);
+            }
+            Err(e) => warn!("Unable to read credentials of API client: {}", e),
+        }
+    }
+
+    /// Host processes running as root aren't subject to the policy.
+    fn is_host_root(&self) -> bool {
+        self.uid == 0 && self.host
+    }
+
+    /// Whether we know where the caller is running, so it can be matched against the policy's
+    /// `[[callers]]` entries.
+    fn is_known(&self) -> bool {
+        self.host || self.container.is_some()
+    }
+}
+
+/// Where a caller is running, as found from its cgroups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    /// A host process, run by a systemd service.
+    Host,
+    /// A process in the host container with the given name.
+    Container(String),
+    /// Anywhere else, like a Kubernetes pod, or a process we couldn't check.
+    Unknown,
+}
+
+/// Returns where the given process is running.  Host containers are run by containerd with the
+/// cgroupfs driver, which puts each container in a cgroup named `/NAMESPACE/ID`, and the ID of a
+/// host container is its name.  Host processes are in the cgroup systemd made for their service,
+/// `/system.slice/UNIT.service`.  Anything else, like the `kubepods` cgroups of Kubernetes pods,
+/// is unknown, so it gets the least access.
+fn origin_of(pid: i32) -> Origin {
+    let cgroups = match fs::read_to_string(format!("/proc/{}/cgroup", pid)) {
+        Ok(cgroups) => cgroups,
+        Err(e) => {
+            warn!("Unable to read cgroups of API client {}: {}", pid, e);
+            return Origin::Unknown;
+        }
+    };
+    origin_from_cgroups(&cgroups)
+}
+
+/// Parses the contents of /proc/PID/cgroup to find where the process is running; see origin_of.
+fn origin_from_cgroups(cgroups: &str) -> Origin {
+    // Each line is "ID:CONTROLLERS:PATH", one per hierarchy.  On hosts with both cgroup v1 and v2,
+    // processes may be left in the root of some hierarchies, so we skip those; every other
+    // hierarchy has to agree.
+    let mut origins = cgroups
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .filter(|path| *path != "/")
+        .map(origin_from_path);
+    match origins.next() {
+        Some(first) if origins.all(|origin| origin == first) => first,
+        _ => Origin::Unknown,
+    }
+}
+
+/// Finds where a process is running from one of its cgroup paths; see origin_of.
+fn origin_from_path(path: &str) -> Origin {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let foreign = segments
+        .iter()
+        .any(|segment| segment.starts_with("kubepods") || segment.starts_with("cri-containerd-"));
+    if foreign {
+        return Origin::Unknown;
+    }
+    let systemd_unit = |name: &str| {
+        [".slice", ".scope", ".service"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    };
+    match segments.as_slice() {
+        ["system.slice", unit] if unit.ends_with(".service") && unit.len() > ".service".len() => {
+            Origin::Host
+        }
+        [namespace, id]
+            if !namespace.is_empty()
+                && !id.is_empty()
+                && !systemd_unit(namespace)
+                && !systemd_unit(id) =>
+        {
+            Origin::Container(id.to_string())
+        }
+        _ => Origin::Unknown,
+    }
+}
+
+/// The rules from the policy file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Policy {
+    default_role: Option<String>,
+    #[serde(default)]
+    roles: HashMap<String, Role>,
+    #[serde(default)]
+    callers: Vec<CallerRule>,
+}
+
+/// Matches callers to a role.  Each given field must match; an entry with no fields matches
+/// every caller.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CallerRule {
+    uid: Option<u32>,
+    gid: Option<u32>,
+    container: Option<String>,
+    role: String,
+}
+
+impl CallerRule {
+    fn matches(&self, caller: &Caller) -> bool {
+        self.uid.map_or(true, |uid| uid == caller.uid)
+            && self.gid.map_or(true, |gid| gid == caller.gid)
+            && self
+                .container
+                .as_ref()
+                .map_or(true, |c| Some(c) == caller.container.as_ref())
+    }
+}
+
+/// What callers with a role may do.  Fields that aren't given don't restrict anything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Role {
+    /// API paths the role may use; each also allows the paths beneath it.
+    routes: Option<Vec<String>>,
+    /// HTTP methods the role may use.
+    methods: Option<Vec<String>>,
+    /// Prefixes of the settings the role may read.
+    readable_settings: Option<Vec<String>>,
+    /// Prefixes of settings the role may not read, even if they match readable_settings.
+    #[serde(default)]
+    hidden_settings: Vec<String>,
+    /// Prefixes of the settings the role may change.
+    writable_settings: Option<Vec<String>>,
+}
+
+impl Policy {
+    /// Loads the policy from the given path; returns None if the file doesn't exist.
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let policy_str = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::PolicyReadSnafu { path }),
+        };
+        let policy: Policy =
+            toml::from_str(&policy_str).context(error::PolicyParseSnafu { path })?;
+
+        let referenced = policy
+            .default_role
+            .iter()
+            .chain(policy.callers.iter().map(|rule| &rule.role));
+        for role in referenced {
+            ensure!(
+                policy.roles.contains_key(role),
+                error::PolicyRoleSnafu { path, role }
+            );
+        }
+        Ok(Some(policy))
+    }
+
+    /// Returns the access given to the caller, or None if the caller isn't allowed to use the
+    /// API at all.  We may not have credentials for the caller, or know where it's running, in
+    /// which case only the default role can apply.
+    fn access_for(&self, caller: Option<&Caller>) -> Option<Access> {
+        let role_name = match caller.filter(|caller| caller.is_known()) {
+            Some(caller) => self
+                .callers
+                .iter()
+                .find(|rule| rule.matches(caller))
+                .map(|rule| &rule.role)
+                .or(self.default_role.as_ref()),
+            None => self.default_role.as_ref(),
+        }?;
+        // Roles were checked when loading.
+        let role = self.roles.get(role_name)?;
+        Some(Access {
+            role_name: Some(role_name.clone()),
+            role: Some(Arc::new(role.clone())),
+        })
+    }
+}
+
+/// Loads the policy file, reloading it when it changes, so a new policy can take effect without
+/// restarting the server.
+pub(crate) struct PolicyFile {
+    path: PathBuf,
+    loaded: sync::Mutex<Option<LoadedPolicy>>,
+}
+
+/// The policy last loaded from the policy file, and the file's modification time when we loaded
+/// it; both are None if the file didn't exist.
+struct LoadedPolicy {
+    modified: Option<SystemTime>,
+    policy: Option<Arc<Policy>>,
+}
+
+impl PolicyFile {
+    pub(crate) fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            loaded: sync::Mutex::new(None),
+        }
+    }
+
+    /// Returns the current policy, or None if there's no policy file.  An invalid policy file
+    /// gives an empty policy, which denies every caller other than host root processes.
+    fn current(&self) -> Option<Arc<Policy>> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        // A panic while loading can't leave a partial policy behind, so we ignore poisoning.
+        let mut loaded = self
+            .loaded
+            .lock()
+            .unwrap_or_else(sync::PoisonError::into_inner);
+        if let Some(loaded) = &*loaded {
+            if loaded.modified == modified {
+                return loaded.policy.clone();
+            }
+        }
+
+        let policy = match Policy::load(&self.path) {
+            Ok(policy) => policy.map(Arc::new),
+            Err(e) => {
+                error!("Denying API access to containers and users: {}", e);
+                Some(Arc::new(Policy::default()))
+            }
+        };
+        *loaded = Some(LoadedPolicy {
+            modified,
+            policy: policy.clone(),
+        });
+        policy
+    }
+}
+
+/// The access given to the caller of a request.  The request middleware adds this to each
+/// request, and handlers can extract it with web::ReqData to check access to settings.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Access {
+    /// The name of the caller's role, or None for full access without a role.
+    role_name: Option<String>,
+    role: Option<Arc<Role>>,
+}
+
+impl Access {
+    pub(crate) fn role_name(&self) -> Option<&str> {
+        self.role_name.as_deref()
+    }
+
+    fn allows_route(&self, method: &Method, path: &str) -> bool {
+        let role = match &self.role {
+            Some(role) => role,
+            None => return true,
+        };
+        let under = |route: &str| {
+            let route = route.trim_end_matches('/');
+            path == route
+                || path
+                    .strip_prefix(route)
+                    .map_or(false, |rest| rest.starts_with('/'))
+        };
+        let route_ok = role
+            .routes
+            .as_ref()
+            .map_or(true, |routes| routes.iter().any(|route| under(route)));
+        // Running commands changes the system, whatever method opened the connection.
+        let method = if under(EXEC_ROUTE) {
+            &Method::POST
+        } else {
+            method
+        };
+        let method_ok = role.methods.as_ref().map_or(true, |methods| {
+            methods
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(method.as_str()))
+        });
+        route_ok && method_ok
+    }
+
+    /// Returns whether the caller may read the setting with the given name.
+    pub(crate) fn can_read(&self, key: &str) -> bool {
+        let role = match &self.role {
+            Some(role) => role,
+            None => return true,
+        };
+        role.readable_settings
+            .as_ref()
+            .map_or(true, |prefixes| any_key_prefix(prefixes, key))
+            && !any_key_prefix(&role.hidden_settings, key)
+    }
+
+    /// Returns whether the caller may read every setting.
+    pub(crate) fn can_read_all(&self) -> bool {
+        self.role.as_ref().map_or(true, |role| {
+            role.readable_settings.is_none() && role.hidden_settings.is_empty()
+        })
+    }
+
+    /// Returns whether the caller may change the setting with the given name.
+    pub(crate) fn can_write(&self, key: &str) -> bool {
+        self.role.as_ref().map_or(true, |role| {
+            role.writable_settings
+                .as_ref()
+                .map_or(true, |prefixes| any_key_prefix(prefixes, key))
+        })
+    }
+
+    /// Returns whether the caller may change every setting.
+    pub(crate) fn can_write_all(&self) -> bool {
+        self.can_write("settings")
+    }
+
+    /// Removes the settings the caller may not read.
+    pub(crate) fn filter_settings(&self, settings: Settings) -> Result<Settings> {
+        if self.can_read_all() {
+            return Ok(settings);
+        }
+        let mut pairs = to_pairs(&settings)
+            .context(error::DataStoreSerializationSnafu { given: "Settings" })?;
+        pairs.retain(|key, _value| self.can_read(key.name()));
+        from_map(&pairs).context(error::DeserializationSnafu {
+            given: "readable settings",
+        })
+    }
+
+    /// Fails if the caller may not change any of the given settings.
+    pub(crate) fn check_write(&self, settings: &Settings) -> Result<()> {
+        if self.can_write_all() {
+            return Ok(());
+        }
+        let pairs =
+            to_pairs(settings).context(error::DataStoreSerializationSnafu { given: "Settings" })?;
+        self.check_write_names(pairs.keys().map(Key::name))
+    }
+
+    /// Fails if the caller may not change any of the settings with the given names.
+    pub(crate) fn check_write_names<I, S>(&self, names: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut denied: Vec<String> = names
+            .into_iter()
+            .filter(|key| !self.can_write(key.as_ref()))
+            .map(|key| key.as_ref().to_string())
+            .collect();
+        denied.sort_unstable();
+        ensure!(
+            denied.is_empty(),
+            error::ForbiddenSnafu {
+                reason: format!("may not change {}", denied.join(", ")),
+            }
+        );
+        Ok(())
+    }
+
+    /// Fails unless the caller may change every setting, for operations like revert that can
+    /// change settings we don't know of in advance.
+    pub(crate) fn check_write_all(&self, operation: &str) -> Result<()> {
+        ensure!(
+            self.can_write_all(),
+            error::ForbiddenSnafu {
+                reason: format!("may not {} without access to all settings", operation),
+            }
+        );
+        Ok(())
+    }
+
+    /// Fails unless the caller may read every setting, for operations like planning that can
+    /// reveal settings indirectly.
+    pub(crate) fn check_read_all(&self, operation: &str) -> Result<()> {
+        ensure!(
+            self.can_read_all(),
+            error::ForbiddenSnafu {
+                reason: format!("may not {} without access to all settings", operation),
+            }
+        );
+        Ok(())
+    }
+}
+
+/// Returns whether the key is, or is beneath, one of the given prefixes.  The "settings." prefix
+/// is implied, as for other settings prefixes in the API.
+fn any_key_prefix(prefixes: &[String], key: &str) -> bool {
+    prefixes.iter().any(|prefix| {
+        let prefix = if prefix.starts_with("settings") {
+            prefix.trim_end_matches('.').to_string()
+        } else {
+            format!("settings.{}", prefix.trim_end_matches('.'))
+        };
+        key == prefix
+            || key
+                .strip_prefix(&prefix)
+                .map_or(false, |rest| rest.starts_with('.'))
+    })
+}
+
+/// Request middleware that decides whether the caller may make the request.  If so, it adds the
+/// caller's Access to the request for handlers to check; if not, it records the denial in the
+/// audit log and returns the response to send instead.
+pub(crate) fn authorize(req: &ServiceRequest) -> std::result::Result<(), ServiceResponse> {
+    let data = req
+        .app_data::<web::Data<SharedData>>()
+        .expect("shared data is always registered");
+    let caller = req.conn_data::<Caller>();
+
+    let access = match data.policy.current() {
+        Some(_) if caller.map_or(false, Caller::is_host_root) => Access::default(),
+        Some(policy) => match policy.access_for(caller) {
+            Some(access) => access,
+            None => return Err(deny(req, data, None, "no role matches the caller")),
+        },
+        None => Access::default(),
+    };
+
+    if !access.allows_route(req.method(), req.path()) {
+        let reason = format!("role may not use {} {}", req.method(), req.path());
+        return Err(deny(req, data, access.role_name(), &reason));
+    }
+
+    req.extensions_mut().insert(access);
+    Ok(())
+}
+
+/// Records a denial made by authorize, and builds the response.
+fn deny(
+    req: &ServiceRequest,
+    data: &SharedData,
+    role: Option<&str>,
+    reason: &str,
+) -> ServiceResponse {
+    let caller = req.conn_data::<Caller>();
+    data.audit
+        .record_denied(caller, role, req.method(), req.path(), reason);
+    let response = error::Error::Forbidden {
+        reason: reason.to_string(),
+    }
+    .error_response();
+    ServiceResponse::new(req.request().clone(), response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POLICY: &str = r#"
+        default-role = "reader"
+
+        [roles.reader]
+        routes = ["/settings", "/os"]
+        methods = ["GET"]
+        hidden-settings = ["settings.kubernetes.bootstrap-token", "motd"]
+
+        [roles.ntp]
+        readable-settings = ["ntp"]
+        writable-settings = ["settings.ntp"]
+
+        [roles.admin]
+
+        [[callers]]
+        container = "admin"
+        role = "admin"
+
+        [[callers]]
+        uid = 1000
+        gid = 1000
+        role = "ntp"
+    "#;
+
+    fn policy() -> Policy {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+        policy
+    }
+
+    /// A caller in the given host container, or a host process if none is given.
+    fn caller(uid: u32, gid: u32, container: Option<&str>) -> Caller {
+        Caller {
+            uid,
+            gid,
+            pid: Some(42),
+            container: container.map(str::to_string),
+            host: container.is_none(),
+        }
+    }
+
+    #[test]
+    fn callers_match_roles() {
+        let policy = policy();
+        let role = |caller: Option<&Caller>| {
+            policy
+                .access_for(caller)
+                .and_then(|access| access.role_name)
+        };
+        assert_eq!(
+            role(Some(&caller(0, 0, Some("admin")))),
+            Some("admin".to_string())
+        );
+        assert_eq!(
+            role(Some(&caller(1000, 1000, None))),
+            Some("ntp".to_string())
+        );
+        // Both the UID and GID must match.
+        assert_eq!(
+            role(Some(&caller(1000, 0, None))),
+            Some("reader".to_string())
+        );
+        assert_eq!(role(None), Some("reader".to_string()));
+
+        // Callers we can't place only get the default role, even if an entry would match.
+        let pod = Caller {
+            host: false,
+            ..caller(1000, 1000, None)
+        };
+        assert_eq!(role(Some(&pod)), Some("reader".to_string()));
+    }
+
+    #[test]
+    fn only_host_services_are_host_root() {
+        assert!(caller(0, 0, None).is_host_root());
+        assert!(!caller(0, 0, Some("admin")).is_host_root());
+        let pod = Caller {
+            host: false,
+            ..caller(0, 0, None)
+        };
+        assert!(!pod.is_host_root());
+    }
+
+    #[test]
+    fn no_default_role_denies() {
+        let policy: Policy = toml::from_str("[roles.admin]\n").unwrap();
+        assert!(policy.access_for(Some(&caller(0, 0, None))).is_none());
+    }
+
+    #[test]
+    fn routes_and_methods() {
+        let access = policy().access_for(None).unwrap();
+        assert!(access.allows_route(&Method::GET, "/settings"));
+        assert!(access.allows_route(&Method::GET, "/settings/export"));
+        assert!(access.allows_route(&Method::GET, "/os"));
+        assert!(!access.allows_route(&Method::PATCH, "/settings"));
+        assert!(!access.allows_route(&Method::GET, "/settingsfoo"));
+        assert!(!access.allows_route(&Method::GET, "/tx"));
+        assert!(!access.allows_route(&Method::GET, "/"));
+
+        let admin = Access::default();
+        assert!(admin.allows_route(&Method::POST, "/actions/reboot"));
+        assert!(admin.allows_route(&Method::GET, "/exec"));
+    }
+
+    #[test]
+    fn exec_needs_write_methods() {
+        let policy: Policy = toml::from_str(
+            r#"
+            default-role = "reader"
+
+            [roles.reader]
+            methods = ["GET"]
+
+            [roles.operator]
+            routes = ["/exec", "/settings"]
+            methods = ["GET", "POST"]
+            "#,
+        )
+        .unwrap();
+        let reader = policy.access_for(None).unwrap();
+        assert!(reader.allows_route(&Method::GET, "/settings"));
+        assert!(reader.allows_route(&Method::GET, "/watch"));
+        assert!(!reader.allows_route(&Method::GET, "/exec"));
+        assert!(!reader.allows_route(&Method::GET, "/exec/"));
+
+        let operator = Access {
+            role_name: Some("operator".to_string()),
+            role: policy.roles.get("operator").cloned().map(Arc::new),
+        };
+        assert!(operator.allows_route(&Method::GET, "/exec"));
+        assert!(!operator.allows_route(&Method::GET, "/os"));
+    }
+
+    #[test]
+    fn setting_prefixes() {
+        let reader = policy().access_for(None).unwrap();
+        assert!(reader.can_read("settings.kubernetes.api-server"));
+        assert!(!reader.can_read("settings.kubernetes.bootstrap-token"));
+        assert!(!reader.can_read("settings.motd"));
+        assert!(reader.can_read("settings.motdx"));
+        assert!(!reader.can_read_all());
+        assert!(reader.can_write_all());
+
+        let ntp = policy()
+            .access_for(Some(&caller(1000, 1000, None)))
+            .unwrap();
+        assert!(ntp.can_read("settings.ntp.time-servers"));
+        assert!(!ntp.can_read("settings.motd"));
+        assert!(ntp.can_write("settings.ntp.time-servers"));
+        assert!(!ntp.can_write("settings.motd"));
+        assert!(!ntp.can_write_all());
+    }
+
+    #[test]
+    fn filter_and_check_settings() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "motd": "hi",
+            "ntp": {"time-servers": ["https://example.com"]}
+        }))
+        .unwrap();
+
+        let ntp = policy()
+            .access_for(Some(&caller(1000, 1000, None)))
+            .unwrap();
+        let err = ntp.check_write(&settings).unwrap_err();
+        assert!(matches!(err, error::Error::Forbidden { .. }));
+        assert!(err.to_string().contains("settings.motd"));
+        assert!(!err.to_string().contains("settings.ntp"));
+
+        let reader = policy().access_for(None).unwrap();
+        let filtered = reader.filter_settings(settings).unwrap();
+        assert_eq!(
+            serde_json::to_value(filtered).unwrap(),
+            serde_json::json!({"ntp": {"time-servers": ["https://example.com"]}})
+        );
+    }
+
+    #[test]
+    fn origins_from_cgroups() {
+        let admin = Origin::Container("admin".to_string());
+        let control = Origin::Container("control".to_string());
+        let cases = vec![
+            ("0::/default/admin\n", admin),
+            (
+                "12:pids:/default/control\n1:name=systemd:/default/control\n0::/\n",
+                control,
+            ),
+            ("0::/system.slice/sundog.service\n", Origin::Host),
+            (
+                "12:pids:/system.slice/apiserver.service\n1:name=systemd:/system.slice/apiserver.service\n0::/\n",
+                Origin::Host,
+            ),
+            // Kubernetes pods, with the systemd and cgroupfs drivers.
+            (
+                "0::/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod2f9ba4ba_7c1f_4f6b_9c0e_1b1e1b8d3a4c.slice/cri-containerd-5d0c8bd7c1a3b3f0e4a1e2cd0f3d8f2b8b0c1a9e0f6a3b2c1d0e9f8a7b6c5d4e3.scope\n",
+                Origin::Unknown,
+            ),
+            (
+                "0::/kubepods.slice/kubepods-pod6a1e0c6a_8f0b_4e0d_9d4c_8a7b6c5d4e3f.slice/cri-containerd-0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c4b5a69788796a5b4c3d2e1f0.scope\n",
+                Origin::Unknown,
+            ),
+            (
+                "11:pids:/kubepods/besteffort/pod2f9ba4ba-7c1f-4f6b-9c0e-1b1e1b8d3a4c/5d0c8bd7c1a3b3f0e4a1e2cd0f3d8f2b8b0c1a9e0f6a3b2c1d0e9f8a7b6c5d4e3\n1:name=systemd:/kubepods/besteffort/pod2f9ba4ba-7c1f-4f6b-9c0e-1b1e1b8d3a4c/5d0c8bd7c1a3b3f0e4a1e2cd0f3d8f2b8b0c1a9e0f6a3b2c1d0e9f8a7b6c5d4e3\n0::/\n",
+                Origin::Unknown,
+            ),
+            (
+                "0::/kubepods/burstable/cri-containerd-5d0c8bd7c1a3\n",
+                Origin::Unknown,
+            ),
+            // Other systemd units, and hierarchies that disagree.
+            ("0::/system.slice/docker-5d0c8bd7c1a3.scope\n", Origin::Unknown),
+            ("0::/system.slice/containerd.service/nested\n", Origin::Unknown),
+            ("0::/user.slice/user-0.slice/session-1.scope\n", Origin::Unknown),
+            (
+                "12:pids:/default/admin\n0::/system.slice/host-containers@admin.service\n",
+                Origin::Unknown,
+            ),
+            ("0::/\n", Origin::Unknown),
+            ("", Origin::Unknown),
+        ];
+        for (cgroups, expected) in cases {
+            assert_eq!(origin_from_cgroups(cgroups), expected, "{}", cgroups);
+        }
+    }
+
+    #[test]
+    fn undefined_role_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        fs::write(&path, "default-role = \"missing\"\n").unwrap();
+        assert!(matches!(
+            Policy::load(&path),
+            Err(error::Error::PolicyRole { .. })
+        ));
+        assert!(Policy::load(dir.path().join("nonexistent"))
+            .unwrap()
+            .is_none());
+    }
+}
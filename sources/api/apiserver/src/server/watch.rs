@@ -0,0 +1,349 @@
+//! The 'watch' module lets clients subscribe to settings changes.  Each client gets a WebSocket,
+//! and every time a transaction is committed, we send the client a text message containing a
+//! SettingsEvent that lists the changed keys and their new values.  Clients can give a 'prefix'
+//! query parameter (a comma-separated list of key prefixes) to only hear about matching keys.
+
+// Implementation note: like 'exec', this uses Actix actors to manage the WebSocket.  Each client
+// connection is a WsWatch actor, and the commit handlers in the parent module send a message to
+// every registered actor through the Watchers registry kept in SharedData.
+
+use super::controller;
+use super::error;
+use super::policy::Access;
+use actix::prelude::{Actor, ActorContext, AsyncContext, Handler, Recipient, StreamHandler};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws::{self, Message};
+use datastore::{Committed, DataStore, Key};
+use log::{debug, error, info, trace};
+use model::watch::SettingsEvent;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{self, Arc};
+use std::time::{Duration, Instant};
+
+/// To guard against stale connections, we send ping messages through the channel regularly as a
+/// 'heartbeat'; this is how often we send them.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+/// If we haven't heard from the client in this much time, we consider it gone and we stop.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts the WebSocket, handing control of the message stream to our WsWatch actor.
+pub(crate) async fn ws_watch(
+    r: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<HashMap<String, String>>,
+    access: web::ReqData<Access>,
+    data: web::Data<crate::server::SharedData>,
+) -> Result<HttpResponse, Error> {
+    info!(
+        "Received watch request to {}:{}",
+        r.connection_info().host(),
+        r.path()
+    );
+
+    let prefixes = match query.get("prefix") {
+        Some(prefix_str) => super::comma_separated("prefix", prefix_str)?
+            .into_iter()
+            // When watching, the settings prefix is implied, so we add it if it wasn't given.
+            .map(|prefix| {
+                let prefix = prefix.trim_end_matches('.');
+                if prefix == "settings" || prefix.starts_with("settings.") {
+                    prefix.to_string()
+                } else {
+                    format!("settings.{}", prefix)
+                }
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    ws::start(
+        WsWatch::new(prefixes, access.into_inner(), data.into_inner()),
+        &r,
+        stream,
+    )
+}
+
+/// Watchers keeps track of the connected clients so the commit handlers can tell them about
+/// changes.
+#[derive(Default)]
+pub(crate) struct Watchers {
+    recipients: sync::Mutex<Vec<Recipient<message::SettingsChanged>>>,
+}
+
+impl Watchers {
+    /// Returns whether there are any connected clients, so callers can skip building an event
+    /// that no one would receive.
+    pub(crate) fn is_empty(&self) -> bool {
+        let mut recipients = self.lock();
+        recipients.retain(|recipient| recipient.connected());
+        recipients.is_empty()
+    }
+
+    /// Sends the given event to every connected client; each client filters it according to its
+    /// requested prefixes.
+    pub(crate) fn notify(&self, event: SettingsEvent) {
+        let event = Arc::new(event);
+        let mut recipients = self.lock();
+        recipients.retain(|recipient| recipient.connected());
+        debug!(
+            "Sending changes from transaction '{}' to {} watch client(s)",
+            event.transaction,
+            recipients.len()
+        );
+        for recipient in recipients.iter() {
+            recipient.do_send(message::SettingsChanged(Arc::clone(&event)));
+        }
+    }
+
+    fn register(&self, recipient: Recipient<message::SettingsChanged>) {
+        self.lock().push(recipient);
+    }
+
+    // A panic while holding the lock can't leave the list in a bad state; the worst case is a
+    // disconnected recipient that gets pruned on the next call, so we ignore poisoning.
+    fn lock(&self) -> sync::MutexGuard<'_, Vec<Recipient<message::SettingsChanged>>> {
+        self.recipients
+            .lock()
+            .unwrap_or_else(sync::PoisonError::into_inner)
+    }
+}
+
+/// Builds the event sent to watchers for the keys changed in a commit.  Changed keys that no
+/// longer have a live value were removed by the commit.
+pub(crate) fn settings_event<D: DataStore>(
+    datastore: &D,
+    transaction: &str,
+    changes: &HashSet<Key>,
+) -> error::Result<SettingsEvent> {
+    let values = controller::get_values(datastore, changes, &Committed::Live)?;
+    let removed = changes
+        .iter()
+        .map(|key| key.name())
+        .filter(|name| !values.contains_key(*name))
+        .cloned()
+        .collect();
+    Ok(SettingsEvent {
+        transaction: transaction.to_string(),
+        changes: values,
+        removed,
+    })
+}
+
+/// WsWatch is an actor that represents the WebSocket connection to a watching client.
+pub(crate) struct WsWatch {
+    /// This tracks the last time we heard from the client; if it's been too long, we consider the
+    /// connection stale and terminate it.
+    heartbeat: Instant,
+
+    /// The key prefixes the client wants to hear about; empty means all keys.
+    prefixes: Vec<String>,
+
+    /// The client's access; changes to settings it can't read aren't sent.
+    access: Access,
+
+    /// We hold the shared data so we can register ourselves with the Watchers registry once the
+    /// actor has an address.
+    data: Arc<crate::server::SharedData>,
+}
+
+impl WsWatch {
+    fn new(prefixes: Vec<String>, access: Access, data: Arc<crate::server::SharedData>) -> Self {
+        Self {
+            heartbeat: Instant::now(),
+            prefixes,
+            access,
+            data,
+        }
+    }
+
+    /// Returns whether the client is interested in the given key, and may read it.  A prefix
+    /// matches the key with that name and the keys beneath it, so "settings.motd" doesn't match
+    /// "settings.motdx".
+    fn matches(&self, key: &str) -> bool {
+        let prefix_matches = |prefix: &str| {
+            let prefix = prefix.trim_end_matches('.');
+            key == prefix
+                || key
+                    .strip_prefix(prefix)
+                    .map_or(false, |rest| rest.starts_with('.'))
+        };
+        (self.prefixes.is_empty() || self.prefixes.iter().any(|p| prefix_matches(p)))
+            && self.access.can_read(key)
+    }
+
+    /// Returns the part of the event the client is interested in, or None if nothing matched.
+    fn filter(&self, event: &SettingsEvent) -> Option<SettingsEvent> {
+        let changes: BTreeMap<_, _> = event
+            .changes
+            .iter()
+            .filter(|(key, _value)| self.matches(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let removed: BTreeSet<_> = event
+            .removed
+            .iter()
+            .filter(|key| self.matches(key))
+            .cloned()
+            .collect();
+        if changes.is_empty() && removed.is_empty() {
+            return None;
+        }
+        Some(SettingsEvent {
+            transaction: event.transaction.clone(),
+            changes,
+            removed,
+        })
+    }
+
+    /// This starts a task that's responsible for confirming that our connection to the client
+    /// isn't stale.  We ping the client regularly so it knows we're alive, and we confirm that the
+    /// client has responded recently so we know it's alive.
+    fn heartbeat(&self, ctx: &mut <Self as Actor>::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |actor, ctx| {
+            if Instant::now().duration_since(actor.heartbeat) > CLIENT_TIMEOUT {
+                info!("watch client heartbeat failed, disconnecting");
+                ctx.stop();
+                return;
+            }
+
+            trace!("watch client heartbeat ok, sending ping");
+            ctx.ping(b"");
+        });
+    }
+}
+
+impl Actor for WsWatch {
+    type Context = ws::WebsocketContext<Self>;
+
+    /// When the actor is first started, we start the heartbeat and register to hear about commits.
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!("Starting watch for prefixes {:?}", self.prefixes);
+        self.heartbeat(ctx);
+        self.data.watchers.register(ctx.address().recipient());
+    }
+}
+
+impl StreamHandler<Result<Message, ws::ProtocolError>> for WsWatch {
+    /// This handler is run every time we receive a message from the client.  Watch clients only
+    /// receive events, so the only messages we expect are heartbeats and Close.
+    fn handle(&mut self, msg: Result<Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(Message::Ping(msg)) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&msg);
+            }
+
+            Ok(Message::Pong(_)) => {
+                self.heartbeat = Instant::now();
+            }
+
+            Ok(Message::Close(reason)) => {
+                info!("Client closed watch connection with reason: {:?}", reason);
+                ctx.close(reason);
+                ctx.stop();
+            }
+
+            Ok(Message::Text(_)) | Ok(Message::Binary(_)) | Ok(Message::Continuation(_)) => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Unsupported,
+                    description: Some("watch clients may not send data".to_string()),
+                }));
+                ctx.stop();
+            }
+
+            // no-op
+            Ok(Message::Nop) => {}
+
+            Err(e) => {
+                error!("Stopping after receiving error message: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+/// The 'message' module contains the non-WebSocket messages that our WebSocket actor can handle.
+mod message {
+    use super::SettingsEvent;
+    use std::sync::Arc;
+
+    /// Represents a committed transaction; shared between all watching clients.
+    #[derive(actix::Message)]
+    #[rtype(result = "()")]
+    pub(crate) struct SettingsChanged(pub(super) Arc<SettingsEvent>);
+}
+
+impl Handler<message::SettingsChanged> for WsWatch {
+    type Result = ();
+
+    /// Sends the changes the client is interested in, if any, as a JSON Text message.
+    fn handle(&mut self, msg: message::SettingsChanged, ctx: &mut Self::Context) -> Self::Result {
+        let event = match self.filter(&msg.0) {
+            Some(event) => event,
+            None => return,
+        };
+        match serde_json::to_string(&event) {
+            Ok(text) => ctx.text(text),
+            Err(e) => error!("Failed to serialize settings event: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use datastore::memory::MemoryDataStore;
+    use datastore::KeyType;
+    use maplit::hashset;
+
+    fn watch(prefixes: &[&str]) -> WsWatch {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate::server::SharedData::for_test(dir.path());
+        WsWatch::new(
+            prefixes.iter().map(|p| p.to_string()).collect(),
+            Access::default(),
+            Arc::new(data),
+        )
+    }
+
+    #[test]
+    fn matches_all_without_prefixes() {
+        let w = watch(&[]);
+        assert!(w.matches("settings.motd"));
+        assert!(w.matches("settings.kubernetes.node-labels.a"));
+    }
+
+    #[test]
+    fn matches_prefixes() {
+        let w = watch(&["settings.kubernetes.", "settings.motd"]);
+        assert!(w.matches("settings.motd"));
+        assert!(w.matches("settings.kubernetes.node-labels.a"));
+        assert!(!w.matches("settings.ntp.time-servers"));
+        assert!(!w.matches("settings.motdx"));
+        assert!(!w.matches("settings.kubernetesfoo"));
+    }
+
+    #[test]
+    fn removed_keys_are_sent() {
+        let mut ds = MemoryDataStore::new();
+        let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+        let hostname = Key::new(KeyType::Data, "settings.hostname").unwrap();
+        ds.set_key(&motd, "\"hi\"", &Committed::Live).unwrap();
+
+        let event = settings_event(&ds, "tx", &hashset!(motd, hostname)).unwrap();
+        assert_eq!(
+            event.changes,
+            BTreeMap::from([("settings.motd".to_string(), "hi".into())])
+        );
+        assert_eq!(
+            event.removed,
+            BTreeSet::from(["settings.hostname".to_string()])
+        );
+
+        // Removals are filtered by prefix like changes.
+        let filtered = watch(&["settings.hostname"]).filter(&event).unwrap();
+        assert!(filtered.changes.is_empty());
+        assert_eq!(filtered.removed, event.removed);
+        assert!(watch(&["settings.ntp"]).filter(&event).is_none());
+    }
+}
@@ -10,9 +10,15 @@ It queries the API for their settings, then configures the system by:
   container at /.bottlerocket/bootstrap-containers/<name>/user-data)
 * creating an environment file used by a bootstrap-container-specific instance of a systemd service
 * creating a systemd drop-in configuration file used by a bootstrap-container-specific
-instance of a systemd service
+instance of a systemd service, which orders it after the bootstrap containers named in its `after`
+setting, limits how long it may run with `timeout-seconds`, and restarts it up to `retries` times
+if it fails
 * ensuring that the bootstrap container's systemd service is enabled/disabled for the next boot
 
+When the bootstrap container's systemd service finishes, `mark-bootstrap` turns off containers
+whose mode is `once`.  If the container fails or times out instead, `mark-bootstrap` reports why
+to the console and records it in /run/bootstrap-containers/<name>.failed.
+
 # Examples
 Given a bootstrap container called `bear` with the following configuration:
 
@@ -74,6 +80,8 @@ journalctl -u bootstrap-containers@bear.service
 extern crate log;
 
 use datastore::{serialize_scalar, Key, KeyType};
+use host_containers::status;
+use serde::Serialize;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ensure, OptionExt, ResultExt};
 use std::collections::HashMap;
@@ -82,6 +90,7 @@ use std::env;
 use std::ffi::OsStr;
 use std::fmt::Write;
 use std::fs;
+use std::io;
 use std::path::Path;
 use std::process::{self, Command};
 use std::str::FromStr;
@@ -92,6 +101,13 @@ const ENV_FILE_DIR: &str = "/etc/bootstrap-containers";
 const DROPIN_FILE_DIR: &str = "/etc/systemd/system";
 const PERSISTENT_STORAGE_DIR: &str = "/local/bootstrap-containers";
 const DROP_IN_FILENAME: &str = "overrides.conf";
+// How long systemd waits before running a failed bootstrap container again.
+const RETRY_DELAY_SECONDS: u32 = 10;
+
+// systemd sets these for ExecStopPost commands, to say how the service ended.
+const SERVICE_RESULT_ENV: &str = "SERVICE_RESULT";
+const EXIT_CODE_ENV: &str = "EXIT_CODE";
+const EXIT_STATUS_ENV: &str = "EXIT_STATUS";
 
 /// Stores user-supplied global arguments
 #[derive(Debug)]
@@ -240,10 +256,12 @@ fn parse_mark_bootstrap_args(args: Vec<String>) -> Result<Subcommand> {
     }))
 }
 
-/// Handles how the bootstrap containers' systemd units are created
+/// Handles how the bootstrap containers' systemd units are created.  The other bootstrap
+/// containers are needed to order this one after them.
 fn handle_bootstrap_container<S>(
     name: S,
     container_details: &model::BootstrapContainer,
+    bootstrap_containers: &HashMap<Identifier, model::BootstrapContainer>,
 ) -> Result<()>
 where
     S: AsRef<str>,
@@ -287,7 +305,14 @@ where
 
     // Write the environment file needed for the systemd service to have details
     // this specific bootstrap container
-    write_config_files(name, source, &mode, essential)?;
+    write_config_files(
+        name,
+        source,
+        &mode,
+        essential,
+        container_details,
+        bootstrap_containers,
+    )?;
 
     if mode == "off" {
         // If mode is 'off', disable the container, and clean up any left over tasks
@@ -330,8 +355,16 @@ where
     Ok(())
 }
 
-/// Write out the EnvironmentFile that systemd uses to fill in arguments to host-ctr
-fn write_config_files<S1, S2, S3>(name: S1, source: S2, mode: S3, essential: bool) -> Result<()>
+/// Write out the EnvironmentFile that systemd uses to fill in arguments to host-ctr, and the
+/// drop-in that sets the unit's dependencies, ordering, timeout, and retries
+fn write_config_files<S1, S2, S3>(
+    name: S1,
+    source: S2,
+    mode: S3,
+    essential: bool,
+    container_details: &model::BootstrapContainer,
+    bootstrap_containers: &HashMap<Identifier, model::BootstrapContainer>,
+) -> Result<()>
 where
     S1: AsRef<str>,
     S2: AsRef<str>,
@@ -371,6 +404,10 @@ where
         .context(error::WriteConfigurationValueSnafu { value: "[Install]" })?;
     writeln!(output, "{}=configured.target", dependency)
         .context(error::WriteConfigurationValueSnafu { value: dependency })?;
+
+    write_order(&mut output, name, container_details, bootstrap_containers)?;
+    write_limits(&mut output, container_details)?;
+
     debug!("Writing drop-in file for {}", name);
     fs::create_dir_all(&drop_in_dir).context(error::MkdirSnafu { dir: &drop_in_dir })?;
     fs::write(&drop_in_path, output)
@@ -379,6 +416,94 @@ where
     Ok(())
 }
 
+/// Adds the unit's ordering after other bootstrap containers to its drop-in.  Containers that will
+/// run in this boot are also required, so this one doesn't run if they fail; containers that are
+/// off or don't exist are left out.
+fn write_order(
+    output: &mut String,
+    name: &str,
+    container_details: &model::BootstrapContainer,
+    bootstrap_containers: &HashMap<Identifier, model::BootstrapContainer>,
+) -> Result<()> {
+    let after = match &container_details.after {
+        Some(after) if !after.is_empty() => after,
+        _ => return Ok(()),
+    };
+
+    writeln!(output, "\n[Unit]")
+        .context(error::WriteConfigurationValueSnafu { value: "[Unit]" })?;
+    for dep in after {
+        let dep_mode = match bootstrap_containers.get(dep) {
+            Some(dep_details) => dep_details.mode.clone().unwrap_or_default(),
+            None => {
+                warn!(
+                    "Bootstrap container '{}' is set to run after '{}', which doesn't exist",
+                    name, dep
+                );
+                continue;
+            }
+        };
+        if dep_mode == "off" {
+            debug!("Not ordering '{}' after '{}', which is off", name, dep);
+            continue;
+        }
+
+        let dep_unit = format!("bootstrap-containers@{}.service", dep);
+        writeln!(output, "After={}", dep_unit)
+            .context(error::WriteConfigurationValueSnafu { value: &dep_unit })?;
+        writeln!(output, "Requires={}", dep_unit)
+            .context(error::WriteConfigurationValueSnafu { value: &dep_unit })?;
+    }
+
+    Ok(())
+}
+
+/// Adds the unit's timeout and retries to its drop-in.  systemd counts each run of the unit toward
+/// its start limit, so the limit is set to the first run plus the retries, and never resets.
+fn write_limits(output: &mut String, container_details: &model::BootstrapContainer) -> Result<()> {
+    let retries = container_details.retries.unwrap_or(0);
+    if retries > 0 {
+        let runs = u32::from(retries) + 1;
+        writeln!(output, "\n[Unit]")
+            .context(error::WriteConfigurationValueSnafu { value: "[Unit]" })?;
+        writeln!(output, "StartLimitIntervalSec=infinity")
+            .context(error::WriteConfigurationValueSnafu { value: "infinity" })?;
+        writeln!(output, "StartLimitBurst={}", runs).context(
+            error::WriteConfigurationValueSnafu {
+                value: runs.to_string(),
+            },
+        )?;
+    }
+
+    let timeout = container_details.timeout_seconds.filter(|t| *t > 0);
+    if retries == 0 && timeout.is_none() {
+        return Ok(());
+    }
+
+    writeln!(output, "\n[Service]")
+        .context(error::WriteConfigurationValueSnafu { value: "[Service]" })?;
+    if let Some(timeout) = timeout {
+        // When the timeout passes, systemd stops host-ctr, which kills the container.
+        writeln!(output, "TimeoutStartSec={}", timeout).context(
+            error::WriteConfigurationValueSnafu {
+                value: timeout.to_string(),
+            },
+        )?;
+    }
+    if retries > 0 {
+        writeln!(output, "Restart=on-failure").context(error::WriteConfigurationValueSnafu {
+            value: "on-failure",
+        })?;
+        writeln!(output, "RestartSec={}", RETRY_DELAY_SECONDS).context(
+            error::WriteConfigurationValueSnafu {
+                value: RETRY_DELAY_SECONDS.to_string(),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
 /// Query the API for the currently defined bootstrap containers
 async fn get_bootstrap_containers<P>(
     socket_path: P,
@@ -498,7 +623,7 @@ where
     let bootstrap_containers = get_bootstrap_containers(socket_path).await?;
     for (name, container_details) in bootstrap_containers.iter() {
         // Continue to handle other bootstrap containers if we fail one
-        if let Err(e) = handle_bootstrap_container(name, container_details) {
+        if let Err(e) = handle_bootstrap_container(name, container_details, &bootstrap_containers) {
             failed += 1;
             error!("Failed to handle bootstrap container '{}': {}", &name, e);
         }
@@ -515,14 +640,82 @@ where
     Ok(())
 }
 
+/// Why a bootstrap container's systemd unit failed, as recorded for the container by
+/// `mark-bootstrap`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RunFailure {
+    /// systemd's result for the unit, like "exit-code" or "timeout".
+    result: String,
+    /// How the main process ended: "exited", "killed", or "dumped".
+    exit_code: Option<String>,
+    /// The exit status or signal name of the main process.
+    exit_status: Option<String>,
+    /// A description of the failure for people.
+    reason: String,
+}
+
+impl RunFailure {
+    /// Builds a failure from the environment systemd gives ExecStopPost commands.  Returns None if
+    /// the unit succeeded, or if we're not running as an ExecStopPost command.
+    fn from_env() -> Option<Self> {
+        let result = env::var(SERVICE_RESULT_ENV).ok()?;
+        if result == "success" {
+            return None;
+        }
+        let exit_code = env::var(EXIT_CODE_ENV).ok().filter(|v| !v.is_empty());
+        let exit_status = env::var(EXIT_STATUS_ENV).ok().filter(|v| !v.is_empty());
+
+        let reason = status::failure_reason(&result, exit_status.as_deref());
+
+        Some(Self {
+            result,
+            exit_code,
+            exit_status,
+            reason,
+        })
+    }
+}
+
 /// Handles the `mark-bootstrap` subcommand, which is called by the bootstrap
 /// container's systemd unit, which could potentially cause a concurrent invocation
 /// in this binary after the API setting finalizes.
+///
+/// The unit calls it after the container succeeds, and again when the unit stops.  If the unit
+/// stopped because the container failed, we report why instead of marking the container.
 async fn mark_bootstrap<P>(args: MarkBootstrapArgs, socket_path: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
     let container_id: &str = args.container_id.as_ref();
+    let failure_path = status::bootstrap_failure_path(container_id);
+
+    if env::var_os(SERVICE_RESULT_ENV).is_some() {
+        if let Some(failure) = RunFailure::from_env() {
+            // Send the reason to the console, along with the container's own output.
+            error!("Bootstrap container '{}' {}", container_id, failure.reason);
+            let json = serde_json::to_string(&failure).context(error::SerializeSnafu)?;
+            fs::write(&failure_path, json)
+                .context(error::WriteConfigurationFileSnafu { path: failure_path })?;
+        } else {
+            debug!(
+                "Bootstrap container '{}' stopped after succeeding",
+                container_id
+            );
+        }
+        return Ok(());
+    }
+
+    // The container succeeded, so any failure recorded from an earlier try is stale.
+    match fs::remove_file(&failure_path) {
+        Ok(()) => info!(
+            "Bootstrap container '{}' succeeded after a retry",
+            container_id
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove {}: {}", failure_path.display(), e),
+    }
+
     let mode = args.mode.as_ref();
     info!("Mode for '{}' is '{}'", container_id, mode);
 
@@ -0,0 +1,314 @@
+//! The drift module compares the sysctls and lockdown mode in effect in the kernel against the
+//! values configured in `settings.kernel`, so we can notice when something changes them behind
+//! our back, for example a privileged container writing to /proc/sys.  It can also restore the
+//! configured values.
+
+use crate::{parse_kernel_setting, sysctl_path_under, LOCKDOWN_PATH, SYSCTL_PATH_PREFIX};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use model::KernelSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The kinds of kernel setting we check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingKind {
+    Sysctl,
+    Lockdown,
+}
+
+impl fmt::Display for SettingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingKind::Sysctl => write!(f, "sysctl"),
+            SettingKind::Lockdown => write!(f, "lockdown"),
+        }
+    }
+}
+
+impl SettingKind {
+    /// Reads the value in effect for a setting of this kind, in the form we compare it.
+    fn read(&self, path: &Path) -> io::Result<String> {
+        let raw = fs::read_to_string(path)?;
+        Ok(match self {
+            SettingKind::Sysctl => self.normalize(&raw),
+            SettingKind::Lockdown => parse_kernel_setting(&raw).to_string(),
+        })
+    }
+
+    /// Puts a value in the form we compare it.  The kernel separates multi-valued sysctls like
+    /// `net.ipv4.tcp_rmem` with tabs, but users generally write them with spaces, so we compare
+    /// the values word by word.
+    fn normalize(&self, value: &str) -> String {
+        match self {
+            SettingKind::Sysctl => value.split_whitespace().collect::<Vec<_>>().join(" "),
+            SettingKind::Lockdown => value.trim().to_string(),
+        }
+    }
+}
+
+/// A kernel setting whose value in effect doesn't match the configured value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Drift {
+    pub kind: SettingKind,
+    pub name: String,
+    pub path: PathBuf,
+    pub expected: String,
+    pub actual: String,
+    /// Whether the configured value was restored and has been read back from the kernel.
+    pub restored: bool,
+}
+
+/// A configured kernel setting whose value in effect couldn't be read, for example a sysctl that
+/// doesn't exist in this kernel or one that depends on a module that isn't loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Unavailable {
+    pub kind: SettingKind,
+    pub name: String,
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// The result of comparing the kernel settings in effect against the configured settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DriftReport {
+    pub checked_at: DateTime<Utc>,
+    /// The number of configured settings we compared.
+    pub checked: usize,
+    pub drift: Vec<Drift>,
+    pub unavailable: Vec<Unavailable>,
+}
+
+/// A report with no settings checked yet.
+impl Default for DriftReport {
+    fn default() -> Self {
+        Self {
+            checked_at: Utc::now(),
+            checked: 0,
+            drift: Vec::new(),
+            unavailable: Vec::new(),
+        }
+    }
+}
+
+impl DriftReport {
+    /// Returns true if any setting has drifted from its configured value and wasn't restored.
+    pub fn has_drift(&self) -> bool {
+        self.drift.iter().any(|drift| !drift.restored)
+    }
+
+    /// Writes the configured value of each drifted setting, then reads it back to see whether the
+    /// kernel accepted it.  Failures are logged rather than returned, as when corndog applies
+    /// sysctls; for example, the kernel won't allow lowering lockdown without a reboot.
+    pub fn restore(&mut self) {
+        for drift in self.drift.iter_mut() {
+            if let Err(e) = fs::write(&drift.path, &drift.expected) {
+                warn!(
+                    "Failed to restore {} '{}' to '{}': {}",
+                    drift.kind, drift.name, drift.expected, e
+                );
+                continue;
+            }
+            match drift.kind.read(&drift.path) {
+                Ok(actual) => {
+                    drift.restored = actual == drift.kind.normalize(&drift.expected);
+                    debug!(
+                        "Wrote '{}' to {} '{}', read back '{}'",
+                        drift.expected, drift.kind, drift.name, actual
+                    );
+                }
+                Err(e) => warn!(
+                    "Failed to read back {} '{}' after restoring it: {}",
+                    drift.kind, drift.name, e
+                ),
+            }
+        }
+    }
+
+    fn check_setting<S, P>(&mut self, kind: SettingKind, name: S, path: P, expected: &str)
+    where
+        S: Into<String>,
+        P: Into<PathBuf>,
+    {
+        let name = name.into();
+        let path = path.into();
+        self.checked += 1;
+        match kind.read(&path) {
+            Ok(actual) if actual == kind.normalize(expected) => {
+                debug!("{} '{}' is '{}' as configured", kind, name, actual)
+            }
+            Ok(actual) => self.drift.push(Drift {
+                kind,
+                name,
+                path,
+                expected: expected.to_string(),
+                actual,
+                restored: false,
+            }),
+            Err(e) => self.unavailable.push(Unavailable {
+                kind,
+                name,
+                path,
+                error: e.to_string(),
+            }),
+        }
+    }
+}
+
+/// Compares the sysctls and lockdown mode in effect in the kernel against the given settings.
+pub fn check(kernel: &KernelSettings) -> DriftReport {
+    check_under(kernel, SYSCTL_PATH_PREFIX, LOCKDOWN_PATH)
+}
+
+/// Like check, but reads sysctls under the given directory and the lockdown mode from the given
+/// file, rather than from the running kernel.
+pub fn check_under<P1, P2>(
+    kernel: &KernelSettings,
+    sysctl_prefix: P1,
+    lockdown_path: P2,
+) -> DriftReport
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let mut report = DriftReport::default();
+
+    if let Some(sysctls) = &kernel.sysctl {
+        // Sort by name so reports are stable.
+        let sysctls: BTreeMap<&str, &String> = sysctls
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value))
+            .collect();
+        for (name, expected) in sysctls {
+            let path = sysctl_path_under(sysctl_prefix.as_ref(), name);
+            report.check_setting(SettingKind::Sysctl, name, path, expected);
+        }
+    }
+
+    if let Some(lockdown) = &kernel.lockdown {
+        report.check_setting(
+            SettingKind::Lockdown,
+            "lockdown",
+            lockdown_path.as_ref(),
+            lockdown,
+        );
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::hashmap;
+    use model::modeled_types::{Lockdown, SysctlKey};
+    use std::convert::TryFrom;
+    use tempfile::TempDir;
+
+    fn sysctl(name: &str) -> SysctlKey {
+        SysctlKey::try_from(name).unwrap()
+    }
+
+    fn setup() -> (TempDir, KernelSettings) {
+        let tempdir = TempDir::new().unwrap();
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("sys/net/ipv4")).unwrap();
+        fs::create_dir_all(root.join("sys/vm")).unwrap();
+        fs::write(root.join("sys/vm/max_map_count"), "262144\n").unwrap();
+        fs::write(root.join("sys/vm/swappiness"), "60\n").unwrap();
+        fs::write(
+            root.join("sys/net/ipv4/tcp_rmem"),
+            "4096\t131072\t6291456\n",
+        )
+        .unwrap();
+        fs::write(root.join("lockdown"), "[none] integrity confidentiality\n").unwrap();
+
+        let kernel = KernelSettings {
+            sysctl: Some(hashmap! {
+                sysctl("vm.max_map_count") => "262144".to_string(),
+                sysctl("vm.swappiness") => "10".to_string(),
+                sysctl("net.ipv4.tcp_rmem") => "4096 131072 6291456".to_string(),
+                sysctl("net.ipv4.not_in_this_kernel") => "1".to_string(),
+            }),
+            lockdown: Some(Lockdown::try_from("integrity").unwrap()),
+            modules: None,
+        };
+        (tempdir, kernel)
+    }
+
+    #[test]
+    fn finds_drift() {
+        let (tempdir, kernel) = setup();
+        let root = tempdir.path();
+        let report = check_under(&kernel, root.join("sys"), root.join("lockdown"));
+
+        assert_eq!(report.checked, 5);
+        assert!(report.has_drift());
+        assert_eq!(
+            report.drift,
+            vec![
+                Drift {
+                    kind: SettingKind::Sysctl,
+                    name: "vm.swappiness".to_string(),
+                    path: root.join("sys/vm/swappiness"),
+                    expected: "10".to_string(),
+                    actual: "60".to_string(),
+                    restored: false,
+                },
+                Drift {
+                    kind: SettingKind::Lockdown,
+                    name: "lockdown".to_string(),
+                    path: root.join("lockdown"),
+                    expected: "integrity".to_string(),
+                    actual: "none".to_string(),
+                    restored: false,
+                },
+            ]
+        );
+        assert_eq!(report.unavailable.len(), 1);
+        assert_eq!(report.unavailable[0].name, "net.ipv4.not_in_this_kernel");
+    }
+
+    #[test]
+    fn restores_drift() {
+        let (tempdir, kernel) = setup();
+        let root = tempdir.path();
+        let mut report = check_under(&kernel, root.join("sys"), root.join("lockdown"));
+        report.restore();
+
+        assert!(!report.has_drift());
+        assert!(report.drift.iter().all(|drift| drift.restored));
+        assert_eq!(
+            fs::read_to_string(root.join("sys/vm/swappiness")).unwrap(),
+            "10"
+        );
+
+        let report = check_under(&kernel, root.join("sys"), root.join("lockdown"));
+        assert!(report.drift.is_empty());
+    }
+
+    #[test]
+    fn no_settings() {
+        let tempdir = TempDir::new().unwrap();
+        let report = check_under(
+            &KernelSettings {
+                lockdown: None,
+                modules: None,
+                sysctl: None,
+            },
+            tempdir.path(),
+            tempdir.path().join("lockdown"),
+        );
+        assert_eq!(report.checked, 0);
+        assert!(!report.has_drift());
+        assert!(report.unavailable.is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+/*!
+This library provides the paths and parsing used by corndog to apply kernel settings, and a
+`drift` module that compares the values in effect in the kernel against the configured settings.
+It's shared with the API server so it can report drift without running corndog.
+*/
+
+use log::trace;
+use std::path::{Path, PathBuf};
+
+pub mod drift;
+
+pub const SYSCTL_PATH_PREFIX: &str = "/proc/sys";
+pub const LOCKDOWN_PATH: &str = "/sys/kernel/security/lockdown";
+
+/// Returns the path of the file under /proc/sys that represents the given sysctl.
+pub fn sysctl_path<S>(name: S) -> PathBuf
+where
+    S: AsRef<str>,
+{
+    sysctl_path_under(SYSCTL_PATH_PREFIX, name)
+}
+
+pub(crate) fn sysctl_path_under<P, S>(prefix: P, name: S) -> PathBuf
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let name = name.as_ref();
+    let mut path = prefix.as_ref().to_path_buf();
+    path.extend(name.replace('.', "/").split('/'));
+    trace!("Path for {}: {}", name, path.display());
+    path
+}
+
+/// The Linux kernel provides human-readable output like `[none] integrity confidentiality` when
+/// you read settings from virtual files like /sys/kernel/security/lockdown.  This parses out the
+/// current value of the setting from that human-readable output.
+///
+/// There are also some files that only output the current value without the other options, so we
+/// return the output as-is (except for trimming whitespace) if there are no brackets.
+pub fn parse_kernel_setting(setting: &str) -> &str {
+    let mut setting = setting.trim();
+    // Take after the '['
+    if let Some(idx) = setting.find('[') {
+        if setting.len() > idx + 1 {
+            setting = &setting[idx + 1..];
+        }
+    }
+    // Take before the ']'
+    if let Some(idx) = setting.find(']') {
+        setting = &setting[..idx];
+    }
+    setting
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_traversal() {
+        assert_eq!(
+            sysctl_path("../../root/file").to_string_lossy(),
+            format!("{}/root/file", SYSCTL_PATH_PREFIX)
+        );
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(
+            "none",
+            parse_kernel_setting("[none] integrity confidentiality")
+        );
+        assert_eq!(
+            "integrity",
+            parse_kernel_setting("none [integrity] confidentiality\n")
+        );
+        assert_eq!(
+            "confidentiality",
+            parse_kernel_setting("none integrity [confidentiality]")
+        );
+    }
+
+    #[test]
+    fn no_brackets() {
+        assert_eq!("none", parse_kernel_setting("none"));
+        assert_eq!(
+            "none integrity confidentiality",
+            parse_kernel_setting("none integrity confidentiality\n")
+        );
+    }
+}
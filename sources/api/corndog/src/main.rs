@@ -3,40 +3,80 @@ corndog is a delicious way to get at the meat inside the kernels.
 It sets kernel-related settings, for example:
 * sysctl values, based on key/value pairs in `settings.kernel.sysctl`
 * lockdown mode, based on the value of `settings.kernel.lockdown`
+
+It can also check that those settings are still in effect, since something with enough privilege,
+like a privileged container, can change them after corndog runs.
+`corndog verify` compares the values in the kernel against the settings and prints a JSON report
+of any drift.
+`corndog reconcile` does the same every `--interval` seconds, printing each report as a line of
+JSON, and keeps running.
+They log to stderr, so the reports on stdout can be parsed.
+With `--restore`, either one writes the configured values back to the kernel, and the report says
+whether each was restored; lockdown can't be lowered until reboot.
+
+The API server reports the same drift, without restoring anything, at `/kernel/drift`.
 */
 
+use corndog::drift::{self, DriftReport};
+use corndog::{parse_kernel_setting, sysctl_path, LOCKDOWN_PATH};
 use log::{debug, error, info, trace, warn};
-use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
+use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger, WriteLogger};
 use snafu::ResultExt;
 use std::collections::HashMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::Path;
 use std::str::FromStr;
 use std::string::String;
+use std::time::Duration;
 use std::{env, process};
 
-const SYSCTL_PATH_PREFIX: &str = "/proc/sys";
-const LOCKDOWN_PATH: &str = "/sys/kernel/security/lockdown";
+const DEFAULT_RECONCILE_INTERVAL_SECS: u64 = 60;
 
 /// Store the args we receive on the command line.
 struct Args {
     subcommand: String,
     log_level: LevelFilter,
     socket_path: String,
+    restore: bool,
+    interval: Duration,
 }
 
 /// Main entry point.
 async fn run() -> Result<()> {
     let args = parse_args(env::args());
 
-    // SimpleLogger will send errors to stderr and anything less to stdout.
-    SimpleLogger::init(args.log_level, LogConfig::default()).context(error::LoggerSnafu)?;
+    if args.subcommand == "verify" || args.subcommand == "reconcile" {
+        // Reports are printed to stdout, so all logging goes to stderr.
+        WriteLogger::init(args.log_level, LogConfig::default(), io::stderr())
+            .context(error::LoggerSnafu)?;
+    } else {
+        // SimpleLogger will send errors to stderr and anything less to stdout.
+        SimpleLogger::init(args.log_level, LogConfig::default()).context(error::LoggerSnafu)?;
+    }
+
+    match args.subcommand.as_ref() {
+        "verify" => {
+            let report = verify(&args.socket_path, args.restore).await?;
+            let json = serde_json::to_string_pretty(&report).context(error::ReportJsonSnafu)?;
+            println!("{}", json);
+        }
+        "reconcile" => reconcile(&args.socket_path, args.restore, args.interval).await,
+        _ => apply(&args.subcommand, &args.socket_path).await?,
+    }
+
+    Ok(())
+}
 
-    // If the user has kernel settings, apply them.
-    let model = get_model(args.socket_path).await?;
+/// If the user has kernel settings, apply the ones for the given subcommand.
+async fn apply<P>(subcommand: &str, socket_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let model = get_model(socket_path).await?;
     if let Some(settings) = model.settings {
         if let Some(kernel) = settings.kernel {
-            match args.subcommand.as_ref() {
+            match subcommand {
                 "sysctl" => {
                     if let Some(sysctls) = kernel.sysctl {
                         debug!("Applying sysctls: {:#?}", sysctls);
@@ -49,7 +89,7 @@ async fn run() -> Result<()> {
                         set_lockdown(&lockdown)?;
                     }
                 }
-                _ => usage_msg(format!("Unknown subcommand '{}'", args.subcommand)), // should be unreachable
+                _ => usage_msg(format!("Unknown subcommand '{}'", subcommand)), // should be unreachable
             }
         }
     }
@@ -57,6 +97,54 @@ async fn run() -> Result<()> {
     Ok(())
 }
 
+/// Compares the kernel settings in effect against the settings from the API, and restores the
+/// configured values if requested.
+async fn verify<P>(socket_path: P, restore: bool) -> Result<DriftReport>
+where
+    P: AsRef<Path>,
+{
+    let model = get_model(socket_path).await?;
+    let mut report = match model.settings.and_then(|settings| settings.kernel) {
+        Some(kernel) => drift::check(&kernel),
+        None => DriftReport::default(),
+    };
+    if restore && !report.drift.is_empty() {
+        report.restore();
+    }
+    Ok(report)
+}
+
+/// Runs `verify` every interval, forever, printing each report as a line of JSON.  Errors are
+/// logged rather than returned so that a passing problem, like the API server restarting, doesn't
+/// end reconciliation.
+async fn reconcile<P>(socket_path: P, restore: bool, interval: Duration)
+where
+    P: AsRef<Path>,
+{
+    loop {
+        match verify(socket_path.as_ref(), restore).await {
+            Ok(report) => {
+                for drift in &report.drift {
+                    warn!(
+                        "{} '{}' is '{}' rather than '{}'{}",
+                        drift.kind,
+                        drift.name,
+                        drift.actual,
+                        drift.expected,
+                        if drift.restored { "; restored" } else { "" }
+                    );
+                }
+                match serde_json::to_string(&report) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => error!("Failed to serialize drift report: {}", e),
+                }
+            }
+            Err(e) => error!("Failed to check kernel settings for drift: {}", e),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 /// Retrieve the current model from the API.
@@ -85,17 +173,6 @@ where
     serde_json::from_str(&response_body).context(error::ResponseJsonSnafu { method, uri })
 }
 
-fn sysctl_path<S>(name: S) -> PathBuf
-where
-    S: AsRef<str>,
-{
-    let name = name.as_ref();
-    let mut path = PathBuf::from(SYSCTL_PATH_PREFIX);
-    path.extend(name.replace('.', "/").split('/'));
-    trace!("Path for {}: {}", name, path.display());
-    path
-}
-
 /// Applies the requested sysctls to the system.  The keys are used to generate the appropriate
 /// path, and the value its contents.
 fn set_sysctls<K>(sysctls: HashMap<K, String>)
@@ -141,27 +218,6 @@ fn set_lockdown(lockdown: &str) -> Result<()> {
     fs::write(LOCKDOWN_PATH, lockdown).context(error::LockdownSnafu { current, lockdown })
 }
 
-/// The Linux kernel provides human-readable output like `[none] integrity confidentiality` when
-/// you read settings from virtual files like /sys/kernel/security/lockdown.  This parses out the
-/// current value of the setting from that human-readable output.
-///
-/// There are also some files that only output the current value without the other options, so we
-/// return the output as-is (except for trimming whitespace) if there are no brackets.
-fn parse_kernel_setting(setting: &str) -> &str {
-    let mut setting = setting.trim();
-    // Take after the '['
-    if let Some(idx) = setting.find('[') {
-        if setting.len() > idx + 1 {
-            setting = &setting[idx + 1..];
-        }
-    }
-    // Take before the ']'
-    if let Some(idx) = setting.find(']') {
-        setting = &setting[..idx];
-    }
-    setting
-}
-
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 /// Print a usage message in the event a bad argument is given.
@@ -173,14 +229,18 @@ fn usage() -> ! {
     Subcommands:
         sysctl
         lockdown
+        verify [ --restore ]
+        reconcile [ --restore ] [ --interval SECONDS ]
 
     Global arguments:
         --socket-path PATH
         --log-level trace|debug|info|warn|error
 
-    Socket path defaults to {}",
+    Socket path defaults to {}
+    Reconcile interval defaults to {} seconds",
         program_name,
         constants::API_SOCKET,
+        DEFAULT_RECONCILE_INTERVAL_SECS,
     );
     process::exit(2);
 }
@@ -196,6 +256,8 @@ fn parse_args(args: env::Args) -> Args {
     let mut log_level = None;
     let mut socket_path = None;
     let mut subcommand = None;
+    let mut restore = false;
+    let mut interval = None;
 
     let mut iter = args.skip(1);
     while let Some(arg) = iter.next() {
@@ -216,16 +278,38 @@ fn parse_args(args: env::Args) -> Args {
                 )
             }
 
-            "sysctl" | "lockdown" => subcommand = Some(arg),
+            "--restore" => restore = true,
+
+            "--interval" => {
+                let interval_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to --interval"));
+                interval = match interval_str.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+                    _ => usage_msg(format!("Invalid interval '{}'", interval_str)),
+                };
+            }
+
+            "sysctl" | "lockdown" | "verify" | "reconcile" => subcommand = Some(arg),
 
             _ => usage(),
         }
     }
 
+    let subcommand = subcommand.unwrap_or_else(|| usage_msg("Must specify a subcommand."));
+    if restore && subcommand != "verify" && subcommand != "reconcile" {
+        usage_msg("--restore is only valid with 'verify' or 'reconcile'");
+    }
+    if interval.is_some() && subcommand != "reconcile" {
+        usage_msg("--interval is only valid with 'reconcile'");
+    }
+
     Args {
-        subcommand: subcommand.unwrap_or_else(|| usage_msg("Must specify a subcommand.")),
+        subcommand,
         log_level: log_level.unwrap_or(LevelFilter::Info),
         socket_path: socket_path.unwrap_or_else(|| constants::API_SOCKET.to_string()),
+        restore,
+        interval: interval.unwrap_or_else(|| Duration::from_secs(DEFAULT_RECONCILE_INTERVAL_SECS)),
     }
 }
 
@@ -281,6 +365,9 @@ mod error {
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
+        #[snafu(display("Unable to serialize drift report: {}", source))]
+        ReportJson { source: serde_json::Error },
+
         #[snafu(display(
             "Error deserializing response as JSON from {} to '{}': {}",
             method,
@@ -295,41 +382,3 @@ mod error {
     }
 }
 type Result<T> = std::result::Result<T, error::Error>;
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn no_traversal() {
-        assert_eq!(
-            sysctl_path("../../root/file").to_string_lossy(),
-            format!("{}/root/file", SYSCTL_PATH_PREFIX)
-        );
-    }
-
-    #[test]
-    fn brackets() {
-        assert_eq!(
-            "none",
-            parse_kernel_setting("[none] integrity confidentiality")
-        );
-        assert_eq!(
-            "integrity",
-            parse_kernel_setting("none [integrity] confidentiality\n")
-        );
-        assert_eq!(
-            "confidentiality",
-            parse_kernel_setting("none integrity [confidentiality]")
-        );
-    }
-
-    #[test]
-    fn no_brackets() {
-        assert_eq!("none", parse_kernel_setting("none"));
-        assert_eq!(
-            "none integrity confidentiality",
-            parse_kernel_setting("none integrity confidentiality\n")
-        );
-    }
-}
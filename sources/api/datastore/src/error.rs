@@ -59,6 +59,29 @@ pub enum Error {
 
     #[snafu(display("Key name beyond maximum length {}: {}", name, max))]
     KeyTooLong { name: String, max: usize },
+
+    #[snafu(display(
+        "History entry {} not found; only the last {} commits are kept",
+        id,
+        limit
+    ))]
+    HistoryNotFound { id: u64, limit: usize },
+
+    #[snafu(display(
+        "Can't revert history entry {}; earlier values of sensitive keys aren't kept: {}",
+        id,
+        keys
+    ))]
+    HistoryRedacted { id: u64, keys: String },
+
+    #[snafu(display("Unable to serialize history entry: {}", source))]
+    HistorySerialization { source: serde_json::Error },
+
+    #[snafu(display("History entry at '{}' is not valid: {}", path.display(), source))]
+    HistoryCorruption {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -4,15 +4,17 @@
 //! Data is kept in files with paths resembling the keys, e.g. a/b/c for a.b.c, and metadata is
 //! kept in a suffixed file next to the data, e.g. a/b/c.meta for metadata "meta" about a.b.c
 
+use chrono::Utc;
 use log::{debug, error, trace};
 use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{self, Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
+use super::history::{self, Change, HistoryEntry, HISTORY_LIMIT};
 use super::key::{Key, KeyType};
 use super::{error, Committed, DataStore, Result};
 
@@ -25,10 +27,14 @@ const METADATA_KEY_PREFIX: &str = ".";
 // allowed in a Key.
 const ENCODE_CHARACTERS: &AsciiSet = &NON_ALPHANUMERIC.remove(b'_').remove(b'-');
 
+// History entries are stored as JSON files named with their ID and this extension.
+const HISTORY_EXTENSION: &str = "json";
+
 #[derive(Debug)]
 pub struct FilesystemDataStore {
     live_path: PathBuf,
     pending_base_path: PathBuf,
+    history_path: PathBuf,
 }
 
 impl FilesystemDataStore {
@@ -36,9 +42,52 @@ impl FilesystemDataStore {
         FilesystemDataStore {
             live_path: base_path.as_ref().join("live"),
             pending_base_path: base_path.as_ref().join("pending"),
+            history_path: base_path.as_ref().join("history"),
         }
     }
 
+    /// Returns the path on the filesystem for the history entry with the given ID.
+    fn history_entry_path(&self, id: u64) -> PathBuf {
+        self.history_path
+            .join(format!("{}.{}", id, HISTORY_EXTENSION))
+    }
+
+    /// Returns the IDs of the history entries on disk, in ascending order.
+    fn history_ids(&self) -> Result<Vec<u64>> {
+        let entries = match fs::read_dir(&self.history_path) {
+            Ok(entries) => entries,
+            // No history has been recorded yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).context(error::IoSnafu {
+                    path: &self.history_path,
+                })
+            }
+        };
+
+        let mut ids = Vec::new();
+        for entry in entries {
+            let entry = entry.context(error::IoSnafu {
+                path: &self.history_path,
+            })?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(HISTORY_EXTENSION) {
+                continue;
+            }
+            let id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+                .context(error::CorruptionSnafu {
+                    msg: "History entry name is not an ID",
+                    path: &path,
+                })?;
+            ids.push(id);
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
     /// Returns the appropriate filesystem path for pending or live data.
     fn base_path(&self, committed: &Committed) -> PathBuf {
         match committed {
@@ -482,7 +531,12 @@ impl DataStore for FilesystemDataStore {
 
     /// We commit by copying pending keys to live, then removing pending.  Something smarter (lock,
     /// atomic flip, etc.) will be required to make the server concurrent.
-    fn commit_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
+    fn commit_transaction_removing<S>(
+        &mut self,
+        transaction: S,
+        remove: &HashSet<Key>,
+        sensitive: &dyn Fn(&str) -> bool,
+    ) -> Result<HashSet<Key>>
     where
         S: Into<String> + AsRef<str>,
     {
@@ -492,24 +546,58 @@ impl DataStore for FilesystemDataStore {
         // Get data for changed keys
         let pending_data = self.get_prefix("settings.", &pending)?;
 
-        // Nothing to do if no keys are present in pending
-        if pending_data.is_empty() {
+        // Keys set in the transaction aren't removed, and keys that aren't populated can't be
+        let mut remove_keys = HashSet::new();
+        for key in remove {
+            if !pending_data.contains_key(key) && self.key_populated(key, &Committed::Live)? {
+                remove_keys.insert(key.clone());
+            }
+        }
+
+        // Nothing to do if no keys are present in pending, and none are removed
+        if pending_data.is_empty() && remove_keys.is_empty() {
             return Ok(Default::default());
         }
 
         // Save Keys for return value
-        let pending_keys: HashSet<Key> = pending_data.keys().cloned().collect();
+        let mut changed_keys: HashSet<Key> = pending_data.keys().cloned().collect();
+
+        // Find what's changing so we can record it in history
+        let values = pending_data
+            .iter()
+            .map(|(key, value)| (key.name().clone(), Some(value.clone())))
+            .chain(remove_keys.iter().map(|key| (key.name().clone(), None)))
+            .collect();
+        let mut changes = history::changes_from(&values, |name| {
+            self.get_key(&Key::new(KeyType::Data, name)?, &Committed::Live)
+        })?;
+
+        // Record history before changing anything, so a change can't be applied without being
+        // recorded for reverts and conditional updates.
+        let transaction = match &pending {
+            Committed::Pending { tx } => tx,
+            Committed::Live => unreachable!("commit is always from a pending transaction"),
+        };
+        history::redact(&mut changes, sensitive);
+        self.record_history(transaction, changes)?;
 
         // Apply changes to live
         debug!("Writing pending keys to live");
         self.set_keys(&pending_data, &Committed::Live)?;
+        for key in remove_keys {
+            trace!("Removing data key {}", key);
+            self.unset_key(&key, &Committed::Live)?;
+            changed_keys.insert(key);
+        }
 
         // Remove pending
-        debug!("Removing old pending keys");
-        let path = self.base_path(&pending);
-        fs::remove_dir_all(&path).context(error::IoSnafu { path })?;
+        if !pending_data.is_empty() {
+            debug!("Removing old pending keys");
+            let path = self.base_path(&pending);
+            fs::remove_dir_all(&path).context(error::IoSnafu { path })?;
+        }
 
-        Ok(pending_keys)
+        Ok(changed_keys)
     }
 
     fn delete_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
@@ -581,6 +669,60 @@ impl DataStore for FilesystemDataStore {
 
         Ok(transactions)
     }
+
+    /// History entries are stored as JSON files named by ID under the history directory.
+    fn history(&self) -> Result<Vec<HistoryEntry>> {
+        let mut entries = Vec::new();
+        for id in self.history_ids()? {
+            let path = self.history_entry_path(id);
+            let data = fs::read_to_string(&path).context(error::IoSnafu { path: &path })?;
+            let entry = serde_json::from_str(&data)
+                .context(error::HistoryCorruptionSnafu { path: &path })?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// We only need the latest entry ID, so we don't have to read the entries themselves.
+    fn generation(&self) -> Result<u64> {
+        Ok(self.history_ids()?.last().copied().unwrap_or(0))
+    }
+
+    fn record_history(
+        &mut self,
+        transaction: &str,
+        changes: BTreeMap<String, Change>,
+    ) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let mut ids = self.history_ids()?;
+        let id = ids.last().map(|id| id + 1).unwrap_or(1);
+        let entry = HistoryEntry {
+            id,
+            transaction: transaction.to_string(),
+            timestamp: Utc::now(),
+            changes,
+        };
+        let data = serde_json::to_string(&entry).context(error::HistorySerializationSnafu)?;
+        debug!(
+            "Recording history entry {} for transaction '{}'",
+            id, transaction
+        );
+        write_file_mkdir(self.history_entry_path(id), data)?;
+        ids.push(id);
+
+        // Remove the oldest entries beyond the limit.
+        let excess = ids.len().saturating_sub(HISTORY_LIMIT);
+        for old_id in &ids[..excess] {
+            let path = self.history_entry_path(*old_id);
+            trace!("Removing old history entry at {}", path.display());
+            fs::remove_file(&path).context(error::DeleteKeySnafu { path: &path })?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -626,6 +768,115 @@ mod test {
         assert_eq!(live.into_os_string(), "/base/live/a/b/c.my-metadata");
     }
 
+    #[test]
+    fn history_is_recorded_and_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = FilesystemDataStore::new(dir.path());
+        let key = Key::new(KeyType::Data, "settings.a").unwrap();
+
+        for i in 0..HISTORY_LIMIT + 2 {
+            let pending = Committed::Pending { tx: "tx".into() };
+            f.set_key(&key, i.to_string(), &pending).unwrap();
+            f.commit_transaction("tx").unwrap();
+        }
+
+        // Committing the same value again doesn't add an entry.
+        let pending = Committed::Pending { tx: "tx".into() };
+        f.set_key(&key, (HISTORY_LIMIT + 1).to_string(), &pending)
+            .unwrap();
+        f.commit_transaction("tx").unwrap();
+
+        let history = f.history().unwrap();
+        assert_eq!(history.len(), HISTORY_LIMIT);
+        assert_eq!(history[0].id, 3);
+        let last = history.last().unwrap();
+        assert_eq!(last.id, HISTORY_LIMIT as u64 + 2);
+        assert_eq!(
+            last.changes["settings.a"],
+            Change {
+                old: Some(HISTORY_LIMIT.to_string()),
+                new: Some((HISTORY_LIMIT + 1).to_string()),
+                redacted: false,
+            }
+        );
+
+        // Revert to the state before the last entry.
+        f.revert_history(last.id, &|_| false).unwrap();
+        assert_eq!(
+            f.get_key(&key, &Committed::Live).unwrap(),
+            Some(HISTORY_LIMIT.to_string())
+        );
+    }
+
+    #[test]
+    fn history_redacts_sensitive_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = FilesystemDataStore::new(dir.path());
+        let secret = Key::new(KeyType::Data, "settings.secret").unwrap();
+        let other = Key::new(KeyType::Data, "settings.other").unwrap();
+        let sensitive = |key: &str| key == "settings.secret";
+
+        let pending = Committed::Pending { tx: "tx".into() };
+        f.set_key(&secret, "\"hunter2\"", &pending).unwrap();
+        f.set_key(&other, "\"a\"", &pending).unwrap();
+        f.commit_transaction_removing("tx", &HashSet::new(), &sensitive)
+            .unwrap();
+
+        // The secret is applied, but isn't written to history.
+        assert_eq!(
+            f.get_key(&secret, &Committed::Live).unwrap(),
+            Some("\"hunter2\"".to_string())
+        );
+        for entry in fs::read_dir(dir.path().join("history")).unwrap() {
+            let data = fs::read_to_string(entry.unwrap().path()).unwrap();
+            assert!(!data.contains("hunter2"));
+        }
+        let history = f.history().unwrap();
+        assert_eq!(
+            history[0].changes["settings.secret"],
+            Change {
+                old: None,
+                new: Some(history::REDACTED.to_string()),
+                redacted: true,
+            }
+        );
+
+        let pending = Committed::Pending { tx: "tx".into() };
+        f.set_key(&secret, "\"swordfish\"", &pending).unwrap();
+        f.set_key(&other, "\"b\"", &pending).unwrap();
+        f.commit_transaction_removing("tx", &HashSet::new(), &sensitive)
+            .unwrap();
+
+        // The earlier secret can't be restored, so the revert is refused without changing anything.
+        let id = f.history().unwrap().last().unwrap().id;
+        f.revert_history(id, &sensitive).unwrap_err();
+        assert_eq!(
+            f.get_key(&other, &Committed::Live).unwrap(),
+            Some("\"b\"".to_string())
+        );
+        assert_eq!(f.history().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn commit_fails_unchanged_without_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = FilesystemDataStore::new(dir.path());
+        let key = Key::new(KeyType::Data, "settings.a").unwrap();
+        let pending = Committed::Pending { tx: "tx".into() };
+        f.set_key(&key, "\"new\"", &pending).unwrap();
+
+        // History can't be written if its directory is a file.
+        fs::write(dir.path().join("history"), "").unwrap();
+        f.commit_transaction("tx").unwrap_err();
+
+        // Nothing was applied, and the transaction can still be committed later.
+        assert_eq!(f.get_key(&key, &Committed::Live).unwrap(), None);
+        assert_eq!(
+            f.get_key(&key, &pending).unwrap(),
+            Some("\"new\"".to_string())
+        );
+    }
+
     #[test]
     fn encode_path_component_works() {
         assert_eq!(encode_path_component("a-b_42"), "a-b_42");
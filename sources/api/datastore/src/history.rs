@@ -0,0 +1,281 @@
+//! The history module defines the record a data store keeps of committed transactions, which lets
+//! users see what changed and revert to an earlier state.
+//!
+//! Values are kept in their serialized datastore form, the same as `DataStore::get_key` returns.
+//! Values of sensitive keys, like credentials, are replaced with `REDACTED` so secrets aren't kept
+//! in history; those keys can't be reverted.
+//!
+//! Entry IDs also serve as the data store's "generation", which clients can use to detect whether
+//! keys changed since they read them.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// The number of committed transactions kept in history; older entries are removed as new ones
+/// are recorded.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Stands in for the values of sensitive keys in history, in serialized datastore form.
+pub const REDACTED: &str = "\"<redacted>\"";
+
+/// A record of a single committed transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Entry IDs increase with each commit, so they can be used to order entries.
+    pub id: u64,
+    /// The name of the transaction that was committed.
+    pub transaction: String,
+    pub timestamp: DateTime<Utc>,
+    /// Map of changed data key name to its old and new values.  Keys whose value didn't change
+    /// aren't included.
+    pub changes: BTreeMap<String, Change>,
+}
+
+/// The value of a data key before and after a commit; None means the key wasn't populated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// Whether the key is sensitive, so populated values were replaced with `REDACTED`.
+    #[serde(default)]
+    pub redacted: bool,
+}
+
+/// Given the history entries of a data store, returns the values each key would need to have to
+/// return to the state before the entry with the given ID was committed.  That's the old value
+/// from the earliest entry at or after the given ID that changed each key.  Returns None if there's
+/// no entry with the given ID.
+pub fn values_before(
+    entries: &[HistoryEntry],
+    id: u64,
+) -> Option<BTreeMap<String, Option<String>>> {
+    if !entries.iter().any(|entry| entry.id == id) {
+        return None;
+    }
+
+    let mut later: Vec<&HistoryEntry> = entries.iter().filter(|entry| entry.id >= id).collect();
+    later.sort_by_key(|entry| entry.id);
+
+    let mut values = BTreeMap::new();
+    for entry in later {
+        for (key, change) in &entry.changes {
+            values
+                .entry(key.clone())
+                .or_insert_with(|| change.old.clone());
+        }
+    }
+    Some(values)
+}
+
+/// Given the history entries of a data store, returns the names of the keys whose value before the
+/// entry with the given ID was redacted, so values_before can't be used to restore them.  Keys that
+/// weren't populated can still be restored, by removing them.
+pub fn redacted_before(entries: &[HistoryEntry], id: u64) -> BTreeSet<String> {
+    let mut later: Vec<&HistoryEntry> = entries.iter().filter(|entry| entry.id >= id).collect();
+    later.sort_by_key(|entry| entry.id);
+
+    let mut redacted = BTreeMap::new();
+    for entry in later {
+        for (key, change) in &entry.changes {
+            redacted
+                .entry(key.clone())
+                .or_insert(change.redacted && change.old.is_some());
+        }
+    }
+    redacted
+        .into_iter()
+        .filter(|(_, redacted)| *redacted)
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// Given the history entries of a data store, returns the names of the keys changed by entries
+/// after the given generation (entry ID).  Returns None if we can't tell, because entries after the
+/// given generation have already been removed from history, or because the generation is newer
+/// than any entry.
+pub fn changed_since(entries: &[HistoryEntry], generation: u64) -> Option<HashSet<String>> {
+    let latest = entries.iter().map(|entry| entry.id).max().unwrap_or(0);
+    let oldest = entries.iter().map(|entry| entry.id).min().unwrap_or(0);
+    if generation > latest || (oldest > 1 && generation + 1 < oldest) {
+        return None;
+    }
+
+    Some(
+        entries
+            .iter()
+            .filter(|entry| entry.id > generation)
+            .flat_map(|entry| entry.changes.keys().cloned())
+            .collect(),
+    )
+}
+
+/// Builds the changes that will be made by setting each key to the given value, skipping keys
+/// whose value wouldn't change.  `current` should return the existing value of a key.
+pub(crate) fn changes_from<F, E>(
+    values: &BTreeMap<String, Option<String>>,
+    mut current: F,
+) -> std::result::Result<BTreeMap<String, Change>, E>
+where
+    F: FnMut(&str) -> std::result::Result<Option<String>, E>,
+{
+    let mut changes = BTreeMap::new();
+    for (key, new) in values {
+        let old = current(key)?;
+        if &old != new {
+            changes.insert(
+                key.clone(),
+                Change {
+                    old,
+                    new: new.clone(),
+                    redacted: false,
+                },
+            );
+        }
+    }
+    Ok(changes)
+}
+
+/// Replaces the populated values of the changes to keys for which `sensitive` returns true with
+/// `REDACTED`, so they can be recorded in history.
+pub(crate) fn redact(changes: &mut BTreeMap<String, Change>, sensitive: &dyn Fn(&str) -> bool) {
+    for (key, change) in changes.iter_mut() {
+        if sensitive(key) {
+            for value in change.old.iter_mut().chain(change.new.iter_mut()) {
+                *value = REDACTED.to_string();
+            }
+            change.redacted = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::btreemap;
+
+    fn entry(id: u64, changes: BTreeMap<String, Change>) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            transaction: format!("tx{}", id),
+            timestamp: Utc::now(),
+            changes,
+        }
+    }
+
+    fn change(old: Option<&str>, new: Option<&str>) -> Change {
+        Change {
+            old: old.map(str::to_string),
+            new: new.map(str::to_string),
+            redacted: false,
+        }
+    }
+
+    #[test]
+    fn values_before_uses_earliest_old_value() {
+        let entries = vec![
+            entry(1, btreemap!("a".to_string() => change(None, Some("1")))),
+            entry(
+                2,
+                btreemap!(
+                    "a".to_string() => change(Some("1"), Some("2")),
+                    "b".to_string() => change(None, Some("x")),
+                ),
+            ),
+            entry(
+                3,
+                btreemap!("a".to_string() => change(Some("2"), Some("3"))),
+            ),
+        ];
+
+        assert_eq!(
+            values_before(&entries, 2).unwrap(),
+            btreemap!("a".to_string() => Some("1".to_string()), "b".to_string() => None)
+        );
+        assert_eq!(
+            values_before(&entries, 3).unwrap(),
+            btreemap!("a".to_string() => Some("2".to_string()))
+        );
+        assert_eq!(values_before(&entries, 4), None);
+    }
+
+    #[test]
+    fn changed_since_generation() {
+        let entries = vec![
+            entry(3, btreemap!("a".to_string() => change(None, Some("1")))),
+            entry(4, btreemap!("b".to_string() => change(None, Some("2")))),
+        ];
+
+        assert_eq!(changed_since(&entries, 4).unwrap(), HashSet::new());
+        assert_eq!(
+            changed_since(&entries, 3).unwrap(),
+            maplit::hashset!("b".to_string())
+        );
+        assert_eq!(changed_since(&entries, 2).unwrap().len(), 2);
+        // Entry 2 was removed from history, so we can't tell what changed after generation 1.
+        assert_eq!(changed_since(&entries, 1), None);
+        // Generations newer than the data store's aren't valid.
+        assert_eq!(changed_since(&entries, 5), None);
+        // Nothing has changed in a new data store.
+        assert_eq!(changed_since(&[], 0).unwrap(), HashSet::new());
+    }
+
+    #[test]
+    fn changes_from_skips_unchanged() {
+        let values = btreemap!(
+            "a".to_string() => Some("1".to_string()),
+            "b".to_string() => None,
+        );
+        let changes = changes_from::<_, ()>(&values, |key| {
+            Ok(if key == "a" {
+                Some("1".to_string())
+            } else {
+                Some("2".to_string())
+            })
+        })
+        .unwrap();
+        assert_eq!(
+            changes,
+            btreemap!("b".to_string() => change(Some("2"), None))
+        );
+    }
+
+    #[test]
+    fn redacts_sensitive_keys() {
+        let mut changes = btreemap!(
+            "settings.a".to_string() => change(Some("1"), Some("2")),
+            "settings.secret".to_string() => change(None, Some("\"hunter2\"")),
+        );
+        redact(&mut changes, &|key| key == "settings.secret");
+        assert_eq!(changes["settings.a"], change(Some("1"), Some("2")));
+        assert_eq!(
+            changes["settings.secret"],
+            Change {
+                old: None,
+                new: Some(REDACTED.to_string()),
+                redacted: true,
+            }
+        );
+
+        let mut later = btreemap!(
+            "settings.secret".to_string() => change(Some("\"hunter2\""), Some("\"swordfish\"")),
+        );
+        redact(&mut later, &|key| key == "settings.secret");
+        let entries = vec![
+            entry(1, changes),
+            entry(2, later),
+            entry(
+                3,
+                btreemap!("settings.a".to_string() => change(Some("2"), Some("3"))),
+            ),
+        ];
+        // The secret wasn't populated before entry 1, so it can be restored by removing it, but
+        // its value before entry 2 is unknown.
+        assert!(redacted_before(&entries, 1).is_empty());
+        assert_eq!(
+            redacted_before(&entries, 2),
+            maplit::btreeset!("settings.secret".to_string())
+        );
+        assert!(redacted_before(&entries, 3).is_empty());
+    }
+}
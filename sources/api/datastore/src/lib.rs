@@ -23,25 +23,28 @@ The `deserialization` module provides code to deserialize datastore-acceptable k
 # Current limitations
 
 * The user (e.g. apiserver) needs to handle locking.
-* There's no support for rolling back transactions.
+* Only the most recent committed transactions are kept in history for reverting; see `history::HISTORY_LIMIT`.
+* Values of keys the user marks sensitive are redacted in history, so they can't be reverted.
 * The `serialization` module can't handle complex types under lists; it assumes lists can be serialized as scalars.
 */
 
 pub mod deserialization;
 pub mod error;
 pub mod filesystem;
+pub mod history;
 pub mod key;
 pub mod memory;
 pub mod serialization;
 
 pub use error::{Error, Result};
 pub use filesystem::FilesystemDataStore;
+pub use history::{Change, HistoryEntry};
 pub use key::{Key, KeyType, KEY_SEPARATOR, KEY_SEPARATOR_STR};
 
 use log::trace;
 use serde::{Deserialize, Serialize};
-use snafu::OptionExt;
-use std::collections::{HashMap, HashSet};
+use snafu::{ensure, OptionExt};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Committed represents whether we want to look at pending (uncommitted) or live (committed) data
 /// in the datastore.
@@ -123,9 +126,28 @@ pub trait DataStore {
     /// Ok(()); we return Err only if we failed to check or remove the key.
     fn unset_metadata(&mut self, metadata_key: &Key, data_key: &Key) -> Result<()>;
 
-    /// Applies pending changes from the given transaction to the live datastore.  Returns the
-    /// list of changed keys.
+    /// Applies pending changes from the given transaction to the live datastore, and records the
+    /// changes in history.  History is recorded first, so if that fails, nothing is applied.
+    /// Every value is recorded as is; use `commit_transaction_removing` if some may be secret.
+    /// Returns the list of changed keys.
     fn commit_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
+    where
+        S: Into<String> + AsRef<str>,
+    {
+        self.commit_transaction_removing(transaction, &HashSet::new(), &|_| false)
+    }
+
+    /// Commits the given transaction like `commit_transaction`, and also removes the given data
+    /// keys from the live datastore, unless the transaction sets them.  The removals are recorded
+    /// in the same history entry as the rest of the commit, so they're applied, and reverted,
+    /// together.  The values of keys for which `sensitive` returns true are redacted in history.
+    /// Returns the list of changed keys, including the removed keys that were populated.
+    fn commit_transaction_removing<S>(
+        &mut self,
+        transaction: S,
+        remove: &HashSet<Key>,
+        sensitive: &dyn Fn(&str) -> bool,
+    ) -> Result<HashSet<Key>>
     where
         S: Into<String> + AsRef<str>;
 
@@ -138,6 +160,91 @@ pub trait DataStore {
     /// Returns a list of the names of any pending transactions in the data store.
     fn list_transactions(&self) -> Result<HashSet<String>>;
 
+    /// Returns the recorded history of committed transactions, oldest first.  Only the most
+    /// recent `history::HISTORY_LIMIT` entries are kept.
+    fn history(&self) -> Result<Vec<HistoryEntry>>;
+
+    /// Records a committed transaction in history under the next entry ID, removing the oldest
+    /// entries beyond `history::HISTORY_LIMIT`.  Nothing is recorded if there are no changes.
+    fn record_history(
+        &mut self,
+        transaction: &str,
+        changes: BTreeMap<String, Change>,
+    ) -> Result<()>;
+
+    /// Restores every key changed by the given history entry, or by any later entry, to the value
+    /// it had before that entry was committed.  The revert is recorded in history as a transaction
+    /// named "revert-ID", with the values of keys for which `sensitive` returns true redacted.
+    /// Fails without changing anything if the value to restore for any key was redacted.  Returns
+    /// the list of changed keys.
+    fn revert_history(
+        &mut self,
+        id: u64,
+        sensitive: &dyn Fn(&str) -> bool,
+    ) -> Result<HashSet<Key>> {
+        let entries = self.history()?;
+        let values = history::values_before(&entries, id).context(error::HistoryNotFoundSnafu {
+            id,
+            limit: history::HISTORY_LIMIT,
+        })?;
+        let redacted = history::redacted_before(&entries, id);
+        ensure!(
+            redacted.is_empty(),
+            error::HistoryRedactedSnafu {
+                id,
+                keys: redacted.into_iter().collect::<Vec<_>>().join(", "),
+            }
+        );
+
+        let mut changes = history::changes_from(&values, |name| {
+            let key = Key::new(KeyType::Data, name)?;
+            self.get_key(&key, &Committed::Live)
+        })?;
+        // Keep the values to restore before they're redacted for history.
+        let restore: BTreeMap<String, Option<String>> = changes
+            .iter()
+            .map(|(name, change)| (name.clone(), change.new.clone()))
+            .collect();
+        history::redact(&mut changes, sensitive);
+
+        // Record history first, so a change can't be applied without being recorded.
+        self.record_history(&format!("revert-{}", id), changes)?;
+
+        let mut changed_keys = HashSet::new();
+        for (name, value) in &restore {
+            let key = Key::new(KeyType::Data, name)?;
+            trace!("Reverting data key {}", name);
+            match value {
+                Some(value) => self.set_key(&key, value, &Committed::Live)?,
+                None => self.unset_key(&key, &Committed::Live)?,
+            }
+            changed_keys.insert(key);
+        }
+        Ok(changed_keys)
+    }
+
+    /// Returns the current generation of the data store, which increases with every commit that
+    /// changes a value.  This is the ID of the most recent history entry, or 0 if there is none.
+    fn generation(&self) -> Result<u64> {
+        Ok(self.history()?.last().map(|entry| entry.id).unwrap_or(0))
+    }
+
+    /// Returns the given data keys that were changed by a commit after the given generation.  If
+    /// history doesn't go back far enough to tell, or the generation is newer than the data
+    /// store's, all of the given keys are returned, since any of them may have changed.
+    fn changed_since(&self, keys: &HashSet<Key>, generation: u64) -> Result<HashSet<Key>> {
+        let entries = self.history()?;
+        let changed = match history::changed_since(&entries, generation) {
+            Some(changed) => changed,
+            None => return Ok(keys.clone()),
+        };
+        Ok(keys
+            .iter()
+            .filter(|key| changed.contains(key.name()))
+            .cloned()
+            .collect())
+    }
+
     /// Set multiple data keys at once in the data store.
     ///
     /// Implementers can replace the default implementation if there's a faster way than setting
@@ -287,6 +394,7 @@ mod test {
     use super::memory::MemoryDataStore;
     use super::{Committed, DataStore, Key, KeyType};
     use maplit::{hashmap, hashset};
+    use std::collections::HashSet;
 
     #[test]
     fn set_unset_keys() {
@@ -339,6 +447,104 @@ mod test {
         assert_eq!(m.get_metadata_raw(&meta, &grandchild).unwrap(), None);
     }
 
+    #[test]
+    fn revert_history() {
+        let mut m = MemoryDataStore::new();
+        let k1 = Key::new(KeyType::Data, "settings.a").unwrap();
+        let k2 = Key::new(KeyType::Data, "settings.b").unwrap();
+        m.set_key(&k1, "1", &Committed::Live).unwrap();
+
+        let pending = Committed::Pending { tx: "tx1".into() };
+        m.set_key(&k1, "2", &pending).unwrap();
+        m.set_key(&k2, "x", &pending).unwrap();
+        m.commit_transaction("tx1").unwrap();
+
+        let pending = Committed::Pending { tx: "tx2".into() };
+        m.set_key(&k1, "3", &pending).unwrap();
+        m.commit_transaction("tx2").unwrap();
+
+        let history = m.history().unwrap();
+        assert_eq!(history.len(), 2);
+        let first = history[0].id;
+
+        // Reverting the first entry undoes it and everything after it.
+        assert_eq!(
+            m.revert_history(first, &|_| false).unwrap(),
+            hashset!(k1.clone(), k2.clone())
+        );
+        assert_eq!(m.get_key(&k1, &Committed::Live).unwrap(), Some("1".into()));
+        assert_eq!(m.get_key(&k2, &Committed::Live).unwrap(), None);
+
+        // The revert is itself recorded.
+        let history = m.history().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[2].transaction, format!("revert-{}", first));
+
+        // Unknown entries are an error.
+        m.revert_history(12345, &|_| false).unwrap_err();
+    }
+
+    #[test]
+    fn commit_transaction_removing() {
+        let mut m = MemoryDataStore::new();
+        let k1 = Key::new(KeyType::Data, "settings.a").unwrap();
+        let k2 = Key::new(KeyType::Data, "settings.b").unwrap();
+        let k3 = Key::new(KeyType::Data, "settings.c").unwrap();
+        let k4 = Key::new(KeyType::Data, "settings.d").unwrap();
+        m.set_key(&k1, "1", &Committed::Live).unwrap();
+        m.set_key(&k3, "3", &Committed::Live).unwrap();
+        let pending = Committed::Pending { tx: "tx".into() };
+        m.set_key(&k3, "4", &pending).unwrap();
+        m.set_key(&k4, "5", &pending).unwrap();
+
+        // Only populated keys are removed, and keys set in the transaction are kept.
+        assert_eq!(
+            m.commit_transaction_removing("tx", &hashset!(k1.clone(), k2, k3.clone()), &|_| false)
+                .unwrap(),
+            hashset!(k1.clone(), k3.clone(), k4.clone())
+        );
+        assert_eq!(m.get_key(&k1, &Committed::Live).unwrap(), None);
+        assert_eq!(m.get_key(&k3, &Committed::Live).unwrap(), Some("4".into()));
+        assert_eq!(m.get_key(&k4, &Committed::Live).unwrap(), Some("5".into()));
+
+        // Everything is recorded in one entry, so it's reverted together.
+        let history = m.history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].transaction, "tx");
+        assert_eq!(history[0].changes["settings.a"].new, None);
+        assert_eq!(history[0].changes.len(), 3);
+
+        m.revert_history(history[0].id, &|_| false).unwrap();
+        assert_eq!(m.get_key(&k1, &Committed::Live).unwrap(), Some("1".into()));
+        assert_eq!(m.get_key(&k3, &Committed::Live).unwrap(), Some("3".into()));
+        assert_eq!(m.get_key(&k4, &Committed::Live).unwrap(), None);
+    }
+
+    #[test]
+    fn changed_since() {
+        let mut m = MemoryDataStore::new();
+        let k1 = Key::new(KeyType::Data, "settings.a").unwrap();
+        let k2 = Key::new(KeyType::Data, "settings.b").unwrap();
+        assert_eq!(m.generation().unwrap(), 0);
+
+        let pending = Committed::Pending { tx: "tx1".into() };
+        m.set_key(&k1, "1", &pending).unwrap();
+        m.commit_transaction("tx1").unwrap();
+        let generation = m.generation().unwrap();
+        assert_eq!(generation, 1);
+
+        let pending = Committed::Pending { tx: "tx2".into() };
+        m.set_key(&k2, "2", &pending).unwrap();
+        m.commit_transaction("tx2").unwrap();
+
+        let keys = hashset!(k1.clone(), k2.clone());
+        assert_eq!(m.changed_since(&keys, generation).unwrap(), hashset!(k2));
+        assert_eq!(m.changed_since(&keys, 0).unwrap(), keys);
+        assert_eq!(m.changed_since(&keys, 2).unwrap(), HashSet::new());
+        // Unknown generations are assumed to conflict.
+        assert_eq!(m.changed_since(&keys, 99).unwrap(), keys);
+    }
+
     #[test]
     fn get_prefix() {
         let mut m = MemoryDataStore::new();
@@ -3,8 +3,10 @@
 //! Mimics some of the decisions made for FilesystemDataStore, e.g. metadata being committed
 //! immediately.
 
-use std::collections::{HashMap, HashSet};
+use chrono::Utc;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
+use super::history::{self, Change, HistoryEntry, HISTORY_LIMIT};
 use super::{Committed, DataStore, Key, Result};
 
 #[derive(Debug, Default)]
@@ -16,6 +18,8 @@ pub struct MemoryDataStore {
     // Map of data keys to their metadata, which in turn is a mapping of metadata keys to
     // arbitrary (string/serialized) values.
     metadata: HashMap<Key, HashMap<Key, String>>,
+    // Committed transactions, oldest first.
+    history: Vec<HistoryEntry>,
 }
 
 impl MemoryDataStore {
@@ -146,19 +150,50 @@ impl DataStore for MemoryDataStore {
         Ok(())
     }
 
-    fn commit_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
+    fn commit_transaction_removing<S>(
+        &mut self,
+        transaction: S,
+        remove: &HashSet<Key>,
+        sensitive: &dyn Fn(&str) -> bool,
+    ) -> Result<HashSet<Key>>
     where
         S: Into<String> + AsRef<str>,
     {
         // Remove anything pending for this transaction
-        if let Some(pending) = self.pending.remove(transaction.as_ref()) {
-            // Apply pending changes to live
-            self.set_keys(&pending, &Committed::Live)?;
-            // Return keys that were committed
-            Ok(pending.keys().cloned().collect())
-        } else {
-            Ok(HashSet::new())
+        let pending = self
+            .pending
+            .remove(transaction.as_ref())
+            .unwrap_or_default();
+        let remove: HashSet<&Key> = remove
+            .iter()
+            .filter(|key| !pending.contains_key(*key))
+            .collect();
+
+        // Find what's changing so we can record it in history
+        let values = pending
+            .iter()
+            .map(|(key, value)| (key.name().clone(), Some(value.clone())))
+            .chain(remove.iter().map(|key| (key.name().clone(), None)))
+            .collect();
+        let mut changes = history::changes_from(&values, |name| {
+            Ok(self
+                .live
+                .get(&Key::new(super::KeyType::Data, name)?)
+                .cloned())
+        })?;
+        // Record history before applying, as the filesystem data store does
+        history::redact(&mut changes, sensitive);
+        self.record_history(transaction.as_ref(), changes)?;
+        // Apply pending changes and removals to live
+        self.set_keys(&pending, &Committed::Live)?;
+        let mut changed: HashSet<Key> = pending.into_keys().collect();
+        for key in remove {
+            if self.live.remove(key).is_some() {
+                changed.insert(key.clone());
+            }
         }
+        // Return keys that were committed
+        Ok(changed)
     }
 
     fn delete_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
@@ -177,6 +212,30 @@ impl DataStore for MemoryDataStore {
     fn list_transactions(&self) -> Result<HashSet<String>> {
         Ok(self.pending.keys().cloned().collect())
     }
+
+    fn history(&self) -> Result<Vec<HistoryEntry>> {
+        Ok(self.history.clone())
+    }
+
+    fn record_history(
+        &mut self,
+        transaction: &str,
+        changes: BTreeMap<String, Change>,
+    ) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let id = self.history.last().map(|entry| entry.id + 1).unwrap_or(1);
+        self.history.push(HistoryEntry {
+            id,
+            transaction: transaction.to_string(),
+            timestamp: Utc::now(),
+            changes,
+        });
+        let excess = self.history.len().saturating_sub(HISTORY_LIMIT);
+        self.history.drain(..excess);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -8,6 +8,61 @@ something like an instance metadata service.
 
 Currently, Amazon EC2 is supported through the IMDSv1 HTTP API.  Data will be taken from files in
 /etc/early-boot-config instead, if available, for testing purposes.
+
+# Sources
+
+Settings are gathered from these sources on every platform, in order of increasing precedence:
+* `/usr/share/early-boot-config/user-data.toml`, user data baked into the image
+* `/var/lib/bottlerocket/user-data.toml`
+* `*.toml` fragments in `/var/lib/bottlerocket/user-data.d`, in order by filename
+* the platform's own sources, like the instance identity document and user data from IMDS on
+  Amazon EC2, the CD-ROM and guestinfo on VMware, or a cloud-init seed image and a URL on bare
+  metal
+* a file named on the kernel command line with `early-boot-config.user-data=/path/to/file.toml`
+
+On bare metal, a NoCloud seed labeled `cidata` or a config drive labeled `config-2` is mounted
+read-only if attached.
+Its user data must be Bottlerocket TOML rather than cloud-config; the hostname from its meta-data
+is applied with lower precedence than its user data.
+
+Bare metal hosts can also fetch user data over HTTP(S), from a URL given on the kernel command line
+with `early-boot-config.user-data-url=https://example.com/user-data.toml`.
+The user data, which may be compressed, must have a detached Ed25519 signature at the same URL with
+`.sig` appended, raw or base64-encoded.
+It's verified against the base64-encoded public key in `/usr/share/early-boot-config/user-data.pub`
+before it's applied, and early-boot-config fails if the signature isn't valid.
+Connection errors, server errors, and `429 Too Many Requests` responses are retried for up to five
+minutes; other errors, like a missing signature, aren't retried.
+If the fetch fails, only the local sources are applied.
+
+Each source is TOML with a `settings` table, and may be compressed.
+The sources are merged into a single change for the API.
+Tables are merged key by key, so a source only overrides the settings it gives; other values,
+including lists, are replaced entirely by a source with higher precedence.
+
+Secrets in user data, like passwords and tokens, can be encrypted to the host's key with
+[age](https://age-encryption.org), using an X25519 recipient and ASCII armor, and given as a
+multi-line string:
+
+```toml
+[settings.kubernetes]
+bootstrap-token = """
+-----BEGIN AGE ENCRYPTED FILE-----
+...
+-----END AGE ENCRYPTED FILE-----
+"""
+```
+
+Encrypted values are decrypted after the sources are merged, with the age identities in
+`/var/lib/bottlerocket/secret-key.txt`, or in a file named on the kernel command line with
+`early-boot-config.secret-key=/path/to/key.txt`.
+The decrypted settings are marked sensitive in the API, so `apiclient get` and `logdog` redact
+their values by default.
+
+The result of the merge is recorded in `/var/lib/bottlerocket/user-data-sources.json`, which lists
+the sources that were found and, for each setting, the source that set it and any sources it
+overrode.
+Values aren't recorded, since user data can contain secrets.
 */
 
 #[macro_use]
@@ -15,14 +70,19 @@ extern crate log;
 
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ensure, ResultExt};
-use std::fs;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
 use std::str::FromStr;
 use std::{env, process};
 
 mod compression;
+mod merge;
 mod provider;
+mod secrets;
 mod settings;
-use crate::provider::{Platform, PlatformDataProvider};
+use crate::merge::MergedSettings;
+use crate::provider::Platform;
 
 // TODO
 // Tests!
@@ -32,6 +92,9 @@ use crate::provider::{Platform, PlatformDataProvider};
 // We create it after running successfully.
 const MARKER_FILE: &str = "/var/lib/bottlerocket/early-boot-config.ran";
 
+// Records which source set each setting, so it can be inspected after boot.
+const PROVENANCE_FILE: &str = "/var/lib/bottlerocket/user-data-sources.json";
+
 /// Store the args we receive on the command line
 #[derive(Debug)]
 struct Args {
@@ -104,38 +167,64 @@ async fn run() -> Result<()> {
     info!("early-boot-config started");
 
     info!("Retrieving platform-specific data");
-    let uri = &format!(
-        "{}?tx={}",
-        constants::API_SETTINGS_URI,
-        constants::LAUNCH_TRANSACTION
-    );
-    let method = "PATCH";
-    for settings_json in Platform
-        .platform_data()
+    let mut merged = MergedSettings::default();
+    for settings_json in provider::settings_sources(&Platform)
         .await
         .context(error::ProviderSnafu)?
     {
-        // Don't send an empty request to the API
-        if settings_json.json.is_empty() {
+        if settings_json
+            .json
+            .as_object()
+            .map_or(false, |m| m.is_empty())
+        {
             warn!("{} was empty", settings_json.desc);
             continue;
         }
+        info!("Merging {}", settings_json.desc);
+        merged.merge(&settings_json);
+    }
 
-        info!("Sending {} to API", settings_json.desc);
-        trace!("Request body: {}", settings_json.json);
-        let (code, response_body) =
-            apiclient::raw_request(&args.socket_path, uri, method, Some(settings_json.json))
-                .await
-                .context(error::APIRequestSnafu { method, uri })?;
-        ensure!(
-            code.is_success(),
-            error::ResponseSnafu {
-                method,
-                uri,
-                code,
-                response_body,
-            }
+    let sensitive =
+        secrets::decrypt_settings(&mut merged.settings, provider::cmdline::KERNEL_CMDLINE)
+            .context(error::DecryptSnafu)?;
+
+    write_provenance(&merged)?;
+
+    // Don't send an empty request to the API
+    if merged.settings.is_empty() {
+        warn!("No settings found in any source");
+    } else {
+        // Mark decrypted settings sensitive before setting them, so their values are redacted
+        // from the start, including in the audit log entry for the change.
+        if !sensitive.is_empty() {
+            info!("Marking {} decrypted settings sensitive", sensitive.len());
+            let body = serde_json::to_string(&sensitive).context(error::SerializeSnafu {
+                what: "sensitive settings",
+            })?;
+            api_request(
+                &args.socket_path,
+                constants::API_SENSITIVE_SETTINGS_URI,
+                "POST",
+                body,
+            )
+            .await?;
+        }
+
+        let uri = &format!(
+            "{}?tx={}",
+            constants::API_SETTINGS_URI,
+            constants::LAUNCH_TRANSACTION
         );
+        let body = serde_json::to_string(&merged.settings).context(error::SerializeSnafu {
+            what: "merged settings",
+        })?;
+
+        info!("Sending merged settings to API");
+        // Decrypted values aren't logged.
+        if sensitive.is_empty() {
+            trace!("Request body: {}", body);
+        }
+        api_request(&args.socket_path, uri, "PATCH", body).await?;
     }
 
     fs::write(MARKER_FILE, "").unwrap_or_else(|e| {
@@ -148,6 +237,42 @@ async fn run() -> Result<()> {
     Ok(())
 }
 
+/// Sends a request to the API, failing unless it succeeds.
+async fn api_request(socket_path: &str, uri: &str, method: &str, body: String) -> Result<()> {
+    let (code, response_body) = apiclient::raw_request(socket_path, uri, method, Some(body))
+        .await
+        .context(error::APIRequestSnafu { method, uri })?;
+    ensure!(
+        code.is_success(),
+        error::ResponseSnafu {
+            method,
+            uri,
+            code,
+            response_body,
+        }
+    );
+    Ok(())
+}
+
+/// Writes the record of which source set each setting.  It's only readable by root, like the
+/// sources themselves.
+fn write_provenance(merged: &MergedSettings) -> Result<()> {
+    let provenance =
+        serde_json::to_string_pretty(&merged.provenance()).context(error::SerializeSnafu {
+            what: "user data sources",
+        })?;
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(PROVENANCE_FILE)
+        .and_then(|mut f| f.write_all(provenance.as_bytes()))
+        .context(error::ProvenanceWriteSnafu {
+            path: PROVENANCE_FILE,
+        })
+}
+
 // Returning a Result from main makes it print a Debug representation of the error, but with Snafu
 // we have nice Display representations of the error, so we wrap "main" (run) and print any error.
 // https://github.com/shepmaster/snafu/issues/110
@@ -174,6 +299,19 @@ mod error {
             source: Box<apiclient::Error>,
         },
 
+        #[snafu(display("Failed to decrypt user data: {}", source))]
+        Decrypt { source: crate::secrets::Error },
+
+        #[snafu(display(
+            "Failed to write record of user data sources to '{}': {}",
+            path,
+            source
+        ))]
+        ProvenanceWrite {
+            path: String,
+            source: std::io::Error,
+        },
+
         #[snafu(display("Provider error: {}", source))]
         Provider { source: Box<dyn std::error::Error> },
 
@@ -187,6 +325,12 @@ mod error {
 
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
+
+        #[snafu(display("Failed to serialize {}: {}", what, source))]
+        Serialize {
+            what: String,
+            source: serde_json::Error,
+        },
     }
 }
 
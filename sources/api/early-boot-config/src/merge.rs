@@ -0,0 +1,227 @@
+//! The merge module combines the settings from each source into a single change for the API, and
+//! keeps track of which source set each setting.
+
+use crate::settings::SettingsJson;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Settings merged from sources in order of increasing precedence.
+#[derive(Debug, Default)]
+pub(crate) struct MergedSettings {
+    pub(crate) settings: Map<String, Value>,
+    sources: Vec<String>,
+    setting_sources: BTreeMap<Vec<String>, SettingSource>,
+}
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SettingSource {
+    /// The source whose value was used.
+    pub(crate) source: String,
+    /// Sources that also set the setting, whose values were overridden, in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) overrides: Vec<String>,
+}
+
+/// A record of the merge, saved so it can be inspected after boot.  It only names sources and
+/// settings, not values, because user data can contain secrets.
+#[derive(Debug, Serialize)]
+pub(crate) struct Provenance<'a> {
+    /// The sources that provided settings, from lowest to highest precedence.
+    pub(crate) sources: &'a [String],
+    /// The source of each setting, by its full name, like "settings.motd".
+    pub(crate) settings: BTreeMap<String, &'a SettingSource>,
+}
+
+impl MergedSettings {
+    /// Merges in the settings from a source with higher precedence than any merged so far.
+    /// Tables are merged key by key, so a source only overrides the settings it gives; any other
+    /// value, including a list, replaces the previous value entirely.
+    pub(crate) fn merge(&mut self, source: &SettingsJson) {
+        if let Value::Object(from) = &source.json {
+            let mut path = vec!["settings".to_string()];
+            Self::merge_table(
+                &mut self.settings,
+                from,
+                &source.desc,
+                &mut path,
+                &mut self.setting_sources,
+            );
+        }
+        self.sources.push(source.desc.clone());
+    }
+
+    fn merge_table(
+        into: &mut Map<String, Value>,
+        from: &Map<String, Value>,
+        desc: &str,
+        path: &mut Vec<String>,
+        setting_sources: &mut BTreeMap<Vec<String>, SettingSource>,
+    ) {
+        for (key, value) in from {
+            path.push(key.clone());
+            match (into.get_mut(key), value) {
+                (Some(Value::Object(into_table)), Value::Object(from_table)) => {
+                    Self::merge_table(into_table, from_table, desc, path, setting_sources)
+                }
+                (None, Value::Object(from_table)) => {
+                    let mut into_table = Map::new();
+                    Self::merge_table(&mut into_table, from_table, desc, path, setting_sources);
+                    into.insert(key.clone(), Value::Object(into_table));
+                }
+                _ => {
+                    // Anything previously set at or below this key is replaced.
+                    let replaced: Vec<Vec<String>> = setting_sources
+                        .keys()
+                        .filter(|setting| setting.starts_with(path))
+                        .cloned()
+                        .collect();
+                    let mut overrides = Vec::new();
+                    for setting in replaced {
+                        if let Some(previous) = setting_sources.remove(&setting) {
+                            for name in previous
+                                .overrides
+                                .into_iter()
+                                .chain(std::iter::once(previous.source))
+                            {
+                                if !overrides.contains(&name) {
+                                    overrides.push(name);
+                                }
+                            }
+                        }
+                    }
+
+                    into.insert(key.clone(), value.clone());
+                    setting_sources.insert(
+                        path.clone(),
+                        SettingSource {
+                            source: desc.to_string(),
+                            overrides,
+                        },
+                    );
+                }
+            }
+            path.pop();
+        }
+    }
+
+    /// Returns the record of which source set each setting.
+    pub(crate) fn provenance(&self) -> Provenance<'_> {
+        Provenance {
+            sources: &self.sources,
+            settings: self
+                .setting_sources
+                .iter()
+                .map(|(path, source)| (setting_name(path), source))
+                .collect(),
+        }
+    }
+}
+
+/// Joins the segments of a setting's path into its name, quoting segments that contain dots, the
+/// way the API and apiclient write them.
+pub(crate) fn setting_name(path: &[String]) -> String {
+    path.iter()
+        .map(|segment| {
+            if segment.contains('.') {
+                format!("\"{}\"", segment)
+            } else {
+                segment.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn source(desc: &str, json: Value) -> SettingsJson {
+        SettingsJson {
+            json,
+            desc: desc.to_string(),
+        }
+    }
+
+    fn merged(sources: &[SettingsJson]) -> MergedSettings {
+        let mut merged = MergedSettings::default();
+        for source in sources {
+            merged.merge(source);
+        }
+        merged
+    }
+
+    #[test]
+    fn later_sources_win() {
+        let merged = merged(&[
+            source(
+                "base",
+                json!({"motd": "base", "kernel": {"sysctl": {"vm.max_map_count": "1"}}}),
+            ),
+            source(
+                "drop-in",
+                json!({"motd": "drop-in", "ntp": {"time-servers": ["a"]}}),
+            ),
+            source(
+                "imds",
+                json!({"kernel": {"lockdown": "integrity"}, "motd": "imds"}),
+            ),
+        ]);
+
+        assert_eq!(
+            Value::Object(merged.settings.clone()),
+            json!({
+                "motd": "imds",
+                "kernel": {"sysctl": {"vm.max_map_count": "1"}, "lockdown": "integrity"},
+                "ntp": {"time-servers": ["a"]},
+            })
+        );
+
+        let provenance = merged.provenance();
+        assert_eq!(provenance.sources, &["base", "drop-in", "imds"]);
+        assert_eq!(
+            provenance.settings["settings.motd"],
+            &SettingSource {
+                source: "imds".to_string(),
+                overrides: vec!["base".to_string(), "drop-in".to_string()],
+            }
+        );
+        assert_eq!(
+            provenance.settings["settings.kernel.sysctl.\"vm.max_map_count\""].source,
+            "base"
+        );
+        assert_eq!(
+            provenance.settings["settings.kernel.lockdown"].source,
+            "imds"
+        );
+    }
+
+    #[test]
+    fn lists_are_replaced() {
+        let merged = merged(&[
+            source("first", json!({"ntp": {"time-servers": ["a", "b"]}})),
+            source("second", json!({"ntp": {"time-servers": ["c"]}})),
+        ]);
+        assert_eq!(merged.settings["ntp"]["time-servers"], json!(["c"]));
+    }
+
+    #[test]
+    fn table_replaced_by_value() {
+        let merged = merged(&[
+            source("first", json!({"a": {"b": 1, "c": 2}})),
+            source("second", json!({"a": {"b": 3}})),
+            source("third", json!({"a": "flat"})),
+        ]);
+        assert_eq!(merged.settings["a"], json!("flat"));
+
+        let provenance = merged.provenance();
+        assert_eq!(provenance.settings.len(), 1);
+        assert_eq!(
+            provenance.settings["settings.a"].overrides,
+            vec!["first", "second"]
+        );
+    }
+}
@@ -1,10 +1,19 @@
-//! The provider module owns the `PlatformDataProvider` trait
+//! The provider module owns the `PlatformDataProvider` trait, and the list of sources that
+//! settings are gathered from on every platform.
 
 use crate::settings::SettingsJson;
 use async_trait::async_trait;
 
+pub(crate) mod cmdline;
 mod local_file;
 
+#[cfg(any(variant_platform = "vmware", variant_platform = "metal"))]
+mod cdrom;
+#[cfg(variant_platform = "metal")]
+mod nocloud;
+#[cfg(variant_platform = "metal")]
+mod remote;
+
 #[cfg(variant_platform = "aws")]
 mod aws;
 #[cfg(variant_platform = "aws")]
@@ -32,3 +41,52 @@ pub(crate) trait PlatformDataProvider {
         &self,
     ) -> std::result::Result<Vec<SettingsJson>, Box<dyn std::error::Error>>;
 }
+
+/// Gathers settings from every source, in order of increasing precedence:
+/// * the base user data file baked into the image
+/// * the local user data file
+/// * fragments in the user data drop-in directory, in order by filename
+/// * the platform's own sources, like IMDS, guestinfo, or a cloud-init seed image
+/// * a user data file named on the kernel command line
+pub(crate) async fn settings_sources<P>(
+    platform: &P,
+) -> std::result::Result<Vec<SettingsJson>, Box<dyn std::error::Error>>
+where
+    P: PlatformDataProvider + Sync,
+{
+    let mut output = Vec::new();
+
+    match local_file::user_data_from_file(local_file::BASE_USER_DATA_FILE)? {
+        Some(s) => output.push(s),
+        None => debug!(
+            "No base user data found: {}",
+            local_file::BASE_USER_DATA_FILE
+        ),
+    }
+
+    match local_file::user_data_from_file(local_file::USER_DATA_FILE)? {
+        Some(s) => output.push(s),
+        None => warn!(
+            "No user data found via local file: {}",
+            local_file::USER_DATA_FILE
+        ),
+    }
+
+    let drop_ins = local_file::drop_in_user_data()?;
+    if drop_ins.is_empty() {
+        debug!(
+            "No user data found in drop-in directory: {}",
+            local_file::USER_DATA_DIR
+        );
+    }
+    output.extend(drop_ins);
+
+    output.extend(platform.platform_data().await?);
+
+    match cmdline::cmdline_user_data(cmdline::KERNEL_CMDLINE)? {
+        Some(s) => output.push(s),
+        None => debug!("No user data named on kernel command line"),
+    }
+
+    Ok(output)
+}
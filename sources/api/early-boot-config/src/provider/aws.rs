@@ -9,8 +9,6 @@ use snafu::{OptionExt, ResultExt};
 use std::fs;
 use std::path::Path;
 
-use crate::provider::local_file::{local_file_user_data, USER_DATA_FILE};
-
 /// Unit struct for AWS so we can implement the PlatformDataProvider trait.
 pub(crate) struct AwsDataProvider;
 
@@ -33,7 +31,7 @@ impl AwsDataProvider {
             .context(error::DecompressionSnafu { what: "user data" })?;
         trace!("Received user data: {}", user_data_str);
 
-        let json = SettingsJson::from_toml_str(&user_data_str, "user data").context(
+        let json = SettingsJson::from_toml_str(&user_data_str, "user data from IMDS").context(
             error::SettingsToJSONSnafu {
                 from: "instance user data",
             },
@@ -92,13 +90,7 @@ impl PlatformDataProvider for AwsDataProvider {
 
         let mut client = ImdsClient::new();
 
-        // Attempt to read from local file first
-        match local_file_user_data()? {
-            Some(s) => output.push(s),
-            None => warn!("No user data found via local file: {}", USER_DATA_FILE),
-        }
-
-        // Instance identity doc next, so the user has a chance to override
+        // Instance identity doc first, so the user has a chance to override
         match Self::identity_document(&mut client).await? {
             Some(s) => output.push(s),
             None => warn!("No instance identity document found."),
@@ -0,0 +1,104 @@
+//! The cdrom module provides methods for gathering userdata from files on mounted media, like a
+//! CD-ROM attached to a VMware guest or a cloud-init seed image.
+
+use super::SettingsJson;
+use crate::compression::expand_file_maybe;
+use snafu::{ensure, ResultExt};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+
+/// Finds the file in a directory with one of the given names.  Media are expected to have at most
+/// one of them.
+pub(crate) fn find_user_data_file<P>(dir: P, filenames: &[&str]) -> Result<Option<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let mut user_data_files = filenames
+        .iter()
+        .map(|filename| dir.join(filename))
+        .filter(|file| file.exists());
+
+    let user_data_file = match user_data_files.next() {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    ensure!(
+        user_data_files.next().is_none(),
+        error::UserDataFileCountSnafu { place: dir }
+    );
+
+    Ok(Some(user_data_file))
+}
+
+/// Reads a TOML user data file, decompressing it if compressed, returning a SettingsJson with the
+/// given description if it's not empty.
+pub(crate) fn read_user_data_file<P>(path: P, desc: &str) -> Result<Option<SettingsJson>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    info!("'{}' exists, using it", path.display());
+    let user_data_str = expand_file_maybe(path).context(error::InputFileReadSnafu { path })?;
+    user_data_from_str(&user_data_str, path, desc)
+}
+
+/// Parses TOML user data read from the given path, returning a SettingsJson with the given
+/// description if it's not empty.
+pub(crate) fn user_data_from_str<P>(
+    user_data_str: &str,
+    path: P,
+    desc: &str,
+) -> Result<Option<SettingsJson>>
+where
+    P: AsRef<Path>,
+{
+    if user_data_str.is_empty() {
+        return Ok(None);
+    }
+
+    // User data could be 700MB compressed!  Eek!  :)
+    if user_data_str.len() <= 2048 {
+        trace!("Received user data: {}", user_data_str);
+    } else {
+        trace!(
+            "Received long user data, starts with: {}",
+            // (this isn't perfect because chars aren't grapheme clusters, but will error
+            // toward printing the whole input, which is fine)
+            String::from_iter(user_data_str.chars().take(2048))
+        );
+    }
+
+    let json =
+        SettingsJson::from_toml_str(user_data_str, desc).context(error::SettingsToJsonSnafu {
+            from: path.as_ref().display().to_string(),
+        })?;
+
+    Ok(Some(json))
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Unable to read input file '{}': {}", path.display(), source))]
+        InputFileRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Unable to serialize settings from {}: {}", from, source))]
+        SettingsToJson {
+            from: String,
+            source: crate::settings::Error,
+        },
+
+        #[snafu(display("Found multiple user data files in '{}', expected 1", place.display()))]
+        UserDataFileCount { place: PathBuf },
+    }
+}
+
+pub(crate) use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
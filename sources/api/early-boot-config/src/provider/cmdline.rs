@@ -0,0 +1,124 @@
+//! The cmdline module provides a method for gathering userdata from a file named on the kernel
+//! command line, like `early-boot-config.user-data=/path/to/user-data.toml`.
+
+use super::SettingsJson;
+use crate::provider::local_file::user_data_from_file;
+use snafu::{ensure, ResultExt};
+use std::fs;
+use std::path::Path;
+
+pub(crate) const KERNEL_CMDLINE: &str = "/proc/cmdline";
+const USER_DATA_PREFIX: &str = "early-boot-config.user-data=";
+
+/// Reads user data from the file named on the given kernel command line, if any.
+pub(crate) fn cmdline_user_data<P>(path: P) -> Result<Option<SettingsJson>>
+where
+    P: AsRef<Path>,
+{
+    let kernel_cmdline = read_kernel_cmdline(path)?;
+    let user_data_path = match param_value(&kernel_cmdline, USER_DATA_PREFIX)? {
+        Some(user_data_path) => user_data_path,
+        None => return Ok(None),
+    };
+    info!(
+        "Kernel command line points to user data at '{}'",
+        user_data_path
+    );
+
+    // Unlike the other local sources, the user asked for this file specifically, so it's an error
+    // if it's missing.
+    ensure!(
+        Path::new(user_data_path).exists(),
+        error::MissingFileSnafu {
+            path: user_data_path
+        }
+    );
+    user_data_from_file(user_data_path).context(error::LocalFileSnafu)
+}
+
+/// Reads the kernel command line from the given path.
+pub(crate) fn read_kernel_cmdline<P>(path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    fs::read_to_string(path).context(error::KernelCmdlineReadSnafu { path })
+}
+
+/// Finds the value of the parameter with the given prefix, like `early-boot-config.user-data=`,
+/// on a kernel command line.  It may only be given once.
+pub(crate) fn param_value<'a>(kernel_cmdline: &'a str, prefix: &str) -> Result<Option<&'a str>> {
+    let mut values = kernel_cmdline
+        .split_whitespace()
+        .filter_map(|s| s.strip_prefix(prefix));
+
+    let value = match values.next() {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    ensure!(
+        values.next().is_none(),
+        error::MultipleParamSnafu {
+            param: prefix.trim_end_matches('=')
+        }
+    );
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_path() {
+        let cmdline = "BOOT_IMAGE=(hd0,gpt3)/vmlinuz root=/dev/dm-0 \
+                       early-boot-config.user-data=/local/user-data.toml quiet";
+        assert_eq!(
+            param_value(cmdline, USER_DATA_PREFIX).unwrap(),
+            Some("/local/user-data.toml")
+        );
+    }
+
+    #[test]
+    fn no_path() {
+        assert_eq!(
+            param_value("root=/dev/dm-0 quiet", USER_DATA_PREFIX).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn multiple_paths() {
+        assert!(param_value(
+            "early-boot-config.user-data=/a.toml early-boot-config.user-data=/b.toml",
+            USER_DATA_PREFIX
+        )
+        .is_err());
+    }
+}
+
+pub(crate) mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Failed to read kernel command line from '{}': {}", path.display(), source))]
+        KernelCmdlineRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display("{}", source))]
+        LocalFile {
+            source: crate::provider::local_file::Error,
+        },
+
+        #[snafu(display("User data file '{}' from kernel command line does not exist", path.display()))]
+        MissingFile { path: PathBuf },
+
+        #[snafu(display("'{}' given multiple times on kernel command line, expected 1", param))]
+        MultipleParam { param: String },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
@@ -1,37 +1,70 @@
-//! The local_file module provides a method for gathering userdata from local file
+//! The local_file module provides methods for gathering userdata from local files
 
 use super::SettingsJson;
 use crate::compression::expand_file_maybe;
 use snafu::ResultExt;
+use std::fs;
 use std::path::Path;
 
+/// User data baked into the image, which every other source can override.
+pub(crate) const BASE_USER_DATA_FILE: &str = "/usr/share/early-boot-config/user-data.toml";
 pub(crate) const USER_DATA_FILE: &str = "/var/lib/bottlerocket/user-data.toml";
+/// A directory of user data fragments, named like `10-example.toml`, applied in order by name.
+pub(crate) const USER_DATA_DIR: &str = "/var/lib/bottlerocket/user-data.d";
 
-pub(crate) fn local_file_user_data(
-) -> std::result::Result<Option<SettingsJson>, Box<dyn std::error::Error>> {
-    if !Path::new(USER_DATA_FILE).exists() {
+/// Reads user data from the given TOML file, which may be compressed, if it exists.
+pub(crate) fn user_data_from_file<P>(path: P) -> Result<Option<SettingsJson>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if !path.exists() {
         return Ok(None);
     }
-    info!("'{}' exists, using it", USER_DATA_FILE);
+    info!("'{}' exists, using it", path.display());
 
     // Read the file, decompressing it if compressed.
-    let user_data_str = expand_file_maybe(USER_DATA_FILE).context(error::InputFileReadSnafu {
-        path: USER_DATA_FILE,
-    })?;
+    let user_data_str = expand_file_maybe(path).context(error::InputFileReadSnafu { path })?;
 
     if user_data_str.is_empty() {
         return Ok(None);
     }
 
-    let json = SettingsJson::from_toml_str(&user_data_str, "user data").context(
-        error::SettingsToJSONSnafu {
-            from: USER_DATA_FILE,
-        },
-    )?;
+    let json =
+        SettingsJson::from_toml_str(&user_data_str, format!("user data from {}", path.display()))
+            .context(error::SettingsToJSONSnafu {
+            from: path.display().to_string(),
+        })?;
 
     Ok(Some(json))
 }
 
+/// Reads user data from each `.toml` file in the drop-in directory, in order by filename, so
+/// later files take precedence.
+pub(crate) fn drop_in_user_data() -> Result<Vec<SettingsJson>> {
+    let dir = Path::new(USER_DATA_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).context(error::ListFilesSnafu { dir })? {
+        let path = entry.context(error::ListFilesSnafu { dir })?.path();
+        if path.extension().map_or(false, |ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut output = Vec::new();
+    for path in paths {
+        if let Some(json) = user_data_from_file(&path)? {
+            output.push(json);
+        }
+    }
+    Ok(output)
+}
+
 mod error {
     use snafu::Snafu;
     use std::io;
@@ -43,6 +76,9 @@ mod error {
         #[snafu(display("Unable to read input file '{}': {}", path.display(), source))]
         InputFileRead { path: PathBuf, source: io::Error },
 
+        #[snafu(display("Unable to list files in '{}': {}", dir.display(), source))]
+        ListFiles { dir: PathBuf, source: io::Error },
+
         #[snafu(display("Unable to serialize settings from {}: {}", from, source))]
         SettingsToJSON {
             from: String,
@@ -50,3 +86,6 @@ mod error {
         },
     }
 }
+
+pub(crate) use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
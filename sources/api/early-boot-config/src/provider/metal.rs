@@ -1,23 +1,27 @@
 //! The metal module implements the `PlatformDataProvider` trait for gathering userdata on bare
 //! metal.
 
-use super::{PlatformDataProvider, SettingsJson};
+use super::{cmdline, nocloud, remote, PlatformDataProvider, SettingsJson};
 use async_trait::async_trait;
 
-use crate::provider::local_file::{local_file_user_data, USER_DATA_FILE};
-
 pub(crate) struct MetalDataProvider;
 
 #[async_trait]
 impl PlatformDataProvider for MetalDataProvider {
+    /// Bare metal hosts are often provisioned with a cloud-init seed image, either a NoCloud seed
+    /// or a config drive, or PXE-booted with a URL for user data on the kernel command line.
+    /// Everything else comes from the local sources read on every platform.
     async fn platform_data(
         &self,
     ) -> std::result::Result<Vec<SettingsJson>, Box<dyn std::error::Error>> {
-        let mut output = Vec::new();
+        let mut output = nocloud::seed_data()?;
+        if output.is_empty() {
+            info!("No NoCloud seed or config drive found");
+        }
 
-        match local_file_user_data()? {
+        match remote::remote_user_data(cmdline::KERNEL_CMDLINE).await? {
             Some(s) => output.push(s),
-            None => warn!("No user data found via local file: {}", USER_DATA_FILE),
+            None => debug!("No user data fetched from a URL"),
         }
 
         Ok(output)
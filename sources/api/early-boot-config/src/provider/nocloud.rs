@@ -0,0 +1,514 @@
+//! The nocloud module provides methods for gathering userdata and meta-data from the seed images
+//! used to provision hosts with cloud-init: a NoCloud seed, labeled `cidata`, or an OpenStack
+//! config drive, labeled `config-2`.
+//!
+//! The image is found by its filesystem label, and mounted read-only while we read it, using the
+//! filesystem we find at the start of the device.  User data is expected to be Bottlerocket TOML;
+//! from meta-data, we use the hostname and log the instance ID.
+
+use super::{cdrom, SettingsJson};
+use serde::Deserialize;
+use serde_json::json;
+use snafu::{ensure, ResultExt};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const DISK_BY_LABEL: &str = "/dev/disk/by-label";
+const SEED_MOUNT: &str = "/run/early-boot-config/seed";
+// Seed images are usually ISO 9660, but can also be VFAT.
+const FILESYSTEMS: [&str; 2] = ["iso9660", "vfat"];
+// Enough of the start of a device to find the ISO 9660 volume descriptor; see filesystem_type.
+const HEADER_LEN: u64 = 0x8006;
+const NONE: Option<&'static [u8]> = None;
+
+/// The kinds of seed image we understand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SeedFormat {
+    NoCloud,
+    ConfigDrive,
+}
+
+impl SeedFormat {
+    const ALL: [SeedFormat; 2] = [SeedFormat::NoCloud, SeedFormat::ConfigDrive];
+
+    /// The filesystem label that identifies the format.
+    fn label(self) -> &'static str {
+        match self {
+            SeedFormat::NoCloud => "cidata",
+            SeedFormat::ConfigDrive => "config-2",
+        }
+    }
+
+    /// Returns the format identified by a filesystem label, if any.  Case doesn't matter, since
+    /// VFAT labels are usually upper case.
+    fn from_label(label: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.label().eq_ignore_ascii_case(label))
+    }
+
+    /// The directory within the image that holds user data, and the names it may have.
+    fn user_data(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            SeedFormat::NoCloud => ("", &["user-data"]),
+            SeedFormat::ConfigDrive => ("openstack/latest", &["user_data"]),
+        }
+    }
+
+    /// The path of the meta-data file within the image.
+    fn meta_data(self) -> &'static str {
+        match self {
+            SeedFormat::NoCloud => "meta-data",
+            SeedFormat::ConfigDrive => "openstack/latest/meta_data.json",
+        }
+    }
+}
+
+impl fmt::Display for SeedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeedFormat::NoCloud => write!(f, "NoCloud seed"),
+            SeedFormat::ConfigDrive => write!(f, "config drive"),
+        }
+    }
+}
+
+/// Finds, mounts, and reads a seed image, returning settings from its meta-data and user data.
+pub(crate) fn seed_data() -> Result<Vec<SettingsJson>> {
+    let (device, format) = match find_seed(DISK_BY_LABEL)? {
+        Some(seed) => seed,
+        None => return Ok(Vec::new()),
+    };
+    info!("Found {} at {}", format, device.display());
+
+    let mount = SeedMount::new(&device, SEED_MOUNT)?;
+    read_seed(mount.path.as_path(), format)
+}
+
+/// Finds a seed image by its filesystem label in the given directory, which is normally
+/// /dev/disk/by-label.  At most one seed image may be attached.
+fn find_seed<P>(by_label: P) -> Result<Option<(PathBuf, SeedFormat)>>
+where
+    P: AsRef<Path>,
+{
+    let by_label = by_label.as_ref();
+    let entries = match fs::read_dir(by_label) {
+        Ok(entries) => entries,
+        // No device has a label.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(error::ListLabelsSnafu { path: by_label }),
+    };
+
+    let mut seeds = Vec::new();
+    for entry in entries {
+        let entry = entry.context(error::ListLabelsSnafu { path: by_label })?;
+        let label = entry.file_name();
+        if let Some(format) = label.to_str().and_then(SeedFormat::from_label) {
+            seeds.push((entry.path(), format));
+        }
+    }
+    ensure!(seeds.len() <= 1, error::SeedCountSnafu);
+
+    Ok(seeds.pop())
+}
+
+/// Reads settings from the root of a seed image.  Meta-data comes first, so that user data can
+/// override it.
+fn read_seed<P>(root: P, format: SeedFormat) -> Result<Vec<SettingsJson>>
+where
+    P: AsRef<Path>,
+{
+    let root = root.as_ref();
+    let mut output = Vec::new();
+
+    match meta_data(root, format)? {
+        Some(s) => output.push(s),
+        None => info!("No hostname found in {} meta-data", format),
+    }
+
+    let (dir, filenames) = format.user_data();
+    let desc = format!("user data from {}", format);
+    let user_data = match cdrom::find_user_data_file(root.join(dir), filenames)
+        .context(error::UserDataSnafu { format })?
+    {
+        Some(file) => {
+            cdrom::read_user_data_file(file, &desc).context(error::UserDataSnafu { format })?
+        }
+        None => None,
+    };
+    match user_data {
+        Some(s) => output.push(s),
+        None => warn!("No user data found in {}", format),
+    }
+
+    Ok(output)
+}
+
+/// The meta-data we use from a NoCloud seed, which is YAML.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct NoCloudMetaData {
+    instance_id: Option<String>,
+    local_hostname: Option<String>,
+}
+
+/// The meta-data we use from a config drive, which is JSON.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigDriveMetaData {
+    uuid: Option<String>,
+    hostname: Option<String>,
+}
+
+/// Reads meta-data from the root of a seed image, returning a SettingsJson with the hostname if
+/// it's given.
+fn meta_data(root: &Path, format: SeedFormat) -> Result<Option<SettingsJson>> {
+    let path = root.join(format.meta_data());
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read_to_string(&path).context(error::InputFileReadSnafu { path: &path })?;
+
+    // An empty NoCloud meta-data file is allowed.
+    let (instance_id, hostname) = match format {
+        _ if data.trim().is_empty() => (None, None),
+        SeedFormat::NoCloud => {
+            let meta_data: NoCloudMetaData =
+                serde_yaml::from_str(&data).context(error::MetaDataYamlSnafu { path: &path })?;
+            (meta_data.instance_id, meta_data.local_hostname)
+        }
+        SeedFormat::ConfigDrive => {
+            let meta_data: ConfigDriveMetaData =
+                serde_json::from_str(&data).context(error::MetaDataJsonSnafu { path: &path })?;
+            (meta_data.uuid, meta_data.hostname)
+        }
+    };
+
+    let mut desc = format!("meta-data from {}", format);
+    if let Some(instance_id) = instance_id {
+        info!("{} is for instance {}", format, instance_id);
+        desc = format!("{} (instance {})", desc, instance_id);
+    }
+
+    match hostname {
+        Some(hostname) => {
+            let val = json!({ "network": { "hostname": hostname } });
+            let json = SettingsJson::from_val(&val, desc)
+                .context(error::SettingsToJsonSnafu { path: &path })?;
+            Ok(Some(json))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reads the start of a device, for filesystem_type.
+fn read_header(device: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(device)?
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Identifies the filesystem of a seed image from the start of its device, returning its name
+/// for mount, or None if it's not ISO 9660 or VFAT.
+fn filesystem_type(header: &[u8]) -> Option<&'static str> {
+    // ISO 9660 volume descriptors start at sector 16, 0x8000, with a type byte followed by the
+    // identifier "CD001".
+    if header.get(0x8001..0x8006) == Some(&b"CD001"[..]) {
+        return Some("iso9660");
+    }
+    // A FAT boot sector ends with the signature 0x55 0xAA, and names its type in the extended
+    // boot record, at 0x36 for FAT12 and FAT16, or 0x52 for FAT32.
+    let signature = header.get(510..512) == Some(&[0x55, 0xAA][..]);
+    let fat = [0x36, 0x52]
+        .iter()
+        .any(|&offset| header.get(offset..offset + 3) == Some(&b"FAT"[..]));
+    if signature && fat {
+        return Some("vfat");
+    }
+    None
+}
+
+/// A seed image mounted read-only, which is unmounted when dropped.
+struct SeedMount {
+    path: PathBuf,
+}
+
+impl SeedMount {
+    fn new<P1, P2>(device: P1, path: P2) -> Result<Self>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let (device, path) = (device.as_ref(), path.as_ref());
+        fs::create_dir_all(path).context(error::MountPointSnafu { path })?;
+
+        let flags = nix::mount::MsFlags::MS_NOSUID
+            | nix::mount::MsFlags::MS_NODEV
+            | nix::mount::MsFlags::MS_NOEXEC
+            | nix::mount::MsFlags::MS_RDONLY;
+
+        // If we can't tell what the filesystem is, we try each one a seed image may have.
+        let filesystems = match read_header(device).map(|header| filesystem_type(&header)) {
+            Ok(Some(filesystem)) => vec![filesystem],
+            Ok(None) => FILESYSTEMS.to_vec(),
+            Err(e) => {
+                warn!("Unable to read {}: {}", device.display(), e);
+                FILESYSTEMS.to_vec()
+            }
+        };
+
+        let mut result = Ok(());
+        for filesystem in filesystems {
+            result = nix::mount::mount(Some(device), path, Some(filesystem), flags, NONE);
+            if result.is_ok() {
+                debug!("Mounted {} as {}", device.display(), filesystem);
+                break;
+            }
+        }
+        result.context(error::MountSnafu { device, path })?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for SeedMount {
+    fn drop(&mut self) {
+        if let Err(e) = nix::mount::umount(&self.path) {
+            warn!("Failed to unmount {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// Builds the contents of a seed image from (path, contents) pairs.
+    fn seed(files: &[(&str, &[u8])]) -> TempDir {
+        let root = TempDir::new().unwrap();
+        for (path, contents) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn nocloud_seed() {
+        let root = seed(&[
+            (
+                "meta-data",
+                b"instance-id: iid-local01\nlocal-hostname: node-1\n",
+            ),
+            ("user-data", b"[settings]\nmotd = \"hello\"\n"),
+        ]);
+
+        let settings = read_seed(root.path(), SeedFormat::NoCloud).unwrap();
+        assert_eq!(settings.len(), 2);
+        assert_eq!(settings[0].json, json!({"network": {"hostname": "node-1"}}));
+        assert_eq!(
+            settings[0].desc,
+            "meta-data from NoCloud seed (instance iid-local01)"
+        );
+        assert_eq!(settings[1].json, json!({"motd": "hello"}));
+        assert_eq!(settings[1].desc, "user data from NoCloud seed");
+    }
+
+    #[test]
+    fn config_drive() {
+        let root = seed(&[
+            (
+                "openstack/latest/meta_data.json",
+                br#"{"uuid": "83679162-1378-4288-a2d4-70e13ec132aa", "hostname": "node-2", "name": "x"}"#,
+            ),
+            (
+                "openstack/latest/user_data",
+                b"[settings.kernel]\nlockdown = \"integrity\"\n",
+            ),
+            ("ec2/latest/user-data", b"ignored"),
+        ]);
+
+        let settings = read_seed(root.path(), SeedFormat::ConfigDrive).unwrap();
+        assert_eq!(settings.len(), 2);
+        assert_eq!(settings[0].json, json!({"network": {"hostname": "node-2"}}));
+        assert_eq!(
+            settings[1].json,
+            json!({"kernel": {"lockdown": "integrity"}})
+        );
+    }
+
+    #[test]
+    fn compressed_user_data_without_meta_data() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"settings.motd = \"zipped\"").unwrap();
+        let root = seed(&[
+            ("meta-data", b""),
+            ("user-data", &encoder.finish().unwrap()),
+        ]);
+
+        let settings = read_seed(root.path(), SeedFormat::NoCloud).unwrap();
+        assert_eq!(settings.len(), 1);
+        assert_eq!(settings[0].json, json!({"motd": "zipped"}));
+    }
+
+    #[test]
+    fn invalid_meta_data() {
+        let root = seed(&[("openstack/latest/meta_data.json", b"{not json")]);
+        assert!(read_seed(root.path(), SeedFormat::ConfigDrive).is_err());
+    }
+
+    #[test]
+    fn find_by_label() {
+        let by_label = seed(&[]);
+        assert!(find_seed(by_label.path()).unwrap().is_none());
+
+        let by_label = seed(&[("CIDATA", b"")]);
+        let (device, format) = find_seed(by_label.path()).unwrap().unwrap();
+        assert_eq!(device, by_label.path().join("CIDATA"));
+        assert_eq!(format, SeedFormat::NoCloud);
+
+        let by_label = seed(&[("config-2", b"")]);
+        let (_, format) = find_seed(by_label.path()).unwrap().unwrap();
+        assert_eq!(format, SeedFormat::ConfigDrive);
+
+        let by_label = seed(&[("cidata", b""), ("config-2", b"")]);
+        assert!(find_seed(by_label.path()).is_err());
+
+        // Other labels are ignored, and so is a missing directory.
+        let by_label = seed(&[("ROOT", b""), ("cidata-old", b"")]);
+        assert!(find_seed(by_label.path()).unwrap().is_none());
+        assert!(find_seed(by_label.path().join("missing")).unwrap().is_none());
+    }
+
+    #[test]
+    fn labels() {
+        for (label, format) in [
+            ("cidata", Some(SeedFormat::NoCloud)),
+            ("CIDATA", Some(SeedFormat::NoCloud)),
+            ("CiData", Some(SeedFormat::NoCloud)),
+            ("config-2", Some(SeedFormat::ConfigDrive)),
+            ("CONFIG-2", Some(SeedFormat::ConfigDrive)),
+            ("config-3", None),
+            ("", None),
+        ] {
+            assert_eq!(SeedFormat::from_label(label), format, "{}", label);
+        }
+    }
+
+    /// The start of an ISO 9660 image: the system area, then a primary volume descriptor.
+    fn iso9660_header() -> Vec<u8> {
+        let mut header = vec![0; HEADER_LEN as usize];
+        header[0x8000] = 1;
+        header[0x8001..0x8006].copy_from_slice(b"CD001");
+        header
+    }
+
+    /// A FAT boot sector, with the type named at the given offset.
+    fn fat_header(offset: usize, name: &[u8; 8]) -> Vec<u8> {
+        let mut header = vec![0; 512];
+        header[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        header[offset..offset + 8].copy_from_slice(name);
+        header[510..512].copy_from_slice(&[0x55, 0xAA]);
+        header
+    }
+
+    #[test]
+    fn filesystem_types() {
+        assert_eq!(filesystem_type(&iso9660_header()), Some("iso9660"));
+        assert_eq!(filesystem_type(&fat_header(0x36, b"FAT12   ")), Some("vfat"));
+        assert_eq!(filesystem_type(&fat_header(0x36, b"FAT16   ")), Some("vfat"));
+        assert_eq!(filesystem_type(&fat_header(0x52, b"FAT32   ")), Some("vfat"));
+
+        // Without the boot sector signature, it's not FAT.
+        let mut header = fat_header(0x36, b"FAT16   ");
+        header[511] = 0;
+        assert_eq!(filesystem_type(&header), None);
+        assert_eq!(filesystem_type(&fat_header(0x36, b"NTFS    ")), None);
+        assert_eq!(filesystem_type(&[0; 512]), None);
+        assert_eq!(filesystem_type(&[]), None);
+        assert_eq!(filesystem_type(&iso9660_header()[..0x8004]), None);
+    }
+
+    #[test]
+    fn read_image_header() {
+        let dir = seed(&[]);
+        let image = dir.path().join("seed.iso");
+        let mut data = iso9660_header();
+        data.extend_from_slice(&[0; 4096]);
+        fs::write(&image, &data).unwrap();
+        let header = read_header(&image).unwrap();
+        assert_eq!(header.len(), HEADER_LEN as usize);
+        assert_eq!(filesystem_type(&header), Some("iso9660"));
+
+        let image = dir.path().join("seed.img");
+        fs::write(&image, fat_header(0x52, b"FAT32   ")).unwrap();
+        assert_eq!(filesystem_type(&read_header(&image).unwrap()), Some("vfat"));
+    }
+}
+
+mod error {
+    use super::SeedFormat;
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Unable to read input file '{}': {}", path.display(), source))]
+        InputFileRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Unable to list filesystem labels in '{}': {}", path.display(), source))]
+        ListLabels { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Unable to deserialize meta-data JSON from '{}': {}", path.display(), source))]
+        MetaDataJson {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Unable to deserialize meta-data YAML from '{}': {}", path.display(), source))]
+        MetaDataYaml {
+            path: PathBuf,
+            source: serde_yaml::Error,
+        },
+
+        #[snafu(display("Failed to mount {} on {}: {}", device.display(), path.display(), source))]
+        Mount {
+            device: PathBuf,
+            path: PathBuf,
+            source: nix::Error,
+        },
+
+        #[snafu(display("Unable to create mount point '{}': {}", path.display(), source))]
+        MountPoint { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Found multiple seed images, expected 1"))]
+        SeedCount,
+
+        #[snafu(display("Unable to serialize settings from {}: {}", path.display(), source))]
+        SettingsToJson {
+            path: PathBuf,
+            source: crate::settings::Error,
+        },
+
+        #[snafu(display("Unable to read user data from {}: {}", format, source))]
+        UserData {
+            format: SeedFormat,
+            source: super::cdrom::Error,
+        },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
@@ -0,0 +1,409 @@
+//! The remote module provides methods for fetching userdata over HTTP(S) from a provisioning
+//! server, named on the kernel command line like
+//! `early-boot-config.user-data-url=https://example.com/user-data.toml`.
+//!
+//! User data is only applied if its detached signature, fetched from the same URL with `.sig`
+//! appended, can be verified against the public key in the image.
+
+use super::cmdline;
+use super::SettingsJson;
+use crate::compression::expand_slice_maybe;
+use reqwest::Client;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use snafu::{ensure, ResultExt};
+use std::fs;
+use std::path::Path;
+use tokio::time::{timeout, Duration};
+use tokio_retry::{strategy::FibonacciBackoff, RetryIf};
+
+const USER_DATA_URL_PREFIX: &str = "early-boot-config.user-data-url=";
+/// A base64-encoded Ed25519 public key, used to verify user data fetched from a URL.
+pub(crate) const USER_DATA_PUBLIC_KEY: &str = "/usr/share/early-boot-config/user-data.pub";
+
+// Retry timeout tied to wicked.service ifup timeout, like IMDS.
+const RETRY_TIMEOUT_SECS: u64 = 300;
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+fn retry_strategy() -> impl Iterator<Item = Duration> {
+    // Retry attempts at 0.25s, 0.5s, 1s, 1.75s, 3s, 5s, 8.25s, 13.5s, 22s and then every 10s after.
+    FibonacciBackoff::from_millis(250).max_delay(Duration::from_secs(10))
+}
+
+/// Finds the user data URL on the given kernel command line, if any.
+pub(crate) fn user_data_url<P>(kernel_cmdline: P) -> Result<Option<String>>
+where
+    P: AsRef<Path>,
+{
+    let kernel_cmdline =
+        cmdline::read_kernel_cmdline(kernel_cmdline).context(error::KernelCmdlineSnafu)?;
+    let url = cmdline::param_value(&kernel_cmdline, USER_DATA_URL_PREFIX)
+        .context(error::KernelCmdlineSnafu)?;
+    Ok(url.map(str::to_string))
+}
+
+/// Reads the public key used to verify user data.
+pub(crate) fn read_public_key<P>(path: P) -> Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let encoded = fs::read_to_string(path).context(error::PublicKeyReadSnafu { path })?;
+    base64::decode(encoded.trim()).context(error::PublicKeyDecodeSnafu { path })
+}
+
+/// User data and its detached signature, as fetched.
+pub(crate) struct SignedUserData {
+    url: String,
+    user_data: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+/// Fetches user data and its signature from the given URL, retrying connection errors, server
+/// errors, and rate limiting until the given timeout.
+pub(crate) async fn fetch(url: &str, retry_timeout: Duration) -> Result<SignedUserData> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .context(error::ClientSnafu)?;
+
+    let user_data = fetch_bytes(&client, url, retry_timeout).await?;
+    let signature_url = format!("{}.sig", url);
+    let signature = fetch_bytes(&client, &signature_url, retry_timeout).await?;
+
+    Ok(SignedUserData {
+        url: url.to_string(),
+        user_data,
+        signature,
+    })
+}
+
+/// Fetches the body of the given URL, retrying transient failures until the given timeout; see
+/// Error::is_transient.
+async fn fetch_bytes(client: &Client, url: &str, retry_timeout: Duration) -> Result<Vec<u8>> {
+    debug!("Requesting {}", url);
+    timeout(
+        retry_timeout,
+        RetryIf::spawn(
+            retry_strategy(),
+            || async {
+                let response = client
+                    .get(url)
+                    .send()
+                    .await
+                    .context(error::RequestSnafu { url })?
+                    .error_for_status()
+                    .context(error::ResponseStatusSnafu { url })?;
+                let body = response
+                    .bytes()
+                    .await
+                    .context(error::ResponseBodySnafu { url })?;
+                Ok(body.to_vec())
+            },
+            |e: &error::Error| {
+                let transient = e.is_transient();
+                if transient {
+                    debug!("{}; retrying", e);
+                }
+                transient
+            },
+        ),
+    )
+    .await
+    .context(error::TimeoutFetchSnafu { url })?
+}
+
+impl SignedUserData {
+    /// Verifies the signature of the user data against the given public key, and if it's valid,
+    /// returns a SettingsJson from the user data, which may be compressed.
+    pub(crate) fn verify(&self, public_key: &[u8]) -> Result<Option<SettingsJson>> {
+        // The signature may be given raw or base64-encoded.
+        let signature = match std::str::from_utf8(&self.signature) {
+            Ok(encoded) => base64::decode(encoded.trim()).ok(),
+            Err(_) => None,
+        }
+        .unwrap_or_else(|| self.signature.clone());
+
+        ensure!(
+            UnparsedPublicKey::new(&ED25519, public_key)
+                .verify(&self.user_data, &signature)
+                .is_ok(),
+            error::SignatureSnafu { url: &self.url }
+        );
+        info!("Verified signature of user data from {}", self.url);
+
+        let user_data_str = expand_slice_maybe(&self.user_data)
+            .context(error::DecompressionSnafu { url: &self.url })?;
+        if user_data_str.is_empty() {
+            return Ok(None);
+        }
+        trace!("Received user data: {}", user_data_str);
+
+        let json =
+            SettingsJson::from_toml_str(&user_data_str, format!("user data from {}", self.url))
+                .context(error::SettingsToJsonSnafu { url: &self.url })?;
+        Ok(Some(json))
+    }
+}
+
+/// Looks for a URL on the kernel command line, then fetches user data from it and verifies it.
+/// If the fetch fails, we log it and return nothing, so that user data from local files still
+/// applies; if the signature is invalid, we fail rather than ignore it.
+pub(crate) async fn remote_user_data<P>(kernel_cmdline: P) -> Result<Option<SettingsJson>>
+where
+    P: AsRef<Path>,
+{
+    let url = match user_data_url(kernel_cmdline)? {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    info!("Kernel command line points to user data at {}", url);
+
+    // Check for the key first, so a missing key isn't mistaken for a failed fetch.
+    let public_key = read_public_key(USER_DATA_PUBLIC_KEY)?;
+    let signed = match fetch(&url, Duration::from_secs(RETRY_TIMEOUT_SECS)).await {
+        Ok(signed) => signed,
+        Err(e) => {
+            warn!("{}; falling back to local user data", e);
+            return Ok(None);
+        }
+    };
+    signed.verify(&public_key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use httptest::responders::status_code;
+    use httptest::{matchers::*, Expectation, Server};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+    use std::io::Write;
+
+    const USER_DATA: &[u8] = b"[settings]\nmotd = \"fetched\"\n";
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn serve(server: &Server, user_data: &[u8], signature: &[u8]) {
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data"))
+                .respond_with(status_code(200).body(user_data.to_vec())),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data.sig"))
+                .respond_with(status_code(200).body(signature.to_vec())),
+        );
+    }
+
+    #[tokio::test]
+    async fn verified_user_data() {
+        let key_pair = key_pair();
+        let signature = base64::encode(key_pair.sign(USER_DATA));
+        let server = Server::run();
+        serve(&server, USER_DATA, signature.as_bytes());
+
+        let url = server.url_str("/user-data");
+        let signed = fetch(&url, Duration::from_secs(5)).await.unwrap();
+        let settings = signed
+            .verify(key_pair.public_key().as_ref())
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.json, json!({"motd": "fetched"}));
+        assert_eq!(settings.desc, format!("user data from {}", url));
+    }
+
+    #[tokio::test]
+    async fn compressed_user_data_raw_signature() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(USER_DATA).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let key_pair = key_pair();
+        let server = Server::run();
+        serve(&server, &compressed, key_pair.sign(&compressed).as_ref());
+
+        let signed = fetch(&server.url_str("/user-data"), Duration::from_secs(5))
+            .await
+            .unwrap();
+        let settings = signed
+            .verify(key_pair.public_key().as_ref())
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.json, json!({"motd": "fetched"}));
+    }
+
+    #[tokio::test]
+    async fn bad_signature() {
+        let key_pair = key_pair();
+        let signature = key_pair.sign(b"[settings]\nmotd = \"other\"\n");
+        let server = Server::run();
+        serve(&server, USER_DATA, signature.as_ref());
+
+        let signed = fetch(&server.url_str("/user-data"), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(signed.verify(key_pair.public_key().as_ref()).is_err());
+        // Signed, but with a different key.
+        assert!(signed
+            .verify(self::key_pair().public_key().as_ref())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_timeout() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data"))
+                .times(1..)
+                .respond_with(status_code(503)),
+        );
+        assert!(fetch(&server.url_str("/user-data"), Duration::from_secs(1))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_retries() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data"))
+                .times(2..)
+                .respond_with(status_code(429)),
+        );
+        let result = fetch(&server.url_str("/user-data"), Duration::from_secs(1)).await;
+        assert!(matches!(result, Err(error::Error::TimeoutFetch { .. })));
+    }
+
+    #[tokio::test]
+    async fn client_error_fails_fast() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data"))
+                .times(1)
+                .respond_with(status_code(404)),
+        );
+        // Well within the retry timeout, we give up after the first request.
+        let result = timeout(
+            Duration::from_secs(5),
+            fetch(&server.url_str("/user-data"), Duration::from_secs(300)),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, Err(error::Error::ResponseStatus { .. })));
+    }
+
+    #[tokio::test]
+    async fn missing_signature_fails_fast() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data"))
+                .times(1)
+                .respond_with(status_code(200).body(USER_DATA.to_vec())),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/user-data.sig"))
+                .times(1)
+                .respond_with(status_code(403)),
+        );
+        let result = timeout(
+            Duration::from_secs(5),
+            fetch(&server.url_str("/user-data"), Duration::from_secs(300)),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, Err(error::Error::ResponseStatus { .. })));
+    }
+
+    #[test]
+    fn finds_url() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let path = tempdir.path().join("cmdline");
+        fs::write(
+            &path,
+            "root=/dev/dm-0 early-boot-config.user-data-url=https://example.com/ud.toml quiet\n",
+        )
+        .unwrap();
+        assert_eq!(
+            user_data_url(&path).unwrap().as_deref(),
+            Some("https://example.com/ud.toml")
+        );
+    }
+}
+
+mod error {
+    use reqwest::StatusCode;
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Unable to create HTTP client: {}", source))]
+        Client { source: reqwest::Error },
+
+        #[snafu(display("Unable to decompress user data from {}: {}", url, source))]
+        Decompression { url: String, source: std::io::Error },
+
+        #[snafu(display("{}", source))]
+        KernelCmdline {
+            source: crate::provider::cmdline::error::Error,
+        },
+
+        #[snafu(display("Unable to decode public key '{}': {}", path.display(), source))]
+        PublicKeyDecode {
+            path: PathBuf,
+            source: base64::DecodeError,
+        },
+
+        #[snafu(display("Unable to read public key '{}' to verify user data: {}", path.display(), source))]
+        PublicKeyRead {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Error requesting {}: {}", url, source))]
+        Request { url: String, source: reqwest::Error },
+
+        #[snafu(display("Unable to read response body from {}: {}", url, source))]
+        ResponseBody { url: String, source: reqwest::Error },
+
+        #[snafu(display("Error response from {}: {}", url, source))]
+        ResponseStatus { url: String, source: reqwest::Error },
+
+        #[snafu(display("Unable to serialize settings from {}: {}", url, source))]
+        SettingsToJson {
+            url: String,
+            source: crate::settings::Error,
+        },
+
+        #[snafu(display("Signature of user data from {} is not valid", url))]
+        Signature { url: String },
+
+        #[snafu(display("Timed out fetching {}: {}", url, source))]
+        TimeoutFetch {
+            url: String,
+            source: tokio::time::error::Elapsed,
+        },
+    }
+
+    impl Error {
+        /// Whether a fetch that failed this way might succeed if retried: the server couldn't be
+        /// reached or the response was cut off, or the server is failing or asked us to slow
+        /// down.  Other client errors, like a missing signature, won't fix themselves.
+        pub(super) fn is_transient(&self) -> bool {
+            match self {
+                Error::Request { .. } | Error::ResponseBody { .. } => true,
+                Error::ResponseStatus { source, .. } => source.status().map_or(true, |status| {
+                    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+                }),
+                _ => false,
+            }
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
@@ -1,8 +1,8 @@
 //! The vmware module implements the `PlatformDataProvider` trait for gathering userdata on VMware
 //! via mounted CDRom or the guestinfo interface
 
-use super::{PlatformDataProvider, SettingsJson};
-use crate::compression::{expand_file_maybe, expand_slice_maybe, OptionalCompressionReader};
+use super::{cdrom, PlatformDataProvider, SettingsJson};
+use crate::compression::{expand_slice_maybe, OptionalCompressionReader};
 use async_trait::async_trait;
 use serde::Deserialize;
 use snafu::{ensure, ResultExt};
@@ -11,12 +11,9 @@ use std::fs::File;
 use std::io::BufReader;
 use std::io::Cursor;
 use std::io::Read;
-use std::iter::FromIterator;
 use std::path::Path;
 use std::str;
 
-use crate::provider::local_file::{local_file_user_data, USER_DATA_FILE};
-
 pub(crate) struct VmwareDataProvider;
 
 impl VmwareDataProvider {
@@ -41,62 +38,29 @@ impl VmwareDataProvider {
         // Given the list of acceptable filenames, ensure only 1 exists and parse
         // it for user data
         info!("Attempting to retrieve user data from mounted CD-ROM");
-        let mut user_data_files = Self::USER_DATA_FILENAMES
-            .iter()
-            .map(|filename| Path::new(Self::CD_ROM_MOUNT).join(filename))
-            .filter(|file| file.exists());
-
-        let user_data_file = match user_data_files.next() {
-            Some(file) => file,
-            None => return Ok(None),
-        };
-
-        ensure!(
-            user_data_files.next().is_none(),
-            error::UserDataFileCountSnafu {
-                place: Self::CD_ROM_MOUNT
-            }
-        );
+        let desc = "user data from CD-ROM";
+        let user_data_file =
+            match cdrom::find_user_data_file(Self::CD_ROM_MOUNT, &Self::USER_DATA_FILENAMES)
+                .context(error::CdromSnafu)?
+            {
+                Some(file) => file,
+                None => return Ok(None),
+            };
 
         // XML files require extra processing, while a user-supplied file should already be in TOML
         // format
-        info!("'{}' exists, using it", user_data_file.display());
-        let user_data_str = match user_data_file.extension().and_then(OsStr::to_str) {
-            Some("xml") | Some("XML") => Self::ovf_user_data(&user_data_file)?,
+        match user_data_file.extension().and_then(OsStr::to_str) {
+            Some("xml") | Some("XML") => {
+                info!("'{}' exists, using it", user_data_file.display());
+                let user_data_str = Self::ovf_user_data(&user_data_file)?;
+                cdrom::user_data_from_str(&user_data_str, &user_data_file, desc)
+                    .context(error::CdromSnafu)
+            }
             // Since we only look for a specific list of file names, we should never find a file
             // with an extension we don't understand.
             Some(_) => unreachable!(),
-            None => {
-                // Read the file, decompressing it if compressed.
-                expand_file_maybe(&user_data_file).context(error::InputFileReadSnafu {
-                    path: &user_data_file,
-                })?
-            }
-        };
-
-        if user_data_str.is_empty() {
-            return Ok(None);
-        }
-
-        // User data could be 700MB compressed!  Eek!  :)
-        if user_data_str.len() <= 2048 {
-            trace!("Received user data: {}", user_data_str);
-        } else {
-            trace!(
-                "Received long user data, starts with: {}",
-                // (this isn't perfect because chars aren't grapheme clusters, but will error
-                // toward printing the whole input, which is fine)
-                String::from_iter(user_data_str.chars().take(2048))
-            );
+            None => cdrom::read_user_data_file(&user_data_file, desc).context(error::CdromSnafu),
         }
-
-        let json = SettingsJson::from_toml_str(&user_data_str, "user data from CD-ROM").context(
-            error::SettingsToJsonSnafu {
-                from: user_data_file.display().to_string(),
-            },
-        )?;
-
-        Ok(Some(json))
     }
 
     /// Read and base64 decode user data contained in an OVF file
@@ -248,13 +212,7 @@ impl PlatformDataProvider for VmwareDataProvider {
     ) -> std::result::Result<Vec<SettingsJson>, Box<dyn std::error::Error>> {
         let mut output = Vec::new();
 
-        // Attempt to read from local file first
-        match local_file_user_data()? {
-            Some(s) => output.push(s),
-            None => warn!("No user data found via local file: {}", USER_DATA_FILE),
-        }
-
-        // Then look at the CD-ROM for user data
+        // Look at the CD-ROM for user data first
         match Self::cdrom_user_data()? {
             Some(s) => output.push(s),
             None => warn!("No user data found via CD-ROM"),
@@ -333,6 +291,9 @@ mod error {
             source: base64::DecodeError,
         },
 
+        #[snafu(display("Unable to read user data from CD-ROM: {}", source))]
+        Cdrom { source: super::cdrom::Error },
+
         #[snafu(display("Failed to decompress {}: {}", what, source))]
         Decompression { what: String, source: io::Error },
 
@@ -368,9 +329,6 @@ mod error {
             source: serde_plain::Error,
         },
 
-        #[snafu(display("Found multiple user data files in '{}', expected 1", place))]
-        UserDataFileCount { place: String },
-
         #[snafu(display("Unable to deserialize XML from: '{}': {}", path.display(), source))]
         XmlDeserialize {
             path: PathBuf,
@@ -0,0 +1,381 @@
+//! The secrets module decrypts secret values in user data.
+//!
+//! Any setting given as a string can be encrypted to the host's key with
+//! [age](https://age-encryption.org), using an X25519 recipient and ASCII armor:
+//!
+//! ```shell
+//! echo 'abcdef.0123456789abcdef' | age --armor -r age1...
+//! ```
+//!
+//! The output is given in user data as a multi-line string:
+//!
+//! ```toml
+//! [settings.kubernetes]
+//! bootstrap-token = """
+//! -----BEGIN AGE ENCRYPTED FILE-----
+//! ...
+//! -----END AGE ENCRYPTED FILE-----
+//! """
+//! ```
+//!
+//! Values are decrypted after the user data sources are merged, using the age identities in the
+//! host's key file.  A single trailing newline is removed from each decrypted value, since tools
+//! like `echo` add one.
+
+use crate::merge::setting_name;
+use crate::provider::cmdline;
+use age::armor::ArmoredReader;
+use age::x25519::Identity;
+use age::Decryptor;
+use serde_json::{Map, Value};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The file holding the host's age identities, one per line, like `AGE-SECRET-KEY-1...`, unless
+/// another is named on the kernel command line.
+pub(crate) const SECRET_KEY_FILE: &str = "/var/lib/bottlerocket/secret-key.txt";
+const SECRET_KEY_PREFIX: &str = "early-boot-config.secret-key=";
+
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+const IDENTITY_PREFIX: &str = "AGE-SECRET-KEY-1";
+
+/// Decrypts any encrypted values in the given settings in place, using the identities in the key
+/// file named on the given kernel command line, or the default key file.  The key file is only
+/// read if there are encrypted values.  Returns the names of the decrypted settings.
+pub(crate) fn decrypt_settings<P>(
+    settings: &mut Map<String, Value>,
+    kernel_cmdline: P,
+) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    if !settings.values().any(contains_encrypted) {
+        return Ok(Vec::new());
+    }
+
+    let key_file = key_file(kernel_cmdline)?;
+    info!(
+        "Found encrypted settings, decrypting with identities from '{}'",
+        key_file.display()
+    );
+    let identities = read_identities(&key_file)?;
+
+    let mut decrypted = Vec::new();
+    let mut path = vec!["settings".to_string()];
+    decrypt_table(settings, &identities, &mut path, &mut decrypted)?;
+    Ok(decrypted)
+}
+
+/// Returns the key file named on the kernel command line, or the default key file.
+fn key_file<P>(kernel_cmdline: P) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let kernel_cmdline =
+        cmdline::read_kernel_cmdline(kernel_cmdline).context(error::KernelCmdlineSnafu)?;
+    let path = cmdline::param_value(&kernel_cmdline, SECRET_KEY_PREFIX)
+        .context(error::KernelCmdlineSnafu)?;
+    Ok(PathBuf::from(path.unwrap_or(SECRET_KEY_FILE)))
+}
+
+fn is_encrypted(s: &str) -> bool {
+    s.trim_start().starts_with(ARMOR_BEGIN)
+}
+
+fn contains_encrypted(value: &Value) -> bool {
+    match value {
+        Value::String(s) => is_encrypted(s),
+        Value::Object(table) => table.values().any(contains_encrypted),
+        Value::Array(list) => list.iter().any(contains_encrypted),
+        _ => false,
+    }
+}
+
+fn decrypt_table(
+    table: &mut Map<String, Value>,
+    identities: &[Identity],
+    path: &mut Vec<String>,
+    decrypted: &mut Vec<String>,
+) -> Result<()> {
+    for (key, value) in table.iter_mut() {
+        path.push(key.clone());
+        match value {
+            Value::Object(inner) => decrypt_table(inner, identities, path, decrypted)?,
+            // A list is a single setting, like the list of registry credentials, so it's
+            // sensitive if anything in it was encrypted.
+            value => {
+                let name = setting_name(path);
+                if decrypt_value(value, identities, &name)? {
+                    decrypted.push(name);
+                }
+            }
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Decrypts any encrypted strings in the given value in place, returning whether there were any.
+fn decrypt_value(value: &mut Value, identities: &[Identity], name: &str) -> Result<bool> {
+    let mut any = false;
+    match value {
+        Value::String(s) if is_encrypted(s) => {
+            debug!("Decrypting {}", name);
+            *s = decrypt_armored(s, identities).context(error::DecryptSnafu { name })?;
+            any = true;
+        }
+        Value::Object(table) => {
+            for value in table.values_mut() {
+                any |= decrypt_value(value, identities, name)?;
+            }
+        }
+        Value::Array(list) => {
+            for value in list.iter_mut() {
+                any |= decrypt_value(value, identities, name)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(any)
+}
+
+/// Reads age identities from a key file; blank lines and comments starting with `#` are ignored.
+fn read_identities(path: &Path) -> Result<Vec<Identity>> {
+    let contents = fs::read_to_string(path).context(error::KeyFileReadSnafu { path })?;
+    let identities = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse::<Identity>()
+                .ok()
+                .context(error::InvalidIdentitySnafu {
+                    path,
+                    identity: line.chars().take(IDENTITY_PREFIX.len()).collect::<String>(),
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!identities.is_empty(), error::NoIdentitiesSnafu { path });
+    Ok(identities)
+}
+
+/// Decrypts an ASCII-armored age file, returning the plaintext as a string.
+fn decrypt_armored(armored: &str, identities: &[Identity]) -> Result<String> {
+    let decryptor =
+        Decryptor::new(ArmoredReader::new(armored.trim().as_bytes())).context(error::AgeSnafu)?;
+    // Passphrases are for people; the host can't be asked for one.
+    ensure!(!decryptor.is_scrypt(), error::PassphraseSnafu);
+    let mut reader = decryptor
+        .decrypt(
+            identities
+                .iter()
+                .map(|identity| identity as &dyn age::Identity),
+        )
+        .context(error::AgeSnafu)?;
+    let mut plaintext = Vec::new();
+    reader
+        .read_to_end(&mut plaintext)
+        .context(error::PayloadSnafu)?;
+
+    let mut plaintext = String::from_utf8(plaintext).context(error::Utf8Snafu)?;
+    if plaintext.ends_with('\n') {
+        plaintext.pop();
+    }
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use age::armor::{ArmoredWriter, Format};
+    use age::secrecy::ExposeSecret;
+    use age::Encryptor;
+    use serde_json::json;
+    use std::io::Write;
+
+    // An identity and a value encrypted to it with 'age --armor -r', as test vectors.
+    const IDENTITY: &str =
+        "AGE-SECRET-KEY-10KU6DNF0A6E6KVEJNX25XLKNVJN96F9GM3KCLGSP2N2FPTG9ZQCQ047JRT";
+    const ENCRYPTED: &str = "\
+-----BEGIN AGE ENCRYPTED FILE-----
+YWdlLWVuY3J5cHRpb24ub3JnL3YxCi0+IFgyNTUxOSBqeUZna2NLSis0V0x0RXk2
+RGExM0l1b2kxZG1qSTVKUWtQblBkb0xRVVRrCmhmd3VnYVNHNFNGck5zckprL29L
+cXkxbmU4SWExQTJsL0NUTUZHNnBIK3cKLT4gZFlWaC1ncmVhc2UgJidxRkArbicg
+cyBUOAozZUJzTldVSDFLTW5OeDd1anVmMkhXSVBEOEtQS3FqOTlxTVdPNEpzd2wz
+RklGNlByb1p6T1RjcGVsbFZhcTc5CkczbnR1enR2WGlWQ2pzak1qU1RyVVBOOUlB
+Ci0tLSA5V0RBVEZoVSszTVpkUnlyRkVDVG9ZYVAxOE5IeStjUURhREU2YWlLeUpn
+CmoF6OG+xlDC1RGJ92Jno7DaGvIKrDbvtPzxbzRYrxSHR3pecin7B3ERENR/DiQJ
+Nltj62DqwpYk
+-----END AGE ENCRYPTED FILE-----
+";
+
+    /// Encrypts to the given identity the way 'age -r' does, armored or not.
+    fn encrypt_with(identity: &Identity, plaintext: &[u8], format: Format) -> Vec<u8> {
+        let recipient = identity.to_public();
+        let encryptor =
+            Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient)).unwrap();
+        let mut output = Vec::new();
+        let armored = ArmoredWriter::wrap_output(&mut output, format).unwrap();
+        let mut writer = encryptor.wrap_output(armored).unwrap();
+        writer.write_all(plaintext).unwrap();
+        writer.finish().unwrap().finish().unwrap();
+        output
+    }
+
+    fn encrypt(identity: &Identity, plaintext: &[u8]) -> String {
+        String::from_utf8(encrypt_with(identity, plaintext, Format::AsciiArmor)).unwrap()
+    }
+
+    #[test]
+    fn test_vector() {
+        let identity = IDENTITY.parse::<Identity>().unwrap();
+        assert_eq!(
+            decrypt_armored(ENCRYPTED, &[identity]).unwrap(),
+            "abcdef.0123456789abcdef"
+        );
+    }
+
+    #[test]
+    fn decrypts_settings() {
+        let identity = Identity::generate();
+        let dir = tempfile::TempDir::new().unwrap();
+        let key_file = dir.path().join("key.txt");
+        fs::write(
+            &key_file,
+            format!(
+                "# created: today\n{}\n",
+                identity.to_string().expose_secret()
+            ),
+        )
+        .unwrap();
+        let cmdline = dir.path().join("cmdline");
+        fs::write(
+            &cmdline,
+            format!("quiet {}{}\n", SECRET_KEY_PREFIX, key_file.display()),
+        )
+        .unwrap();
+
+        let mut settings = json!({
+            "motd": "hi",
+            "kubernetes": {
+                "bootstrap-token": encrypt(&identity, b"abcdef.0123456789abcdef\n"),
+            },
+            "container-registry": {
+                "credentials": [
+                    {"registry": "docker.io", "password": "plain"},
+                    {"registry": "example.com", "password": encrypt(&identity, b"pw")},
+                ],
+            },
+        });
+        let decrypted = decrypt_settings(settings.as_object_mut().unwrap(), &cmdline).unwrap();
+        assert_eq!(
+            decrypted,
+            vec![
+                "settings.container-registry.credentials",
+                "settings.kubernetes.bootstrap-token"
+            ]
+        );
+        assert_eq!(
+            settings["container-registry"]["credentials"][1]["password"],
+            "pw"
+        );
+        assert_eq!(
+            settings["kubernetes"]["bootstrap-token"],
+            "abcdef.0123456789abcdef"
+        );
+        assert_eq!(settings["motd"], "hi");
+    }
+
+    #[test]
+    fn no_encrypted_settings_needs_no_key() {
+        let mut settings = json!({"motd": "hi"});
+        let decrypted =
+            decrypt_settings(settings.as_object_mut().unwrap(), "/nonexistent").unwrap();
+        assert!(decrypted.is_empty());
+    }
+
+    #[test]
+    fn invalid_identities() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let key_file = dir.path().join("key.txt");
+        fs::write(&key_file, "# nothing here\n\n").unwrap();
+        assert!(read_identities(&key_file).is_err());
+        let public = Identity::generate().to_public().to_string();
+        fs::write(&key_file, public).unwrap();
+        assert!(read_identities(&key_file).is_err());
+    }
+
+    #[test]
+    fn wrong_identity() {
+        let identity = Identity::generate();
+        let armored = encrypt(&identity, b"secret");
+        assert!(decrypt_armored(&armored, &[Identity::generate()]).is_err());
+        assert_eq!(
+            decrypt_armored(&armored, &[Identity::generate(), identity]).unwrap(),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn tampered_payload() {
+        let identity = Identity::generate();
+        let mut data = encrypt_with(&identity, b"secret", Format::Binary);
+        *data.last_mut().unwrap() ^= 1;
+        let mut armored = Vec::new();
+        let mut writer = ArmoredWriter::wrap_output(&mut armored, Format::AsciiArmor).unwrap();
+        writer.write_all(&data).unwrap();
+        writer.finish().unwrap();
+        let tampered = String::from_utf8(armored).unwrap();
+        assert!(decrypt_armored(&tampered, &[identity]).is_err());
+    }
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("{}", source))]
+        Age { source: age::DecryptError },
+
+        #[snafu(display("Unable to decrypt {}: {}", name, source))]
+        Decrypt {
+            name: String,
+            #[snafu(source(from(Error, Box::new)))]
+            source: Box<Error>,
+        },
+
+        #[snafu(display("Invalid age identity '{}...' in '{}'", identity, path.display()))]
+        InvalidIdentity { path: PathBuf, identity: String },
+
+        #[snafu(display("{}", source))]
+        KernelCmdline {
+            source: crate::provider::cmdline::error::Error,
+        },
+
+        #[snafu(display("Unable to read secret key file '{}': {}", path.display(), source))]
+        KeyFileRead {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("No age identities in '{}'", path.display()))]
+        NoIdentities { path: PathBuf },
+
+        #[snafu(display("Encrypted with a passphrase rather than to the host's key"))]
+        Passphrase,
+
+        #[snafu(display("Encrypted payload is truncated or has been modified: {}", source))]
+        Payload { source: std::io::Error },
+
+        #[snafu(display("Decrypted value is not UTF-8: {}", source))]
+        Utf8 { source: std::string::FromUtf8Error },
+    }
+}
+
+pub(crate) use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
@@ -7,7 +7,7 @@ use snafu::{OptionExt, ResultExt};
 /// SettingsJson represents a change that a provider would like to make in the API.
 #[derive(Debug)]
 pub(crate) struct SettingsJson {
-    pub(crate) json: String,
+    pub(crate) json: serde_json::Value,
     pub(crate) desc: String,
 }
 
@@ -23,7 +23,7 @@ impl SettingsJson {
         S: Into<String>,
     {
         Ok(Self {
-            json: serde_json::to_string(&data).context(error::SettingsToJSONSnafu)?,
+            json: serde_json::to_value(data).context(error::SettingsToJSONSnafu)?,
             desc: desc.into(),
         })
     }
@@ -0,0 +1,7 @@
+/*!
+This library lets the API server and `bootstrap-containers` see the state of host containers and
+bootstrap containers the same way: from the systemd unit that runs each one, and from host-ctr,
+which asks host-containerd about the container itself.
+*/
+
+pub mod status;
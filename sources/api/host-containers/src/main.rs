@@ -8,6 +8,9 @@ It queries the API for their settings, then configures the system by:
   user-data setting is set for the host container.  (The decoded contents are available to the
   container at /.bottlerocket/host-containers/NAME/user-data)
 * creating an environment file used by a host-container-specific instance of a systemd service
+* creating a container config file that host-ctr applies to the container, with its CPU and
+  memory limits, read-only mounts of host paths, and environment variables
+* creating a systemd drop-in for the service with the host container's restart policy
 * ensuring the host container's systemd service is enabled/started or disabled/stopped
 */
 
@@ -16,19 +19,22 @@ extern crate log;
 
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::ffi::OsStr;
 use std::fmt::Write;
 use std::fs;
-use std::os::unix::fs::PermissionsExt;
+use std::io::Write as _;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::process::{self, Command};
 use std::str::FromStr;
 
-use model::modeled_types::Identifier;
+use model::modeled_types::{HostContainerRestartPolicy, Identifier};
+use serde::Serialize;
 
 const ENV_FILE_DIR: &str = "/etc/host-containers";
+const SYSTEMD_UNIT_DIR: &str = "/etc/systemd/system";
 const PERSISTENT_STORAGE_BASE_DIR: &str = "/local/host-containers";
 
 mod error {
@@ -82,6 +88,18 @@ mod error {
         #[snafu(display("Failed to write EnvironmentFile to {}: {}", path.display(), source))]
         EnvFileWriteFailed { path: PathBuf, source: io::Error },
 
+        #[snafu(display("Failed to serialize container config for {}: {}", name, source))]
+        ContainerConfigJson {
+            name: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to write container config to {}: {}", path.display(), source))]
+        ContainerConfigWrite { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to write systemd drop-in to {}: {}", path.display(), source))]
+        DropInWrite { path: PathBuf, source: io::Error },
+
         #[snafu(display("Failed to execute '{:?}': {}", command, source))]
         ExecutionFailure {
             command: Command,
@@ -285,6 +303,109 @@ where
     Ok(())
 }
 
+/// The container config that host-ctr applies to the container's spec, in addition to the settings
+/// in the environment file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ContainerConfig<'a> {
+    /// CPU time, in microseconds, the container may use in each 100ms CFS period.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_quota: Option<u64>,
+    /// Memory limit in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    mounts: Vec<ContainerMount<'a>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    environment: BTreeMap<&'a str, &'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct ContainerMount<'a> {
+    source: &'a str,
+    destination: &'a str,
+}
+
+impl<'a> From<&'a model::HostContainer> for ContainerConfig<'a> {
+    fn from(image_details: &'a model::HostContainer) -> Self {
+        let mounts = image_details
+            .mounts
+            .iter()
+            .flatten()
+            .filter_map(|mount| match (&mount.source, &mount.destination) {
+                (Some(source), Some(destination)) => Some(ContainerMount {
+                    source,
+                    destination,
+                }),
+                _ => {
+                    warn!("Ignoring host container mount without both source and destination");
+                    None
+                }
+            })
+            .collect();
+        // Sort the variables so the file only changes when the settings do.
+        let environment = image_details
+            .environment
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.as_ref(), value.as_ref()))
+            .collect();
+
+        ContainerConfig {
+            cpu_quota: image_details.cpu_limit.as_ref().map(|cpu| cpu.cpu_quota()),
+            memory_limit: image_details.memory_limit.as_ref().map(|mem| mem.bytes()),
+            mounts,
+            environment,
+        }
+    }
+}
+
+/// Write out the container config that host-ctr reads to set the container's resource limits,
+/// mounts, and environment.  The environment may hold credentials, so only root can read it.
+fn write_container_config<S>(name: S, image_details: &model::HostContainer) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref();
+    let path = Path::new(ENV_FILE_DIR).join(format!("{}.json", name));
+
+    let config = ContainerConfig::from(image_details);
+    let json =
+        serde_json::to_string_pretty(&config).context(error::ContainerConfigJsonSnafu { name })?;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .context(error::ContainerConfigWriteSnafu { path: &path })?;
+    file.write_all(json.as_bytes())
+        .context(error::ContainerConfigWriteSnafu { path })?;
+
+    Ok(())
+}
+
+/// Write out a systemd drop-in for the host container's service with its restart policy.  Returns
+/// true if the drop-in changed, in which case systemd needs to reload its units.
+fn write_restart_drop_in<S>(name: S, restart_policy: &HostContainerRestartPolicy) -> Result<bool>
+where
+    S: AsRef<str>,
+{
+    let dir =
+        Path::new(SYSTEMD_UNIT_DIR).join(format!("host-containers@{}.service.d", name.as_ref()));
+    let path = dir.join("restart.conf");
+    let drop_in = format!("[Service]\nRestart={}\n", restart_policy);
+
+    if fs::read_to_string(&path).ok().as_deref() == Some(drop_in.as_str()) {
+        return Ok(false);
+    }
+
+    fs::create_dir_all(&dir).context(error::MkdirSnafu { dir: &dir })?;
+    fs::write(&path, drop_in).context(error::DropInWriteSnafu { path })?;
+    Ok(true)
+}
+
 /// Store the args we receive on the command line
 struct Args {
     log_level: LevelFilter,
@@ -386,6 +507,18 @@ where
     // Write the environment file needed for the systemd service to have details about this
     // specific host container
     write_env_file(name, source, enabled, superpowered)?;
+    write_container_config(name, image_details)?;
+
+    // Apply the restart policy through a drop-in for the host container's service.  systemd only
+    // sees it after a reload, which we skip if nothing changed since it reloads every unit.
+    let restart_policy = image_details.restart_policy.clone().unwrap_or_default();
+    if write_restart_drop_in(name, &restart_policy)? {
+        debug!(
+            "Reloading systemd units for host container '{}' restart policy '{}'",
+            name, restart_policy
+        );
+        command(constants::SYSTEMCTL_BIN, ["daemon-reload"])?;
+    }
 
     // Now start/stop the container according to the 'enabled' setting
     let unit_name = format!("host-containers@{}.service", name);
@@ -0,0 +1,513 @@
+//! The status module describes the state of each configured host container and bootstrap
+//! container.  systemd knows whether the unit running host-ctr is up, when it started, and how it
+//! last ended; host-ctr knows whether the container exists yet, whether its task is running, and
+//! which image it runs.  Together they tell us whether the container is still being pulled, is
+//! running, or has exited or failed.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{debug, trace};
+use model::modeled_types::Identifier;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The directory where bootstrap containers' units leave their sentinel and failure files.
+pub const BOOTSTRAP_CONTAINERS_RUN_DIR: &str = "/run/bootstrap-containers";
+
+/// The unit properties we need from `systemctl show`.
+const UNIT_PROPERTIES: &str =
+    "ActiveState,SubState,Result,UnitFileState,ExecMainStartTimestamp,ExecMainCode,ExecMainStatus";
+/// The format of systemd timestamps when shown with `--timestamp=utc`.
+const SYSTEMD_TIMESTAMP_FORMAT: &str = "%a %Y-%m-%d %H:%M:%S UTC";
+/// The ExecMainCode systemd reports when the main process exited on its own, rather than being
+/// killed by a signal; these are the CLD_* codes from waitid(2).
+const CLD_EXITED: &str = "1";
+
+/// The kinds of container we describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerKind {
+    Host,
+    Bootstrap,
+}
+
+impl ContainerKind {
+    /// The systemd unit that runs the container with the given name.
+    fn unit(&self, name: &str) -> String {
+        match self {
+            ContainerKind::Host => format!("host-containers@{}.service", name),
+            ContainerKind::Bootstrap => format!("bootstrap-containers@{}.service", name),
+        }
+    }
+
+    /// The container's ID in host-containerd.
+    fn container_id(&self, name: &str) -> String {
+        match self {
+            ContainerKind::Host => name.to_string(),
+            ContainerKind::Bootstrap => format!("boot.{}", name),
+        }
+    }
+}
+
+impl fmt::Display for ContainerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerKind::Host => write!(f, "host"),
+            ContainerKind::Bootstrap => write!(f, "bootstrap"),
+        }
+    }
+}
+
+/// The states a container can be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerState {
+    /// The unit is starting, but the container isn't running yet; host-ctr pulls the image first.
+    Pulling,
+    Running,
+    /// The container finished, successfully or not, and isn't being restarted yet.
+    Exited,
+    /// The unit failed and won't be restarted.
+    Failed,
+    /// The container is enabled, but hasn't started in this boot.
+    Stopped,
+    /// The container isn't enabled, or its mode is "off".
+    Disabled,
+}
+
+/// The state of a configured container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ContainerStatus {
+    pub name: String,
+    pub kind: ContainerKind,
+    pub state: ContainerState,
+    /// The configured image source.
+    pub source: Option<String>,
+    /// The digest of the image the container was created from.
+    pub image_digest: Option<String>,
+    /// When the unit last started the container, including time spent pulling.
+    pub started_at: Option<DateTime<Utc>>,
+    /// The exit code of the container, if it exited on its own.
+    pub exit_code: Option<i32>,
+    /// Why the container last failed, if it did.
+    pub last_error: Option<String>,
+}
+
+/// The container's unit, as described by `systemctl show`.
+#[derive(Debug, Default)]
+struct UnitStatus {
+    active_state: String,
+    sub_state: String,
+    result: String,
+    unit_file_state: String,
+    started_at: Option<DateTime<Utc>>,
+    exit_code: String,
+    exit_status: String,
+}
+
+impl UnitStatus {
+    fn parse(output: &str) -> Self {
+        let mut status = UnitStatus::default();
+        for line in output.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let value = value.trim().to_string();
+            match key {
+                "ActiveState" => status.active_state = value,
+                "SubState" => status.sub_state = value,
+                "Result" => status.result = value,
+                "UnitFileState" => status.unit_file_state = value,
+                "ExecMainStartTimestamp" => {
+                    // Empty if the unit hasn't started since boot.
+                    status.started_at =
+                        NaiveDateTime::parse_from_str(&value, SYSTEMD_TIMESTAMP_FORMAT)
+                            .ok()
+                            .map(|naive| DateTime::from_utc(naive, Utc))
+                }
+                "ExecMainCode" => status.exit_code = value,
+                "ExecMainStatus" => status.exit_status = value,
+                _ => {}
+            }
+        }
+        status
+    }
+
+    fn is_enabled(&self) -> bool {
+        matches!(self.unit_file_state.as_str(), "enabled" | "enabled-runtime")
+    }
+
+    /// The main process's exit code, if it exited on its own.
+    fn exit_code(&self) -> Option<i32> {
+        if self.exit_code == CLD_EXITED {
+            self.exit_status.parse().ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// The container, as described by `host-ctr status`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct HostCtrStatus {
+    exists: bool,
+    #[serde(default)]
+    status: String,
+    image_digest: Option<String>,
+    exit_status: Option<i32>,
+}
+
+/// Describes a failed run of a container for people, given systemd's result for its unit and the
+/// exit status or signal of the main process.
+pub fn failure_reason(result: &str, exit_status: Option<&str>) -> String {
+    match (result, exit_status) {
+        ("timeout", _) => "timed out, and was stopped".to_string(),
+        ("exit-code", Some(status)) => format!("exited with status {}", status),
+        ("signal" | "core-dump", Some(signal)) => format!("was killed by signal {}", signal),
+        ("start-limit-hit", _) => "failed too many times".to_string(),
+        (result, _) => format!("failed with result '{}'", result),
+    }
+}
+
+/// The file where a bootstrap container's unit records why the container failed.
+pub fn bootstrap_failure_path(name: &str) -> PathBuf {
+    PathBuf::from(BOOTSTRAP_CONTAINERS_RUN_DIR).join(format!("{}.failed", name))
+}
+
+/// Returns the status of each configured host container and bootstrap container, sorted by kind
+/// and name.
+pub fn statuses(
+    host_containers: &HashMap<Identifier, model::HostContainer>,
+    bootstrap_containers: &HashMap<Identifier, model::BootstrapContainer>,
+) -> Result<Vec<ContainerStatus>> {
+    let mut statuses = Vec::new();
+    for (name, container) in host_containers {
+        statuses.push(host_container_status(name, container)?);
+    }
+    for (name, container) in bootstrap_containers {
+        statuses.push(bootstrap_container_status(name, container)?);
+    }
+    statuses.sort_by(|a, b| (a.kind as u8, &a.name).cmp(&(b.kind as u8, &b.name)));
+    Ok(statuses)
+}
+
+/// Returns the status of a host container.
+pub fn host_container_status(
+    name: &str,
+    container: &model::HostContainer,
+) -> Result<ContainerStatus> {
+    let kind = ContainerKind::Host;
+    let unit = unit_status(&kind.unit(name))?;
+    let ctr = host_ctr_status(&kind.container_id(name));
+    let enabled = container.enabled.unwrap_or(false);
+    let last_error = (unit.result != "success" && !unit.result.is_empty())
+        .then(|| failure_reason(&unit.result, Some(&unit.exit_status)));
+
+    Ok(status(
+        name,
+        kind,
+        container.source.as_deref(),
+        enabled,
+        &unit,
+        ctr,
+        last_error,
+    ))
+}
+
+/// Returns the status of a bootstrap container.
+pub fn bootstrap_container_status(
+    name: &str,
+    container: &model::BootstrapContainer,
+) -> Result<ContainerStatus> {
+    let kind = ContainerKind::Bootstrap;
+    let unit = unit_status(&kind.unit(name))?;
+    let ctr = host_ctr_status(&kind.container_id(name));
+    let enabled = container
+        .mode
+        .as_deref()
+        .map_or(false, |mode| mode != "off");
+    let last_error = bootstrap_failure(name)?;
+
+    Ok(status(
+        name,
+        kind,
+        container.source.as_deref(),
+        enabled,
+        &unit,
+        ctr,
+        last_error,
+    ))
+}
+
+fn status(
+    name: &str,
+    kind: ContainerKind,
+    source: Option<&str>,
+    enabled: bool,
+    unit: &UnitStatus,
+    ctr: Option<HostCtrStatus>,
+    last_error: Option<String>,
+) -> ContainerStatus {
+    let ctr = ctr.unwrap_or_default();
+    let state = state(kind, enabled, unit, &ctr);
+    let exit_code = match state {
+        ContainerState::Exited | ContainerState::Failed => ctr.exit_status.or(unit.exit_code()),
+        _ => None,
+    };
+
+    ContainerStatus {
+        name: name.to_string(),
+        kind,
+        state,
+        source: source.map(str::to_string),
+        image_digest: ctr.image_digest,
+        started_at: unit.started_at,
+        exit_code,
+        last_error,
+    }
+}
+
+/// Works out the container's state from its unit and container.
+fn state(
+    kind: ContainerKind,
+    enabled: bool,
+    unit: &UnitStatus,
+    ctr: &HostCtrStatus,
+) -> ContainerState {
+    let running = ctr.exists && ctr.status == "running";
+    match unit.active_state.as_str() {
+        "failed" => ContainerState::Failed,
+        // systemd is waiting to restart the container after it exited.
+        "activating" if unit.sub_state == "auto-restart" => ContainerState::Exited,
+        _ if running => ContainerState::Running,
+        // Bootstrap container units stay active after the container exits successfully.
+        "active" if kind == ContainerKind::Bootstrap && unit.sub_state == "exited" => {
+            ContainerState::Exited
+        }
+        "active" | "activating" | "reloading" => ContainerState::Pulling,
+        _ if unit.started_at.is_some() => ContainerState::Exited,
+        _ if enabled || unit.is_enabled() => ContainerState::Stopped,
+        _ => ContainerState::Disabled,
+    }
+}
+
+fn unit_status(unit: &str) -> Result<UnitStatus> {
+    let mut command = Command::new(constants::SYSTEMCTL_BIN);
+    command.args([
+        "show",
+        "--timestamp=utc",
+        "--property",
+        UNIT_PROPERTIES,
+        unit,
+    ]);
+    let output = command
+        .output()
+        .context(error::ExecutionFailureSnafu { command })?;
+    ensure!(
+        output.status.success(),
+        error::CommandFailureSnafu {
+            bin_path: constants::SYSTEMCTL_BIN,
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    trace!("Status of {}: {}", unit, stdout);
+    Ok(UnitStatus::parse(&stdout))
+}
+
+/// Asks host-ctr about the container.  Returns None if it can't say, for example because
+/// host-containerd isn't running yet; the unit's status is still useful then.
+fn host_ctr_status(container_id: &str) -> Option<HostCtrStatus> {
+    let output = match Command::new(constants::HOST_CTR_BIN)
+        .args(["status", "--container-id", container_id])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Failed to run host-ctr for '{}': {}", container_id, e);
+            return None;
+        }
+    };
+    if !output.status.success() {
+        debug!(
+            "host-ctr failed to get status of '{}': {}",
+            container_id,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return None;
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| debug!("Invalid status from host-ctr for '{}': {}", container_id, e))
+        .ok()
+}
+
+/// Reads why the bootstrap container failed, as recorded by its unit, if it has.
+fn bootstrap_failure(name: &str) -> Result<Option<String>> {
+    #[derive(Deserialize)]
+    struct Failure {
+        reason: String,
+    }
+
+    let path = bootstrap_failure_path(name);
+    let json = match fs::read(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(error::ReadFailureSnafu { path }),
+    };
+    let failure: Failure =
+        serde_json::from_slice(&json).context(error::ParseFailureSnafu { path })?;
+    Ok(Some(failure.reason))
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed to execute '{:?}': {}", command, source))]
+        ExecutionFailure { command: Command, source: io::Error },
+
+        #[snafu(display("'{}' failed - stderr: {}", bin_path, stderr))]
+        CommandFailure { bin_path: String, stderr: String },
+
+        #[snafu(display("Failed to read {}: {}", path.display(), source))]
+        ReadFailure { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to parse {}: {}", path.display(), source))]
+        ParseFailure {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RUNNING: &str = "\
+ActiveState=active
+SubState=running
+Result=success
+UnitFileState=enabled
+ExecMainStartTimestamp=Sun 2026-10-18 04:08:39 UTC
+ExecMainCode=0
+ExecMainStatus=0
+";
+
+    fn ctr(status: &str, exit_status: Option<i32>) -> HostCtrStatus {
+        HostCtrStatus {
+            exists: true,
+            status: status.to_string(),
+            image_digest: Some("sha256:0123".to_string()),
+            exit_status,
+        }
+    }
+
+    #[test]
+    fn parse_unit() {
+        let unit = UnitStatus::parse(RUNNING);
+        assert_eq!(unit.active_state, "active");
+        assert!(unit.is_enabled());
+        assert_eq!(
+            unit.started_at.unwrap().to_rfc3339(),
+            "2026-10-18T04:08:39+00:00"
+        );
+        assert_eq!(unit.exit_code(), None);
+
+        let unit = UnitStatus::parse("ExecMainStartTimestamp=\nExecMainCode=1\nExecMainStatus=3\n");
+        assert_eq!(unit.started_at, None);
+        assert_eq!(unit.exit_code(), Some(3));
+    }
+
+    #[test]
+    fn host_container_states() {
+        let host = ContainerKind::Host;
+        let unit = UnitStatus::parse(RUNNING);
+        assert_eq!(
+            state(host, true, &unit, &ctr("running", None)),
+            ContainerState::Running
+        );
+        // host-ctr is still pulling the image.
+        assert_eq!(
+            state(host, true, &unit, &HostCtrStatus::default()),
+            ContainerState::Pulling
+        );
+
+        let unit = UnitStatus::parse(
+            "ActiveState=activating\nSubState=auto-restart\nResult=exit-code\nExecMainCode=1\nExecMainStatus=2\n",
+        );
+        let status = status(
+            "admin",
+            host,
+            None,
+            true,
+            &unit,
+            Some(ctr("stopped", Some(2))),
+            None,
+        );
+        assert_eq!(status.state, ContainerState::Exited);
+        assert_eq!(status.exit_code, Some(2));
+
+        let unit = UnitStatus::parse("ActiveState=inactive\nUnitFileState=disabled\n");
+        assert_eq!(
+            state(host, false, &unit, &HostCtrStatus::default()),
+            ContainerState::Disabled
+        );
+        assert_eq!(
+            state(host, true, &unit, &HostCtrStatus::default()),
+            ContainerState::Stopped
+        );
+    }
+
+    #[test]
+    fn bootstrap_container_states() {
+        let bootstrap = ContainerKind::Bootstrap;
+        let unit = UnitStatus::parse(
+            "ActiveState=active\nSubState=exited\nResult=success\nExecMainStartTimestamp=Sun 2026-10-18 04:08:39 UTC\nExecMainCode=1\nExecMainStatus=0\n",
+        );
+        let status = status("disk", bootstrap, None, true, &unit, None, None);
+        assert_eq!(status.state, ContainerState::Exited);
+        assert_eq!(status.exit_code, Some(0));
+
+        let unit = UnitStatus::parse("ActiveState=failed\nResult=timeout\n");
+        assert_eq!(
+            state(bootstrap, true, &unit, &HostCtrStatus::default()),
+            ContainerState::Failed
+        );
+    }
+
+    #[test]
+    fn reasons() {
+        assert_eq!(
+            failure_reason("exit-code", Some("1")),
+            "exited with status 1"
+        );
+        assert_eq!(
+            failure_reason("signal", Some("KILL")),
+            "was killed by signal KILL"
+        );
+        assert_eq!(
+            failure_reason("timeout", None),
+            "timed out, and was stopped"
+        );
+        assert_eq!(
+            failure_reason("resources", None),
+            "failed with result 'resources'"
+        );
+    }
+}
@@ -174,6 +174,108 @@ mod test_add_prefixes_migration {
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
+/// We use this migration when we add settings to each entry of a map with user-defined keys, like
+/// settings.host-containers, and want to make sure they're removed before we go back to old
+/// versions that don't understand them.  The settings are named relative to an entry, for example
+/// "restart-policy" for settings.host-containers.<name>.restart-policy, and anything under them,
+/// like the keys of a map setting, is removed too.
+pub struct AddEntrySettingsMigration<'a> {
+    pub map: &'static str,
+    pub settings: &'a [&'static str],
+}
+
+impl AddEntrySettingsMigration<'_> {
+    /// Returns whether the key is one of the settings, or under one, in any entry of the map.
+    fn matches(&self, key: &str) -> bool {
+        let within_entry = key
+            .strip_prefix(self.map)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.split_once('.'))
+            .map(|(_entry, setting)| setting);
+        within_entry.map_or(false, |within_entry| {
+            self.settings.iter().any(|setting| {
+                within_entry == *setting
+                    || within_entry
+                        .strip_prefix(setting)
+                        .map_or(false, |rest| rest.starts_with('.'))
+            })
+        })
+    }
+}
+
+impl Migration for AddEntrySettingsMigration<'_> {
+    /// New versions must either have a default for the settings or generate them; we don't need to
+    /// do anything.
+    fn forward(&mut self, input: MigrationData) -> Result<MigrationData> {
+        println!(
+            "AddEntrySettingsMigration({}, {:?}) has no work to do on upgrade.",
+            self.map, self.settings
+        );
+        Ok(input)
+    }
+
+    /// Older versions don't know about the settings; we remove them from every entry so that old
+    /// versions don't see them and fail deserialization.  (The settings must be optional in new
+    /// versions, and safe to remove.)
+    fn backward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        let settings = input
+            .data
+            .keys()
+            .filter(|k| self.matches(k))
+            .cloned()
+            .collect::<Vec<_>>();
+        for setting in settings {
+            if let Some(data) = input.data.remove(&setting) {
+                println!("Removed {}, which was set to '{}'", setting, data);
+            }
+        }
+        Ok(input)
+    }
+}
+
+#[cfg(test)]
+mod test_add_entry_settings_migration {
+    use super::AddEntrySettingsMigration;
+    use crate::{Migration, MigrationData};
+    use maplit::hashmap;
+    use std::collections::HashMap;
+
+    #[test]
+    fn removes_from_each_entry() {
+        let data = MigrationData {
+            data: hashmap! {
+                "settings.containers.a.source".into() => 0.into(),
+                "settings.containers.a.limit".into() => 0.into(),
+                "settings.containers.b.limit".into() => 0.into(),
+                "settings.containers.b.env.ONE".into() => 0.into(),
+                "settings.containers.b.env.TWO".into() => 0.into(),
+                "settings.containers.b.environment".into() => 0.into(),
+                "settings.containers.limit".into() => 0.into(),
+                "settings.other.a.limit".into() => 0.into(),
+            },
+            metadata: HashMap::new(),
+        };
+        // Run backward, e.g. downgrade, to test that the right keys are removed
+        let result = AddEntrySettingsMigration {
+            map: "settings.containers",
+            settings: &["limit", "env"],
+        }
+        .backward(data)
+        .unwrap();
+        assert_eq!(
+            result.data,
+            hashmap! {
+                "settings.containers.a.source".into() => 0.into(),
+                "settings.containers.b.environment".into() => 0.into(),
+                "settings.containers.limit".into() => 0.into(),
+                "settings.other.a.limit".into() => 0.into(),
+            }
+        );
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
 /// We use this migration when we remove settings from the model, so the new version doesn't see
 /// them and error.
 pub struct RemoveSettingsMigration<'a>(pub &'a [&'static str]);
@@ -0,0 +1,22 @@
+use migration_helpers::common_migrations::AddEntrySettingsMigration;
+use migration_helpers::{migrate, Result};
+use std::process;
+
+/// We added settings for the order, timeout, and retries of each bootstrap container.  Remove them
+/// from every bootstrap container if we downgrade.
+fn run() -> Result<()> {
+    migrate(AddEntrySettingsMigration {
+        map: "settings.bootstrap-containers",
+        settings: &["after", "timeout-seconds", "retries"],
+    })
+}
+
+// Returning a Result from main makes it print a Debug representation of the error, but with Snafu
+// we have nice Display representations of the error, so we wrap "main" (run) and print any error.
+// https://github.com/shepmaster/snafu/issues/110
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,28 @@
+use migration_helpers::common_migrations::AddEntrySettingsMigration;
+use migration_helpers::{migrate, Result};
+use std::process;
+
+/// We added settings for each host container's restart policy, resource limits, mounts, and
+/// environment.  Remove them from every host container if we downgrade.
+fn run() -> Result<()> {
+    migrate(AddEntrySettingsMigration {
+        map: "settings.host-containers",
+        settings: &[
+            "restart-policy",
+            "cpu-limit",
+            "memory-limit",
+            "mounts",
+            "environment",
+        ],
+    })
+}
+
+// Returning a Result from main makes it print a Debug representation of the error, but with Snafu
+// we have nice Display representations of the error, so we wrap "main" (run) and print any error.
+// https://github.com/shepmaster/snafu/issues/110
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,21 @@
+use migration_helpers::common_migrations::AddSettingsMigration;
+use migration_helpers::{migrate, Result};
+use std::process;
+
+/// We added a setting for the address where metricdog serves metrics to local scrapers.  Remove
+/// it if we downgrade.
+fn run() -> Result<()> {
+    migrate(AddSettingsMigration(&[
+        "settings.metrics.exporter-listen-address",
+    ]))
+}
+
+// Returning a Result from main makes it print a Debug representation of the error, but with Snafu
+// we have nice Display representations of the error, so we wrap "main" (run) and print any error.
+// https://github.com/shepmaster/snafu/issues/110
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
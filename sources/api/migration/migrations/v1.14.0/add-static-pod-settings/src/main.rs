@@ -0,0 +1,22 @@
+use migration_helpers::common_migrations::AddEntrySettingsMigration;
+use migration_helpers::{migrate, Result};
+use std::process;
+
+/// We added settings for fetching each static pod's manifest from a URL, and for rendering it as a
+/// template.  Remove them from every static pod if we downgrade.
+fn run() -> Result<()> {
+    migrate(AddEntrySettingsMigration {
+        map: "settings.kubernetes.static-pods",
+        settings: &["manifest-url", "manifest-sha256", "template"],
+    })
+}
+
+// Returning a Result from main makes it print a Debug representation of the error, but with Snafu
+// we have nice Display representations of the error, so we wrap "main" (run) and print any error.
+// https://github.com/shepmaster/snafu/issues/110
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,303 @@
+//! After a kernel crash, `capture-dump` leaves the dmesg and memory dumps in the kdump logs
+//! directory and reboots.  On the next boot, `report-crash` moves them into a directory of their
+//! own, named for the time they were captured, and writes a small JSON crash report next to them
+//! with the panic message and the last lines of the kernel log.  The latest report is also written
+//! to a well-known path so the API server and logdog can return it.  Only the most recent crash
+//! directories are kept, since memory dumps are large and would otherwise fill the disk after
+//! repeated crashes.
+
+use crate::error::{self, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the directories holding each crash's dumps.
+const CRASH_DIR_PREFIX: &str = "crash-";
+/// Name of the crash report written in each crash directory.
+const CRASH_REPORT_FILE: &str = "crash-report.json";
+/// The number of lines from the end of the kernel log to include in a crash report.
+const DMESG_TAIL_LINES: usize = 50;
+/// The kernel's message when it panics, followed by the reason.
+const PANIC_MARKER: &str = "Kernel panic - ";
+/// Messages that indicate what went wrong before the panic, if the panic message is missing.
+const FAULT_MARKERS: &[&str] = &["BUG: ", "Oops: ", "general protection fault"];
+
+/// A summary of a kernel crash, written after the next boot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct CrashReport {
+    /// When the dumps were captured, shortly after the crash.
+    pub(crate) captured_at: DateTime<Utc>,
+    /// The directory holding the dumps from this crash.
+    pub(crate) dump_dir: PathBuf,
+    /// The panic message from the kernel log, or the first fault message if there's no panic
+    /// message, for example "Kernel panic - not syncing: Fatal exception".
+    pub(crate) panic: Option<String>,
+    /// The last lines of the kernel log before the crash.
+    pub(crate) dmesg_tail: Vec<String>,
+    /// Whether a memory dump was captured, in addition to the kernel log.
+    pub(crate) vmcore: bool,
+}
+
+impl CrashReport {
+    /// Builds a report from the dmesg dump in the given crash directory.
+    fn from_dump_dir<P>(dump_dir: P, captured_at: DateTime<Utc>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let dump_dir = dump_dir.as_ref();
+        let dmesg_path = dump_dir.join(crate::DMESG_DUMP_FILE);
+        let dmesg = match fs::read(&dmesg_path) {
+            // The log can have odd bytes in it after a crash; we'd rather have the rest.
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).context(error::ReadFileSnafu { path: dmesg_path }),
+        };
+
+        Ok(Self {
+            captured_at,
+            dump_dir: dump_dir.to_path_buf(),
+            panic: find_panic(&dmesg),
+            dmesg_tail: dmesg_tail(&dmesg, DMESG_TAIL_LINES),
+            vmcore: dump_dir.join(crate::KDUMP_FILE).exists(),
+        })
+    }
+}
+
+/// Strips the timestamp that the kernel puts before each log line, like `[   12.345678] `.
+fn strip_timestamp(line: &str) -> &str {
+    if line.starts_with('[') {
+        if let Some(idx) = line.find("] ") {
+            return &line[idx + 2..];
+        }
+    }
+    line
+}
+
+/// Finds the reason for the crash in the kernel log.  We prefer the panic message, using the last
+/// one in case the crash kernel logged its own, and otherwise the first fault message, since later
+/// ones are often fallout from the first.
+fn find_panic(dmesg: &str) -> Option<String> {
+    let mut messages = dmesg.lines().map(|line| strip_timestamp(line).trim());
+    if let Some(panic) = messages
+        .clone()
+        .filter(|m| m.starts_with(PANIC_MARKER))
+        .last()
+    {
+        return Some(panic.to_string());
+    }
+    messages
+        .find(|m| FAULT_MARKERS.iter().any(|marker| m.starts_with(marker)))
+        .map(str::to_string)
+}
+
+/// Returns up to `count` lines from the end of the kernel log.
+fn dmesg_tail(dmesg: &str, count: usize) -> Vec<String> {
+    let lines: Vec<&str> = dmesg.lines().filter(|l| !l.trim().is_empty()).collect();
+    let start = lines.len().saturating_sub(count);
+    lines[start..].iter().map(|l| l.to_string()).collect()
+}
+
+/// Moves new dumps from the top of `logs_dir` into a crash directory, writes a crash report for
+/// them, and updates the latest report at `latest_report`.  Then removes all but the `keep` most
+/// recent crash directories.  Returns the report for new dumps, if there were any.
+pub(crate) fn report_crash<P1, P2>(
+    logs_dir: P1,
+    latest_report: P2,
+    keep: usize,
+) -> Result<Option<CrashReport>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let logs_dir = logs_dir.as_ref();
+    let report = collect_new_dumps(logs_dir)?;
+
+    if let Some(report) = &report {
+        let json = serde_json::to_vec_pretty(report).context(error::CrashReportJsonSnafu)?;
+        for path in [
+            report.dump_dir.join(CRASH_REPORT_FILE),
+            latest_report.as_ref().to_path_buf(),
+        ] {
+            fs::write(&path, &json).context(error::WriteFileSnafu { path })?;
+        }
+    }
+
+    prune_crash_dirs(logs_dir, keep)?;
+    Ok(report)
+}
+
+/// Moves the files left by `capture-dump` into a new crash directory, and returns a crash report
+/// for them.  Returns None if there aren't any.
+fn collect_new_dumps(logs_dir: &Path) -> Result<Option<CrashReport>> {
+    let dmesg_path = logs_dir.join(crate::DMESG_DUMP_FILE);
+    let kdump_path = logs_dir.join(crate::KDUMP_FILE);
+    // capture-dump writes dmesg first, but be ready for it to have failed.
+    let marker = if dmesg_path.exists() {
+        dmesg_path
+    } else if kdump_path.exists() {
+        kdump_path
+    } else {
+        debug!("No new dumps in {}", logs_dir.display());
+        return Ok(None);
+    };
+
+    // The dumps were written by the crash kernel right after the crash, so their modification
+    // time is as close as we can get to the time of the crash.
+    let captured_at: DateTime<Utc> = fs::metadata(&marker)
+        .and_then(|metadata| metadata.modified())
+        .context(error::ReadFileSnafu { path: &marker })?
+        .into();
+
+    let dump_dir = logs_dir.join(format!(
+        "{}{}",
+        CRASH_DIR_PREFIX,
+        captured_at.format("%Y%m%dT%H%M%SZ")
+    ));
+    fs::create_dir_all(&dump_dir).context(error::CreateDirSnafu { path: &dump_dir })?;
+
+    for filename in [crate::DMESG_DUMP_FILE, crate::KDUMP_FILE, crate::LOG_FILE] {
+        let from = logs_dir.join(filename);
+        if from.exists() {
+            let to = dump_dir.join(filename);
+            fs::rename(&from, &to).context(error::MoveFileSnafu { from, to })?;
+        }
+    }
+
+    let report = CrashReport::from_dump_dir(&dump_dir, captured_at)?;
+    match &report.panic {
+        Some(panic) => warn!("Kernel crashed at {}: {}", captured_at, panic),
+        None => warn!("Kernel crashed at {}", captured_at),
+    }
+    info!("Moved dumps to {}", dump_dir.display());
+    Ok(Some(report))
+}
+
+/// Removes all but the `keep` most recent crash directories.  Their names sort by the time the
+/// dumps were captured.
+fn prune_crash_dirs(logs_dir: &Path, keep: usize) -> Result<()> {
+    let entries = fs::read_dir(logs_dir).context(error::ReadDirSnafu { path: logs_dir })?;
+    let mut crash_dirs = Vec::new();
+    for entry in entries {
+        let entry = entry.context(error::ReadDirSnafu { path: logs_dir })?;
+        let is_crash_dir = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(CRASH_DIR_PREFIX)
+            && entry.path().is_dir();
+        if is_crash_dir {
+            crash_dirs.push(entry.path());
+        }
+    }
+    crash_dirs.sort();
+
+    let excess = crash_dirs.len().saturating_sub(keep);
+    for dir in &crash_dirs[..excess] {
+        info!("Removing old crash dumps in {}", dir.display());
+        fs::remove_dir_all(dir).context(error::RemoveDirSnafu { path: dir })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DMESG: &str = "\
+[    0.000000] Linux version 5.15.79 (builder@buildkitsandbox)
+[  120.442110] sysrq: Trigger a crash
+[  120.442571] Kernel panic - not syncing: sysrq triggered crash
+[  120.443012] CPU: 1 PID: 3211 Comm: bash Not tainted 5.15.79 #1
+[  120.443500] Call Trace:
+";
+
+    #[test]
+    fn panic_message() {
+        assert_eq!(
+            find_panic(DMESG).as_deref(),
+            Some("Kernel panic - not syncing: sysrq triggered crash")
+        );
+    }
+
+    #[test]
+    fn fault_message() {
+        let dmesg = "\
+[   10.000000] BUG: kernel NULL pointer dereference, address: 0000000000000000
+[   10.000100] Oops: 0000 [#1] SMP NOPTI
+";
+        assert_eq!(
+            find_panic(dmesg).as_deref(),
+            Some("BUG: kernel NULL pointer dereference, address: 0000000000000000")
+        );
+        assert_eq!(find_panic("[    1.0] all is well\n"), None);
+    }
+
+    #[test]
+    fn tail() {
+        assert_eq!(
+            dmesg_tail(DMESG, 2),
+            vec![
+                "[  120.443012] CPU: 1 PID: 3211 Comm: bash Not tainted 5.15.79 #1",
+                "[  120.443500] Call Trace:",
+            ]
+        );
+        assert_eq!(dmesg_tail(DMESG, 50).len(), 5);
+    }
+
+    #[test]
+    fn reports_new_dumps() {
+        let tempdir = TempDir::new().unwrap();
+        let logs_dir = tempdir.path();
+        let latest = logs_dir.join("latest.json");
+        fs::write(logs_dir.join(crate::DMESG_DUMP_FILE), DMESG).unwrap();
+        fs::write(logs_dir.join(crate::KDUMP_FILE), "vmcore").unwrap();
+        fs::write(logs_dir.join(crate::LOG_FILE), "log").unwrap();
+
+        let report = report_crash(logs_dir, &latest, 3).unwrap().unwrap();
+        assert!(report.vmcore);
+        assert_eq!(
+            report.panic.as_deref(),
+            Some("Kernel panic - not syncing: sysrq triggered crash")
+        );
+        assert!(report.dump_dir.join(crate::KDUMP_FILE).exists());
+        assert!(report.dump_dir.join(crate::LOG_FILE).exists());
+        assert!(!logs_dir.join(crate::DMESG_DUMP_FILE).exists());
+
+        let written: CrashReport =
+            serde_json::from_slice(&fs::read(report.dump_dir.join(CRASH_REPORT_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(written, report);
+        let latest: CrashReport = serde_json::from_slice(&fs::read(&latest).unwrap()).unwrap();
+        assert_eq!(latest, report);
+
+        // Nothing new the next time.
+        assert!(report_crash(logs_dir, logs_dir.join("latest.json"), 3)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn keeps_recent_dumps() {
+        let tempdir = TempDir::new().unwrap();
+        let logs_dir = tempdir.path();
+        for name in [
+            "crash-20260301T101500Z",
+            "crash-20260114T080000Z",
+            "crash-20260228T235959Z",
+        ] {
+            fs::create_dir(logs_dir.join(name)).unwrap();
+            fs::write(logs_dir.join(name).join(crate::KDUMP_FILE), "vmcore").unwrap();
+        }
+        fs::write(logs_dir.join("crash-not-a-dir"), "").unwrap();
+
+        prune_crash_dirs(logs_dir, 2).unwrap();
+        assert!(!logs_dir.join("crash-20260114T080000Z").exists());
+        assert!(logs_dir.join("crash-20260228T235959Z").exists());
+        assert!(logs_dir.join("crash-20260301T101500Z").exists());
+        assert!(logs_dir.join("crash-not-a-dir").exists());
+    }
+}
@@ -17,6 +17,15 @@ pub(super) enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to create directory '{}': {}", path.display(), source))]
+    CreateDir {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Error serializing crash report to JSON: {}", source))]
+    CrashReportJson { source: serde_json::error::Error },
+
     #[snafu(display("Kexec load syscalls are disabled, please make sure the value of `kernel.kexec_load_disabled` is 0"))]
     KexecLoadDisabled,
 
@@ -35,12 +44,31 @@ pub(super) enum Error {
     #[snafu(display("Failed to create mount '{}': '{}'", path, source))]
     Mount { path: String, source: nix::Error },
 
+    #[snafu(display("Failed to move '{}' to '{}': {}", from.display(), to.display(), source))]
+    MoveFile {
+        source: std::io::Error,
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    #[snafu(display("Failed to remove directory '{}': {}", path.display(), source))]
+    RemoveDir {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
     #[snafu(display("Failed to delete file '{}': '{}'", path, source))]
     RemoveFile {
         path: String,
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to read directory '{}': {}", path.display(), source))]
+    ReadDir {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
     #[snafu(display("Failed to read from file '{}': {}", path.display(), source))]
     ReadFile {
         source: std::io::Error,
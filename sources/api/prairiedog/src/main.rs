@@ -5,9 +5,12 @@ It does the following:
   - _digs_ to find the active boot partition and mounts it in /boot
   - loads the crash kernel from /boot
   - creates memory dumps when the kernel panics
+  - reports crashes from the dumps after the next boot, and removes old dumps
   - generates kernel boot config from settings
   - generates settings from the existing kernel boot config file
 
+The report of the latest kernel crash is kept in `/var/lib/bottlerocket/kernel-crash-report.json`,
+and the API server returns it from `/os/crash-report`.
 */
 
 #[macro_use]
@@ -26,6 +29,7 @@ use std::thread;
 use std::time::Duration;
 
 mod bootconfig;
+mod crash;
 mod error;
 mod initrd;
 
@@ -42,6 +46,9 @@ const LOG_FILE: &str = "prairiedog.log";
 const DMESG_DUMP_FILE: &str = "dmesg.log";
 const KDUMP_FILE: &str = "vmcore.dump";
 
+// The number of crashes whose dumps are kept by default
+const DEFAULT_KEPT_CRASHES: usize = 3;
+
 // Stores how much memory was allocated for the crash kernel
 const KEXEC_CRASH_SIZE: &str = "/sys/kernel/kexec_crash_size";
 // Enables/disables the kexec_load/kexec_file_load syscalls
@@ -73,6 +80,7 @@ struct Args {
 enum Subcommand {
     PrepareBoot(PrepareBootArgs),
     CaptureDump(CaptureDumpArgs),
+    ReportCrash(ReportCrashArgs),
     LoadCrashKernel(LoadCrashKernelArgs),
     GenerateBootConfig(GenerateBootConfigArgs),
     GenerateBootSettings(GenerateBootSettingsArgs),
@@ -89,6 +97,15 @@ struct PrepareBootArgs {}
 /// Captures the dmesg and kdump dumps from the memory image
 struct CaptureDumpArgs {}
 
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "report-crash")]
+/// Writes a crash report for dumps captured since the last boot, and removes old dumps
+struct ReportCrashArgs {
+    #[argh(option, default = "DEFAULT_KEPT_CRASHES")]
+    /// the number of crashes whose dumps are kept
+    keep: usize,
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand, name = "load-crash-kernel")]
 /// Loads the crash kernel with kexec
@@ -184,6 +201,18 @@ fn capture_dump() -> Result<()> {
     Ok(())
 }
 
+/// Reports a kernel crash if `capture-dump` left new dumps before the last reboot
+fn report_crash(keep: usize) -> Result<()> {
+    match crash::report_crash(KDUMP_LOGS_PATH, constants::KERNEL_CRASH_REPORT, keep)? {
+        Some(report) => info!(
+            "Wrote crash report for dumps in {}",
+            report.dump_dir.display()
+        ),
+        None => info!("No new kernel crash dumps"),
+    }
+    Ok(())
+}
+
 // Mounts the active boot partition
 fn prepare_boot() -> Result<()> {
     // Get the current partitions state
@@ -339,6 +368,7 @@ async fn run() -> Result<()> {
 
     match args.subcommand {
         Subcommand::CaptureDump(_) => capture_dump(),
+        Subcommand::ReportCrash(report_crash_args) => report_crash(report_crash_args.keep),
         Subcommand::PrepareBoot(_) => prepare_boot(),
         Subcommand::LoadCrashKernel(_) => load_crash_kernel(),
         Subcommand::GenerateBootConfig(_) => generate_boot_config(args.socket_path).await,
@@ -5,13 +5,30 @@ static-pods ensures static pods are running as defined in settings.
 
 It queries for all existing static pod settings, then configures the system as follows:
 * If the pod is enabled, it creates the manifest file in the pod manifest path that kubelet is
-  configured to read from and populates the file with the base64-decoded manifest setting value.
+  configured to read from and populates the file with the pod's manifest.
 * If the pod is enabled and the manifest file already exists, it overwrites the existing manifest
-  file with the base64-decoded manifest setting value.
+  file with the pod's manifest.
 * If the pod is disabled, it ensures the manifest file is removed from the pod manifest path.
+
+The manifest is either given inline, base64-encoded in the `manifest` setting, or fetched from
+`manifest-url`.  A fetched manifest must match the SHA-256 digest in `manifest-sha256`.
+Connection errors, server errors, and `429 Too Many Requests` responses are retried for up to two
+minutes; other errors aren't retried.
+
+If `template` is true, the manifest is rendered as a handlebars template, with the same data and
+helpers as configuration file templates, so it can refer to settings like
+`{{settings.kubernetes.node-ip}}`.
+Templates are only rendered when static-pods runs: at boot, and when a
+`settings.kubernetes.static-pods` setting changes.  Changing another setting the template refers to
+doesn't re-render it; change one of the pod's settings, or reboot, to pick up the new value.
+
+Before it's written, the manifest is checked to be a single Kubernetes pod in YAML or JSON, so a
+broken download or template doesn't replace a working manifest.
 */
 
 use model::modeled_types::Identifier;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ensure, OptionExt, ResultExt};
 use std::collections::HashMap;
@@ -21,29 +38,202 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
+use std::time::Duration;
 use tempfile::{NamedTempFile, TempDir};
+use tokio::time::timeout;
+use tokio_retry::{strategy::FibonacciBackoff, RetryIf};
 
 const STATIC_POD_DIR: &str = "/etc/kubernetes/static-pods";
 const ETC_KUBE_DIR: &str = "/etc/kubernetes";
+// How long to wait for a manifest to download from its URL.
+const MANIFEST_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// How long to keep retrying a download that fails in a way that might not last.
+const MANIFEST_RETRY_TIMEOUT: Duration = Duration::from_secs(120);
 
 type Result<T> = std::result::Result<T, error::Error>;
 
-/// Query the API for the currently defined static pods
-async fn get_static_pods<P>(socket_path: P) -> Result<Option<HashMap<Identifier, model::StaticPod>>>
+/// Query the API for the current settings, which hold the static pods and the data for manifest
+/// templates
+async fn get_model<P>(socket_path: P) -> Result<model::Model>
 where
     P: AsRef<Path>,
 {
     debug!("Requesting settings values");
-    let settings = schnauzer::get_settings(socket_path)
+    schnauzer::get_settings(socket_path)
         .await
-        .context(error::RetrieveSettingsSnafu)?
-        .settings
-        .context(error::MissingSettingsSnafu)?;
+        .context(error::RetrieveSettingsSnafu)
+}
 
-    Ok(settings
+/// Returns the currently defined static pods
+fn get_static_pods(model: &model::Model) -> Result<Option<&HashMap<Identifier, model::StaticPod>>> {
+    Ok(model
+        .settings
+        .as_ref()
+        .context(error::MissingSettingsSnafu)?
         .kubernetes
+        .as_ref()
         .context(error::MissingSettingsSnafu)?
-        .static_pods)
+        .static_pods
+        .as_ref())
+}
+
+/// Gets the static pod's manifest, either from the `manifest` setting or from its URL
+async fn get_manifest(name: &str, pod_info: &model::StaticPod) -> Result<String> {
+    match (&pod_info.manifest, &pod_info.manifest_url) {
+        (Some(manifest), None) => {
+            let manifest =
+                base64::decode(manifest.as_bytes()).context(error::Base64DecodeSnafu { name })?;
+            String::from_utf8(manifest).context(error::ManifestUtf8Snafu { name })
+        }
+        (None, Some(url)) => {
+            let digest = pod_info
+                .manifest_sha256
+                .as_ref()
+                .context(error::MissingFieldSnafu {
+                    name,
+                    field: "manifest-sha256",
+                })?;
+            fetch_manifest(name, url, digest, MANIFEST_RETRY_TIMEOUT).await
+        }
+        (Some(_), Some(_)) => error::ConflictingManifestsSnafu { name }.fail(),
+        (None, None) => error::MissingFieldSnafu {
+            name,
+            field: "manifest",
+        }
+        .fail(),
+    }
+}
+
+fn retry_strategy() -> impl Iterator<Item = Duration> {
+    // Back off quickly at first, in case the network isn't quite up, then try every 10s.
+    FibonacciBackoff::from_millis(250).max_delay(Duration::from_secs(10))
+}
+
+/// Whether a download that failed this way might succeed if retried: the server couldn't be
+/// reached or the response was cut off, or the server is failing or asked us to slow down.
+fn is_transient(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        }
+        None => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+    }
+}
+
+/// Downloads a manifest, retrying transient failures until the given timeout, and makes sure it's
+/// the one the user expects by comparing its digest
+async fn fetch_manifest(
+    name: &str,
+    url: &str,
+    expected_sha256: &str,
+    retry_timeout: Duration,
+) -> Result<String> {
+    debug!("Fetching manifest for static pod '{}' from '{}'", name, url);
+    let client = reqwest::Client::builder()
+        .timeout(MANIFEST_FETCH_TIMEOUT)
+        .build()
+        .context(error::ManifestFetchSnafu { name, url })?;
+    let manifest = timeout(
+        retry_timeout,
+        RetryIf::spawn(
+            retry_strategy(),
+            || async {
+                client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await
+            },
+            |e: &reqwest::Error| {
+                let transient = is_transient(e);
+                if transient {
+                    warn!(
+                        "Failed to fetch manifest for static pod '{}', retrying: {}",
+                        name, e
+                    );
+                }
+                transient
+            },
+        ),
+    )
+    .await
+    .context(error::ManifestFetchTimeoutSnafu { name, url })?
+    .context(error::ManifestFetchSnafu { name, url })?;
+
+    let actual_sha256 = format!("{:x}", Sha256::digest(&manifest));
+    ensure!(
+        actual_sha256.eq_ignore_ascii_case(expected_sha256),
+        error::ManifestDigestSnafu {
+            name,
+            url,
+            expected: expected_sha256,
+            actual: actual_sha256,
+        }
+    );
+    String::from_utf8(manifest.to_vec()).context(error::ManifestUtf8Snafu { name })
+}
+
+/// Renders the manifest as a template, with the same data and helpers as configuration files
+fn render_manifest(name: &str, manifest: &str, model: &model::Model) -> Result<String> {
+    let registry =
+        schnauzer::build_template_registry().context(error::BuildTemplateRegistrySnafu)?;
+    registry
+        .render_template(manifest, model)
+        .context(error::RenderManifestSnafu { name })
+}
+
+/// The parts of a pod manifest that kubelet needs to run a static pod
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodManifest {
+    api_version: String,
+    kind: String,
+    metadata: PodMetadata,
+    spec: PodSpec,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodMetadata {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodSpec {
+    containers: Vec<serde_yaml::Value>,
+}
+
+/// Checks that the manifest is a single Kubernetes pod in YAML (or JSON), so we don't replace a
+/// working manifest with one kubelet would reject
+fn validate_manifest(name: &str, manifest: &str) -> Result<()> {
+    let pod: PodManifest =
+        serde_yaml::from_str(manifest).context(error::ManifestYamlSnafu { name })?;
+    ensure!(
+        pod.api_version == "v1" && pod.kind == "Pod",
+        error::InvalidManifestSnafu {
+            name,
+            reason: format!(
+                "expected apiVersion 'v1' and kind 'Pod', found '{}' and '{}'",
+                pod.api_version, pod.kind
+            ),
+        }
+    );
+    ensure!(
+        !pod.metadata.name.is_empty(),
+        error::InvalidManifestSnafu {
+            name,
+            reason: "metadata.name is empty",
+        }
+    );
+    ensure!(
+        !pod.spec.containers.is_empty(),
+        error::InvalidManifestSnafu {
+            name,
+            reason: "spec.containers is empty",
+        }
+    );
+    Ok(())
 }
 
 /// Write out the manifest file to the pod manifest path with a given filename
@@ -97,7 +287,11 @@ where
     Ok(())
 }
 
-fn handle_static_pod<S>(name: S, pod_info: &model::StaticPod) -> Result<()>
+async fn handle_static_pod<S>(
+    name: S,
+    pod_info: &model::StaticPod,
+    model: &model::Model,
+) -> Result<()>
 where
     S: AsRef<str>,
 {
@@ -109,16 +303,11 @@ where
     })?;
 
     if enabled {
-        let manifest = pod_info
-            .manifest
-            .as_ref()
-            .context(error::MissingFieldSnafu {
-                name,
-                field: "manifest",
-            })?;
-
-        let manifest =
-            base64::decode(manifest.as_bytes()).context(error::Base64DecodeSnafu { name })?;
+        let mut manifest = get_manifest(name, pod_info).await?;
+        if pod_info.template.unwrap_or(false) {
+            manifest = render_manifest(name, &manifest, model)?;
+        }
+        validate_manifest(name, &manifest)?;
 
         info!("Writing static pod '{}' to '{}'", name, STATIC_POD_DIR);
 
@@ -143,10 +332,11 @@ async fn run() -> Result<()> {
     info!("static-pods started");
 
     let mut failed = 0u32;
-    if let Some(static_pods) = get_static_pods(args.socket_path).await? {
+    let model = get_model(args.socket_path).await?;
+    if let Some(static_pods) = get_static_pods(&model)? {
         for (name, pod) in static_pods.iter() {
             // Continue to handle other static pods if we fail one
-            if let Err(e) = handle_static_pod(name, pod) {
+            if let Err(e) = handle_static_pod(name, pod, &model).await {
                 failed += 1;
                 error!("Failed to handle static pod '{}': {}", &name, e);
             }
@@ -281,6 +471,83 @@ mod error {
             source: base64::DecodeError,
         },
 
+        #[snafu(display(
+            "Static pod '{}' has both 'manifest' and 'manifest-url'; give only one",
+            name
+        ))]
+        ConflictingManifests { name: String },
+
+        #[snafu(display(
+            "Failed to fetch manifest for static pod '{}' from '{}': {}",
+            name,
+            url,
+            source
+        ))]
+        ManifestFetch {
+            name: String,
+            url: String,
+            source: reqwest::Error,
+        },
+
+        #[snafu(display(
+            "Timed out fetching manifest for static pod '{}' from '{}': {}",
+            name,
+            url,
+            source
+        ))]
+        ManifestFetchTimeout {
+            name: String,
+            url: String,
+            source: tokio::time::error::Elapsed,
+        },
+
+        #[snafu(display(
+            "Manifest for static pod '{}' from '{}' has SHA-256 digest {}, expected {}",
+            name,
+            url,
+            actual,
+            expected
+        ))]
+        ManifestDigest {
+            name: String,
+            url: String,
+            expected: String,
+            actual: String,
+        },
+
+        #[snafu(display("Manifest for static pod '{}' is not UTF-8: {}", name, source))]
+        ManifestUtf8 {
+            name: String,
+            source: std::string::FromUtf8Error,
+        },
+
+        #[snafu(display("Failed to build template registry: {}", source))]
+        BuildTemplateRegistry { source: schnauzer::Error },
+
+        #[snafu(display("Failed to render manifest for static pod '{}': {}", name, source))]
+        RenderManifest {
+            name: String,
+            #[snafu(source(from(handlebars::RenderError, Box::new)))]
+            source: Box<handlebars::RenderError>,
+        },
+
+        #[snafu(display(
+            "Manifest for static pod '{}' is not a valid pod manifest: {}",
+            name,
+            source
+        ))]
+        ManifestYaml {
+            name: String,
+            source: serde_yaml::Error,
+        },
+
+        #[snafu(display(
+            "Manifest for static pod '{}' is not a valid pod manifest: {}",
+            name,
+            reason
+        ))]
+        InvalidManifest { name: String, reason: String },
+
         #[snafu(display("Failed to create directory '{}': '{}'", dir.display(), source))]
         Mkdir {
             dir: PathBuf,
@@ -316,3 +583,125 @@ mod error {
         },
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use httptest::responders::status_code;
+    use httptest::{matchers::*, Expectation, Server};
+    use serde_json::json;
+
+    const POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: agent
+spec:
+  containers:
+  - name: agent
+    image: public.ecr.aws/example/agent:latest
+    args: ["--node-ip", "{{settings.kubernetes.node-ip}}"]
+"#;
+
+    #[test]
+    fn valid_manifests() {
+        validate_manifest("agent", POD).unwrap();
+        // kubelet also accepts JSON manifests.
+        let json = json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": "agent"},
+            "spec": {"containers": [{"name": "agent", "image": "agent"}]},
+        });
+        validate_manifest("agent", &json.to_string()).unwrap();
+    }
+
+    #[test]
+    fn invalid_manifests() {
+        for manifest in &[
+            "",
+            "not: [valid",
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: agent\n",
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata:\n  name: agent\nspec:\n  containers: [{}]\n",
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: agent\nspec:\n  containers: []\n",
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: ''\nspec:\n  containers: [{}]\n",
+        ] {
+            validate_manifest("agent", manifest).unwrap_err();
+        }
+        // Two documents in one file.
+        validate_manifest("agent", &format!("{}---{}", POD, POD)).unwrap_err();
+    }
+
+    #[test]
+    fn render_settings() {
+        let model: model::Model = serde_json::from_value(json!({
+            "settings": {"kubernetes": {"node-ip": "10.0.0.1"}}
+        }))
+        .unwrap();
+        let manifest = render_manifest("agent", POD, &model).unwrap();
+        assert!(manifest.contains(r#"["--node-ip", "10.0.0.1"]"#));
+        validate_manifest("agent", &manifest).unwrap();
+
+        // Unknown settings are an error, rather than rendering as empty strings.
+        render_manifest("agent", "{{settings.kubernetes.nope}}", &model).unwrap_err();
+    }
+
+    fn sha256(data: &str) -> String {
+        format!("{:x}", Sha256::digest(data.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn fetches_manifest() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/agent.yaml"))
+                .respond_with(status_code(200).body(POD)),
+        );
+        let url = server.url_str("/agent.yaml");
+        let manifest = fetch_manifest("agent", &url, &sha256(POD), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(manifest, POD);
+    }
+
+    #[tokio::test]
+    async fn wrong_digest() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/agent.yaml"))
+                .respond_with(status_code(200).body(POD)),
+        );
+        let url = server.url_str("/agent.yaml");
+        let result = fetch_manifest("agent", &url, &sha256("other"), Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(error::Error::ManifestDigest { .. })));
+    }
+
+    #[tokio::test]
+    async fn server_error_retries() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/agent.yaml"))
+                .times(2..)
+                .respond_with(status_code(503)),
+        );
+        let url = server.url_str("/agent.yaml");
+        let result = fetch_manifest("agent", &url, &sha256(POD), Duration::from_secs(1)).await;
+        assert!(matches!(
+            result,
+            Err(error::Error::ManifestFetchTimeout { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn client_error_fails_fast() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/agent.yaml"))
+                .times(1)
+                .respond_with(status_code(404)),
+        );
+        let url = server.url_str("/agent.yaml");
+        let result = fetch_manifest("agent", &url, &sha256(POD), Duration::from_secs(300)).await;
+        assert!(matches!(result, Err(error::Error::ManifestFetch { .. })));
+    }
+}
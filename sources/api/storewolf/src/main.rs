@@ -5,6 +5,16 @@ storewolf creates the filesystem datastore used by the API system.
 
 It creates the datastore at a provided path and populates any default settings, as given in the
 TOML files of the current variant's `defaults.d` directory, unless the datastore already exists.
+
+It also records the default value of each setting in `default-value` metadata, so the API can tell
+which settings were changed from their defaults.  These records are updated on every run, so they
+always reflect the defaults of the running version; records for settings the running version has
+no default for are removed.
+
+The API marks the settings the user sets with `user-set` metadata.  Datastores from versions that
+didn't do this have no `default-value` records either; the first time storewolf runs on one, it
+marks the settings that differ from their defaults and have no setting generator, assuming the user
+set them.
 */
 #[macro_use]
 extern crate log;
@@ -24,6 +34,12 @@ use datastore::serialization::{to_pairs, to_pairs_with_prefix};
 use datastore::{self, DataStore, FilesystemDataStore, ScalarError};
 use model::modeled_types::SingleLineString;
 
+/// The metadata key that records the default value of each setting, in datastore form.
+const DEFAULT_VALUE_METADATA: &str = "default-value";
+
+/// The metadata key the API uses to mark settings the user set.
+const USER_SET_METADATA: &str = "user-set";
+
 mod error {
     use std::io;
     use std::path::PathBuf;
@@ -99,6 +115,19 @@ mod error {
             source: Box<datastore::Error>,
         },
 
+        #[snafu(display("Unable to remove metadata from the datastore: {}", source))]
+        DeleteMetadata {
+            #[snafu(source(from(datastore::Error, Box::new)))]
+            source: Box<datastore::Error>,
+        },
+
+        #[snafu(display("Unable to read key '{}' from the datastore: {}", key, source))]
+        ReadKey {
+            key: String,
+            #[snafu(source(from(datastore::Error, Box::new)))]
+            source: Box<datastore::Error>,
+        },
+
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
@@ -280,6 +309,39 @@ fn populate_default_datastore<P: AsRef<Path>>(
             },
         )?;
 
+        // Record the default values, even for settings that already exist, so the API can tell
+        // which settings differ from the current version's defaults.
+        let default_md_key =
+            Key::new(KeyType::Meta, DEFAULT_VALUE_METADATA).context(error::InvalidKeySnafu {
+                key_type: KeyType::Meta,
+                key: DEFAULT_VALUE_METADATA,
+            })?;
+        let tracked = existing_metadata
+            .values()
+            .any(|md_keys| md_keys.contains(&default_md_key));
+        if !existing_data.is_empty() && !tracked {
+            mark_user_settings(
+                &mut datastore,
+                &existing_data,
+                &existing_metadata,
+                &def_settings,
+            )?;
+        }
+        for (key, val) in &def_settings {
+            datastore
+                .set_metadata(&default_md_key, key, val)
+                .context(error::WriteMetadataSnafu)?;
+        }
+        // Defaults recorded by another version, for example before a downgrade, don't apply.
+        for (key, md_keys) in &existing_metadata {
+            if md_keys.contains(&default_md_key) && !def_settings.contains_key(key) {
+                debug!("Removing stale default value of {}", key);
+                datastore
+                    .unset_metadata(&default_md_key, key)
+                    .context(error::DeleteMetadataSnafu)?;
+            }
+        }
+
         // For each of the default settings, check if it exists in the
         // datastore. If not, add it to the map of settings to write
         let mut settings_to_write = HashMap::new();
@@ -385,6 +447,42 @@ fn populate_default_datastore<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Marks the existing settings that differ from their defaults, and have no setting generator, as
+/// set by the user.  This is for datastores from versions where the API didn't mark them itself.
+fn mark_user_settings(
+    datastore: &mut FilesystemDataStore,
+    existing_data: &HashSet<Key>,
+    existing_metadata: &HashMap<Key, HashSet<Key>>,
+    def_settings: &HashMap<Key, String>,
+) -> Result<()> {
+    info!("Marking settings that differ from their defaults as set by the user");
+    let user_set_md_key =
+        Key::new(KeyType::Meta, USER_SET_METADATA).context(error::InvalidKeySnafu {
+            key_type: KeyType::Meta,
+            key: USER_SET_METADATA,
+        })?;
+    for key in existing_data {
+        if !key.name().starts_with("settings.") {
+            continue;
+        }
+        let generated = existing_metadata.get(key).map_or(false, |md_keys| {
+            md_keys.iter().any(|md| md.name() == "setting-generator")
+        });
+        if generated {
+            continue;
+        }
+        let value = datastore
+            .get_key(key, &datastore::Committed::Live)
+            .context(error::ReadKeySnafu { key: key.name() })?;
+        if value.is_some() && value.as_ref() != def_settings.get(key) {
+            datastore
+                .set_metadata(&user_set_md_key, key, "true")
+                .context(error::WriteMetadataSnafu)?;
+        }
+    }
+    Ok(())
+}
+
 /// Store the args we receive on the command line
 struct Args {
     data_store_base_path: String,
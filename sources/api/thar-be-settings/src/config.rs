@@ -139,6 +139,17 @@ impl RenderedConfigFile {
         }
     }
 
+    /// Returns the path where the rendered template will be written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a unified diff between the file currently on disk and the rendered template, or
+    /// None if they're the same.
+    pub fn diff(&self) -> Result<Option<String>> {
+        crate::plan::diff_file(&self.path, &self.rendered)
+    }
+
     /// Writes the rendered template at the proper location
     fn write_to_disk(&self) -> Result<()> {
         if let Some(dirname) = self.path.parent() {
@@ -79,4 +79,39 @@ pub enum Error {
         uri: String,
         source: schnauzer::Error,
     },
+
+    #[snafu(display("Unable to determine keys in pending transaction: {}", source))]
+    PendingKeys {
+        source: datastore::serialization::Error,
+    },
+
+    #[snafu(display("Unable to serialize settings model: {}", source))]
+    ModelSerialize { source: serde_json::Error },
+
+    #[snafu(display("Settings with pending changes don't match the model: {}", source))]
+    ModelDeserialize { source: serde_json::Error },
+
+    #[snafu(display("Settings model has no 'settings' field"))]
+    MissingSettings,
+
+    #[snafu(display("Failed to read configuration file '{}': {}", path.display(), source))]
+    ReadConfigFile { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to read stats file '{}': {}", path.display(), source))]
+    StatsRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to parse stats file '{}': {}", path.display(), source))]
+    StatsParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Failed to lock stats file '{}': {}", path.display(), source))]
+    StatsLock { path: PathBuf, source: nix::Error },
+
+    #[snafu(display("Failed to serialize stats: {}", source))]
+    StatsSerialize { source: serde_json::Error },
+
+    #[snafu(display("Failed to write stats file '{}': {}", path.display(), source))]
+    StatsWrite { path: PathBuf, source: io::Error },
 }
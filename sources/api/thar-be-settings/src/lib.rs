@@ -12,6 +12,13 @@ It then renders the templates and rewrites the affected configuration files.
 Service data from the API includes any commands needed to restart services affected by configuration file changes, which are run here.
 
 In the standalone ("all keys") mode, it queries the API for all services and configuration files, then renders and rewrites all configuration files and restarts all services.
+
+In the "plan" mode, it's given the name of a pending transaction, and finds the affected services and configuration files the same way, but without changing anything.
+It renders the affected configuration files with the pending settings applied, and prints JSON containing a unified diff of each file that would change, along with the restart commands of each affected service.
+The API server uses this to answer `/tx/plan` requests.
+
+In the "specific keys" and "all keys" modes, it also keeps running totals of how long rendering configuration files and restarting services take, and how often each fails, in `/run/thar-be-settings/stats.json`.
+metricdog exports these as metrics.
 */
 
 #[macro_use]
@@ -23,7 +30,9 @@ use std::io::{self, Read};
 
 pub mod config;
 pub mod error;
+pub mod plan;
 pub mod service;
+pub mod stats;
 
 pub use error::Error;
 type Result<T> = std::result::Result<T, Error>;
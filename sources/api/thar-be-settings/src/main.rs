@@ -2,15 +2,18 @@
 extern crate log;
 
 use nix::unistd::{fork, ForkResult};
-use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
+use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger, WriteLogger};
 use snafu::ResultExt;
 use std::collections::HashSet;
 use std::env;
+use std::io;
 use std::process;
 use std::str::FromStr;
+use std::time::Instant;
 use tokio::runtime::Runtime;
 
-use thar_be_settings::{config, get_changed_settings, service};
+use thar_be_settings::stats::{self, Phase};
+use thar_be_settings::{config, get_changed_settings, plan, service};
 
 mod error {
     use snafu::Snafu;
@@ -22,6 +25,9 @@ mod error {
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
+        #[snafu(display("Failed to serialize plan: {}", source))]
+        PlanSerialize { source: serde_json::Error },
+
         #[snafu(display("Failure to read template '{}' from '{}': {}", name, path.display(), source))]
         TemplateRegister {
             name: String,
@@ -33,11 +39,13 @@ mod error {
 
 /// RunMode represents how thar-be-settings was requested to be run, either handling all
 /// configuration files and services, or handling configuration files and services based on
-/// specific keys given by the user.
+/// specific keys given by the user, or describing what would be done for the keys in a pending
+/// transaction without doing it.
 #[derive(Debug)]
 enum RunMode {
     All,
     SpecificKeys,
+    Plan { transaction: String },
 }
 
 /// Store the args we receive on the command line
@@ -53,7 +61,7 @@ fn usage() -> ! {
     let program_name = env::args().next().unwrap_or_else(|| "program".to_string());
     eprintln!(
         r"Usage: {}
-            [ --all ]
+            [ --all | --plan TRANSACTION ]
             [ --daemon ]
             [ --socket-path PATH ]
             [ --log-level trace|debug|info|warn|error ]
@@ -63,6 +71,12 @@ fn usage() -> ! {
     will be read from stdin; only files related to those keys will be written,
     and only services related to those keys will be restarted.
 
+    If --plan is given, nothing is written or restarted.  Instead, the changes
+    in the given pending transaction are used to print a JSON description of
+    what committing and applying it would do: a unified diff of each
+    configuration file that would change, and the restart-commands of each
+    affected service.
+
     If --daemon is given, thar-be-settings will fork and do its work in a new
     process; this is useful to prevent blocking an API call.
 
@@ -91,6 +105,14 @@ fn parse_args(args: env::Args) -> Args {
         match arg.as_ref() {
             "--all" => mode = RunMode::All,
 
+            "--plan" => {
+                mode = RunMode::Plan {
+                    transaction: iter
+                        .next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --plan")),
+                }
+            }
+
             "--daemon" => daemon = true,
 
             "--log-level" => {
@@ -132,21 +154,7 @@ async fn write_config_files(
     let config_files = config::get_affected_config_files(&args.socket_path, files_limit).await?;
     trace!("Found config files: {:?}", config_files);
 
-    // Build the template registry from config file metadata
-    debug!("Building template registry");
-    let mut template_registry = schnauzer::build_template_registry()?;
-    for (name, metadata) in &config_files {
-        debug!(
-            "Registering {} at path '{}'",
-            &name, &metadata.template_path
-        );
-        template_registry
-            .register_template_file(name, metadata.template_path.as_ref())
-            .context(error::TemplateRegisterSnafu {
-                name: name.as_str(),
-                path: metadata.template_path.as_ref(),
-            })?;
-    }
+    let template_registry = build_template_registry(&config_files)?;
 
     // Get all settings values for config file templates
     debug!("Requesting settings values");
@@ -155,7 +163,7 @@ async fn write_config_files(
     // Ensure all files render properly
     info!("Rendering config files...");
     let strict = match &args.mode {
-        RunMode::SpecificKeys => true,
+        RunMode::SpecificKeys | RunMode::Plan { .. } => true,
         RunMode::All => false,
     };
     let rendered = config::render_config_files(&template_registry, config_files, settings, strict)?;
@@ -173,9 +181,84 @@ async fn write_config_files(
     Ok(())
 }
 
+/// Build the template registry from config file metadata
+fn build_template_registry(
+    config_files: &model::ConfigurationFiles,
+) -> Result<handlebars::Handlebars<'static>, Box<dyn std::error::Error>> {
+    debug!("Building template registry");
+    let mut template_registry = schnauzer::build_template_registry()?;
+    for (name, metadata) in config_files {
+        debug!(
+            "Registering {} at path '{}'",
+            &name, &metadata.template_path
+        );
+        template_registry
+            .register_template_file(name, metadata.template_path.as_ref())
+            .context(error::TemplateRegisterSnafu {
+                name: name.as_str(),
+                path: metadata.template_path.as_ref(),
+            })?;
+    }
+    Ok(template_registry)
+}
+
+/// Prints a JSON description of what committing and applying the given pending transaction would
+/// do, without writing any files or restarting any services.
+async fn print_plan(args: &Args, transaction: &str) -> Result<(), Box<dyn std::error::Error>> {
+    info!(
+        "Requesting pending settings in transaction '{}'",
+        transaction
+    );
+    let uri = "/tx";
+    let pending: serde_json::Value =
+        schnauzer::get_json(&args.socket_path, uri, Some(("tx", transaction))).await?;
+    let changed_settings = plan::pending_keys(&pending)?;
+
+    let services = if changed_settings.is_empty() {
+        service::Services::default()
+    } else {
+        service::get_affected_services(&args.socket_path, Some(changed_settings)).await?
+    };
+    trace!("Found services: {:?}", services);
+
+    let config_file_names = config::get_config_file_names(&services);
+    let rendered = if config_file_names.is_empty() {
+        Vec::new()
+    } else {
+        let config_files =
+            config::get_affected_config_files(&args.socket_path, Some(config_file_names)).await?;
+        trace!("Found config files: {:?}", config_files);
+        let template_registry = build_template_registry(&config_files)?;
+
+        // Render with the pending settings applied over the live settings.
+        let settings = schnauzer::get_settings(&args.socket_path).await?;
+        let settings = plan::apply_pending(settings, pending)?;
+        config::render_config_files(&template_registry, config_files, settings, true)?
+    };
+
+    let plan = plan::Plan::new(&rendered, &services)?;
+    let output = serde_json::to_string(&plan).context(error::PlanSerializeSnafu)?;
+    println!("{}", output);
+    Ok(())
+}
+
+/// Adds a run of the given phase, started at `started`, to the totals that metricdog exports.
+/// Failing to record it doesn't fail the run.
+fn record_stats<T, E>(phase: Phase, started: Instant, result: &Result<T, E>) {
+    if let Err(e) = stats::record(stats::STATS_FILE, phase, started.elapsed(), result.is_ok()) {
+        warn!("Unable to record {} stats: {}", phase, e);
+    }
+}
+
 async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
-    // SimpleLogger will send errors to stderr and anything less to stdout.
-    SimpleLogger::init(args.log_level, LogConfig::default()).context(error::LoggerSnafu)?;
+    if let RunMode::Plan { .. } = &args.mode {
+        // The plan is printed to stdout, so all logging goes to stderr.
+        WriteLogger::init(args.log_level, LogConfig::default(), io::stderr())
+            .context(error::LoggerSnafu)?;
+    } else {
+        // SimpleLogger will send errors to stderr and anything less to stdout.
+        SimpleLogger::init(args.log_level, LogConfig::default()).context(error::LoggerSnafu)?;
+    }
 
     info!("thar-be-settings started");
 
@@ -202,20 +285,35 @@ async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
             let config_file_names = config::get_config_file_names(&services);
 
             if !config_file_names.is_empty() {
-                write_config_files(&args, Some(config_file_names)).await?;
+                let started = Instant::now();
+                let result = write_config_files(&args, Some(config_file_names)).await;
+                record_stats(Phase::Render, started, &result);
+                result?;
             }
 
             // Now go bounce the affected services
             info!("Restarting affected services...");
-            service::restart_services(services)?;
+            let started = Instant::now();
+            let result = service::restart_services(services);
+            record_stats(Phase::Restart, started, &result);
+            result?;
         }
         RunMode::All => {
-            write_config_files(&args, None).await?;
+            let started = Instant::now();
+            let result = write_config_files(&args, None).await;
+            record_stats(Phase::Render, started, &result);
+            result?;
 
             info!("Restarting all services...");
             let services = service::get_affected_services(&args.socket_path, None).await?;
             trace!("Found services: {:?}", services);
-            service::restart_services(services)?;
+            let started = Instant::now();
+            let result = service::restart_services(services);
+            record_stats(Phase::Restart, started, &result);
+            result?;
+        }
+        RunMode::Plan { ref transaction } => {
+            print_plan(&args, transaction).await?;
         }
     }
 
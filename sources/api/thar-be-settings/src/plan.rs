@@ -0,0 +1,141 @@
+//! The plan module builds a description of what applying a pending transaction would do - which
+//! configuration files would change, and which restart commands would run - without doing it.
+
+use crate::config::RenderedConfigFile;
+use crate::service::Services;
+use crate::{error, Result};
+use serde::Serialize;
+use snafu::{OptionExt, ResultExt};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// What applying a pending transaction would change on the system.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    /// Map of configuration file path to a unified diff of its current and newly rendered
+    /// contents.  Files that wouldn't change aren't included.
+    pub files: BTreeMap<String, String>,
+    /// Map of affected service name to the restart commands that would run for it.
+    pub restart_commands: BTreeMap<String, Vec<String>>,
+}
+
+impl Plan {
+    /// Builds a Plan from the files that would be rendered and the services that would be
+    /// restarted.
+    pub fn new(rendered: &[RenderedConfigFile], services: &Services) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for cfg in rendered {
+            if let Some(diff) = cfg.diff()? {
+                files.insert(cfg.path().display().to_string(), diff);
+            }
+        }
+
+        let restart_commands = services
+            .0
+            .iter()
+            .map(|(name, service)| (name.clone(), service.model.restart_commands.clone()))
+            .collect();
+
+        Ok(Self {
+            files,
+            restart_commands,
+        })
+    }
+}
+
+/// Returns the names of the settings in a pending transaction, as returned by the API's /tx.
+pub fn pending_keys(pending: &serde_json::Value) -> Result<HashSet<String>> {
+    let pairs = datastore::serialization::to_pairs_with_prefix("settings", pending)
+        .context(error::PendingKeysSnafu)?;
+    Ok(pairs.keys().map(|key| key.name().to_string()).collect())
+}
+
+/// Returns the given model with the settings from a pending transaction applied, so we can render
+/// templates as they'd look after the transaction is committed.
+pub fn apply_pending(model: model::Model, pending: serde_json::Value) -> Result<model::Model> {
+    let mut model_value = serde_json::to_value(model).context(error::ModelSerializeSnafu)?;
+    let settings = model_value
+        .get_mut("settings")
+        .context(error::MissingSettingsSnafu)?;
+    merge_values(settings, pending);
+    serde_json::from_value(model_value).context(error::ModelDeserializeSnafu)
+}
+
+/// Recursively merges `merge_from` into `merge_into`; values in `merge_from` take precedence.
+fn merge_values(merge_into: &mut serde_json::Value, merge_from: serde_json::Value) {
+    use serde_json::Value;
+    match (merge_into, merge_from) {
+        (Value::Object(into), Value::Object(from)) => {
+            for (key, from_value) in from {
+                match into.get_mut(&key) {
+                    Some(into_value) => merge_values(into_value, from_value),
+                    None => {
+                        into.insert(key, from_value);
+                    }
+                }
+            }
+        }
+        (merge_into, merge_from) => *merge_into = merge_from,
+    }
+}
+
+/// Returns a unified diff between the file currently at `path` and the given new contents, or
+/// None if they're the same.  A file that doesn't exist yet is treated as empty.
+pub(crate) fn diff_file(path: &Path, new: &str) -> Result<Option<String>> {
+    let old = match std::fs::read_to_string(path) {
+        Ok(old) => old,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).context(error::ReadConfigFileSnafu { path }),
+    };
+    Ok(diff_text(&path.display().to_string(), &old, new))
+}
+
+fn diff_text(name: &str, old: &str, new: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(name, name)
+        .to_string();
+    Some(diff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_nested() {
+        let mut into = json!({"motd": "hi", "ntp": {"time-servers": ["a"]}, "kernel": {"x": 1}});
+        let from = json!({"ntp": {"time-servers": ["b", "c"]}, "kernel": {"y": 2}});
+        merge_values(&mut into, from);
+        assert_eq!(
+            into,
+            json!({"motd": "hi", "ntp": {"time-servers": ["b", "c"]}, "kernel": {"x": 1, "y": 2}})
+        );
+    }
+
+    #[test]
+    fn pending_key_names() {
+        let pending = json!({"motd": "hi", "kernel": {"sysctl": {"vm.max_map_count": "1"}}});
+        let keys = pending_keys(&pending).unwrap();
+        assert_eq!(
+            keys,
+            maplit::hashset!(
+                "settings.motd".to_string(),
+                "settings.kernel.sysctl.\"vm.max_map_count\"".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn diff() {
+        assert_eq!(diff_text("f", "same\n", "same\n"), None);
+        assert_eq!(
+            diff_text("/etc/motd", "old\n", "new\n").unwrap(),
+            "--- /etc/motd\n+++ /etc/motd\n@@ -1 +1 @@\n-old\n+new\n"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! The stats module keeps running totals of how long thar-be-settings spends rendering
+//! configuration files and running restart commands, and how often each fails, so metricdog can
+//! export them as metrics.  thar-be-settings runs briefly, many times per boot, so the totals are
+//! kept in a file under /run; they start over at each boot.
+
+use crate::error::{self, Error};
+use nix::fcntl::{flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Where the totals are kept.
+pub const STATS_FILE: &str = "/run/thar-be-settings/stats.json";
+
+/// The phases of applying settings that we keep totals for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Rendering and writing configuration files.
+    Render,
+    /// Running the restart commands of affected services.
+    Restart,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Phase::Render => write!(f, "render"),
+            Phase::Restart => write!(f, "restart"),
+        }
+    }
+}
+
+/// The totals for each phase since boot.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Stats {
+    pub render: PhaseStats,
+    pub restart: PhaseStats,
+}
+
+/// The totals for one phase.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PhaseStats {
+    pub runs: u64,
+    pub failures: u64,
+    /// The total time spent in the phase, across runs.
+    pub duration_seconds: f64,
+    /// The time spent in the most recent run.
+    pub last_duration_seconds: f64,
+}
+
+impl Stats {
+    fn phase_mut(&mut self, phase: Phase) -> &mut PhaseStats {
+        match phase {
+            Phase::Render => &mut self.render,
+            Phase::Restart => &mut self.restart,
+        }
+    }
+
+    /// Adds a run of the given phase to the totals.
+    pub fn add(&mut self, phase: Phase, duration: Duration, succeeded: bool) {
+        let totals = self.phase_mut(phase);
+        totals.runs += 1;
+        if !succeeded {
+            totals.failures += 1;
+        }
+        totals.duration_seconds += duration.as_secs_f64();
+        totals.last_duration_seconds = duration.as_secs_f64();
+    }
+}
+
+/// Adds a run of the given phase to the totals in the given file.  The file is locked while it's
+/// updated, because the API server can start thar-be-settings again before an earlier run ends.
+pub fn record<P>(path: P, phase: Phase, duration: Duration, succeeded: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(error::StatsWriteSnafu { path })?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)
+        .context(error::StatsWriteSnafu { path })?;
+    flock(file.as_raw_fd(), FlockArg::LockExclusive).context(error::StatsLockSnafu { path })?;
+
+    let mut stats = read(&mut file, path)?;
+    stats.add(phase, duration, succeeded);
+    let json = serde_json::to_vec(&stats).context(error::StatsSerializeSnafu)?;
+
+    file.set_len(0).context(error::StatsWriteSnafu { path })?;
+    file.rewind().context(error::StatsWriteSnafu { path })?;
+    file.write_all(&json)
+        .context(error::StatsWriteSnafu { path })?;
+    // The lock is released when the file is closed.
+    Ok(())
+}
+
+/// Returns the totals in the given file, or empty totals if there's no file yet.
+pub fn load<P>(path: P) -> Result<Stats>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Stats::default()),
+        Err(e) => return Err(e).context(error::StatsReadSnafu { path }),
+    };
+    flock(file.as_raw_fd(), FlockArg::LockShared).context(error::StatsLockSnafu { path })?;
+    read(&mut file, path)
+}
+
+fn read(file: &mut File, path: &Path) -> Result<Stats> {
+    let mut json = String::new();
+    file.read_to_string(&mut json)
+        .context(error::StatsReadSnafu { path })?;
+    // The file is empty if we just created it.
+    if json.is_empty() {
+        return Ok(Stats::default());
+    }
+    serde_json::from_str(&json).context(error::StatsParseSnafu { path })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn record_and_load() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("thar-be-settings").join("stats.json");
+        assert_eq!(load(&path).unwrap(), Stats::default());
+
+        record(&path, Phase::Render, Duration::from_millis(250), true).unwrap();
+        record(&path, Phase::Render, Duration::from_millis(500), false).unwrap();
+        record(&path, Phase::Restart, Duration::from_secs(2), true).unwrap();
+
+        let stats = load(&path).unwrap();
+        assert_eq!(
+            stats.render,
+            PhaseStats {
+                runs: 2,
+                failures: 1,
+                duration_seconds: 0.75,
+                last_duration_seconds: 0.5,
+            }
+        );
+        assert_eq!(stats.restart.runs, 1);
+        assert_eq!(stats.restart.failures, 0);
+        assert_eq!(stats.restart.duration_seconds, 2.0);
+    }
+}
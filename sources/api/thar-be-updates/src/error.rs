@@ -129,12 +129,22 @@ pub enum Error {
     #[snafu(display("Invalid state transition from {:?} to {:?}", from, to))]
     InvalidStateTransition { from: UpdateState, to: UpdateState },
 
+    #[snafu(display("Failed to read boot health check result from updog: {}", source))]
+    BootHealthCheck {
+        source: update_metadata::error::Error,
+    },
+
     #[snafu(display("Command '{:?}' not allowed when state is '{:?}'", command, state))]
     DisallowCommand {
         command: UpdateCommand,
         state: UpdateState,
     },
 
+    #[snafu(display("Failed to read update progress from updog: {}", source))]
+    UpdateProgress {
+        source: update_metadata::error::Error,
+    },
+
     #[snafu(display("Chosen update does not exist"))]
     UpdateDoesNotExist,
 
@@ -147,6 +157,14 @@ pub enum Error {
     #[snafu(display("No image information for the active partition set"))]
     ActivePartition,
 
+    #[snafu(display("Invalid maintenance window settings: {}", source))]
+    MaintenanceWindows {
+        source: update_metadata::error::Error,
+    },
+
+    #[snafu(display("Outside of maintenance window; {}", next))]
+    OutsideMaintenanceWindow { next: String },
+
     #[snafu(display("Logger setup error: {}", source))]
     Logger { source: log::SetLoggerError },
 
@@ -162,4 +180,5 @@ pub enum TbuErrorStatus {
     DisallowCommand = 65,
     UpdateDoesNotExist = 66,
     NoStagedImage = 67,
+    OutsideMaintenanceWindow = 68,
 }
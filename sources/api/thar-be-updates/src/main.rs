@@ -10,12 +10,17 @@ Upon receiving a command not allowed by the update state, thar-be-updates exits
 Otherwise, thar-be-updates forks a child process to spawn the necessary process to do the work.
 The parent process immediately returns back to the caller with an exit status of `0`.
 The output and status of the command will be written to the update status file.
+After `prepare`, the status also shows how many bytes of the update's images updog has written, out of the total.
+If `prepare` is interrupted, running it again resumes writing where updog left off.
+If maintenance windows are set in `settings.updates.maintenance`, `activate` is refused outside of them, and the status shows the window that's open now, or the next one.
+The status also includes the result of the health check updog runs on the first boot of an update, including whether it rolled back to the previous version and why.
 This allows the caller to synchronously call thar-be-updates without having to wait for a result to come back.
 
 thar-be-updates uses a lockfile to control read/write access to the disks and the update status file.
 
 */
 
+use chrono::Utc;
 use fs2::FileExt;
 use log::{debug, warn};
 use nix::unistd::{fork, ForkResult};
@@ -32,8 +37,8 @@ use tempfile::NamedTempFile;
 use thar_be_updates::error;
 use thar_be_updates::error::{Error, Result, TbuErrorStatus};
 use thar_be_updates::status::{
-    get_update_status, UpdateCommand, UpdateState, UpdateStatus, UPDATE_LOCKFILE,
-    UPDATE_STATUS_FILE,
+    get_maintenance_windows, get_update_status, UpdateCommand, UpdateState, UpdateStatus,
+    UPDATE_LOCKFILE, UPDATE_STATUS_FILE,
 };
 
 const UPDATE_STATUS_DIR: &str = "/run/cache/thar-be-updates";
@@ -211,6 +216,10 @@ fn prepare(status: &mut UpdateStatus) -> Result<()> {
             .output()
             .context(error::UpdogSnafu)?;
         status.set_recent_command_info(UpdateCommand::Prepare, &output);
+        // Whether or not it finished, record how far updog got; it resumes from there next time
+        if let Err(e) = status.update_staging_progress() {
+            warn!("Unable to get update progress: {}", e);
+        }
         if !output.status.success() {
             warn!("Failed to prepare the update with updog");
             return error::PrepareUpdateSnafu.fail();
@@ -296,6 +305,18 @@ fn drive_state_machine(
                 update_status.staging_partition().is_some(),
                 error::StagingPartitionSnafu
             );
+            // Only apply the update, letting the host reboot into it, in a maintenance window
+            let windows = get_maintenance_windows(socket_path)?;
+            update_status.update_maintenance_window(&windows);
+            ensure!(
+                windows.is_open(Utc::now()),
+                error::OutsideMaintenanceWindowSnafu {
+                    next: match update_status.next_maintenance_window() {
+                        Some(window) => format!("next window starts at {}", window.start),
+                        None => "no window is scheduled".to_string(),
+                    }
+                }
+            );
             activate(update_status)?;
             // If we succeed in activating the update, we transition to `Ready`
             UpdateState::Ready
@@ -344,6 +365,10 @@ fn run() -> Result<()> {
         initialize_update_status()?;
     }
     let mut update_status = get_update_status(&lockfile)?;
+    // The health check runs after boot, so it may have finished since the status was created
+    if let Err(e) = update_status.update_boot_health_check() {
+        warn!("Unable to get boot health check result: {}", e);
+    }
 
     // The commands inside drive_state_machine update the update_status object (hence &mut) to
     // reflect success or failure, and we want to reflect that in our status file regardless of
@@ -359,6 +384,7 @@ fn match_error_to_exit_status(err: Error) -> i32 {
         Error::DisallowCommand { .. } => TbuErrorStatus::DisallowCommand,
         Error::UpdateDoesNotExist { .. } => TbuErrorStatus::UpdateDoesNotExist,
         Error::StagingPartition { .. } => TbuErrorStatus::NoStagedImage,
+        Error::OutsideMaintenanceWindow { .. } => TbuErrorStatus::OutsideMaintenanceWindow,
         _ => TbuErrorStatus::OtherError,
     }
     .to_i32()
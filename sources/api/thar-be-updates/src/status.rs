@@ -3,14 +3,19 @@ use crate::error::Result;
 use bottlerocket_release::BottlerocketRelease;
 use chrono::{DateTime, Utc};
 use model::modeled_types::FriendlyVersion;
+use model::UpdateMaintenance;
 use serde::{Deserialize, Serialize};
 use signpost::State;
 use snafu::{OptionExt, ResultExt};
 use std::convert::TryInto;
 use std::fs::File;
 use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
 use std::process::Output;
 use tokio::runtime::Runtime;
+use update_metadata::health::{HealthCheckResult, HEALTH_CHECK_PATH};
+use update_metadata::progress::PROGRESS_PATH;
+use update_metadata::window::{MaintenanceWindows, WindowTime};
 
 pub const UPDATE_LOCKFILE: &str = "/run/lock/thar-be-updates.lock";
 pub const UPDATE_STATUS_FILE: &str = "/run/cache/thar-be-updates/status.json";
@@ -51,6 +56,16 @@ impl StagedImage {
     }
 }
 
+/// StagingProgress represents how much of an update has been written to the staging partition set
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StagingProgress {
+    version: semver::Version,
+    /// Bytes of the update's images written so far
+    bytes_written: u64,
+    /// Total bytes of the update's images
+    bytes_total: u64,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum CommandStatus {
     Success,
@@ -84,6 +99,11 @@ pub struct UpdateStatus {
     chosen_update: Option<UpdateImage>,
     active_partition: Option<StagedImage>,
     staging_partition: Option<StagedImage>,
+    staging_progress: Option<StagingProgress>,
+    /// The result of the health check on the first boot of the last update
+    boot_health_check: Option<HealthCheckResult>,
+    /// The maintenance window that's open now, or the next one to open, if any are configured
+    next_maintenance_window: Option<WindowTime>,
     most_recent_command: Option<CommandResult>,
 }
 
@@ -105,6 +125,30 @@ pub fn get_update_status(_lockfile: &File) -> Result<UpdateStatus> {
     })
 }
 
+/// Builds the maintenance windows from the update settings.  Updates can be applied at any time
+/// if no windows are set.
+pub fn maintenance_windows(maintenance: Option<&UpdateMaintenance>) -> Result<MaintenanceWindows> {
+    let windows = maintenance
+        .and_then(|maintenance| maintenance.windows.as_deref())
+        .unwrap_or_default();
+    let timezone = maintenance
+        .and_then(|maintenance| maintenance.timezone.as_ref())
+        .map(AsRef::as_ref);
+    MaintenanceWindows::new(windows, timezone).context(error::MaintenanceWindowsSnafu)
+}
+
+/// Retrieves the maintenance windows from the update settings in the API.
+pub fn get_maintenance_windows(socket_path: &str) -> Result<MaintenanceWindows> {
+    let settings = get_settings(socket_path)?;
+    let maintenance: Option<UpdateMaintenance> = serde_json::from_value(
+        settings["updates"]["maintenance"].to_owned(),
+    )
+    .context(error::GetSettingSnafu {
+        setting: "/settings/updates/maintenance",
+    })?;
+    maintenance_windows(maintenance.as_ref())
+}
+
 /// Retrieves settings from the API.
 ///
 /// NOTE: this function creates its own tokio runtime to make the async apiclient call.  It should
@@ -134,6 +178,9 @@ impl UpdateStatus {
             chosen_update: None,
             active_partition: None,
             staging_partition: None,
+            staging_progress: None,
+            boot_health_check: None,
+            next_maintenance_window: None,
             most_recent_command: None,
         }
     }
@@ -142,6 +189,10 @@ impl UpdateStatus {
         &self.update_state
     }
 
+    pub fn available_updates(&self) -> &[semver::Version] {
+        &self.available_updates
+    }
+
     pub fn set_update_state(&mut self, state: UpdateState) {
         self.update_state = state;
     }
@@ -190,6 +241,38 @@ impl UpdateStatus {
         });
     }
 
+    /// Updates how far along writing the chosen update to the staging partition set is, based on
+    /// the progress updog records
+    pub fn update_staging_progress(&mut self) -> Result<()> {
+        let progress = update_metadata::progress::load(Path::new(PROGRESS_PATH))
+            .context(error::UpdateProgressSnafu)?;
+        let chosen_version = self.chosen_update.as_ref().map(UpdateImage::version);
+        self.staging_progress = progress
+            .filter(|progress| Some(&progress.version) == chosen_version)
+            .map(|progress| StagingProgress {
+                bytes_written: progress.bytes_written(),
+                bytes_total: progress.bytes_total(),
+                version: progress.version,
+            });
+        Ok(())
+    }
+
+    /// Updates the result of the last boot health check, as recorded by updog
+    pub fn update_boot_health_check(&mut self) -> Result<()> {
+        self.boot_health_check = update_metadata::health::load(Path::new(HEALTH_CHECK_PATH))
+            .context(error::BootHealthCheckSnafu)?;
+        Ok(())
+    }
+
+    /// Updates the maintenance window shown as open now, or opening next
+    pub fn update_maintenance_window(&mut self, windows: &MaintenanceWindows) {
+        self.next_maintenance_window = windows.next(Utc::now());
+    }
+
+    pub fn next_maintenance_window(&self) -> Option<&WindowTime> {
+        self.next_maintenance_window.as_ref()
+    }
+
     /// Mark staging partition as next to boot
     pub fn mark_staging_partition_next_to_boot(&mut self) -> Result<()> {
         if let Some(staging_partition) = &mut self.staging_partition {
@@ -6,6 +6,7 @@
 pub const API_SOCKET: &str = "/run/api.sock";
 pub const API_SETTINGS_URI: &str = "/settings";
 pub const API_SETTINGS_GENERATORS_URI: &str = "/metadata/setting-generators";
+pub const API_SENSITIVE_SETTINGS_URI: &str = "/metadata/sensitive";
 
 // Shared transaction used by boot time services
 pub const LAUNCH_TRANSACTION: &str = "bottlerocket-launch";
@@ -13,3 +14,6 @@ pub const LAUNCH_TRANSACTION: &str = "bottlerocket-launch";
 // Shared binaries' locations
 pub const SYSTEMCTL_BIN: &str = "/bin/systemctl";
 pub const HOST_CTR_BIN: &str = "/bin/host-ctr";
+
+// Shared files
+pub const KERNEL_CRASH_REPORT: &str = "/var/lib/bottlerocket/kernel-crash-report.json";
@@ -0,0 +1,161 @@
+package main
+
+import (
+	"bufio"
+	"encoding/json"
+	"fmt"
+	"io"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+
+	"github.com/containerd/containerd/oci"
+	runtimespec "github.com/opencontainers/runtime-spec/specs-go"
+	"github.com/pkg/errors"
+)
+
+// cpuPeriod is the CFS period, in microseconds, that a container's CPU quota
+// is measured against.
+const cpuPeriod uint64 = 100000
+
+// mountInfoPath lists the mounts in host-ctr's mount namespace, which is the
+// host's
+const mountInfoPath = "/proc/self/mountinfo"
+
+// ContainerMount contains a host path to mount read-only in the container
+type ContainerMount struct {
+	Source      string `json:"source"`
+	Destination string `json:"destination"`
+}
+
+// ContainerConfig contains the resource limits, mounts and environment
+// configured for a host container
+type ContainerConfig struct {
+	// CPUQuota is the CPU time, in microseconds, the container may use in
+	// each CFS period
+	CPUQuota int64 `json:"cpu-quota,omitempty"`
+	// MemoryLimit is the memory limit of the container, in bytes
+	MemoryLimit uint64            `json:"memory-limit,omitempty"`
+	Mounts      []ContainerMount  `json:"mounts,omitempty"`
+	Environment map[string]string `json:"environment,omitempty"`
+}
+
+// NewContainerConfig unmarshalls a container configuration file and sets up a ContainerConfig
+func NewContainerConfig(containerConfigFile string) (*ContainerConfig, error) {
+	raw, err := os.ReadFile(containerConfigFile)
+	if err != nil {
+		return nil, err
+	}
+
+	config := ContainerConfig{}
+	return &config, json.Unmarshal(raw, &config)
+}
+
+// CheckMounts fails if a configured mount has other filesystems mounted
+// beneath its source. The "ro" option only makes the top of a recursive bind
+// mount read-only, so the container could write to those submounts.
+func (c *ContainerConfig) CheckMounts() error {
+	if len(c.Mounts) == 0 {
+		return nil
+	}
+
+	mountInfo, err := os.Open(mountInfoPath)
+	if err != nil {
+		return errors.Wrapf(err, "failed to open %s", mountInfoPath)
+	}
+	defer mountInfo.Close()
+	mountPoints, err := readMountPoints(mountInfo)
+	if err != nil {
+		return errors.Wrapf(err, "failed to read %s", mountInfoPath)
+	}
+
+	for _, mount := range c.Mounts {
+		source, err := filepath.EvalSymlinks(mount.Source)
+		if err != nil {
+			return errors.Wrapf(err, "failed to resolve mount source %s", mount.Source)
+		}
+		if submount := findSubmount(source, mountPoints); submount != "" {
+			return errors.Errorf("mount source %s can't be mounted read-only, since %s is mounted beneath it", mount.Source, submount)
+		}
+	}
+	return nil
+}
+
+// mountInfoUnescaper undoes the octal escapes the kernel uses for whitespace
+// and backslashes in mountinfo paths
+var mountInfoUnescaper = strings.NewReplacer(`\040`, " ", `\011`, "\t", `\012`, "\n", `\134`, `\`)
+
+// readMountPoints returns the mount points listed in mountinfo format
+func readMountPoints(mountInfo io.Reader) ([]string, error) {
+	mountPoints := []string{}
+	scanner := bufio.NewScanner(mountInfo)
+	for scanner.Scan() {
+		// The mount point is the fifth field
+		fields := strings.Fields(scanner.Text())
+		if len(fields) < 5 {
+			return nil, errors.Errorf("invalid mountinfo line %q", scanner.Text())
+		}
+		mountPoints = append(mountPoints, mountInfoUnescaper.Replace(fields[4]))
+	}
+	return mountPoints, scanner.Err()
+}
+
+// findSubmount returns a mount point beneath the given path, other than the
+// path itself, or "" if there are none
+func findSubmount(path string, mountPoints []string) string {
+	path = filepath.Clean(path)
+	for _, mountPoint := range mountPoints {
+		if mountPoint == path {
+			continue
+		}
+		if path == "/" || strings.HasPrefix(mountPoint, path+"/") {
+			return mountPoint
+		}
+	}
+	return ""
+}
+
+// SpecOpts returns the spec options that apply the container configuration.
+// They're meant to be applied after the image config and the container type's
+// options, so the configured environment takes precedence over the image's.
+func (c *ContainerConfig) SpecOpts() []oci.SpecOpts {
+	specOpts := []oci.SpecOpts{}
+
+	if c.CPUQuota > 0 {
+		specOpts = append(specOpts, oci.WithCPUCFS(c.CPUQuota, cpuPeriod))
+	}
+	if c.MemoryLimit > 0 {
+		specOpts = append(specOpts, oci.WithMemoryLimit(c.MemoryLimit))
+	}
+
+	if len(c.Mounts) > 0 {
+		mounts := []runtimespec.Mount{}
+		for _, mount := range c.Mounts {
+			mounts = append(mounts, runtimespec.Mount{
+				Options:     []string{"rbind", "ro"},
+				Destination: mount.Destination,
+				Source:      mount.Source,
+				Type:        "bind",
+			})
+		}
+		specOpts = append(specOpts, withMounts(mounts))
+	}
+
+	if len(c.Environment) > 0 {
+		// Sort the variables so the spec is the same each time
+		names := make([]string, 0, len(c.Environment))
+		for name := range c.Environment {
+			names = append(names, name)
+		}
+		sort.Strings(names)
+
+		env := make([]string, 0, len(names))
+		for _, name := range names {
+			env = append(env, fmt.Sprintf("%s=%s", name, c.Environment[name]))
+		}
+		specOpts = append(specOpts, oci.WithEnv(env))
+	}
+
+	return specOpts
+}
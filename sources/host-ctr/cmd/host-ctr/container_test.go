@@ -0,0 +1,80 @@
+package main
+
+import (
+	"context"
+	"os"
+	"path/filepath"
+	"strings"
+	"testing"
+
+	"github.com/containerd/containerd/containers"
+	"github.com/containerd/containerd/oci"
+	runtimespec "github.com/opencontainers/runtime-spec/specs-go"
+	"github.com/stretchr/testify/assert"
+)
+
+// Test that a container config file is read and applied to the spec
+func TestContainerConfigSpecOpts(t *testing.T) {
+	path := filepath.Join(t.TempDir(), "admin.json")
+	raw := `{
+  "cpu-quota": 50000,
+  "memory-limit": 268435456,
+  "mounts": [{"source": "/var/log", "destination": "/host/var/log"}],
+  "environment": {"LOG_LEVEL": "debug", "AGENT_MODE": "node"}
+}`
+	assert.NoError(t, os.WriteFile(path, []byte(raw), 0600))
+
+	config, err := NewContainerConfig(path)
+	assert.NoError(t, err)
+
+	spec := oci.Spec{
+		Process: &runtimespec.Process{Env: []string{"PATH=/usr/bin", "LOG_LEVEL=info"}},
+		Linux:   &runtimespec.Linux{},
+	}
+	for _, opt := range config.SpecOpts() {
+		assert.NoError(t, opt(context.Background(), nil, &containers.Container{}, &spec))
+	}
+
+	assert.Equal(t, int64(50000), *spec.Linux.Resources.CPU.Quota)
+	assert.Equal(t, cpuPeriod, *spec.Linux.Resources.CPU.Period)
+	assert.Equal(t, int64(268435456), *spec.Linux.Resources.Memory.Limit)
+	assert.Equal(t, []runtimespec.Mount{
+		{
+			Destination: "/host/var/log",
+			Type:        "bind",
+			Source:      "/var/log",
+			Options:     []string{"rbind", "ro", "rprivate"},
+		},
+	}, spec.Mounts)
+	assert.Equal(t, []string{"PATH=/usr/bin", "LOG_LEVEL=debug", "AGENT_MODE=node"}, spec.Process.Env)
+}
+
+// Test that an empty container config doesn't change the spec
+func TestEmptyContainerConfig(t *testing.T) {
+	config := ContainerConfig{}
+	assert.Empty(t, config.SpecOpts())
+}
+
+// Test that mount points are read from mountinfo, including escaped paths
+func TestReadMountPoints(t *testing.T) {
+	mountInfo := `22 1 259:1 / / ro,relatime shared:1 - ext4 /dev/root ro
+25 22 0:22 / /var rw,nosuid,nodev shared:2 - ext4 /dev/nvme1n1p1 rw
+40 25 0:45 / /var/lib/kubelet/pods/abc/volumes/my\040volume rw,relatime shared:20 - tmpfs tmpfs rw
+`
+	mountPoints, err := readMountPoints(strings.NewReader(mountInfo))
+	assert.NoError(t, err)
+	assert.Equal(t, []string{"/", "/var", "/var/lib/kubelet/pods/abc/volumes/my volume"}, mountPoints)
+
+	_, err = readMountPoints(strings.NewReader("22 1 259:1 /\n"))
+	assert.Error(t, err)
+}
+
+// Test that submounts are found beneath mount sources, but not beside them
+func TestFindSubmount(t *testing.T) {
+	mountPoints := []string{"/", "/var", "/var/lib/kubelet/pods/abc/volumes/data", "/var/log-archive"}
+	assert.Equal(t, "/var/lib/kubelet/pods/abc/volumes/data", findSubmount("/var/lib/kubelet", mountPoints))
+	assert.Equal(t, "/var/lib/kubelet/pods/abc/volumes/data", findSubmount("/var/lib/kubelet/", mountPoints))
+	assert.Equal(t, "", findSubmount("/var/log", mountPoints))
+	assert.Equal(t, "", findSubmount("/var/lib/kubelet/pods/abc/volumes/data", mountPoints))
+	assert.Equal(t, "/var", findSubmount("/", mountPoints))
+}
@@ -67,6 +67,7 @@ func App() *cli.App {
 		namespace        string
 		superpowered     bool
 		registryConfig   string
+		containerConfig  string
 		cType            string
 		useCachedImage   bool
 	)
@@ -122,6 +123,11 @@ func App() *cli.App {
 					Usage:       "path to image registry configuration",
 					Destination: &registryConfig,
 				},
+				&cli.StringFlag{
+					Name:        "container-config",
+					Usage:       "path to container configuration with resource limits, mounts and environment",
+					Destination: &containerConfig,
+				},
 				&cli.StringFlag{
 					Name:        "container-type",
 					Usage:       "specifies one of: [host, bootstrap]",
@@ -136,7 +142,7 @@ func App() *cli.App {
 				},
 			},
 			Action: func(c *cli.Context) error {
-				return runCtr(containerdSocket, namespace, containerID, source, superpowered, registryConfig, containerType(cType), useCachedImage)
+				return runCtr(containerdSocket, namespace, containerID, source, superpowered, registryConfig, containerConfig, containerType(cType), useCachedImage)
 			},
 		},
 		{
@@ -181,6 +187,21 @@ func App() *cli.App {
 				return cleanUp(containerdSocket, namespace, containerID)
 			},
 		},
+		{
+			Name:  "status",
+			Usage: "print the specified container's status as JSON",
+			Flags: []cli.Flag{
+				&cli.StringFlag{
+					Name:        "container-id",
+					Usage:       "the id of the container to describe",
+					Destination: &containerID,
+					Required:    true,
+				},
+			},
+			Action: func(c *cli.Context) error {
+				return containerStatus(containerdSocket, namespace, containerID)
+			},
+		},
 	}
 
 	return app
@@ -238,7 +259,7 @@ func SliceContains(s []string, v string) bool {
 	return false
 }
 
-func runCtr(containerdSocket string, namespace string, containerID string, source string, superpowered bool, registryConfigPath string, cType containerType, useCachedImage bool) error {
+func runCtr(containerdSocket string, namespace string, containerID string, source string, superpowered bool, registryConfigPath string, containerConfigPath string, cType containerType, useCachedImage bool) error {
 	// Check if the containerType provided is valid
 	if !cType.IsValid() {
 		return errors.New("Invalid container type")
@@ -249,6 +270,20 @@ func runCtr(containerdSocket string, namespace string, containerID string, sourc
 		return errors.New("Bootstrap containers can't be superpowered")
 	}
 
+	// Read the container configuration before doing any work, so a bad
+	// configuration doesn't leave a half-configured container behind
+	var containerConfig *ContainerConfig
+	if containerConfigPath != "" {
+		var err error
+		containerConfig, err = NewContainerConfig(containerConfigPath)
+		if err != nil {
+			return errors.Wrapf(err, "failed to read container config from %s", containerConfigPath)
+		}
+		if err := containerConfig.CheckMounts(); err != nil {
+			return errors.Wrapf(err, "invalid mounts in container config %s", containerConfigPath)
+		}
+	}
+
 	ctx, cancel := context.WithCancel(context.Background())
 	defer cancel()
 	ctx = namespaces.WithNamespace(ctx, namespace)
@@ -332,6 +367,11 @@ func runCtr(containerdSocket string, namespace string, containerID string, sourc
 			specOpts = append(specOpts, withDefault())
 		}
 
+		// Apply the configured resource limits, mounts and environment
+		if containerConfig != nil {
+			specOpts = append(specOpts, containerConfig.SpecOpts()...)
+		}
+
 		ctrOpts := containerd.WithNewSpec(specOpts...)
 
 		// Create the container.
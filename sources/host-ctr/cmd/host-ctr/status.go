@@ -0,0 +1,92 @@
+package main
+
+import (
+	"context"
+	"encoding/json"
+	"io"
+	"os"
+
+	"github.com/containerd/containerd"
+	"github.com/containerd/containerd/errdefs"
+	"github.com/containerd/containerd/namespaces"
+)
+
+// ContainerStatus describes a container and its task, as seen by containerd
+type ContainerStatus struct {
+	// Exists is false if containerd doesn't know about the container, for
+	// example because its image is still being pulled
+	Exists bool `json:"exists"`
+	// Status is the status of the container's task, like "running" or
+	// "stopped", or empty if there's no task
+	Status      string `json:"status,omitempty"`
+	Image       string `json:"image,omitempty"`
+	ImageDigest string `json:"image-digest,omitempty"`
+	// ExitStatus is the exit status of the task, once it's stopped
+	ExitStatus *uint32 `json:"exit-status,omitempty"`
+}
+
+// containerStatus prints the status of the given container as JSON
+func containerStatus(containerdSocket string, namespace string, containerID string) error {
+	ctx, cancel := context.WithCancel(context.Background())
+	defer cancel()
+	ctx = namespaces.WithNamespace(ctx, namespace)
+
+	client, err := newContainerdClient(ctx, containerdSocket, namespace)
+	if err != nil {
+		return err
+	}
+	defer client.Close()
+
+	status, err := getContainerStatus(ctx, client, containerID)
+	if err != nil {
+		return err
+	}
+	return writeContainerStatus(os.Stdout, status)
+}
+
+// getContainerStatus looks up the container and its task in containerd
+func getContainerStatus(ctx context.Context, client *containerd.Client, containerID string) (*ContainerStatus, error) {
+	status := ContainerStatus{}
+
+	container, err := client.LoadContainer(ctx, containerID)
+	if err != nil {
+		if errdefs.IsNotFound(err) {
+			return &status, nil
+		}
+		return nil, err
+	}
+	status.Exists = true
+
+	img, err := container.Image(ctx)
+	if err != nil && !errdefs.IsNotFound(err) {
+		return nil, err
+	}
+	if img != nil {
+		status.Image = img.Name()
+		status.ImageDigest = img.Target().Digest.String()
+	}
+
+	task, err := container.Task(ctx, nil)
+	if err != nil {
+		if errdefs.IsNotFound(err) {
+			return &status, nil
+		}
+		return nil, err
+	}
+	taskStatus, err := task.Status(ctx)
+	if err != nil {
+		return nil, err
+	}
+	status.Status = string(taskStatus.Status)
+	if taskStatus.Status == containerd.Stopped {
+		exitStatus := taskStatus.ExitStatus
+		status.ExitStatus = &exitStatus
+	}
+
+	return &status, nil
+}
+
+// writeContainerStatus writes the status as a line of JSON
+func writeContainerStatus(w io.Writer, status *ContainerStatus) error {
+	return json.NewEncoder(w).Encode(status)
+}
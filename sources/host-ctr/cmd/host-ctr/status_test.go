@@ -0,0 +1,26 @@
+package main
+
+import (
+	"bytes"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+)
+
+// Test that container status is written with only the fields that are known
+func TestWriteContainerStatus(t *testing.T) {
+	var buf bytes.Buffer
+	assert.NoError(t, writeContainerStatus(&buf, &ContainerStatus{}))
+	assert.Equal(t, "{\"exists\":false}\n", buf.String())
+
+	buf.Reset()
+	exitStatus := uint32(1)
+	assert.NoError(t, writeContainerStatus(&buf, &ContainerStatus{
+		Exists:      true,
+		Status:      "stopped",
+		Image:       "public.ecr.aws/bottlerocket/bottlerocket-admin:v0.9.4",
+		ImageDigest: "sha256:0123",
+		ExitStatus:  &exitStatus,
+	}))
+	assert.Equal(t, `{"exists":true,"status":"stopped","image":"public.ecr.aws/bottlerocket/bottlerocket-admin:v0.9.4","image-digest":"sha256:0123","exit-status":1}`+"\n", buf.String())
+}
@@ -160,6 +160,15 @@ pub(crate) enum Error {
     #[snafu(display("Cannot write to / as a file."))]
     RootAsFile { backtrace: Backtrace },
 
+    #[snafu(display("Invalid sensitive setting name '{}': {}", name, source))]
+    SensitiveKey {
+        name: String,
+        source: datastore::Error,
+    },
+
+    #[snafu(display("Unable to deserialize sensitive settings: {}", source))]
+    SensitiveSettingsJson { source: serde_json::Error },
+
     #[snafu(display("Error serializing Settings: {} ", source))]
     SerializeSettings { source: serialization::Error },
 
@@ -11,10 +11,11 @@
 use crate::error::{self, Result};
 use datastore::deserialization::from_map;
 use datastore::serialization::to_pairs;
+use datastore::{Key, KeyType};
 use glob::{glob, Pattern};
 use reqwest::blocking::{Client, Response};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::path::Path;
@@ -165,6 +166,11 @@ where
         settings_map.retain(|k, _| !pattern.matches(k.name().as_str()))
     }
 
+    // Filter settings marked sensitive in the API, and the settings beneath them
+    for sensitive in get_sensitive_settings().await? {
+        settings_map.retain(|k, _| !k.starts_with_segments(sensitive.segments()))
+    }
+
     // Serialize the map back to a `Settings` to remove the escaping so it writes nicely to file
     let settings: model::Settings =
         from_map(&settings_map).context(error::DeserializeSettingsSnafu)?;
@@ -185,6 +191,22 @@ async fn get_settings() -> Result<model::Settings> {
     serde_json::from_str(&response_body).context(error::SettingsJsonSnafu)
 }
 
+/// Uses `apiclient` to request the names of settings marked sensitive, like secrets decrypted from
+/// user data
+async fn get_sensitive_settings() -> Result<Vec<Key>> {
+    let uri = constants::API_SENSITIVE_SETTINGS_URI;
+    let (_status, response_body) = apiclient::raw_request(constants::API_SOCKET, uri, "GET", None)
+        .await
+        .context(error::ApiClientSnafu { uri })?;
+
+    let sensitive: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&response_body).context(error::SensitiveSettingsJsonSnafu)?;
+    sensitive
+        .keys()
+        .map(|name| Key::new(KeyType::Data, name).context(error::SensitiveKeySnafu { name }))
+        .collect()
+}
+
 /// Runs an `exec` `LogRequest`'s `instructions` and writes its output to to `tempdir`.
 fn handle_exec_request<P>(request: &LogRequest<'_>, tempdir: P) -> Result<()>
 where
@@ -24,4 +24,6 @@ pub(crate) enum Command {
     SendBootSuccess,
     /// check services and report their health.
     SendHealthPing,
+    /// serve metrics for local scrapers, if an exporter listen address is configured.
+    ServeMetrics,
 }
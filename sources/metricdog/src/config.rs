@@ -15,6 +15,9 @@ pub(crate) struct Config {
     pub(crate) seed: u32,
     pub(crate) version_lock: String,
     pub(crate) ignore_waves: bool,
+    /// Where to serve metrics for local scrapers; we don't if this isn't set.
+    #[serde(default)]
+    pub(crate) exporter_listen_address: Option<String>,
 }
 
 impl Config {
@@ -57,6 +60,18 @@ mod test {
     ignore_waves = false
     "#;
 
+    // This is what a config looks like if the user opts in to the metrics exporter.
+    const EXPORTER_CONFIG: &str = r#"
+    metrics_url = "https://example.com"
+    send_metrics = true
+    service_checks = ["a", "b", "c",]
+    region = "us-west-2"
+    seed = 1234
+    version_lock = "v0.1.2"
+    ignore_waves = false
+    exporter_listen_address = "/run/metricdog/metrics.sock"
+    "#;
+
     #[test]
     fn standard_config() {
         let dir = TempDir::new().unwrap();
@@ -73,6 +88,7 @@ mod test {
         assert_eq!(1234, config.seed);
         assert_eq!("v0.1.2", config.version_lock);
         assert!(!config.ignore_waves);
+        assert!(config.exporter_listen_address.is_none());
     }
 
     #[test]
@@ -92,4 +108,16 @@ mod test {
         assert_eq!("v0.1.2", config.version_lock);
         assert!(!config.ignore_waves);
     }
+
+    #[test]
+    fn exporter_config() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, EXPORTER_CONFIG).unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(
+            Some("/run/metricdog/metrics.sock"),
+            config.exporter_listen_address.as_deref()
+        );
+    }
 }
@@ -7,6 +7,19 @@ use url::Url;
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub(crate) enum Error {
+    #[snafu(display("Error requesting '{}' from API: {}", uri, source))]
+    ApiRequest {
+        uri: String,
+        #[snafu(source(from(apiclient::Error, Box::new)))]
+        source: Box<apiclient::Error>,
+    },
+
+    #[snafu(display("Unable to parse API response from '{}': {}", uri, source))]
+    ApiResponse {
+        uri: String,
+        source: serde_json::Error,
+    },
+
     #[snafu(display("Unable to load Bottlerocket release info: '{}'", source))]
     BottlerocketRelease { source: bottlerocket_release::Error },
 
@@ -38,6 +51,37 @@ pub(crate) enum Error {
     #[snafu(display("Error receiving HTTP response {}: {}", url.as_str(), source))]
     HttpResponse { url: Url, source: reqwest::Error },
 
+    #[snafu(display("Unable to listen for metrics scrapes at '{}': {}", address, source))]
+    Listen {
+        address: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "Invalid exporter listen address '{}', expected a socket path or IP address and port: {}",
+        input,
+        source
+    ))]
+    ListenAddress {
+        input: String,
+        source: std::net::AddrParseError,
+    },
+
+    #[snafu(display(
+        "Invalid exporter listen address '{}', IP address must be a loopback address, like 127.0.0.1 or ::1",
+        input
+    ))]
+    NonLoopbackListenAddress { input: String },
+
+    #[snafu(display("Error serving metrics: {}", source))]
+    Serve { source: std::io::Error },
+
+    #[snafu(display("Unable to prepare socket path {}: {}", path.display(), source))]
+    SocketPath {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Unable to parse URL {}: {}", url, source))]
     UrlParse {
         url: String,
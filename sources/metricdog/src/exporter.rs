@@ -0,0 +1,544 @@
+//! The exporter serves metrics about the host in the OpenMetrics text format, so a local scraper,
+//! like a Prometheus agent in a host container, can collect them.  Each scrape gathers:
+//!
+//! * request counts and latencies, and datastore commit counts, from the API server;
+//! * how long thar-be-settings spends rendering configuration files and restarting services, and
+//!   how often each fails;
+//! * the update state, as reported by thar-be-updates through the API;
+//! * the health of the services in `service_checks`.
+//!
+//! Sources that can't be read are left out of the scrape, and reported in
+//! `bottlerocket_metrics_source_up`.
+
+use crate::config::Config;
+use crate::error::{self, Error, Result};
+use crate::openmetrics::{self, MetricType, Writer};
+use crate::service_check::{ServiceCheck, ServiceHealth};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use snafu::{ensure, ResultExt};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use thar_be_settings::stats::{self as settings_stats, PhaseStats, Stats};
+use thar_be_updates::status::{UpdateState, UpdateStatus};
+
+/// Where the exporter listens: a Unix-domain socket if the address is an absolute path, otherwise
+/// an IP address and port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ListenAddress {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl FromStr for ListenAddress {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        if input.starts_with('/') {
+            return Ok(ListenAddress::Unix(PathBuf::from(input)));
+        }
+        let address: SocketAddr = input.parse().context(error::ListenAddressSnafu { input })?;
+        // Metrics aren't authenticated, so they're only served on this host.
+        ensure!(
+            address.ip().is_loopback(),
+            error::NonLoopbackListenAddressSnafu { input }
+        );
+        Ok(ListenAddress::Tcp(address))
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenAddress::Unix(path) => write!(f, "{}", path.display()),
+            ListenAddress::Tcp(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+/// What the request handler needs to check the host's services.
+struct ServiceChecks {
+    services: Vec<String>,
+    service_check: Arc<dyn ServiceCheck>,
+}
+
+/// Serves metrics at `/metrics` on the configured listen address until stopped.  Returns right
+/// away if no listen address is configured, because the exporter is opt-in.
+pub(crate) fn serve(config: Config, service_check: Arc<dyn ServiceCheck>) -> Result<()> {
+    let address: ListenAddress = match &config.exporter_listen_address {
+        Some(address) => address.parse()?,
+        None => {
+            info!("No exporter listen address is configured; not serving metrics");
+            return Ok(());
+        }
+    };
+    let checks = web::Data::new(ServiceChecks {
+        services: config.service_checks,
+        service_check,
+    });
+
+    actix_web::rt::System::new().block_on(async move {
+        // Scrapes are infrequent, so one worker is plenty.
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(checks.clone())
+                .route("/metrics", web::get().to(get_metrics))
+        })
+        .workers(1);
+
+        let server = match &address {
+            ListenAddress::Tcp(socket_address) => server.bind(socket_address),
+            ListenAddress::Unix(path) => {
+                prepare_socket_path(path)?;
+                server.bind_uds(path)
+            }
+        }
+        .context(error::ListenSnafu {
+            address: address.to_string(),
+        })?;
+
+        info!("Serving metrics at {}", address);
+        server.run().await.context(error::ServeSnafu)
+    })
+}
+
+/// Removes any socket left behind by a previous run, and creates the socket's directory.
+fn prepare_socket_path(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context(error::SocketPathSnafu { path }),
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(error::SocketPathSnafu { path })?;
+    }
+    Ok(())
+}
+
+async fn get_metrics(checks: web::Data<ServiceChecks>) -> HttpResponse {
+    let api = api_get(constants::API_SOCKET, "/metrics")
+        .await
+        .map_err(|e| warn!("Unable to get API server metrics: {}", e))
+        .ok();
+    let update_status = api_get(constants::API_SOCKET, "/updates/status")
+        .await
+        .map_err(|e| warn!("Unable to get update status: {}", e))
+        .ok();
+    // Reading the settings applier's totals waits on a file lock, and checking services runs
+    // commands, so we do those on a blocking thread.
+    match web::block(move || Snapshot::collect(api, update_status, &checks)).await {
+        Ok(snapshot) => HttpResponse::Ok()
+            .content_type(openmetrics::CONTENT_TYPE)
+            .body(snapshot.render()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// The API server's totals, as returned by its GET /metrics.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ApiMetrics {
+    pub(crate) requests: Vec<RouteMetrics>,
+    pub(crate) commits: u64,
+    pub(crate) committed_keys: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct RouteMetrics {
+    pub(crate) method: String,
+    pub(crate) route: String,
+    /// Responses by status code.
+    pub(crate) responses: BTreeMap<u16, u64>,
+    pub(crate) duration_seconds: Histogram,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Histogram {
+    pub(crate) buckets: Vec<Bucket>,
+    pub(crate) sum: f64,
+    pub(crate) count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Bucket {
+    pub(crate) le: f64,
+    pub(crate) count: u64,
+}
+
+/// Makes a GET request to the API and deserializes the response.
+async fn api_get<T>(socket_path: &str, uri: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let (_status, body) = apiclient::raw_request(socket_path, uri, "GET", None)
+        .await
+        .context(error::ApiRequestSnafu { uri })?;
+    serde_json::from_str(&body).context(error::ApiResponseSnafu { uri })
+}
+
+/// Everything a scrape reports.  Sources that couldn't be read are None.
+#[derive(Debug, Default)]
+pub(crate) struct Snapshot {
+    pub(crate) api: Option<ApiMetrics>,
+    pub(crate) settings_applier: Option<Stats>,
+    pub(crate) update_status: Option<UpdateStatus>,
+    pub(crate) services: Vec<(String, Option<ServiceHealth>)>,
+}
+
+impl Snapshot {
+    fn collect(
+        api: Option<ApiMetrics>,
+        update_status: Option<UpdateStatus>,
+        checks: &ServiceChecks,
+    ) -> Self {
+        let settings_applier = settings_stats::load(settings_stats::STATS_FILE)
+            .map_err(|e| warn!("Unable to read settings applier stats: {}", e))
+            .ok();
+        let services = checks
+            .services
+            .iter()
+            .map(|service| {
+                let health = checks
+                    .service_check
+                    .check(service)
+                    .map_err(|e| warn!("Unable to check service '{}': {}", service, e))
+                    .ok();
+                (service.clone(), health)
+            })
+            .collect();
+        Self {
+            api,
+            settings_applier,
+            update_status,
+            services,
+        }
+    }
+
+    /// Renders the snapshot as an OpenMetrics document.
+    pub(crate) fn render(&self) -> String {
+        let mut writer = Writer::default();
+
+        writer.family(
+            "bottlerocket_metrics_source_up",
+            MetricType::Gauge,
+            "Whether the source of a set of metrics could be read.",
+        );
+        for (source, up) in [
+            ("apiserver", self.api.is_some()),
+            ("settings-applier", self.settings_applier.is_some()),
+            ("updates", self.update_status.is_some()),
+        ] {
+            writer.sample(
+                "bottlerocket_metrics_source_up",
+                &[("source", source)],
+                u8::from(up),
+            );
+        }
+
+        if let Some(api) = &self.api {
+            render_api(&mut writer, api);
+        }
+        if let Some(stats) = &self.settings_applier {
+            render_settings_applier(&mut writer, stats);
+        }
+        if let Some(status) = &self.update_status {
+            render_update_status(&mut writer, status);
+        }
+        render_services(&mut writer, &self.services);
+
+        writer.finish()
+    }
+}
+
+fn render_api(writer: &mut Writer, api: &ApiMetrics) {
+    writer.family(
+        "bottlerocket_api_requests",
+        MetricType::Counter,
+        "Requests handled by the API server, by method, route, and response status.",
+    );
+    for route in &api.requests {
+        for (status, count) in &route.responses {
+            writer.sample(
+                "bottlerocket_api_requests_total",
+                &[
+                    ("method", &route.method),
+                    ("route", &route.route),
+                    ("status", &status.to_string()),
+                ],
+                count,
+            );
+        }
+    }
+
+    writer.family(
+        "bottlerocket_api_request_duration_seconds",
+        MetricType::Histogram,
+        "How long the API server took to respond to requests, by method and route.",
+    );
+    for route in &api.requests {
+        let histogram = &route.duration_seconds;
+        let buckets: Vec<(f64, u64)> = histogram
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.le, bucket.count))
+            .collect();
+        writer.histogram(
+            "bottlerocket_api_request_duration_seconds",
+            &[("method", &route.method), ("route", &route.route)],
+            &buckets,
+            histogram.sum,
+            histogram.count,
+        );
+    }
+
+    writer.family(
+        "bottlerocket_datastore_commits",
+        MetricType::Counter,
+        "Transactions committed to the datastore.",
+    );
+    writer.sample("bottlerocket_datastore_commits_total", &[], api.commits);
+    writer.family(
+        "bottlerocket_datastore_committed_keys",
+        MetricType::Counter,
+        "Settings changed by transactions committed to the datastore.",
+    );
+    writer.sample(
+        "bottlerocket_datastore_committed_keys_total",
+        &[],
+        api.committed_keys,
+    );
+}
+
+fn render_settings_applier(writer: &mut Writer, stats: &Stats) {
+    let phases: [(&str, &PhaseStats); 2] = [("render", &stats.render), ("restart", &stats.restart)];
+
+    writer.family(
+        "bottlerocket_settings_applier_runs",
+        MetricType::Counter,
+        "Times thar-be-settings rendered configuration files or ran restart commands, since boot.",
+    );
+    for (phase, totals) in phases {
+        writer.sample(
+            "bottlerocket_settings_applier_runs_total",
+            &[("phase", phase)],
+            totals.runs,
+        );
+    }
+    writer.family(
+        "bottlerocket_settings_applier_failures",
+        MetricType::Counter,
+        "Times rendering configuration files or running restart commands failed, since boot.",
+    );
+    for (phase, totals) in phases {
+        writer.sample(
+            "bottlerocket_settings_applier_failures_total",
+            &[("phase", phase)],
+            totals.failures,
+        );
+    }
+    writer.family(
+        "bottlerocket_settings_applier_duration_seconds",
+        MetricType::Counter,
+        "Time spent rendering configuration files or running restart commands, since boot.",
+    );
+    for (phase, totals) in phases {
+        writer.sample(
+            "bottlerocket_settings_applier_duration_seconds_total",
+            &[("phase", phase)],
+            totals.duration_seconds,
+        );
+    }
+    writer.family(
+        "bottlerocket_settings_applier_last_duration_seconds",
+        MetricType::Gauge,
+        "Time spent rendering configuration files or running restart commands in the last run.",
+    );
+    for (phase, totals) in phases {
+        writer.sample(
+            "bottlerocket_settings_applier_last_duration_seconds",
+            &[("phase", phase)],
+            totals.last_duration_seconds,
+        );
+    }
+}
+
+fn render_update_status(writer: &mut Writer, status: &UpdateStatus) {
+    writer.family(
+        "bottlerocket_update_state",
+        MetricType::StateSet,
+        "The state of updates to the host.",
+    );
+    let current = status.update_state();
+    for (state, is_current) in [
+        ("Idle", matches!(current, UpdateState::Idle)),
+        ("Available", matches!(current, UpdateState::Available)),
+        ("Staged", matches!(current, UpdateState::Staged)),
+        ("Ready", matches!(current, UpdateState::Ready)),
+    ] {
+        writer.sample(
+            "bottlerocket_update_state",
+            &[("bottlerocket_update_state", state)],
+            u8::from(is_current),
+        );
+    }
+
+    writer.family(
+        "bottlerocket_update_available_updates",
+        MetricType::Gauge,
+        "Versions the host could update to, as of the last check for updates.",
+    );
+    writer.sample(
+        "bottlerocket_update_available_updates",
+        &[],
+        status.available_updates().len(),
+    );
+}
+
+fn render_services(writer: &mut Writer, services: &[(String, Option<ServiceHealth>)]) {
+    writer.family(
+        "bottlerocket_service_healthy",
+        MetricType::Gauge,
+        "Whether each service in settings.metrics.service-checks is running without failures.",
+    );
+    for (service, health) in services {
+        if let Some(health) = health {
+            writer.sample(
+                "bottlerocket_service_healthy",
+                &[("service", service)],
+                u8::from(health.is_healthy),
+            );
+        }
+    }
+    writer.family(
+        "bottlerocket_service_exit_code",
+        MetricType::Gauge,
+        "The exit code of each unhealthy service, if it exited.",
+    );
+    for (service, health) in services {
+        if let Some(exit_code) = health.as_ref().and_then(|health| health.exit_code) {
+            writer.sample(
+                "bottlerocket_service_exit_code",
+                &[("service", service)],
+                exit_code,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use thar_be_settings::stats::Phase;
+
+    #[test]
+    fn render_snapshot() {
+        let api: ApiMetrics = serde_json::from_str(
+            r#"{
+                "requests": [{
+                    "method": "GET",
+                    "route": "/settings",
+                    "responses": {"200": 2, "403": 1},
+                    "duration-seconds": {
+                        "buckets": [{"le": 0.01, "count": 2}, {"le": 1.0, "count": 3}],
+                        "sum": 0.5,
+                        "count": 3
+                    }
+                }],
+                "commits": 4,
+                "committed-keys": 7
+            }"#,
+        )
+        .unwrap();
+        let mut settings_applier = Stats::default();
+        settings_applier.add(Phase::Render, Duration::from_millis(500), false);
+        let snapshot = Snapshot {
+            api: Some(api),
+            settings_applier: Some(settings_applier),
+            update_status: Some(UpdateStatus::new()),
+            services: vec![
+                (
+                    "apiserver".to_string(),
+                    Some(ServiceHealth {
+                        is_healthy: true,
+                        exit_code: None,
+                    }),
+                ),
+                (
+                    "kubelet".to_string(),
+                    Some(ServiceHealth {
+                        is_healthy: false,
+                        exit_code: Some(255),
+                    }),
+                ),
+                ("chronyd".to_string(), None),
+            ],
+        };
+
+        let rendered = snapshot.render();
+        for line in [
+            "bottlerocket_metrics_source_up{source=\"apiserver\"} 1",
+            "bottlerocket_api_requests_total{method=\"GET\",route=\"/settings\",status=\"403\"} 1",
+            "bottlerocket_api_request_duration_seconds_bucket{method=\"GET\",route=\"/settings\",le=\"0.01\"} 2",
+            "bottlerocket_api_request_duration_seconds_bucket{method=\"GET\",route=\"/settings\",le=\"+Inf\"} 3",
+            "bottlerocket_datastore_commits_total 4",
+            "bottlerocket_datastore_committed_keys_total 7",
+            "bottlerocket_settings_applier_failures_total{phase=\"render\"} 1",
+            "bottlerocket_settings_applier_runs_total{phase=\"restart\"} 0",
+            "bottlerocket_settings_applier_last_duration_seconds{phase=\"render\"} 0.5",
+            "bottlerocket_update_state{bottlerocket_update_state=\"Idle\"} 1",
+            "bottlerocket_update_state{bottlerocket_update_state=\"Ready\"} 0",
+            "bottlerocket_update_available_updates 0",
+            "bottlerocket_service_healthy{service=\"apiserver\"} 1",
+            "bottlerocket_service_healthy{service=\"kubelet\"} 0",
+            "bottlerocket_service_exit_code{service=\"kubelet\"} 255",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing '{}' in:\n{}",
+                line,
+                rendered
+            );
+        }
+        assert!(!rendered.contains("chronyd"));
+        assert!(rendered.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn unreadable_sources() {
+        let rendered = Snapshot::default().render();
+        assert!(rendered.contains("bottlerocket_metrics_source_up{source=\"updates\"} 0\n"));
+        assert!(!rendered.contains("bottlerocket_api_requests_total"));
+    }
+
+    #[test]
+    fn listen_address() {
+        assert_eq!(
+            "/run/metricdog/metrics.sock"
+                .parse::<ListenAddress>()
+                .unwrap(),
+            ListenAddress::Unix(PathBuf::from("/run/metricdog/metrics.sock"))
+        );
+        assert_eq!(
+            "127.0.0.1:9100".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp("127.0.0.1:9100".parse().unwrap())
+        );
+        assert_eq!(
+            "[::1]:9100".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp("[::1]:9100".parse().unwrap())
+        );
+        "localhost:9100".parse::<ListenAddress>().unwrap_err();
+        "0.0.0.0:9100".parse::<ListenAddress>().unwrap_err();
+        "10.0.0.1:9100".parse::<ListenAddress>().unwrap_err();
+        "[::]:9100".parse::<ListenAddress>().unwrap_err();
+    }
+}
@@ -16,6 +16,26 @@ not its traffic should be proxied. These are set with the `network.http-proxy` a
 settings when Metricdog is invoked by systemd. If you run Metricdog manually, you would need to
 seed the environment with these variables manually.
 
+### Metrics Exporter
+
+Metricdog can also serve metrics for local scrapers, such as a Prometheus agent running in a host container.
+This is opt-in: `metricdog serve-metrics` only listens if `exporter_listen_address` is configured, either as the absolute path of a Unix-domain socket or as a loopback IP address and port, like `127.0.0.1:9100`.
+It doesn't send anything anywhere, so it doesn't depend on `send_metrics`.
+
+Metrics are served at `/metrics` in the [OpenMetrics](https://openmetrics.io) text format.
+Each scrape reports:
+
+* `bottlerocket_api_requests_total`: requests handled by the API server, by `method`, `route`, and response `status`.
+* `bottlerocket_api_request_duration_seconds`: a histogram of how long the API server took to respond, by `method` and `route`.
+* `bottlerocket_datastore_commits_total` and `bottlerocket_datastore_committed_keys_total`: transactions committed to the datastore, and the settings they changed.
+* `bottlerocket_settings_applier_runs_total`, `bottlerocket_settings_applier_failures_total`, `bottlerocket_settings_applier_duration_seconds_total`, and `bottlerocket_settings_applier_last_duration_seconds`: how often, and for how long, `thar-be-settings` rendered configuration files and restarted services, by `phase` (`render` or `restart`).
+* `bottlerocket_update_state`: the update state reported by `thar-be-updates`, as a state set.
+* `bottlerocket_update_available_updates`: the number of versions the host could update to.
+* `bottlerocket_service_healthy` and `bottlerocket_service_exit_code`: the health of each service in `service_checks`.
+* `bottlerocket_metrics_source_up`: whether each `source` of metrics could be read; metrics from sources that couldn't be read are left out.
+
+The API server's totals start over when it restarts, and the settings applier's at each boot.
+
 # What it Sends
 
 ### The standard set of metrics:
@@ -54,17 +74,21 @@ seed = 1234
 version_lock = "latest"
 # whether bottlerocket should ignore update roll-out timing
 ignore_waves = false
+# optional: where to serve metrics for local scrapers, a socket path or an address and port
+exporter_listen_address = "/run/metricdog/metrics.sock"
 ```
 */
 
 mod args;
 mod config;
 mod error;
+mod exporter;
 #[cfg(test)]
 mod main_test;
 mod metricdog;
 #[cfg(test)]
 mod metricdog_test;
+mod openmetrics;
 mod service_check;
 
 use crate::args::{Arguments, Command};
@@ -77,6 +101,7 @@ use log::error;
 use simplelog::{Config as LogConfig, SimpleLogger};
 use snafu::ResultExt;
 use std::process;
+use std::sync::Arc;
 use structopt::StructOpt;
 
 fn main() -> ! {
@@ -99,6 +124,11 @@ pub(crate) fn main_inner(arguments: Arguments, service_check: Box<dyn ServiceChe
         Some(filepath) => Config::from_file(filepath)?,
     };
 
+    // the exporter only serves metrics locally, so it doesn't depend on the opt-out flag
+    if let Command::ServeMetrics = arguments.command {
+        return exporter::serve(config, Arc::from(service_check));
+    }
+
     // exit early with no error if the opt-out flag is set
     if !config.send_metrics {
         return Ok(());
@@ -127,6 +157,8 @@ pub(crate) fn main_inner(arguments: Arguments, service_check: Box<dyn ServiceChe
         Command::SendHealthPing => {
             metricdog.send_health_ping()?;
         }
+        // handled above
+        Command::ServeMetrics => {}
     }
     Ok(())
 }
@@ -74,6 +74,7 @@ fn send_healthy_ping() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            exporter_listen_address: None,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -117,6 +118,7 @@ fn send_unhealthy_ping() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            exporter_listen_address: None,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -153,6 +155,7 @@ fn send_boot_success() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            exporter_listen_address: None,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -0,0 +1,170 @@
+//! Writes metrics in the OpenMetrics text format, which Prometheus and compatible scrapers read:
+//! https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+
+use std::fmt::{Display, Write};
+
+/// The Content-Type of OpenMetrics text.
+pub(crate) const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The types of metric family we write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+    StateSet,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+            MetricType::StateSet => "stateset",
+        }
+    }
+}
+
+/// Builds an OpenMetrics document one metric family at a time.  Each family's samples must be
+/// written right after the family is started, and a family can't be started twice.
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    output: String,
+}
+
+impl Writer {
+    /// Starts a metric family; the samples written next belong to it.
+    pub(crate) fn family(&mut self, name: &str, metric_type: MetricType, help: &str) {
+        // Writing to a String can't fail.
+        let _ = writeln!(self.output, "# TYPE {} {}", name, metric_type.as_str());
+        let _ = writeln!(self.output, "# HELP {} {}", name, escape(help, false));
+    }
+
+    /// Writes a sample.  The name is the family's name plus any suffix its type requires, like
+    /// "_total" for counters.
+    pub(crate) fn sample<V: Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) {
+        self.output.push_str(name);
+        if !labels.is_empty() {
+            self.output.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.output.push(',');
+                }
+                let _ = write!(self.output, "{}=\"{}\"", label, escape(value, true));
+            }
+            self.output.push('}');
+        }
+        let _ = writeln!(self.output, " {}", value);
+    }
+
+    /// Writes the samples of a histogram, given its cumulative bucket counts by upper bound, along
+    /// with the sum and count of all observations.  The "+Inf" bucket is added from the count.
+    pub(crate) fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        buckets: &[(f64, u64)],
+        sum: f64,
+        count: u64,
+    ) {
+        let bucket_name = format!("{}_bucket", name);
+        for (le, bucket_count) in buckets {
+            // Debug formatting gives bounds like "1.0", the canonical form the format asks for.
+            let le = format!("{:?}", le);
+            self.sample(&bucket_name, &with_le(labels, &le), bucket_count);
+        }
+        self.sample(&bucket_name, &with_le(labels, "+Inf"), count);
+        self.sample(&format!("{}_sum", name), labels, sum);
+        self.sample(&format!("{}_count", name), labels, count);
+    }
+
+    /// Returns the finished document.
+    pub(crate) fn finish(mut self) -> String {
+        self.output.push_str("# EOF\n");
+        self.output
+    }
+}
+
+/// Returns the labels of a histogram bucket with the given upper bound.
+fn with_le<'a>(labels: &[(&'a str, &'a str)], le: &'a str) -> Vec<(&'a str, &'a str)> {
+    let mut bucket_labels = labels.to_vec();
+    bucket_labels.push(("le", le));
+    bucket_labels
+}
+
+/// Escapes text for a HELP line or, if `quoted`, for a label value.
+fn escape(text: &str, quoted: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quoted => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn families_and_samples() {
+        let mut writer = Writer::default();
+        writer.family("requests", MetricType::Counter, "Requests handled.");
+        writer.sample("requests_total", &[("route", "/settings")], 3);
+        writer.family("healthy", MetricType::Gauge, "Whether it's healthy.");
+        writer.sample("healthy", &[], 1);
+        assert_eq!(
+            writer.finish(),
+            "# TYPE requests counter\n\
+             # HELP requests Requests handled.\n\
+             requests_total{route=\"/settings\"} 3\n\
+             # TYPE healthy gauge\n\
+             # HELP healthy Whether it's healthy.\n\
+             healthy 1\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        let mut writer = Writer::default();
+        writer.family("duration_seconds", MetricType::Histogram, "How long.");
+        writer.histogram(
+            "duration_seconds",
+            &[("method", "GET")],
+            &[(0.5, 2), (1.0, 3)],
+            1.75,
+            4,
+        );
+        assert_eq!(
+            writer.finish(),
+            "# TYPE duration_seconds histogram\n\
+             # HELP duration_seconds How long.\n\
+             duration_seconds_bucket{method=\"GET\",le=\"0.5\"} 2\n\
+             duration_seconds_bucket{method=\"GET\",le=\"1.0\"} 3\n\
+             duration_seconds_bucket{method=\"GET\",le=\"+Inf\"} 4\n\
+             duration_seconds_sum{method=\"GET\"} 1.75\n\
+             duration_seconds_count{method=\"GET\"} 4\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let mut writer = Writer::default();
+        writer.family("x", MetricType::Gauge, "A \"quoted\"\nhelp \\ text");
+        writer.sample("x", &[("l", "a \"b\"\nc \\ d")], 0);
+        assert_eq!(
+            writer.finish(),
+            "# TYPE x gauge\n\
+             # HELP x A \"quoted\"\\nhelp \\\\ text\n\
+             x{l=\"a \\\"b\\\"\\nc \\\\ d\"} 0\n\
+             # EOF\n"
+        );
+    }
+}
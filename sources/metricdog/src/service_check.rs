@@ -11,7 +11,8 @@ pub(crate) struct ServiceHealth {
     pub(crate) exit_code: Option<i32>,
 }
 
-pub(crate) trait ServiceCheck {
+/// Send and Sync so the metrics exporter can share it between requests.
+pub(crate) trait ServiceCheck: Send + Sync {
     /// Checks the given service to see if it is healthy.
     fn check(&self, service_name: &str) -> Result<ServiceHealth>;
 }
@@ -35,16 +35,25 @@ This can be disabled by specifying the argument `add_option = false`.
 
 Fields are all wrapped in `Option<...>`.
 Similar to the `serde` attribute added to fields, this is because we don't want users to have to specify fields they aren't changing, and can be disabled the same way, by specifying `add_option = false`.
+
+# Schema
+
+An implementation of the model crate's `schema::Schema` trait is generated for the struct.
+It describes the struct as a JSON Schema object with a property for each field, using the name the field is serialized with, plus any names it can also be deserialized from, like serde aliases.
+The schema of each field comes from the `Schema` implementation of its type, so every type used in a model needs one.
+Unknown properties aren't allowed, matching `deny_unknown_fields`.
+Fields are only required if `add_option = false` is given and the field isn't an `Option` and doesn't have a serde `default`.
 */
 
 extern crate proc_macro;
 
 use darling::FromMeta;
 use proc_macro::TokenStream;
-use quote::ToTokens;
+use quote::{quote, ToTokens};
 use syn::visit_mut::{self, VisitMut};
 use syn::{
-    parse_macro_input, parse_quote, Attribute, AttributeArgs, Field, ItemStruct, Visibility,
+    parse_macro_input, parse_quote, Attribute, AttributeArgs, Field, ItemStruct, Lit, Meta,
+    NestedMeta, Type, Visibility,
 };
 
 /// Define a `#[model]` attribute that can be placed on structs to be used in an API model.
@@ -63,7 +72,11 @@ pub fn model(args: TokenStream, input: TokenStream) -> TokenStream {
     let mut ast: ItemStruct =
         syn::parse(input).expect("Unable to parse item `model` was placed on - is it a struct?");
     helper.visit_item_struct_mut(&mut ast);
-    ast.into_token_stream().into()
+    let schema = helper.schema_impl(&ast);
+
+    let mut output = ast.into_token_stream();
+    output.extend(schema);
+    output.into()
 }
 
 /// Store any args given by the user inside `#[model(...)]`.
@@ -95,6 +108,117 @@ impl From<ParsedArgs> for ModelHelper {
     }
 }
 
+impl ModelHelper {
+    /// Generates an implementation of the model crate's `Schema` trait for the (already modified)
+    /// struct, with a property for each name its fields can be serialized or deserialized with.
+    fn schema_impl(&self, node: &ItemStruct) -> proc_macro2::TokenStream {
+        let mut properties = Vec::new();
+        let mut required = Vec::new();
+        for field in &node.fields {
+            let names = match FieldNames::from_field(field) {
+                Some(names) => names,
+                None => continue,
+            };
+            let ty = &field.ty;
+            for name in names.all() {
+                properties.push(quote!((#name, <#ty as crate::schema::Schema>::schema())));
+            }
+            if !self.add_option && !names.default && !is_option(ty) {
+                required.push(names.serialize);
+            }
+        }
+
+        let name = &node.ident;
+        let (impl_generics, ty_generics, where_clause) = node.generics.split_for_impl();
+        quote! {
+            impl #impl_generics crate::schema::Schema for #name #ty_generics #where_clause {
+                fn schema() -> crate::schema::Value {
+                    crate::schema::object(vec![#(#properties),*], &[#(#required),*])
+                }
+            }
+        }
+    }
+}
+
+/// The names a field is serialized and deserialized with, taking into account the kebab-case
+/// renaming we add to structs and any serde attributes on the field itself.
+#[derive(Debug)]
+struct FieldNames {
+    serialize: String,
+    deserialize: String,
+    aliases: Vec<String>,
+    default: bool,
+}
+
+impl FieldNames {
+    /// Returns None for unnamed fields, which don't have a name in the serialized form.
+    fn from_field(field: &Field) -> Option<Self> {
+        let ident = field.ident.as_ref()?.to_string();
+        let name = ident.trim_start_matches("r#").replace('_', "-");
+        let mut names = FieldNames {
+            serialize: name.clone(),
+            deserialize: name,
+            aliases: Vec::new(),
+            default: false,
+        };
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("serde"))
+        {
+            let list = match attr.parse_meta() {
+                Ok(Meta::List(list)) => list,
+                _ => continue,
+            };
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::NameValue(pair)) => {
+                        if let Lit::Str(value) = pair.lit {
+                            if pair.path.is_ident("rename") {
+                                names.serialize = value.value();
+                                names.deserialize = value.value();
+                            } else if pair.path.is_ident("alias") {
+                                names.aliases.push(value.value());
+                            }
+                        }
+                    }
+                    // The rename(serialize = "...", deserialize = "...") form
+                    NestedMeta::Meta(Meta::List(inner)) if inner.path.is_ident("rename") => {
+                        for nested in inner.nested {
+                            if let NestedMeta::Meta(Meta::NameValue(pair)) = nested {
+                                if let Lit::Str(value) = pair.lit {
+                                    if pair.path.is_ident("serialize") {
+                                        names.serialize = value.value();
+                                    } else if pair.path.is_ident("deserialize") {
+                                        names.deserialize = value.value();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => {
+                        names.default = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some(names)
+    }
+
+    /// Every distinct name the field can appear under, starting with its serialized name.
+    fn all(&self) -> Vec<&String> {
+        let mut all = vec![&self.serialize];
+        for name in std::iter::once(&self.deserialize).chain(&self.aliases) {
+            if !all.contains(&name) {
+                all.push(name);
+            }
+        }
+        all
+    }
+}
+
 /// VisitMut helps us modify the node types we want without digging through the huge token trees
 /// need to represent them.
 impl VisitMut for ModelHelper {
@@ -173,3 +297,16 @@ fn is_attr_set(attr_name: &'static str, attrs: &[Attribute]) -> bool {
     }
     false
 }
+
+/// Checks whether the given type is an `Option<...>`.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "Option")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
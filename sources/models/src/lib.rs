@@ -177,6 +177,12 @@ pub use variant::*;
 // Types used to communicate between client and server for 'apiclient exec'.
 pub mod exec;
 
+// Types used to communicate between client and server for 'apiclient watch'.
+pub mod watch;
+
+// JSON Schema descriptions of the model, generated from the `#[model]` definitions.
+pub mod schema;
+
 // Below, we define common structures used in the API surface; specific variants build a Settings
 // structure based on these, and that's what gets exposed via the API.  (Specific variants' models
 // are in subdirectories and linked into place by build.rs at variant/current.)
@@ -190,13 +196,16 @@ use crate::de::{deserialize_mirrors, deserialize_node_taints};
 use crate::modeled_types::{
     BootConfigKey, BootConfigValue, BootstrapContainerMode, CpuManagerPolicy, CredentialProvider,
     DNSDomain, ECSAgentImagePullBehavior, ECSAgentLogLevel, ECSAttributeKey, ECSAttributeValue,
-    ECSDurationValue, EtcHostsEntries, FriendlyVersion, Identifier, IntegerPercent, KmodKey,
-    KubernetesAuthenticationMode, KubernetesBootstrapToken, KubernetesCloudProvider,
-    KubernetesClusterDnsIp, KubernetesClusterName, KubernetesDurationValue,
-    KubernetesEvictionHardKey, KubernetesLabelKey, KubernetesLabelValue, KubernetesQuantityValue,
-    KubernetesReservedResourceKey, KubernetesTaintValue, KubernetesThresholdValue, Lockdown,
-    OciDefaultsCapability, OciDefaultsResourceLimitType, PemCertificateString, SingleLineString,
-    SysctlKey, TopologyManagerPolicy, TopologyManagerScope, Url, ValidBase64, ValidLinuxHostname,
+    ECSDurationValue, EnvironmentVariableName, EtcHostsEntries, FriendlyVersion,
+    HostContainerCpuLimit, HostContainerMemoryLimit, HostContainerMountPath,
+    HostContainerRestartPolicy, Identifier, IntegerPercent, KmodKey, KubernetesAuthenticationMode,
+    KubernetesBootstrapToken, KubernetesCloudProvider, KubernetesClusterDnsIp,
+    KubernetesClusterName, KubernetesDurationValue, KubernetesEvictionHardKey, KubernetesLabelKey,
+    KubernetesLabelValue, KubernetesQuantityValue, KubernetesReservedResourceKey,
+    KubernetesTaintValue, KubernetesThresholdValue, Lockdown, MaintenanceWindow,
+    MetricsListenAddress, OciDefaultsCapability, OciDefaultsResourceLimitType,
+    PemCertificateString, Sha256Digest, SingleLineString, SysctlKey, TimezoneName,
+    TopologyManagerPolicy, TopologyManagerScope, Url, ValidBase64, ValidLinuxHostname,
 };
 
 // Kubernetes static pod manifest settings
@@ -204,6 +213,12 @@ use crate::modeled_types::{
 struct StaticPod {
     enabled: bool,
     manifest: ValidBase64,
+    // Fetched instead of giving `manifest`; its content must match `manifest-sha256`.
+    manifest_url: Url,
+    manifest_sha256: Sha256Digest,
+    // Renders the manifest as a template, so it can refer to settings like
+    // {{settings.kubernetes.node-ip}}.
+    template: bool,
 }
 
 // Kubernetes related settings. The dynamic settings are retrieved from
@@ -319,6 +334,28 @@ struct UpdatesSettings {
     // Version to update to when updating via the API.
     version_lock: FriendlyVersion,
     ignore_waves: bool,
+    health_check: UpdateHealthCheck,
+    maintenance: UpdateMaintenance,
+}
+
+// Checks that must pass within the timeout on the first boot of an update before the boot is
+// marked successful.  If they don't, the host rolls back to the previous version and reboots.
+#[model]
+struct UpdateHealthCheck {
+    // systemd units that must be active.
+    units: Vec<SingleLineString>,
+    // URLs that must respond to a GET with a success status.
+    http_probes: Vec<Url>,
+    timeout_seconds: u32,
+}
+
+// Recurring times when updates may be applied and the host rebooted into them.  Updates can be
+// applied at any time if there are no windows.
+#[model]
+struct UpdateMaintenance {
+    windows: Vec<MaintenanceWindow>,
+    // Timezone for the window times; UTC if not set.
+    timezone: TimezoneName,
 }
 
 #[model]
@@ -327,6 +364,19 @@ struct HostContainer {
     enabled: bool,
     superpowered: bool,
     user_data: ValidBase64,
+    // When systemd restarts the container after it exits; "always" if not set.
+    restart_policy: HostContainerRestartPolicy,
+    cpu_limit: HostContainerCpuLimit,
+    memory_limit: HostContainerMemoryLimit,
+    // Host paths mounted read-only in the container.
+    mounts: Vec<HostContainerMount>,
+    environment: HashMap<EnvironmentVariableName, SingleLineString>,
+}
+
+#[model]
+struct HostContainerMount {
+    source: HostContainerMountPath,
+    destination: HostContainerMountPath,
 }
 
 // Network settings. These settings will affect host service components' network behavior
@@ -402,6 +452,8 @@ struct MetricsSettings {
     metrics_url: Url,
     send_metrics: bool,
     service_checks: Vec<String>,
+    // Where metricdog serves metrics for local scrapers; it doesn't if this isn't set.
+    exporter_listen_address: MetricsListenAddress,
 }
 
 // CloudFormation settings
@@ -469,6 +521,12 @@ struct BootstrapContainer {
     mode: BootstrapContainerMode,
     user_data: ValidBase64,
     essential: bool,
+    // Names of other bootstrap containers that must finish before this one starts.
+    after: Vec<Identifier>,
+    // How long the container may run before it's stopped and considered failed; no limit if unset.
+    timeout_seconds: u32,
+    // How many times to run the container again if it fails; 0 if unset.
+    retries: u8,
 }
 
 ///// PEM Certificates
@@ -3,6 +3,7 @@ use regex::Regex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 // Just need serde's Error in scope to get its trait methods
 use super::error::{self, big_pattern_error};
+use crate::schema::{self, Schema, Value};
 use scalar::traits::{Scalar, Validate};
 use scalar::ValidationError;
 use scalar_derive::Scalar;
@@ -44,6 +45,12 @@ impl Validate for ECSAttributeKey {
     }
 }
 
+impl Schema for ECSAttributeKey {
+    fn schema() -> Value {
+        schema::pattern(&ECS_ATTRIBUTE_KEY)
+    }
+}
+
 #[cfg(test)]
 mod test_ecs_attribute_key {
     use super::ECSAttributeKey;
@@ -129,6 +136,12 @@ impl TryFrom<&str> for ECSAttributeValue {
 
 string_impls_for!(ECSAttributeValue, "ECSAttributeValue");
 
+impl Schema for ECSAttributeValue {
+    fn schema() -> Value {
+        schema::pattern(&ECS_ATTRIBUTE_VALUE)
+    }
+}
+
 #[cfg(test)]
 mod test_ecs_attribute_value {
     use super::ECSAttributeValue;
@@ -187,6 +200,12 @@ pub enum ECSAgentLogLevel {
     Crit,
 }
 
+impl Schema for ECSAgentLogLevel {
+    fn schema() -> Value {
+        schema::one_of(&["debug", "info", "warn", "error", "crit"])
+    }
+}
+
 #[cfg(test)]
 mod test_ecs_agent_log_level {
     use super::ECSAgentLogLevel;
@@ -226,6 +245,12 @@ impl ECSAgentImagePullBehavior {
     }
 }
 
+impl Schema for ECSAgentImagePullBehavior {
+    fn schema() -> Value {
+        schema::one_of(&["default", "always", "once", "prefer-cached"])
+    }
+}
+
 #[cfg(test)]
 mod test_ecs_agent_image_pull_behavior {
     use super::ECSAgentImagePullBehavior;
@@ -273,6 +298,12 @@ impl TryFrom<&str> for ECSDurationValue {
 
 string_impls_for!(ECSDurationValue, "ECSDurationValue");
 
+impl Schema for ECSDurationValue {
+    fn schema() -> Value {
+        schema::pattern(&ECS_DURATION_VALUE)
+    }
+}
+
 #[cfg(test)]
 mod test_ecs_duration_value {
     use super::ECSDurationValue;
@@ -4,7 +4,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 // Just need serde's Error in scope to get its trait methods
 use super::error;
 use serde::de::Error as _;
-use serde_json::Value;
+use serde_json::{json, Value};
 use snafu::{ensure, ResultExt};
 use std::borrow::Borrow;
 use std::convert::TryFrom;
@@ -13,6 +13,7 @@ use std::fmt::{Display, Formatter};
 use std::net::IpAddr;
 use std::ops::Deref;
 
+use crate::schema::{self, Schema};
 use crate::SingleLineString;
 
 // Declare constant values usable by any type
@@ -51,6 +52,12 @@ impl TryFrom<&str> for KubernetesName {
 
 string_impls_for!(KubernetesName, "KubernetesName");
 
+impl Schema for KubernetesName {
+    fn schema() -> Value {
+        schema::pattern(&KUBERNETES_NAME)
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_name {
     use super::KubernetesName;
@@ -116,6 +123,12 @@ impl TryFrom<&str> for KubernetesLabelKey {
 
 string_impls_for!(KubernetesLabelKey, "KubernetesLabelKey");
 
+impl Schema for KubernetesLabelKey {
+    fn schema() -> Value {
+        schema::pattern(&KUBERNETES_LABEL_KEY)
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_label_key {
     use super::KubernetesLabelKey;
@@ -192,6 +205,12 @@ impl TryFrom<&str> for KubernetesLabelValue {
 
 string_impls_for!(KubernetesLabelValue, "KubernetesLabelValue");
 
+impl Schema for KubernetesLabelValue {
+    fn schema() -> Value {
+        schema::pattern(&KUBERNETES_LABEL_VALUE)
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_label_value {
     use super::KubernetesLabelValue;
@@ -265,6 +284,12 @@ impl TryFrom<&str> for KubernetesTaintValue {
 
 string_impls_for!(KubernetesTaintValue, "KubernetesTaintValue");
 
+impl Schema for KubernetesTaintValue {
+    fn schema() -> Value {
+        schema::pattern(&KUBERNETES_TAINT_VALUE)
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_taint_value {
     use super::KubernetesTaintValue;
@@ -340,6 +365,14 @@ impl TryFrom<&str> for KubernetesClusterName {
 
 string_impls_for!(KubernetesClusterName, "KubernetesClusterName");
 
+impl Schema for KubernetesClusterName {
+    fn schema() -> Value {
+        let mut schema = schema::pattern(&KUBERNETES_LABEL_VALUE);
+        schema["minLength"] = 1.into();
+        schema
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_cluster_name {
     use super::KubernetesClusterName;
@@ -385,6 +418,12 @@ impl TryFrom<&str> for KubernetesAuthenticationMode {
 
 string_impls_for!(KubernetesAuthenticationMode, "KubernetesAuthenticationMode");
 
+impl Schema for KubernetesAuthenticationMode {
+    fn schema() -> Value {
+        schema::one_of(&["aws", "tls"])
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_authentication_mode {
     use super::KubernetesAuthenticationMode;
@@ -440,6 +479,12 @@ impl TryFrom<&str> for KubernetesBootstrapToken {
 
 string_impls_for!(KubernetesBootstrapToken, "KubernetesBootstrapToken");
 
+impl Schema for KubernetesBootstrapToken {
+    fn schema() -> Value {
+        schema::pattern(&KUBERNETES_BOOTSTRAP_TOKEN)
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_bootstrap_token {
     use super::KubernetesBootstrapToken;
@@ -501,6 +546,19 @@ impl TryFrom<&str> for KubernetesEvictionHardKey {
 }
 string_impls_for!(KubernetesEvictionHardKey, "KubernetesEvictionHardKey");
 
+impl Schema for KubernetesEvictionHardKey {
+    fn schema() -> Value {
+        schema::one_of(&[
+            "memory.available",
+            "nodefs.available",
+            "nodefs.inodesFree",
+            "imagefs.available",
+            "imagefs.inodesFree",
+            "pid.available",
+        ])
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_eviction_hard_key {
     use super::KubernetesEvictionHardKey;
@@ -582,6 +640,18 @@ impl TryFrom<&str> for KubernetesThresholdValue {
 }
 string_impls_for!(KubernetesThresholdValue, "KubernetesThresholdValue");
 
+impl Schema for KubernetesThresholdValue {
+    fn schema() -> Value {
+        // A quantity, or a percentage below 100.
+        json!({
+            "anyOf": [
+                schema::pattern(&KUBERNETES_QUANTITY),
+                { "type": "string", "pattern": r"^([0-9]{1,2}(\.[0-9]*)?|\.[0-9]+)%$" },
+            ],
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_threshold_value {
     use super::KubernetesThresholdValue;
@@ -652,6 +722,12 @@ string_impls_for!(
     "KubernetesReservedResourceKey"
 );
 
+impl Schema for KubernetesReservedResourceKey {
+    fn schema() -> Value {
+        schema::one_of(&["cpu", "memory", "ephemeral-storage"])
+    }
+}
+
 #[cfg(test)]
 mod test_reserved_resources_key {
     use super::KubernetesReservedResourceKey;
@@ -702,6 +778,12 @@ impl TryFrom<&str> for KubernetesQuantityValue {
 }
 string_impls_for!(KubernetesQuantityValue, "KubernetesQuantityValue");
 
+impl Schema for KubernetesQuantityValue {
+    fn schema() -> Value {
+        schema::pattern(&KUBERNETES_QUANTITY)
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_quantity_value {
     use super::KubernetesQuantityValue;
@@ -763,6 +845,13 @@ impl TryFrom<&str> for KubernetesCloudProvider {
 
 string_impls_for!(KubernetesCloudProvider, "KubernetesCloudProvider");
 
+impl Schema for KubernetesCloudProvider {
+    fn schema() -> Value {
+        // An empty value is stored as a pair of quotes, which is also accepted.
+        schema::one_of(&["aws", "external", "", "\"\""])
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_cloud_provider {
     use super::KubernetesCloudProvider;
@@ -812,6 +901,12 @@ impl TryFrom<&str> for CpuManagerPolicy {
 }
 string_impls_for!(CpuManagerPolicy, "CpuManagerPolicy");
 
+impl Schema for CpuManagerPolicy {
+    fn schema() -> Value {
+        schema::one_of(&["static", "none"])
+    }
+}
+
 #[cfg(test)]
 mod test_cpu_manager_policy {
     use super::CpuManagerPolicy;
@@ -867,6 +962,14 @@ impl TryFrom<&str> for KubernetesDurationValue {
 
 string_impls_for!(KubernetesDurationValue, "KubernetesDurationValue");
 
+impl Schema for KubernetesDurationValue {
+    fn schema() -> Value {
+        let mut schema = schema::pattern(&KUBERNETES_DURATION_VALUE);
+        schema["minLength"] = 1.into();
+        schema
+    }
+}
+
 #[cfg(test)]
 mod test_kubernetes_duration_value {
     use super::KubernetesDurationValue;
@@ -934,6 +1037,12 @@ impl TryFrom<&str> for TopologyManagerScope {
 }
 string_impls_for!(TopologyManagerScope, "TopologyManagerScope");
 
+impl Schema for TopologyManagerScope {
+    fn schema() -> Value {
+        schema::one_of(&["container", "pod"])
+    }
+}
+
 #[cfg(test)]
 mod test_topology_manager_scope {
     use super::TopologyManagerScope;
@@ -987,6 +1096,12 @@ impl TryFrom<&str> for TopologyManagerPolicy {
 }
 string_impls_for!(TopologyManagerPolicy, "TopologyManagerPolicy");
 
+impl Schema for TopologyManagerPolicy {
+    fn schema() -> Value {
+        schema::one_of(&["none", "restricted", "best-effort", "single-numa-node"])
+    }
+}
+
 #[cfg(test)]
 mod test_topology_manager_policy {
     use super::TopologyManagerPolicy;
@@ -1059,6 +1174,22 @@ impl Display for IntegerPercent {
     }
 }
 
+impl Schema for IntegerPercent {
+    fn schema() -> Value {
+        // Either a number or a string containing one.
+        json!({
+            "oneOf": [
+                {
+                    "type": "integer",
+                    "minimum": IMAGE_GC_THRESHOLD_MIN,
+                    "maximum": IMAGE_GC_THRESHOLD_MAX,
+                },
+                { "type": "string", "pattern": r"^\+?0*([0-9]|[1-9][0-9]|100)$" },
+            ],
+        })
+    }
+}
+
 impl Serialize for IntegerPercent {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -1207,6 +1338,14 @@ pub enum KubernetesClusterDnsIp {
     Vector(Vec<IpAddr>),
 }
 
+impl Schema for KubernetesClusterDnsIp {
+    fn schema() -> Value {
+        json!({
+            "anyOf": [IpAddr::schema(), Vec::<IpAddr>::schema()],
+        })
+    }
+}
+
 impl KubernetesClusterDnsIp {
     pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a IpAddr> + 'a> {
         match self {
@@ -1324,3 +1463,18 @@ pub struct CredentialProvider {
     image_patterns: Vec<SingleLineString>,
     cache_duration: Option<KubernetesDurationValue>,
 }
+
+impl Schema for CredentialProvider {
+    fn schema() -> Value {
+        // Unlike models, this doesn't deny unknown fields.
+        json!({
+            "type": "object",
+            "properties": {
+                "enabled": bool::schema(),
+                "image-patterns": Vec::<SingleLineString>::schema(),
+                "cache-duration": KubernetesDurationValue::schema(),
+            },
+            "required": ["enabled", "image-patterns"],
+        })
+    }
+}
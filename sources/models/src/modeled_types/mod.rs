@@ -86,6 +86,24 @@ pub mod error {
         #[snafu(display("Invalid Linux lockdown mode '{}'", input))]
         InvalidLockdown { input: String },
 
+        #[snafu(display("Invalid maintenance window '{}': start and end must differ", input))]
+        InvalidMaintenanceWindow { input: String },
+
+        #[snafu(display("Invalid host container restart policy '{}'", input))]
+        InvalidHostContainerRestartPolicy { input: String },
+
+        #[snafu(display("Invalid host container CPU limit '{}': {}", input, msg))]
+        InvalidHostContainerCpuLimit { input: String, msg: String },
+
+        #[snafu(display("Invalid host container memory limit '{}': {}", input, msg))]
+        InvalidHostContainerMemoryLimit { input: String, msg: String },
+
+        #[snafu(display("Invalid host container mount path '{}': {}", input, msg))]
+        InvalidHostContainerMountPath { input: String, msg: String },
+
+        #[snafu(display("Invalid metrics listen address '{}': {}", input, msg))]
+        InvalidMetricsListenAddress { input: String, msg: String },
+
         #[snafu(display("Invalid sysctl key '{}': {}", input, msg))]
         InvalidSysctlKey { input: String, msg: String },
 
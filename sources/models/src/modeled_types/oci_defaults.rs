@@ -1,3 +1,4 @@
+use crate::schema::{self, Schema, Value};
 use scalar_derive::Scalar;
 use serde::{Deserialize, Serialize};
 
@@ -57,6 +58,54 @@ impl OciDefaultsCapability {
     }
 }
 
+impl Schema for OciDefaultsCapability {
+    fn schema() -> Value {
+        schema::one_of(&[
+            "audit-control",
+            "audit-read",
+            "audit-write",
+            "block-suspend",
+            "bpf",
+            "checkpoint-restore",
+            "chown",
+            "dac-override",
+            "dac-read-search",
+            "fowner",
+            "fsetid",
+            "ipc-lock",
+            "ipc-owner",
+            "kill",
+            "lease",
+            "linux-immutable",
+            "mac-admin",
+            "mac-override",
+            "mknod",
+            "net-admin",
+            "net-bind-service",
+            "net-broadcast",
+            "net-raw",
+            "perfmon",
+            "setgid",
+            "setfcap",
+            "setpcap",
+            "setuid",
+            "sys-admin",
+            "sys-boot",
+            "sys-chroot",
+            "sys-module",
+            "sys-nice",
+            "sys-pacct",
+            "sys-ptrace",
+            "sys-rawio",
+            "sys-resource",
+            "sys-time",
+            "sys-tty-config",
+            "syslog",
+            "wake-alarm",
+        ])
+    }
+}
+
 #[cfg(test)]
 mod oci_defaults_capabilities {
     use super::*;
@@ -84,6 +133,16 @@ mod oci_defaults_capabilities {
 
         check_capability_strings(OciDefaultsCapability::Mknod, "mknod", "CAP_MKNOD");
     }
+
+    #[test]
+    fn schema_values_are_capabilities() {
+        let schema = OciDefaultsCapability::schema();
+        let values = schema["enum"].as_array().unwrap();
+        assert_eq!(values.len(), 41);
+        for value in values {
+            serde_json::from_value::<OciDefaultsCapability>(value.clone()).unwrap();
+        }
+    }
 }
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
@@ -107,6 +166,12 @@ impl OciDefaultsResourceLimitType {
     }
 }
 
+impl Schema for OciDefaultsResourceLimitType {
+    fn schema() -> Value {
+        schema::one_of(&["max-open-files"])
+    }
+}
+
 #[cfg(test)]
 mod oci_defaults_rlimits {
     use super::*;
@@ -1,14 +1,16 @@
 use super::error;
+use crate::schema::{self, Schema, Value};
 use lazy_static::lazy_static;
 use regex::Regex;
 use semver::Version;
 use serde::de::Error as _;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use snafu::{ensure, ResultExt};
+use serde_json::json;
+use snafu::{ensure, OptionExt, ResultExt};
 use std::borrow::Borrow;
 use std::convert::TryFrom;
 use std::fmt;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
 use std::str::FromStr;
 use url::Host;
@@ -38,6 +40,15 @@ impl TryFrom<&str> for ValidBase64 {
 
 string_impls_for!(ValidBase64, "ValidBase64");
 
+impl Schema for ValidBase64 {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "contentEncoding": "base64",
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_valid_base64 {
     use super::ValidBase64;
@@ -98,6 +109,15 @@ impl TryFrom<&str> for SingleLineString {
 
 string_impls_for!(SingleLineString, "SingleLineString");
 
+impl Schema for SingleLineString {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "pattern": r"^[^\n\r\u000B\u000C\u0085\u2028\u2029]*$",
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_single_line_string {
     use super::SingleLineString;
@@ -188,6 +208,15 @@ impl TryFrom<&str> for ValidLinuxHostname {
 
 string_impls_for!(ValidLinuxHostname, "ValidLinuxHostname");
 
+impl Schema for ValidLinuxHostname {
+    fn schema() -> Value {
+        let mut schema = schema::pattern(&VALID_LINUX_HOSTNAME);
+        schema["format"] = "hostname".into();
+        schema["not"] = json!({ "pattern": r"^[-.]|\.\.|\.-|[^.]{64}" });
+        schema
+    }
+}
+
 #[cfg(test)]
 mod test_valid_linux_hostname {
     use super::ValidLinuxHostname;
@@ -241,6 +270,12 @@ pub struct EtcHostsEntries(
     Vec<(IpAddr, Vec<ValidLinuxHostname>)>,
 );
 
+impl Schema for EtcHostsEntries {
+    fn schema() -> Value {
+        Vec::<(IpAddr, Vec<ValidLinuxHostname>)>::schema()
+    }
+}
+
 impl EtcHostsEntries {
     pub fn iter_merged(&self) -> impl Iterator<Item = (IpAddr, Vec<ValidLinuxHostname>)> {
         let mut merged: indexmap::IndexMap<IpAddr, Vec<ValidLinuxHostname>> =
@@ -404,6 +439,15 @@ impl TryFrom<&str> for Identifier {
 
 string_impls_for!(Identifier, "Identifier");
 
+impl Schema for Identifier {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "pattern": format!("^[a-zA-Z0-9-]{{0,{}}}$", CONTAINERD_ID_LENGTH),
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_valid_identifier {
     use super::{Identifier, CONTAINERD_ID_LENGTH};
@@ -468,6 +512,16 @@ impl TryFrom<&str> for Url {
 
 string_impls_for!(Url, "Url");
 
+impl Schema for Url {
+    fn schema() -> Value {
+        // URLs without a scheme are accepted, so this is a reference rather than a full URI.
+        json!({
+            "type": "string",
+            "format": "uri-reference",
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_url {
     use super::Url;
@@ -555,6 +609,15 @@ impl TryFrom<FriendlyVersion> for semver::Version {
 
 string_impls_for!(FriendlyVersion, "FriendlyVersion");
 
+impl Schema for FriendlyVersion {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "pattern": r"^(latest|v?(0|[1-9][0-9]*)\.(0|[1-9][0-9]*)\.(0|[1-9][0-9]*)(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?)$",
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_version {
     use super::FriendlyVersion;
@@ -654,6 +717,16 @@ impl TryFrom<&str> for DNSDomain {
 
 string_impls_for!(DNSDomain, "DNSDomain");
 
+impl Schema for DNSDomain {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "format": "hostname",
+            "not": { "pattern": r"^\." },
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_dns_domain {
     use super::DNSDomain;
@@ -729,6 +802,14 @@ impl TryFrom<&str> for SysctlKey {
 
 string_impls_for!(SysctlKey, "SysctlKey");
 
+impl Schema for SysctlKey {
+    fn schema() -> Value {
+        let mut schema = schema::pattern(&SYSCTL_KEY);
+        schema["not"] = json!({ "pattern": r"^[./]|\.\." });
+        schema
+    }
+}
+
 #[cfg(test)]
 mod test_sysctl_key {
     use super::SysctlKey;
@@ -820,6 +901,15 @@ impl TryFrom<&str> for BootConfigKey {
 
 string_impls_for!(BootConfigKey, "BootConfigKey");
 
+impl Schema for BootConfigKey {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "pattern": r"^[a-zA-Z0-9_-]+(\.[a-zA-Z0-9_-]+)*$",
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_bootconfig_key {
     use super::BootConfigKey;
@@ -884,6 +974,17 @@ impl TryFrom<&str> for BootConfigValue {
 
 string_impls_for!(BootConfigValue, "BootConfigValue");
 
+impl Schema for BootConfigValue {
+    fn schema() -> Value {
+        // Printable ASCII, without both kinds of quotes.
+        json!({
+            "type": "string",
+            "pattern": r"^[\x20-\x7E]*$",
+            "not": { "pattern": r#"^(?=.*")(?=.*')"# },
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_bootconfig_value {
     use super::BootConfigValue;
@@ -938,6 +1039,12 @@ impl TryFrom<&str> for Lockdown {
 
 string_impls_for!(Lockdown, "Lockdown");
 
+impl Schema for Lockdown {
+    fn schema() -> Value {
+        schema::one_of(&["none", "integrity", "confidentiality"])
+    }
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -969,6 +1076,12 @@ impl Default for BootstrapContainerMode {
 
 string_impls_for!(BootstrapContainerMode, "BootstrapContainerMode");
 
+impl Schema for BootstrapContainerMode {
+    fn schema() -> Value {
+        schema::one_of(&["off", "once", "always"])
+    }
+}
+
 #[cfg(test)]
 mod test_valid_container_mode {
     use super::BootstrapContainerMode;
@@ -1036,6 +1149,16 @@ impl Default for PemCertificateString {
 
 string_impls_for!(PemCertificateString, "PemCertificateString");
 
+impl Schema for PemCertificateString {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "contentEncoding": "base64",
+            "contentMediaType": "application/x-pem-file",
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_valid_pem_certificate_string {
     use super::PemCertificateString;
@@ -1105,6 +1228,15 @@ impl TryFrom<&str> for KmodKey {
 
 string_impls_for!(KmodKey, "KmodKey");
 
+impl Schema for KmodKey {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "pattern": format!("^[a-zA-Z0-9_-]{{0,{}}}$", KMOD_KEY_LENGTH),
+        })
+    }
+}
+
 #[cfg(test)]
 mod test_valid_kmod_key {
     use super::{KmodKey, KMOD_KEY_LENGTH};
@@ -1130,3 +1262,792 @@ mod test_valid_kmod_key {
         assert!(KmodKey::try_from(vec!["z"; KMOD_KEY_LENGTH + 1].join("")).is_err());
     }
 }
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// MaintenanceWindow represents a recurring time when updates may be applied, written as days and
+/// a time range, like "Mon-Fri 02:00-05:00" or "* 22:00-02:00".  Days are '*', or a
+/// comma-separated list of three-letter day names and ranges.  A window whose end isn't after its
+/// start continues into the next day.  MaintenanceWindow stores the original string and makes it
+/// accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MaintenanceWindow {
+    inner: String,
+}
+
+lazy_static! {
+    pub(crate) static ref MAINTENANCE_WINDOW: Regex = {
+        let day = "(Mon|Tue|Wed|Thu|Fri|Sat|Sun)";
+        let days = format!(r"(\*|{day}(-{day})?(,{day}(-{day})?)*)");
+        let time = "([01][0-9]|2[0-3]):[0-5][0-9]";
+        Regex::new(&format!(
+            r"^{days} (?P<start>{time})-(?P<end>{time}|24:00)$"
+        ))
+        .unwrap()
+    };
+}
+
+impl TryFrom<&str> for MaintenanceWindow {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let captures = MAINTENANCE_WINDOW
+            .captures(input)
+            .context(error::PatternSnafu {
+                thing: "Maintenance window",
+                pattern: MAINTENANCE_WINDOW.clone(),
+                input,
+            })?;
+        ensure!(
+            captures["start"] != captures["end"],
+            error::InvalidMaintenanceWindowSnafu { input }
+        );
+        Ok(MaintenanceWindow {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(MaintenanceWindow, "MaintenanceWindow");
+
+impl Schema for MaintenanceWindow {
+    fn schema() -> Value {
+        schema::pattern(&MAINTENANCE_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod test_maintenance_window {
+    use super::MaintenanceWindow;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_maintenance_window() {
+        for ok in &[
+            "Mon-Fri 02:00-05:00",
+            "Sat,Sun 00:00-24:00",
+            "Mon-Wed,Fri,Sat-Sun 23:59-00:00",
+            "* 22:00-02:00",
+        ] {
+            MaintenanceWindow::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_maintenance_window() {
+        for err in &[
+            "",
+            "02:00-05:00",
+            "Mon 02:00-02:00",
+            "Mon 2:00-05:00",
+            "Mon 02:00-24:01",
+            "Mon 24:00-02:00",
+            "Monday 02:00-05:00",
+            "mon 02:00-05:00",
+            "Mon- 02:00-05:00",
+            "Mon,* 02:00-05:00",
+            "Mon  02:00-05:00",
+        ] {
+            MaintenanceWindow::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// TimezoneName represents a string that looks like the name of a timezone in the tz database,
+/// like "America/New_York" or "UTC".  We don't carry the database here, so users of the name may
+/// still find it's unknown.  TimezoneName stores the original string and makes it accessible
+/// through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TimezoneName {
+    inner: String,
+}
+
+lazy_static! {
+    pub(crate) static ref TIMEZONE_NAME: Regex =
+        Regex::new(r"^[A-Za-z][A-Za-z0-9_+-]{0,31}(/[A-Za-z0-9_+-]{1,32}){0,2}$").unwrap();
+}
+
+impl TryFrom<&str> for TimezoneName {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        ensure!(
+            TIMEZONE_NAME.is_match(input),
+            error::PatternSnafu {
+                thing: "Timezone name",
+                pattern: TIMEZONE_NAME.clone(),
+                input,
+            }
+        );
+        Ok(TimezoneName {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(TimezoneName, "TimezoneName");
+
+impl Schema for TimezoneName {
+    fn schema() -> Value {
+        schema::pattern(&TIMEZONE_NAME)
+    }
+}
+
+#[cfg(test)]
+mod test_timezone_name {
+    use super::TimezoneName;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_timezone_name() {
+        for ok in &[
+            "UTC",
+            "America/New_York",
+            "America/Argentina/Buenos_Aires",
+            "Etc/GMT+5",
+            "America/Port-au-Prince",
+        ] {
+            TimezoneName::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_timezone_name() {
+        for err in &["", "/UTC", "America/", "../etc/passwd", "UTC ", "a/b/c/d"] {
+            TimezoneName::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// HostContainerRestartPolicy represents when systemd restarts a host container after it exits:
+/// "always", "on-failure", or "no".  It stores the original string and makes it accessible
+/// through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct HostContainerRestartPolicy {
+    inner: String,
+}
+
+impl TryFrom<&str> for HostContainerRestartPolicy {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        ensure!(
+            matches!(input, "always" | "on-failure" | "no"),
+            error::InvalidHostContainerRestartPolicySnafu { input }
+        );
+        Ok(HostContainerRestartPolicy {
+            inner: input.to_string(),
+        })
+    }
+}
+
+/// Host containers have always been restarted whenever they exit.
+impl Default for HostContainerRestartPolicy {
+    fn default() -> Self {
+        HostContainerRestartPolicy {
+            inner: "always".to_string(),
+        }
+    }
+}
+
+string_impls_for!(HostContainerRestartPolicy, "HostContainerRestartPolicy");
+
+impl Schema for HostContainerRestartPolicy {
+    fn schema() -> Value {
+        schema::one_of(&["always", "on-failure", "no"])
+    }
+}
+
+#[cfg(test)]
+mod test_host_container_restart_policy {
+    use super::HostContainerRestartPolicy;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_restart_policy() {
+        for ok in &["always", "on-failure", "no"] {
+            HostContainerRestartPolicy::try_from(*ok).unwrap();
+        }
+        assert_eq!(&*HostContainerRestartPolicy::default(), "always");
+    }
+
+    #[test]
+    fn invalid_restart_policy() {
+        for err in &["", "never", "on-abnormal", "Always"] {
+            HostContainerRestartPolicy::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// HostContainerCpuLimit represents the number of CPUs a host container may use, like "0.5" or
+/// "2", with up to three decimal places.  It stores the original string and makes it accessible
+/// through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct HostContainerCpuLimit {
+    inner: String,
+    millicpus: u64,
+}
+
+lazy_static! {
+    pub(crate) static ref HOST_CONTAINER_CPU_LIMIT: Regex =
+        Regex::new(r"^(?P<whole>[0-9]{1,4})(\.(?P<fraction>[0-9]{1,3}))?$").unwrap();
+}
+
+/// The CFS period that CPU limits are measured against, in microseconds.
+pub const HOST_CONTAINER_CPU_PERIOD: u64 = 100_000;
+/// The kernel doesn't accept a CFS quota under 1ms, which is a hundredth of a CPU in our period.
+const HOST_CONTAINER_MIN_MILLICPUS: u64 = 10;
+
+impl HostContainerCpuLimit {
+    /// Returns the CPU time, in microseconds, the container may use in each CFS period of
+    /// `HOST_CONTAINER_CPU_PERIOD` microseconds.
+    pub fn cpu_quota(&self) -> u64 {
+        self.millicpus * HOST_CONTAINER_CPU_PERIOD / 1000
+    }
+}
+
+impl TryFrom<&str> for HostContainerCpuLimit {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let captures = HOST_CONTAINER_CPU_LIMIT
+            .captures(input)
+            .context(error::PatternSnafu {
+                thing: "Host container CPU limit",
+                pattern: HOST_CONTAINER_CPU_LIMIT.clone(),
+                input,
+            })?;
+        // The regex limits the digits, so these fit.
+        let whole: u64 = captures["whole"].parse().unwrap_or_default();
+        let fraction: u64 = captures
+            .name("fraction")
+            .map(|m| format!("{:0<3}", m.as_str()).parse().unwrap_or_default())
+            .unwrap_or(0);
+        let millicpus = whole * 1000 + fraction;
+        ensure!(
+            millicpus >= HOST_CONTAINER_MIN_MILLICPUS,
+            error::InvalidHostContainerCpuLimitSnafu {
+                input,
+                msg: "must be at least 0.01",
+            }
+        );
+        Ok(HostContainerCpuLimit {
+            inner: input.to_string(),
+            millicpus,
+        })
+    }
+}
+
+string_impls_for!(HostContainerCpuLimit, "HostContainerCpuLimit");
+
+impl Schema for HostContainerCpuLimit {
+    fn schema() -> Value {
+        schema::pattern(&HOST_CONTAINER_CPU_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod test_host_container_cpu_limit {
+    use super::HostContainerCpuLimit;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_cpu_limit() {
+        for (ok, quota) in &[
+            ("1", 100_000),
+            ("0.5", 50_000),
+            ("0.25", 25_000),
+            ("0.01", 1_000),
+            ("2.125", 212_500),
+            ("16", 1_600_000),
+        ] {
+            assert_eq!(
+                HostContainerCpuLimit::try_from(*ok).unwrap().cpu_quota(),
+                *quota
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_cpu_limit() {
+        for err in &[
+            "", "0", "0.0", "0.001", "-1", ".5", "1.", "1.2345", "500m", "12345",
+        ] {
+            HostContainerCpuLimit::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// HostContainerMemoryLimit represents the amount of memory a host container may use, as a number
+/// of bytes with an optional decimal (K, M, G, T) or binary (Ki, Mi, Gi, Ti) suffix, like "512Mi".
+/// It stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct HostContainerMemoryLimit {
+    inner: String,
+    bytes: u64,
+}
+
+lazy_static! {
+    pub(crate) static ref HOST_CONTAINER_MEMORY_LIMIT: Regex =
+        Regex::new(r"^(?P<number>[1-9][0-9]{0,14})(?P<suffix>[KMGT]i?)?$").unwrap();
+}
+
+/// runc refuses memory limits under 6MiB, since a container can't start with less.
+const HOST_CONTAINER_MIN_MEMORY_BYTES: u64 = 6 * 1024 * 1024;
+
+impl HostContainerMemoryLimit {
+    /// Returns the memory limit in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl TryFrom<&str> for HostContainerMemoryLimit {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let captures =
+            HOST_CONTAINER_MEMORY_LIMIT
+                .captures(input)
+                .context(error::PatternSnafu {
+                    thing: "Host container memory limit",
+                    pattern: HOST_CONTAINER_MEMORY_LIMIT.clone(),
+                    input,
+                })?;
+        // The regex limits the digits, so this fits.
+        let number: u64 = captures["number"].parse().unwrap_or_default();
+        let multiplier: u64 = match captures.name("suffix").map(|m| m.as_str()) {
+            None => 1,
+            Some("K") => 1000,
+            Some("M") => 1000_u64.pow(2),
+            Some("G") => 1000_u64.pow(3),
+            Some("T") => 1000_u64.pow(4),
+            Some("Ki") => 1024,
+            Some("Mi") => 1024_u64.pow(2),
+            Some("Gi") => 1024_u64.pow(3),
+            Some("Ti") => 1024_u64.pow(4),
+            Some(_) => unreachable!("suffix not allowed by regex"),
+        };
+        let bytes = number.checked_mul(multiplier).context(
+            error::InvalidHostContainerMemoryLimitSnafu {
+                input,
+                msg: "too large",
+            },
+        )?;
+        ensure!(
+            bytes >= HOST_CONTAINER_MIN_MEMORY_BYTES,
+            error::InvalidHostContainerMemoryLimitSnafu {
+                input,
+                msg: "must be at least 6Mi",
+            }
+        );
+        Ok(HostContainerMemoryLimit {
+            inner: input.to_string(),
+            bytes,
+        })
+    }
+}
+
+string_impls_for!(HostContainerMemoryLimit, "HostContainerMemoryLimit");
+
+impl Schema for HostContainerMemoryLimit {
+    fn schema() -> Value {
+        schema::pattern(&HOST_CONTAINER_MEMORY_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod test_host_container_memory_limit {
+    use super::HostContainerMemoryLimit;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_memory_limit() {
+        for (ok, bytes) in &[
+            ("6291456", 6_291_456),
+            ("6Mi", 6_291_456),
+            ("512Mi", 536_870_912),
+            ("100M", 100_000_000),
+            ("2Gi", 2_147_483_648),
+            ("1T", 1_000_000_000_000),
+            ("8192Ki", 8_388_608),
+        ] {
+            assert_eq!(
+                HostContainerMemoryLimit::try_from(*ok).unwrap().bytes(),
+                *bytes
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_memory_limit() {
+        for err in &[
+            "",
+            "0",
+            "5Mi",
+            "1024",
+            "512mi",
+            "512MB",
+            "1.5Gi",
+            "-1Gi",
+            "0512Mi",
+            "999999999999999Ti",
+        ] {
+            HostContainerMemoryLimit::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// HostContainerMountPath represents an absolute path used as the source or destination of a
+/// host container mount.  It may not be "/", or contain "." or ".." components, so it's clear
+/// what's being mounted where.  It stores the original string and makes it accessible through
+/// standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct HostContainerMountPath {
+    inner: String,
+}
+
+/// The kernel's PATH_MAX, including the trailing null.
+const HOST_CONTAINER_MOUNT_PATH_MAX: usize = 4095;
+
+impl TryFrom<&str> for HostContainerMountPath {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        ensure!(
+            input.starts_with('/'),
+            error::InvalidHostContainerMountPathSnafu {
+                input,
+                msg: "must be an absolute path",
+            }
+        );
+        ensure!(
+            input.len() <= HOST_CONTAINER_MOUNT_PATH_MAX,
+            error::InvalidHostContainerMountPathSnafu {
+                input,
+                msg: format!("must be at most {} bytes", HOST_CONTAINER_MOUNT_PATH_MAX),
+            }
+        );
+        ensure!(
+            !input.chars().any(|c| c.is_control()),
+            error::InvalidHostContainerMountPathSnafu {
+                input,
+                msg: "must not contain control characters",
+            }
+        );
+        // Skip the empty component before the leading slash.
+        for component in input.split('/').skip(1) {
+            ensure!(
+                !matches!(component, "" | "." | ".."),
+                error::InvalidHostContainerMountPathSnafu {
+                    input,
+                    msg: "must not be '/', or contain empty, '.', or '..' components",
+                }
+            );
+        }
+        Ok(HostContainerMountPath {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(HostContainerMountPath, "HostContainerMountPath");
+
+impl Schema for HostContainerMountPath {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "pattern": "^/[^/]",
+            "maxLength": HOST_CONTAINER_MOUNT_PATH_MAX,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_host_container_mount_path {
+    use super::HostContainerMountPath;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_mount_path() {
+        for ok in &[
+            "/var/log",
+            "/etc/ssl/certs",
+            "/run/agent.sock",
+            "/opt/..data",
+        ] {
+            HostContainerMountPath::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_mount_path() {
+        for err in &[
+            "",
+            "/",
+            "var/log",
+            "/var/log/",
+            "/var//log",
+            "/var/./log",
+            "/var/log/../../etc",
+            "/var/log\n",
+        ] {
+            HostContainerMountPath::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// EnvironmentVariableName represents a portable environment variable name: ASCII letters,
+/// digits, and underscores, not starting with a digit.  It stores the original string and makes
+/// it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct EnvironmentVariableName {
+    inner: String,
+}
+
+lazy_static! {
+    pub(crate) static ref ENVIRONMENT_VARIABLE_NAME: Regex =
+        Regex::new(r"^[A-Za-z_][A-Za-z0-9_]{0,254}$").unwrap();
+}
+
+impl TryFrom<&str> for EnvironmentVariableName {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        ensure!(
+            ENVIRONMENT_VARIABLE_NAME.is_match(input),
+            error::PatternSnafu {
+                thing: "Environment variable name",
+                pattern: ENVIRONMENT_VARIABLE_NAME.clone(),
+                input,
+            }
+        );
+        Ok(EnvironmentVariableName {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(EnvironmentVariableName, "EnvironmentVariableName");
+
+impl Schema for EnvironmentVariableName {
+    fn schema() -> Value {
+        schema::pattern(&ENVIRONMENT_VARIABLE_NAME)
+    }
+}
+
+#[cfg(test)]
+mod test_environment_variable_name {
+    use super::EnvironmentVariableName;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_environment_variable_name() {
+        for ok in &["LOG_LEVEL", "_private", "agent2", "x"] {
+            EnvironmentVariableName::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_environment_variable_name() {
+        for err in &["", "2FAST", "LOG-LEVEL", "A=B", "PATH ", "NAÏVE"] {
+            EnvironmentVariableName::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// Sha256Digest represents the SHA-256 digest of some content, as 64 hexadecimal characters.  It
+/// stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Sha256Digest {
+    inner: String,
+}
+
+lazy_static! {
+    pub(crate) static ref SHA256_DIGEST: Regex = Regex::new(r"^[0-9a-fA-F]{64}$").unwrap();
+}
+
+impl TryFrom<&str> for Sha256Digest {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        ensure!(
+            SHA256_DIGEST.is_match(input),
+            error::PatternSnafu {
+                thing: "SHA-256 digest",
+                pattern: SHA256_DIGEST.clone(),
+                input,
+            }
+        );
+        Ok(Sha256Digest {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(Sha256Digest, "Sha256Digest");
+
+impl Schema for Sha256Digest {
+    fn schema() -> Value {
+        schema::pattern(&SHA256_DIGEST)
+    }
+}
+
+#[cfg(test)]
+mod test_sha256_digest {
+    use super::Sha256Digest;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_sha256_digest() {
+        for ok in &[
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+        ] {
+            Sha256Digest::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_sha256_digest() {
+        for err in &[
+            "",
+            "e3b0c44298fc1c149afbf4c8996fb924",
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "g3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8550",
+        ] {
+            Sha256Digest::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// MetricsListenAddress represents where the metrics exporter listens: either the absolute path of
+/// a Unix-domain socket, like "/run/metricdog/metrics.sock", or a loopback IP address and port,
+/// like "127.0.0.1:9100" or "[::1]:9100".  Metrics aren't authenticated, so they're only served to
+/// local scrapers.  It stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MetricsListenAddress {
+    inner: String,
+}
+
+/// The size of sun_path in sockaddr_un, less the trailing null.
+const METRICS_LISTEN_SOCKET_PATH_MAX: usize = 107;
+
+impl TryFrom<&str> for MetricsListenAddress {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        if input.starts_with('/') {
+            ensure!(
+                input.len() <= METRICS_LISTEN_SOCKET_PATH_MAX,
+                error::InvalidMetricsListenAddressSnafu {
+                    input,
+                    msg: format!(
+                        "socket path must be at most {} bytes",
+                        METRICS_LISTEN_SOCKET_PATH_MAX
+                    ),
+                }
+            );
+            ensure!(
+                !input.ends_with('/') && !input.chars().any(|c| c.is_control()),
+                error::InvalidMetricsListenAddressSnafu {
+                    input,
+                    msg: "socket path must name a file, and not contain control characters",
+                }
+            );
+        } else {
+            let address = input.parse::<SocketAddr>().ok().context(
+                error::InvalidMetricsListenAddressSnafu {
+                    input,
+                    msg: "must be an absolute socket path, or an IP address and port",
+                },
+            )?;
+            ensure!(
+                address.ip().is_loopback(),
+                error::InvalidMetricsListenAddressSnafu {
+                    input,
+                    msg: "IP address must be a loopback address, like 127.0.0.1 or ::1",
+                }
+            );
+            ensure!(
+                address.port() != 0,
+                error::InvalidMetricsListenAddressSnafu {
+                    input,
+                    msg: "port must not be 0",
+                }
+            );
+        }
+        Ok(MetricsListenAddress {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(MetricsListenAddress, "MetricsListenAddress");
+
+impl Schema for MetricsListenAddress {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "minLength": 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_metrics_listen_address {
+    use super::MetricsListenAddress;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_metrics_listen_address() {
+        for ok in &[
+            "/run/metricdog/metrics.sock",
+            "127.0.0.1:9100",
+            "127.0.0.53:9100",
+            "[::1]:9100",
+        ] {
+            MetricsListenAddress::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_metrics_listen_address() {
+        let long_path = format!("/run/{}", "a".repeat(103));
+        for err in &[
+            "",
+            "/run/metricdog/",
+            "/run/metric\ndog.sock",
+            long_path.as_str(),
+            "run/metricdog.sock",
+            "localhost:9100",
+            "127.0.0.1",
+            "127.0.0.1:0",
+            "::1:9100",
+            "0.0.0.0:9100",
+            "10.0.0.1:9100",
+            "[::]:9100",
+            "[fe80::1]:9100",
+            "[::ffff:127.0.0.1]:9100",
+        ] {
+            MetricsListenAddress::try_from(*err).unwrap_err();
+        }
+    }
+}
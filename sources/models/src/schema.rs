@@ -0,0 +1,283 @@
+//! This module describes the model as a [JSON Schema](https://json-schema.org/) document, so
+//! clients can find out which settings exist and what values they accept without hard-coding it.
+//!
+//! Structs marked with `#[model]` get a `Schema` implementation generated by `model-derive`.
+//! Standard types have implementations here, and modeled types implement it next to their
+//! validation code, describing their checks with JSON Schema keywords like `pattern`, `enum`,
+//! and `minimum`/`maximum`.  Where a check can't be expressed exactly, like parsing a URL, the
+//! schema uses the closest `format` and the API remains the final authority.
+
+use bottlerocket_release::BottlerocketRelease;
+use serde_json::{json, Map};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::Settings;
+
+pub use serde_json::Value;
+
+/// The JSON Schema dialect used for the documents we generate.
+pub const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Types that can describe the values they accept as a JSON Schema.
+pub trait Schema {
+    fn schema() -> Value;
+}
+
+/// Returns the JSON Schema document for the current variant's settings.
+pub fn settings() -> Value {
+    let mut schema = Settings::schema();
+    if let Value::Object(map) = &mut schema {
+        map.insert("$schema".to_string(), DIALECT.into());
+        map.insert("title".to_string(), "settings".into());
+    }
+    schema
+}
+
+/// Builds the schema of an object with the given properties, which doesn't allow any others.
+/// This is used by the `Schema` implementations generated for `#[model]` structs.
+pub fn object(properties: Vec<(&str, Value)>, required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    let mut schema = json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+/// The schema of a string matching the given regular expression.
+pub(crate) fn pattern(regex: &regex::Regex) -> Value {
+    json!({
+        "type": "string",
+        "pattern": ecma_pattern(regex.as_str()),
+    })
+}
+
+/// The schema of a string that must be one of the given values.
+pub(crate) fn one_of(values: &[&str]) -> Value {
+    json!({
+        "type": "string",
+        "enum": values,
+    })
+}
+
+/// JSON Schema patterns use ECMA-262 regular expression syntax.  The patterns of our modeled types
+/// are written for the `regex` crate, often in verbose mode with whitespace and comments, so this
+/// converts the parts of that syntax we use into their ECMA-262 equivalents.
+pub(crate) fn ecma_pattern(pattern: &str) -> String {
+    let (verbose, pattern) = match pattern.strip_prefix("(?x)") {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+
+    let mut converted = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                // Escaped whitespace and '#' are only special in verbose mode.
+                Some(escaped) if verbose && (escaped.is_whitespace() || escaped == '#') => {
+                    converted.push(escaped)
+                }
+                Some(escaped) => {
+                    converted.push('\\');
+                    converted.push(escaped);
+                }
+                None => converted.push('\\'),
+            },
+            c if verbose && c.is_whitespace() => {}
+            '#' if verbose => {
+                // Comments run to the end of the line.
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c => converted.push(c),
+        }
+    }
+
+    converted
+        .replace("[:alnum:]", "a-zA-Z0-9")
+        .replace("[:alpha:]", "a-zA-Z")
+        .replace("[:digit:]", "0-9")
+        .replace("(?P<", "(?<")
+}
+
+// Standard types
+
+impl Schema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+macro_rules! integer_schema_for {
+    ($($for:ty),*) => {
+        $(
+            impl Schema for $for {
+                fn schema() -> Value {
+                    json!({
+                        "type": "integer",
+                        "minimum": <$for>::MIN,
+                        "maximum": <$for>::MAX,
+                    })
+                }
+            }
+        )*
+    };
+}
+
+integer_schema_for!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Schema for IpAddr {
+    fn schema() -> Value {
+        json!({
+            "type": "string",
+            "anyOf": [{ "format": "ipv4" }, { "format": "ipv6" }],
+        })
+    }
+}
+
+/// Optional fields accept the same values as their inner type; leaving them out is handled by
+/// not listing them as required.
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({
+            "type": "array",
+            "items": T::schema(),
+        })
+    }
+}
+
+impl<A: Schema, B: Schema> Schema for (A, B) {
+    fn schema() -> Value {
+        json!({
+            "type": "array",
+            "prefixItems": [A::schema(), B::schema()],
+            "items": false,
+            "minItems": 2,
+        })
+    }
+}
+
+/// Maps are objects whose keys are validated by the key type and values by the value type.
+impl<K: Schema, V: Schema> Schema for HashMap<K, V> {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "propertyNames": K::schema(),
+            "additionalProperties": V::schema(),
+        })
+    }
+}
+
+/// Metadata values can be anything.
+impl Schema for toml::Value {
+    fn schema() -> Value {
+        json!({})
+    }
+}
+
+/// OS release information is generated, not set through the API.
+impl Schema for BottlerocketRelease {
+    fn schema() -> Value {
+        json!({ "type": "object", "readOnly": true })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::modeled_types::{IntegerPercent, KubernetesLabelKey};
+    use regex::Regex;
+
+    #[test]
+    fn verbose_patterns() {
+        let label_key = KubernetesLabelKey::schema();
+        let pattern = label_key["pattern"].as_str().unwrap();
+        assert_eq!(
+            pattern,
+            "^([a-zA-Z0-9.-]{1,253}/)?[a-zA-Z0-9](([a-zA-Z0-9._-]{0,61})?[a-zA-Z0-9])?$"
+        );
+
+        // The converted pattern still matches the same things.
+        let converted = Regex::new(pattern).unwrap();
+        for key in &["a", "example.com/a-b", "A1.b_c"] {
+            assert!(converted.is_match(key));
+            assert!(KubernetesLabelKey::try_from(*key).is_ok());
+        }
+        for key in &["", "-a", "a-", "/a", "a/b/c"] {
+            assert!(!converted.is_match(key));
+            assert!(KubernetesLabelKey::try_from(*key).is_err());
+        }
+    }
+
+    #[test]
+    fn escapes_and_comments() {
+        assert_eq!(
+            ecma_pattern("(?x)^ [a\\ b\\#]  # comment\n \\. $"),
+            "^[a b#]\\.$"
+        );
+        assert_eq!(ecma_pattern("^a b#$"), "^a b#$");
+        assert_eq!(ecma_pattern("(?P<start>x)"), "(?<start>x)");
+    }
+
+    #[test]
+    fn ranges() {
+        let percent = IntegerPercent::schema();
+        let integer = &percent["oneOf"][0];
+        assert_eq!(integer["minimum"], 0);
+        assert_eq!(integer["maximum"], 100);
+        assert_eq!(u8::schema()["maximum"], 255);
+    }
+
+    #[test]
+    fn settings_document() {
+        let schema = settings();
+        assert_eq!(schema["$schema"], DIALECT);
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["properties"]["motd"]["type"], "string");
+        // Field names are kebab-case, and nested models are described in place.
+        let kernel = &schema["properties"]["kernel"];
+        assert_eq!(kernel["properties"]["lockdown"]["enum"][0], "none");
+        assert!(kernel["properties"]["sysctl"]["propertyNames"]["pattern"].is_string());
+        // Fields renamed for serialization are listed under each name they're accepted with.
+        let boot = crate::BootSettings::schema();
+        assert_eq!(
+            boot["properties"]["kernel"],
+            boot["properties"]["kernel-parameters"]
+        );
+    }
+
+    #[test]
+    fn required_fields() {
+        let service = crate::Service::schema();
+        assert_eq!(
+            service["required"],
+            json!(["configuration-files", "restart-commands"])
+        );
+        assert!(crate::NtpSettings::schema().get("required").is_none());
+    }
+}
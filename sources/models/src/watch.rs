@@ -0,0 +1,21 @@
+//! The 'watch' module holds types used to communicate between client and server for
+//! 'apiclient watch'.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Sent to watching clients every time a transaction is committed.  Clients only receive the
+/// changes that match their requested prefixes, and aren't sent an event at all if nothing
+/// matched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsEvent {
+    /// The name of the transaction that was committed.
+    pub transaction: String,
+    /// The keys that changed in the commit, in dotted form like "settings.motd", mapped to their
+    /// new values.
+    pub changes: BTreeMap<String, serde_json::Value>,
+    /// The keys that were removed in the commit, for example by a revert or a pruning apply, in
+    /// dotted form.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub removed: BTreeSet<String>,
+}
@@ -14,6 +14,7 @@ enum Command {
     CancelUpgrade,
     RollbackToInactive,
     HasBootEverSucceeded,
+    HasActiveSucceeded,
     RewriteTable,
 }
 
@@ -31,6 +32,7 @@ SUBCOMMANDS:
     cancel-upgrade          Reverse upgrade-to-inactive
     rollback-to-inactive    Deprioritizes the inactive partitions
     has-boot-ever-succeeded Checks whether boot has ever succeeded
+    has-active-succeeded    Exits 0 if the active partitions were marked successfully booted, 1 if not
     rewrite-table           Rewrite the partition table with no changes to disk (used for testing this code)");
     std::process::exit(1)
 }
@@ -71,6 +73,11 @@ fn main() {
                     println!("true");
                 }
             }
+            Command::HasActiveSucceeded => {
+                if !state.active_has_succeeded() {
+                    std::process::exit(1)
+                }
+            }
             Command::RewriteTable => state.write()?,
         }
         Ok(())
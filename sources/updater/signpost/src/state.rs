@@ -299,6 +299,12 @@ impl State {
         Ok(())
     }
 
+    /// Returns whether the active partition set has been marked as successfully booted.  After an
+    /// upgrade, it isn't until something calls `mark_successful_boot`.
+    pub fn active_has_succeeded(&self) -> bool {
+        self.gptprio(self.active()).successful()
+    }
+
     /// Returns whether boot has ever succeeded or not
     pub fn has_boot_succeeded(&mut self) -> bool {
         let private_flags = GptPrio::from(self.gpt_attributes(self.private_partition_num));
@@ -0,0 +1,324 @@
+//! Delta images let a host build an update image from the image it's already running, only
+//! downloading the blocks that changed.
+//!
+//! A delta is a stream of operations that write the new image from start to finish: either copy a
+//! range of bytes from the old image, or insert literal data from the delta itself.  This lets us
+//! apply a delta with constant memory, reading the old image from the active partition and writing
+//! the new image to the inactive partition.  Like full images, deltas are LZ4-compressed in the
+//! repository; the functions here work with the uncompressed stream.
+//!
+//! The format is:
+//! * The magic bytes `DELTA_MAGIC`.
+//! * Any number of operations, each a one-byte tag followed by its fields, little-endian:
+//!   * `OP_COPY`: u64 offset into the old image, u64 length.
+//!   * `OP_DATA`: u64 length, followed by that many bytes of data.
+//! * `OP_END`: u64 total length of the new image, so truncated deltas are detected.
+
+use crate::error::{self, Result};
+use snafu::{ensure, ResultExt};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Identifies the delta format, and its version.
+pub const DELTA_MAGIC: &[u8; 8] = b"BRDELTA1";
+
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_DATA: u8 = 2;
+
+/// The size of the blocks we compare between old and new images when creating a delta.  Matches
+/// the page and filesystem block size, so unchanged files line up.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Applies the delta read from `delta` to the old image read from `source`, writing the new image
+/// to `target`.  Returns the number of bytes written.
+pub fn apply<S, D, T>(source: &mut S, delta: &mut D, target: &mut T) -> Result<u64>
+where
+    S: Read + Seek,
+    D: Read,
+    T: Write,
+{
+    let mut magic = [0; DELTA_MAGIC.len()];
+    delta
+        .read_exact(&mut magic)
+        .context(error::DeltaReadSnafu)?;
+    ensure!(&magic == DELTA_MAGIC, error::DeltaMagicSnafu);
+
+    let mut written = 0;
+    loop {
+        match read_u8(delta)? {
+            OP_COPY => {
+                let offset = read_u64(delta)?;
+                let length = read_u64(delta)?;
+                source
+                    .seek(SeekFrom::Start(offset))
+                    .context(error::DeltaSourceSnafu { offset, length })?;
+                let copied = io::copy(&mut source.by_ref().take(length), target)
+                    .context(error::DeltaWriteSnafu)?;
+                ensure!(
+                    copied == length,
+                    error::DeltaSourceShortSnafu {
+                        offset,
+                        length,
+                        copied
+                    }
+                );
+                written += length;
+            }
+            OP_DATA => {
+                let length = read_u64(delta)?;
+                let copied = io::copy(&mut delta.by_ref().take(length), target)
+                    .context(error::DeltaWriteSnafu)?;
+                ensure!(copied == length, error::DeltaTruncatedSnafu);
+                written += length;
+            }
+            OP_END => {
+                let expected = read_u64(delta)?;
+                ensure!(
+                    expected == written,
+                    error::DeltaLengthSnafu { expected, written }
+                );
+                return Ok(written);
+            }
+            tag => return error::DeltaOpSnafu { tag }.fail(),
+        }
+    }
+}
+
+/// Creates a delta that builds the image read from `target` out of the image read from `source`,
+/// writing it to `delta`.  Blocks of `block_size` bytes in the new image that exist anywhere in the
+/// old image, at a multiple of `block_size`, are copied; everything else is included as data.
+pub fn create<S, T, D>(
+    source: &mut S,
+    target: &mut T,
+    delta: &mut D,
+    block_size: usize,
+) -> Result<()>
+where
+    S: Read + Seek,
+    T: Read,
+    D: Write,
+{
+    // Index the old image's blocks by a hash of their contents.
+    let mut index: HashMap<u64, Vec<u64>> = HashMap::new();
+    let mut block = vec![0; block_size];
+    let mut offset = 0;
+    loop {
+        let len = read_block(source, &mut block).context(error::DeltaReadSnafu)?;
+        if len < block_size {
+            break;
+        }
+        index.entry(hash_block(&block)).or_default().push(offset);
+        offset += block_size as u64;
+    }
+
+    delta
+        .write_all(DELTA_MAGIC)
+        .context(error::DeltaWriteSnafu)?;
+    let mut pending = PendingOp::None;
+    let mut old_block = vec![0; block_size];
+    let mut total = 0;
+    loop {
+        let len = read_block(target, &mut block).context(error::DeltaReadSnafu)?;
+        if len == 0 {
+            break;
+        }
+        let new_block = &block[..len];
+        total += len as u64;
+
+        // Find an identical block in the old image; the hash only narrows the candidates.
+        let mut found = None;
+        if len == block_size {
+            for candidate in index.get(&hash_block(new_block)).into_iter().flatten() {
+                source
+                    .seek(SeekFrom::Start(*candidate))
+                    .context(error::DeltaReadSnafu)?;
+                source
+                    .read_exact(&mut old_block)
+                    .context(error::DeltaReadSnafu)?;
+                if old_block == new_block {
+                    found = Some(*candidate);
+                    break;
+                }
+            }
+        }
+
+        pending = match (pending, found) {
+            // Extend a copy if this block follows the last one in the old image, too.
+            (PendingOp::Copy { offset, length }, Some(found)) if offset + length == found => {
+                PendingOp::Copy {
+                    offset,
+                    length: length + len as u64,
+                }
+            }
+            (PendingOp::Data(mut data), None) => {
+                data.extend_from_slice(new_block);
+                PendingOp::Data(data)
+            }
+            (pending, found) => {
+                pending.write(delta)?;
+                match found {
+                    Some(offset) => PendingOp::Copy {
+                        offset,
+                        length: len as u64,
+                    },
+                    None => PendingOp::Data(new_block.to_vec()),
+                }
+            }
+        };
+    }
+    pending.write(delta)?;
+
+    delta.write_all(&[OP_END]).context(error::DeltaWriteSnafu)?;
+    delta
+        .write_all(&total.to_le_bytes())
+        .context(error::DeltaWriteSnafu)?;
+    Ok(())
+}
+
+/// The operation being built up by `create` as it walks through the new image.
+enum PendingOp {
+    None,
+    Copy { offset: u64, length: u64 },
+    Data(Vec<u8>),
+}
+
+impl PendingOp {
+    fn write<W: Write>(self, delta: &mut W) -> Result<()> {
+        let mut op = Vec::new();
+        match self {
+            Self::None => return Ok(()),
+            Self::Copy { offset, length } => {
+                op.push(OP_COPY);
+                op.extend_from_slice(&offset.to_le_bytes());
+                op.extend_from_slice(&length.to_le_bytes());
+            }
+            Self::Data(data) => {
+                op.push(OP_DATA);
+                op.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                op.extend_from_slice(&data);
+            }
+        }
+        delta.write_all(&op).context(error::DeltaWriteSnafu)
+    }
+}
+
+fn hash_block(block: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    block.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Fills `buf` from `reader` unless it reaches the end first, returning the number of bytes read.
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf).context(error::DeltaReadSnafu)?;
+    Ok(buf[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf).context(error::DeltaReadSnafu)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const BLOCK: usize = 16;
+
+    fn round_trip(old: &[u8], new: &[u8]) -> Vec<u8> {
+        let mut delta = Vec::new();
+        create(
+            &mut Cursor::new(old),
+            &mut Cursor::new(new),
+            &mut delta,
+            BLOCK,
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        let written = apply(&mut Cursor::new(old), &mut delta.as_slice(), &mut output).unwrap();
+        assert_eq!(written, new.len() as u64);
+        assert_eq!(output, new);
+        delta
+    }
+
+    #[test]
+    fn unchanged_blocks_are_copied() {
+        let old: Vec<u8> = (0..BLOCK * 64).map(|i| (i / BLOCK) as u8).collect();
+        let mut new = old.clone();
+        // Change one block, and move another.
+        new[BLOCK * 2..BLOCK * 3].fill(0xff);
+        new.copy_within(BLOCK * 63..BLOCK * 64, 0);
+        // Add a partial block at the end.
+        new.extend_from_slice(b"tail");
+
+        let delta = round_trip(&old, &new);
+        // Much smaller than the new image, since most blocks are copied.
+        assert!(
+            delta.len() < new.len() / 2,
+            "delta too big: {}",
+            delta.len()
+        );
+    }
+
+    #[test]
+    fn unrelated_images() {
+        round_trip(b"", b"something new that's not block aligned");
+        round_trip(b"something old that's not block aligned", b"");
+        round_trip(&[1; BLOCK * 3], &[2; BLOCK * 3]);
+    }
+
+    #[test]
+    fn bad_deltas() {
+        let old: Vec<u8> = (0..BLOCK * 2).map(|i| (i / BLOCK) as u8).collect();
+        let mut delta = Vec::new();
+        create(
+            &mut Cursor::new(&old),
+            &mut Cursor::new(&old),
+            &mut delta,
+            BLOCK,
+        )
+        .unwrap();
+
+        // Truncated
+        let truncated = &delta[..delta.len() - 1];
+        apply(&mut Cursor::new(&old), &mut &truncated[..], &mut Vec::new()).unwrap_err();
+
+        // Bad magic
+        let mut bad_magic = delta.clone();
+        bad_magic[0] = b'X';
+        apply(
+            &mut Cursor::new(&old),
+            &mut bad_magic.as_slice(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+
+        // Source shorter than the delta expects
+        apply(
+            &mut Cursor::new(&old[..BLOCK]),
+            &mut delta.as_slice(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+    }
+}
@@ -56,6 +56,60 @@ pub enum Error {
         source: parse_datetime::Error,
     },
 
+    #[snafu(display(
+        "Delta expected {} bytes from old image at offset {}, got {}",
+        length,
+        offset,
+        copied
+    ))]
+    DeltaSourceShort {
+        offset: u64,
+        length: u64,
+        copied: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Failed to read {} bytes from old image at offset {}: {}",
+        length,
+        offset,
+        source
+    ))]
+    DeltaSource {
+        offset: u64,
+        length: u64,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Delta produced {} bytes, expected {}", written, expected))]
+    DeltaLength {
+        expected: u64,
+        written: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Data is not a delta image; bad magic bytes"))]
+    DeltaMagic { backtrace: Backtrace },
+
+    #[snafu(display("Unknown operation {} in delta image", tag))]
+    DeltaOp { tag: u8, backtrace: Backtrace },
+
+    #[snafu(display("Failed to read delta image: {}", source))]
+    DeltaRead {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Delta image ended unexpectedly"))]
+    DeltaTruncated { backtrace: Backtrace },
+
+    #[snafu(display("Failed to write delta output: {}", source))]
+    DeltaWrite {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Duplicate key ID: {}", keyid))]
     DuplicateKeyId { backtrace: Backtrace, keyid: u32 },
 
@@ -95,6 +149,16 @@ pub enum Error {
         source: toml::de::Error,
     },
 
+    #[snafu(display("Invalid maintenance window timezone '{}'", input))]
+    InvalidTimezone { input: String, backtrace: Backtrace },
+
+    #[snafu(display("Invalid maintenance window '{}': {}", input, msg))]
+    InvalidWindow {
+        input: String,
+        msg: &'static str,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display(
         "Migration {} given for {} but name implies it is for {}",
         name,
@@ -131,6 +195,13 @@ pub enum Error {
         target: Box<Version>,
     },
 
+    #[snafu(display("Failed to parse '{}': {}", path.display(), source))]
+    StateFileParse {
+        path: PathBuf,
+        source: serde_json::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to serialize update information: {}", source))]
     UpdateSerialize {
         source: serde_json::Error,
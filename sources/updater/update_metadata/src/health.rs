@@ -0,0 +1,36 @@
+//! The result of checking the health of the first boot of an update.  updog records it so
+//! `thar-be-updates` can report whether the update was kept, or why it was rolled back.
+
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where updog records the result of the last boot health check.  This is on persistent storage
+/// so it survives the reboot after a rollback.
+pub const HEALTH_CHECK_PATH: &str = "/var/lib/bottlerocket-updog/health-check.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheckResult {
+    /// The version whose boot was checked.
+    pub version: Version,
+    /// When the check finished.
+    pub timestamp: DateTime<Utc>,
+    /// Whether all checks passed, so the boot was marked successful.
+    pub healthy: bool,
+    /// Descriptions of the checks that didn't pass.
+    pub failures: Vec<String>,
+    /// Whether the host rolled back to the previous version.
+    pub rolled_back: bool,
+}
+
+/// Loads the last health check result from the given path, returning None if there is none.
+pub fn load(path: &Path) -> Result<Option<HealthCheckResult>> {
+    crate::load_state_file(path)
+}
+
+/// Atomically writes a health check result to the given path.
+pub fn store(path: &Path, result: &HealthCheckResult) -> Result<()> {
+    crate::store_state_file(path, result)
+}
@@ -1,11 +1,16 @@
 mod de;
+pub mod delta;
 pub mod error;
+pub mod health;
+pub mod progress;
 mod se;
+pub mod window;
 
 use crate::error::Result;
 use chrono::{DateTime, Utc};
 use parse_datetime::parse_offset;
 use semver::Version;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use snafu::{ensure, ResultExt};
 use std::cmp::Ordering;
@@ -84,6 +89,31 @@ pub struct Images {
     pub hash: String,
 }
 
+/// A delta image, which builds one of the update's images from the same image of an older
+/// version.  See the `delta` module for the format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaImage {
+    /// The target name of the LZ4-compressed delta.
+    pub target: String,
+    /// The hex-encoded SHA-256 digest of the image that results from applying the delta, used to
+    /// confirm it was applied to the right image.
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaImages {
+    pub boot: DeltaImage,
+    pub root: DeltaImage,
+    pub hash: DeltaImage,
+}
+
+/// A set of delta images that update a host running `from_version` to the update's version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delta {
+    pub from_version: Version,
+    pub images: DeltaImages,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Update {
     pub variant: String,
@@ -93,6 +123,10 @@ pub struct Update {
     #[serde(deserialize_with = "de::deserialize_bound")]
     pub waves: BTreeMap<u32, DateTime<Utc>>,
     pub images: Images,
+    /// Optional delta images, which hosts running one of the listed versions can use instead of
+    /// the full images.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deltas: Vec<Delta>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -137,6 +171,31 @@ pub fn write_file(path: &Path, manifest: &Manifest) -> Result<()> {
     Ok(())
 }
 
+/// Loads JSON that updog keeps about an update from the given path, returning None if the file
+/// doesn't exist.
+pub(crate) fn load_state_file<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(error::FileReadSnafu { path }),
+    };
+    serde_json::from_slice(&data)
+        .map(Some)
+        .context(error::StateFileParseSnafu { path })
+}
+
+/// Atomically writes JSON that updog keeps about an update to the given path, so an
+/// interruption can't leave it half-written.
+pub(crate) fn store_state_file<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(error::FileWriteSnafu { path: parent })?;
+    }
+    let data = serde_json::to_vec(value).context(error::UpdateSerializeSnafu)?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data).context(error::FileWriteSnafu { path: &tmp })?;
+    fs::rename(&tmp, path).context(error::FileWriteSnafu { path })
+}
+
 impl Manifest {
     /// Parses a `Manifest` from JSON, which is presented by a `Read` object.
     pub fn from_json<R: Read>(r: R) -> Result<Self> {
@@ -168,6 +227,7 @@ impl Manifest {
             max_version,
             images,
             waves: BTreeMap::new(),
+            deltas: Vec::new(),
         };
         self.update_max_version(
             &update.max_version,
@@ -178,6 +238,26 @@ impl Manifest {
         Ok(())
     }
 
+    /// Adds a delta to the updates matching the given variant, arch, and version, replacing any
+    /// existing delta from the same version.  Returns the number of matching updates.
+    pub fn add_delta(
+        &mut self,
+        image_version: Version,
+        arch: String,
+        variant: String,
+        delta: &Delta,
+    ) -> usize {
+        let matching = self.get_matching_updates(variant, arch, image_version);
+        let num_matching = matching.len();
+        for update in matching {
+            update
+                .deltas
+                .retain(|existing| existing.from_version != delta.from_version);
+            update.deltas.push(delta.clone());
+        }
+        num_matching
+    }
+
     /// Update the maximum version for all updates that optionally match the
     /// architecture and variant of some new update.
     pub fn update_max_version(
@@ -299,6 +379,13 @@ impl Manifest {
 }
 
 impl Update {
+    /// Returns the delta that builds this update's images from those of `version`, if any.
+    pub fn delta_from(&self, version: &Version) -> Option<&Delta> {
+        self.deltas
+            .iter()
+            .find(|delta| &delta.from_version == version)
+    }
+
     /// Returns the update wave that Updog belongs to, based on the seed value.
     /// Depending on the waves described in the update, the possible results are
     /// - Some wave described by a start and end time, and the starting seed and ending seed.
@@ -473,6 +560,7 @@ mod tests {
                 root: String::from("root"),
                 hash: String::from("hash"),
             },
+            deltas: Vec::new(),
         }
     }
 
@@ -638,6 +726,7 @@ mod tests {
                 root: String::from("root"),
                 hash: String::from("hash"),
             },
+            deltas: Vec::new(),
         };
         let seed = 1024;
         // Construct a DateTime object for 1/1/2000 00:00:00
@@ -0,0 +1,185 @@
+//! Tracks how much of an update has been written to the inactive partition set.
+//!
+//! Images are written in chunks, and the SHA-256 digest of each chunk is recorded as it's written.
+//! If writing is interrupted, the next attempt can check the chunks already on disk against their
+//! digests and skip rewriting the ones that match, and can skip images that were completed.  The
+//! progress is also what lets `thar-be-updates` report how far along an update is.
+
+use crate::error::Result;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Where updog keeps its progress.  This is on persistent storage so it survives a reboot.
+pub const PROGRESS_PATH: &str = "/var/lib/bottlerocket-updog/progress.json";
+
+/// The size of the chunks images are written and verified in.
+pub const CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Progress of writing an update's images to the inactive partition set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteProgress {
+    /// The version of the update being written.
+    pub version: Version,
+    /// Progress of each image, in the order they're written.
+    pub images: Vec<ImageProgress>,
+}
+
+/// Progress of writing one image to a partition.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageProgress {
+    /// The partition the image is written to.
+    pub partition: PathBuf,
+    /// The name of the target the image is being written from, either a full image or a delta.
+    pub target: String,
+    /// The size of the target in the repository.
+    pub target_length: u64,
+    /// How many bytes of the target have been written, as of the last checkpoint.
+    pub target_written: u64,
+    /// The size of each chunk; only the last chunk of a complete image may be smaller.
+    pub chunk_size: u64,
+    /// Hex-encoded SHA-256 digests of the chunks written so far, in order.
+    pub chunks: Vec<String>,
+    /// The number of bytes written to the partition so far.
+    pub size: u64,
+    /// Whether the whole image has been written and verified.
+    pub complete: bool,
+}
+
+impl WriteProgress {
+    pub fn new(version: Version) -> Self {
+        Self {
+            version,
+            images: Vec::new(),
+        }
+    }
+
+    /// Returns the progress of the image being written to the given partition, starting it if
+    /// it's not yet known.  If the image is now being written from a different target, its
+    /// target and byte counts are reset, but the chunks already written are kept; they describe
+    /// the contents of the partition, no matter where they came from.
+    pub fn image_mut(
+        &mut self,
+        partition: &Path,
+        target: &str,
+        target_length: u64,
+    ) -> &mut ImageProgress {
+        let index = match self
+            .images
+            .iter()
+            .position(|image| image.partition == partition)
+        {
+            Some(index) => index,
+            None => {
+                self.images.push(ImageProgress {
+                    partition: partition.to_path_buf(),
+                    target: target.to_string(),
+                    target_length,
+                    target_written: 0,
+                    chunk_size: CHUNK_SIZE,
+                    chunks: Vec::new(),
+                    size: 0,
+                    complete: false,
+                });
+                self.images.len() - 1
+            }
+        };
+        let image = &mut self.images[index];
+        if image.target != target {
+            image.target = target.to_string();
+            image.target_length = target_length;
+            image.target_written = 0;
+        }
+        image
+    }
+
+    /// Forgets any images not being written to one of the given partitions, for example because
+    /// we've since booted from the other partition set.
+    pub fn retain_partitions(&mut self, partitions: &[&Path]) {
+        self.images
+            .retain(|image| partitions.contains(&image.partition.as_path()));
+    }
+
+    /// The number of bytes of update targets written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.images
+            .iter()
+            .map(|image| {
+                if image.complete {
+                    image.target_length
+                } else {
+                    image.target_written
+                }
+            })
+            .sum()
+    }
+
+    /// The total number of bytes of the update targets being written.
+    pub fn bytes_total(&self) -> u64 {
+        self.images.iter().map(|image| image.target_length).sum()
+    }
+}
+
+impl ImageProgress {
+    /// Returns the offset and length of each recorded chunk.
+    pub fn chunk_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let chunk_size = self.chunk_size;
+        let size = self.size;
+        (0..self.chunks.len() as u64).map(move |i| {
+            let offset = i * chunk_size;
+            (offset, chunk_size.min(size.saturating_sub(offset)))
+        })
+    }
+}
+
+/// Loads progress from the given path, returning None if there is none.
+pub fn load(path: &Path) -> Result<Option<WriteProgress>> {
+    crate::load_state_file(path)
+}
+
+/// Atomically writes progress to the given path, so an interruption can't leave it half-written.
+pub fn store(path: &Path, progress: &WriteProgress) -> Result<()> {
+    crate::store_state_file(path, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_progress() {
+        let mut progress = WriteProgress::new(Version::new(1, 2, 3));
+        let root = Path::new("/dev/root-b");
+        let boot = Path::new("/dev/boot-b");
+
+        let image = progress.image_mut(root, "root-delta", 100);
+        image.target_written = 40;
+        image.chunks.push("abc".to_string());
+        image.size = CHUNK_SIZE;
+        progress.image_mut(boot, "boot", 50).complete = true;
+        assert_eq!(progress.bytes_written(), 90);
+        assert_eq!(progress.bytes_total(), 150);
+
+        // Falling back to the full image keeps the chunks, but resets the byte counts.
+        let image = progress.image_mut(root, "root", 300);
+        assert_eq!(image.target_written, 0);
+        assert_eq!(image.chunks.len(), 1);
+        assert_eq!(progress.bytes_total(), 350);
+
+        progress.retain_partitions(&[root]);
+        assert_eq!(progress.images.len(), 1);
+    }
+
+    #[test]
+    fn chunk_ranges() {
+        let mut progress = WriteProgress::new(Version::new(1, 2, 3));
+        let image = progress.image_mut(Path::new("/dev/hash-b"), "hash", 10);
+        image.chunk_size = 10;
+        image.chunks = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        image.size = 25;
+        assert_eq!(
+            image.chunk_ranges().collect::<Vec<_>>(),
+            vec![(0, 10), (10, 10), (20, 5)]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Maintenance windows limit when an update can be applied and the host rebooted into it.
+//!
+//! Each window is written as days followed by a time range, like `Mon-Fri 02:00-05:00`.  Days are
+//! `*` for every day, or a comma-separated list of three-letter day names and ranges, like
+//! `Sat,Sun` or `Mon-Wed,Fri`.  Times are `HH:MM`, and the end may be `24:00`.  If the end isn't
+//! after the start, the window continues past midnight into the next day, so `Fri 22:00-02:00`
+//! ends early on Saturday.  Times are in the configured timezone, or UTC if none is given.
+
+use crate::error::{self, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt};
+
+const DAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// A set of maintenance windows in a timezone.  With no windows, updates can be applied at any
+/// time.
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindows {
+    windows: Vec<Window>,
+    timezone: Tz,
+}
+
+/// One occurrence of a maintenance window.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowTime {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    /// Whether the window starts on each day, indexed from Monday.
+    days: [bool; 7],
+    /// Minutes after midnight.
+    start: u32,
+    end: u32,
+}
+
+impl MaintenanceWindows {
+    /// Parses the given windows, in the given timezone name, or UTC if it's not given.
+    pub fn new<S: AsRef<str>>(windows: &[S], timezone: Option<&str>) -> Result<Self> {
+        let timezone = match timezone {
+            Some(name) => name
+                .parse()
+                .ok()
+                .context(error::InvalidTimezoneSnafu { input: name })?,
+            None => Tz::UTC,
+        };
+        let windows = windows
+            .iter()
+            .map(|window| parse_window(window.as_ref()))
+            .collect::<Result<_>>()?;
+        Ok(Self { windows, timezone })
+    }
+
+    /// Returns whether updates can be applied at the given time.
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        self.windows.is_empty() || self.next(now).map_or(false, |window| window.start <= now)
+    }
+
+    /// Returns the window that's open at the given time, or the next one to open; None if there
+    /// are no windows.
+    pub fn next(&self, now: DateTime<Utc>) -> Option<WindowTime> {
+        let today = now.with_timezone(&self.timezone).date_naive();
+        // Start from yesterday, in case a window that started then crosses midnight.
+        (-1..=7)
+            .filter_map(|offset| today.checked_add_signed(Duration::days(offset)))
+            .flat_map(|date| {
+                self.windows
+                    .iter()
+                    .filter(move |window| {
+                        window.days[date.weekday().num_days_from_monday() as usize]
+                    })
+                    .map(move |window| self.occurrence(window, date))
+            })
+            .filter(|window| window.end > now)
+            .min_by_key(|window| window.start)
+    }
+
+    /// Returns the occurrence of a window that starts on the given date.
+    fn occurrence(&self, window: &Window, date: NaiveDate) -> WindowTime {
+        let end_date = if window.end > window.start {
+            date
+        } else {
+            date.succ_opt().unwrap_or(date)
+        };
+        WindowTime {
+            start: self.to_utc(date, window.start),
+            end: self.to_utc(end_date, window.end),
+        }
+    }
+
+    fn to_utc(&self, date: NaiveDate, minutes: u32) -> DateTime<Utc> {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+        let local = midnight + Duration::minutes(i64::from(minutes));
+        // Times skipped by a daylight saving change happen at the same time an hour later.
+        let local = self
+            .timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .unwrap_or_else(|| self.timezone.from_utc_datetime(&local));
+        local.with_timezone(&Utc)
+    }
+}
+
+fn parse_window(input: &str) -> Result<Window> {
+    let (days, times) = input
+        .trim()
+        .split_once(' ')
+        .context(error::InvalidWindowSnafu {
+            input,
+            msg: "expected days and a time range separated by a space",
+        })?;
+    let (start, end) = times
+        .trim()
+        .split_once('-')
+        .context(error::InvalidWindowSnafu {
+            input,
+            msg: "expected a time range like 02:00-05:00",
+        })?;
+    let start = parse_time(start).context(error::InvalidWindowSnafu {
+        input,
+        msg: "invalid start time",
+    })?;
+    let end = parse_time(end).context(error::InvalidWindowSnafu {
+        input,
+        msg: "invalid end time",
+    })?;
+    ensure!(
+        start < MINUTES_PER_DAY && start != end,
+        error::InvalidWindowSnafu {
+            input,
+            msg: "start and end must be different times",
+        }
+    );
+    let days = parse_days(days).context(error::InvalidWindowSnafu {
+        input,
+        msg: "invalid days; expected '*', or day names like Mon-Fri,Sun",
+    })?;
+    Ok(Window { days, start, end })
+}
+
+/// Parses `HH:MM` into minutes after midnight, allowing `24:00`.
+fn parse_time(input: &str) -> Option<u32> {
+    let (hours, minutes) = input.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let total = hours * 60 + minutes;
+    (minutes < 60 && total <= MINUTES_PER_DAY).then_some(total)
+}
+
+fn parse_days(input: &str) -> Option<[bool; 7]> {
+    if input == "*" {
+        return Some([true; 7]);
+    }
+    let mut days = [false; 7];
+    for part in input.split(',') {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let first = parse_day(first)?;
+        let last = parse_day(last)?;
+        // Ranges can wrap around the end of the week, like Sat-Mon.
+        let mut day = first;
+        loop {
+            days[day] = true;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % DAYS.len();
+        }
+    }
+    Some(days)
+}
+
+fn parse_day(input: &str) -> Option<usize> {
+    let day: Weekday = input.parse().ok()?;
+    // chrono also accepts full day names; we only document the short form.
+    if input.len() != 3 {
+        return None;
+    }
+    DAYS.iter().position(|d| *d == day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse() {
+        let window = parse_window("Mon-Wed,Sat 02:00-05:30").unwrap();
+        assert_eq!(window.days, [true, true, true, false, false, true, false]);
+        assert_eq!((window.start, window.end), (120, 330));
+        assert_eq!(
+            parse_window("Sat-Mon 22:00-24:00").unwrap().days,
+            [true, false, false, false, false, true, true]
+        );
+        assert_eq!(parse_window("* 00:00-24:00").unwrap().days, [true; 7]);
+
+        for bad in [
+            "02:00-05:00",
+            "Mon 02:00",
+            "Mon 2:00-05:00",
+            "Mon 02:00-24:01",
+            "Mon 02:60-05:00",
+            "Mon 02:00-02:00",
+            "Mon 00:00-24:00x",
+            "Monday 02:00-05:00",
+            "Mon,Funday 02:00-05:00",
+        ] {
+            parse_window(bad).unwrap_err();
+        }
+        MaintenanceWindows::new(&["* 00:00-01:00"], Some("Mars/Olympus_Mons")).unwrap_err();
+    }
+
+    #[test]
+    fn next_window() {
+        let windows = MaintenanceWindows::new(&["Mon-Fri 02:00-05:00"], None).unwrap();
+        // Wednesday, inside the window.
+        let now = utc("2023-03-01T03:00:00Z");
+        assert!(windows.is_open(now));
+        assert_eq!(
+            windows.next(now).unwrap(),
+            WindowTime {
+                start: utc("2023-03-01T02:00:00Z"),
+                end: utc("2023-03-01T05:00:00Z"),
+            }
+        );
+        // Friday, after the window; the next is Monday.
+        let now = utc("2023-03-03T06:00:00Z");
+        assert!(!windows.is_open(now));
+        assert_eq!(
+            windows.next(now).unwrap().start,
+            utc("2023-03-06T02:00:00Z")
+        );
+
+        // No windows means updates can happen any time.
+        let windows = MaintenanceWindows::new::<&str>(&[], None).unwrap();
+        assert!(windows.is_open(now));
+        assert!(windows.next(now).is_none());
+    }
+
+    #[test]
+    fn overnight_in_timezone() {
+        let windows =
+            MaintenanceWindows::new(&["Fri 22:00-02:00"], Some("America/New_York")).unwrap();
+        // Saturday 01:00 in New York, in the window that started Friday.
+        let now = utc("2023-03-04T06:00:00Z");
+        assert!(windows.is_open(now));
+        assert_eq!(
+            windows.next(now).unwrap(),
+            WindowTime {
+                start: utc("2023-03-04T03:00:00Z"),
+                end: utc("2023-03-04T07:00:00Z"),
+            }
+        );
+        // After daylight saving time starts, the window is an hour earlier in UTC.
+        let now = utc("2023-03-12T00:00:00Z");
+        assert!(!windows.is_open(now));
+        assert_eq!(
+            windows.next(now).unwrap().start,
+            utc("2023-03-18T02:00:00Z")
+        );
+    }
+}
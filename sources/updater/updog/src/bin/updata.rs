@@ -9,12 +9,14 @@ extern crate log;
 use crate::error::Result;
 use chrono::{DateTime, Utc};
 use semver::Version;
+use sha2::{Digest, Sha256};
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
-use snafu::{ErrorCompat, OptionExt, ResultExt};
-use std::fs;
-use std::path::PathBuf;
+use snafu::{ensure, ErrorCompat, OptionExt, ResultExt};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
-use update_metadata::{Images, Manifest, Release, UpdateWaves};
+use update_metadata::{Delta, DeltaImage, DeltaImages, Images, Manifest, Release, UpdateWaves};
 
 #[derive(Debug, StructOpt)]
 struct GeneralArgs {
@@ -75,6 +77,136 @@ impl AddUpdateArgs {
     }
 }
 
+#[derive(Debug, StructOpt)]
+struct CreateDeltaArgs {
+    // uncompressed image of the version hosts are updating from
+    #[structopt(long = "old")]
+    old: PathBuf,
+
+    // uncompressed image of the version hosts are updating to
+    #[structopt(long = "new")]
+    new: PathBuf,
+
+    // where to write the LZ4-compressed delta
+    #[structopt(short = "o", long = "output")]
+    output: PathBuf,
+}
+
+impl CreateDeltaArgs {
+    fn run(&self) -> Result<()> {
+        let mut old = BufReader::new(
+            File::open(&self.old).context(error::DeltaFileSnafu { path: &self.old })?,
+        );
+        let mut new = BufReader::new(
+            File::open(&self.new).context(error::DeltaFileSnafu { path: &self.new })?,
+        );
+        let output =
+            File::create(&self.output).context(error::DeltaFileSnafu { path: &self.output })?;
+        let mut encoder = lz4::EncoderBuilder::new()
+            .build(BufWriter::new(output))
+            .context(error::DeltaFileSnafu { path: &self.output })?;
+        update_metadata::delta::create(
+            &mut old,
+            &mut new,
+            &mut encoder,
+            update_metadata::delta::DEFAULT_BLOCK_SIZE,
+        )
+        .context(error::DeltaCreateSnafu)?;
+        let (mut output, result) = encoder.finish();
+        result.context(error::DeltaFileSnafu { path: &self.output })?;
+        io::Write::flush(&mut output).context(error::DeltaFileSnafu { path: &self.output })?;
+
+        // The hash is needed for add-delta, so hosts can check the image they build.
+        println!("{}", sha256_file(&self.new)?);
+        Ok(())
+    }
+}
+
+/// Returns the hex-encoded SHA-256 digest of the file at the given path.
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut f = File::open(path).context(error::DeltaFileSnafu { path })?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut f, &mut hasher).context(error::DeltaFileSnafu { path })?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[derive(Debug, StructOpt)]
+struct AddDeltaArgs {
+    // metadata file to modify
+    file: PathBuf,
+
+    // image 'variant', eg. 'aws-ecs-1'
+    #[structopt(short = "f", long = "variant")]
+    variant: String,
+
+    // version of the update the delta builds
+    #[structopt(short = "v", long = "version")]
+    image_version: Version,
+
+    // architecture image is built for
+    #[structopt(short = "a", long = "arch")]
+    arch: String,
+
+    // version the delta applies to
+    #[structopt(long = "from-version")]
+    from_version: Version,
+
+    // root delta target name
+    #[structopt(short = "r", long = "root")]
+    root: String,
+
+    // SHA-256 of the root image built from the delta, as printed by create-delta
+    #[structopt(long = "root-sha256")]
+    root_sha256: String,
+
+    // boot delta target name
+    #[structopt(short = "b", long = "boot")]
+    boot: String,
+
+    // SHA-256 of the boot image built from the delta
+    #[structopt(long = "boot-sha256")]
+    boot_sha256: String,
+
+    // verity "hash" delta target name
+    #[structopt(short = "h", long = "hash")]
+    hash: String,
+
+    // SHA-256 of the verity "hash" image built from the delta
+    #[structopt(long = "hash-sha256")]
+    hash_sha256: String,
+}
+
+impl AddDeltaArgs {
+    fn run(self) -> Result<()> {
+        let mut manifest: Manifest = update_metadata::load_file(&self.file)?;
+        let num_matching = manifest.add_delta(
+            self.image_version,
+            self.arch,
+            self.variant,
+            &Delta {
+                from_version: self.from_version,
+                images: DeltaImages {
+                    boot: DeltaImage {
+                        target: self.boot,
+                        sha256: self.boot_sha256,
+                    },
+                    root: DeltaImage {
+                        target: self.root,
+                        sha256: self.root_sha256,
+                    },
+                    hash: DeltaImage {
+                        target: self.hash,
+                        sha256: self.hash_sha256,
+                    },
+                },
+            },
+        );
+        ensure!(num_matching > 0, error::DeltaNoUpdateSnafu);
+        update_metadata::write_file(&self.file, &manifest)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, StructOpt)]
 struct RemoveUpdateArgs {
     // metadata file to create/modify
@@ -241,6 +373,10 @@ enum Command {
     SetWaves(WaveArgs),
     /// Set the global maximum image version
     SetMaxVersion(MaxVersionArgs),
+    /// Create an LZ4-compressed delta between two images, printing the new image's SHA-256
+    CreateDelta(CreateDeltaArgs),
+    /// Add a delta from an older version to an update in the manifest
+    AddDelta(AddDeltaArgs),
     /// Remove an update from the manifest, including wave information
     RemoveUpdate(RemoveUpdateArgs),
     /// Copy the migrations from an input file to an output file
@@ -263,6 +399,8 @@ fn main_inner() -> Result<()> {
         Command::AddUpdate(args) => args.run(),
         Command::SetWaves(args) => args.set(),
         Command::SetMaxVersion(args) => args.run(),
+        Command::CreateDelta(args) => args.run(),
+        Command::AddDelta(args) => args.run(),
         Command::RemoveUpdate(args) => args.run(),
         Command::SetMigrations(args) => args.set(),
         Command::Validate(args) => match update_metadata::load_file(&args.file) {
@@ -292,8 +430,6 @@ fn main() -> ! {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
-    use std::path::Path;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -416,4 +552,69 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn deltas() -> Result<()> {
+        let dir = tempfile::tempdir().context(error::TmpFileCreateSnafu)?;
+        let old: Vec<u8> = (0..16).flat_map(|block| [block; 4096]).collect();
+        let mut new = old.clone();
+        new[8192..12288].fill(0xff);
+        fs::write(dir.path().join("old"), &old).unwrap();
+        fs::write(dir.path().join("new"), &new).unwrap();
+
+        CreateDeltaArgs {
+            old: dir.path().join("old"),
+            new: dir.path().join("new"),
+            output: dir.path().join("delta.lz4"),
+        }
+        .run()?;
+        let delta = File::open(dir.path().join("delta.lz4")).unwrap();
+        let mut built = Vec::new();
+        update_metadata::delta::apply(
+            &mut io::Cursor::new(&old),
+            &mut lz4::Decoder::new(delta).unwrap(),
+            &mut built,
+        )
+        .unwrap();
+        assert_eq!(built, new);
+
+        let manifest_path = dir.path().join("manifest.json");
+        update_metadata::write_file(&manifest_path, &Manifest::default()).unwrap();
+        AddUpdateArgs {
+            file: manifest_path.clone(),
+            variant: String::from("yum"),
+            arch: String::from("x86_64"),
+            image_version: Version::parse("1.2.4").unwrap(),
+            max_version: None,
+            boot: String::from("boot"),
+            root: String::from("root"),
+            hash: String::from("hash"),
+        }
+        .run()?;
+        let sha256 = sha256_file(&dir.path().join("new"))?;
+        AddDeltaArgs {
+            file: manifest_path.clone(),
+            variant: String::from("yum"),
+            arch: String::from("x86_64"),
+            image_version: Version::parse("1.2.4").unwrap(),
+            from_version: Version::parse("1.2.3").unwrap(),
+            root: String::from("root-delta"),
+            root_sha256: sha256.clone(),
+            boot: String::from("boot-delta"),
+            boot_sha256: sha256.clone(),
+            hash: String::from("hash-delta"),
+            hash_sha256: sha256,
+        }
+        .run()?;
+
+        let m: Manifest = update_metadata::load_file(&manifest_path)?;
+        let delta = m.updates[0]
+            .delta_from(&Version::parse("1.2.3").unwrap())
+            .unwrap();
+        assert_eq!(delta.images.root.target, "root-delta");
+        assert!(m.updates[0]
+            .delta_from(&Version::parse("1.2.2").unwrap())
+            .is_none());
+        Ok(())
+    }
 }
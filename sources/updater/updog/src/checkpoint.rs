@@ -0,0 +1,258 @@
+//! Writes images to partitions in chunks, checkpointing progress as it goes, so an interrupted
+//! write can be resumed.  See `update_metadata::progress` for what's recorded.
+//!
+//! Targets are downloaded before they're written, and an interrupted download is resumed; see the
+//! `download` module.  A resumed write still reads the downloaded image again from the start, but
+//! chunks that are already on disk and match their recorded digest aren't written again, and
+//! images that were completed aren't read at all.
+
+use crate::error::{self, Result};
+use sha2::{Digest, Sha256};
+use snafu::ResultExt;
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::rc::Rc;
+use update_metadata::progress::{self, ImageProgress, WriteProgress};
+
+/// Counts the bytes read from the inner reader, so we can report how much of a target we've
+/// written.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R> CountingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            count: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub(crate) fn count(&self) -> Rc<Cell<u64>> {
+        Rc::clone(&self.count)
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// Writes an image to a partition one chunk at a time, recording each chunk's digest and
+/// storing the progress after each one.
+pub(crate) struct CheckpointWriter<'a> {
+    file: File,
+    partition: &'a Path,
+    progress: &'a mut WriteProgress,
+    progress_path: &'a Path,
+    target_read: Rc<Cell<u64>>,
+    buf: Vec<u8>,
+    chunk: usize,
+    written: u64,
+}
+
+impl<'a> CheckpointWriter<'a> {
+    /// Starts writing the image for `partition`, which must already be in `progress`.
+    /// `target_read` is the number of bytes read from the target so far.
+    pub(crate) fn new(
+        partition: &'a Path,
+        progress: &'a mut WriteProgress,
+        progress_path: &'a Path,
+        target_read: Rc<Cell<u64>>,
+    ) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(partition)
+            .context(error::OpenPartitionSnafu { path: partition })?;
+        let mut writer = Self {
+            file,
+            partition,
+            progress,
+            progress_path,
+            target_read,
+            buf: Vec::new(),
+            chunk: 0,
+            written: 0,
+        };
+        let image = writer.image();
+        image.complete = false;
+        let chunk_size = image.chunk_size;
+        writer.buf = Vec::with_capacity(usize::try_from(chunk_size).unwrap_or(0));
+        Ok(writer)
+    }
+
+    /// Writes the final chunk and marks the image complete.  Callers should only call this once
+    /// they've verified the image; until then, a resumed write will read it again.
+    pub(crate) fn finish(mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            let chunk = std::mem::take(&mut self.buf);
+            self.write_chunk(&chunk).context(error::WriteUpdateSnafu)?;
+        }
+        self.file.sync_all().context(error::WriteUpdateSnafu)?;
+
+        let (chunks, written) = (self.chunk, self.written);
+        let image = self.image();
+        image.chunks.truncate(chunks);
+        image.size = written;
+        image.target_written = image.target_length;
+        image.complete = true;
+        progress::store(self.progress_path, self.progress)?;
+        Ok(())
+    }
+
+    fn image(&mut self) -> &mut ImageProgress {
+        let partition = self.partition;
+        self.progress
+            .images
+            .iter_mut()
+            .find(|image| image.partition == partition)
+            .expect("image progress must be started before writing")
+    }
+
+    fn write_chunk(&mut self, data: &[u8]) -> io::Result<()> {
+        let digest = sha256_hex(data);
+        let index = self.chunk;
+        let image = self.image();
+        let offset = index as u64 * image.chunk_size;
+        let recorded = image.chunks.get(index) == Some(&digest);
+
+        if !(recorded && chunk_matches(&self.file, offset, data.len(), &digest)?) {
+            self.file.write_all_at(data, offset)?;
+            let image = self.image();
+            if index < image.chunks.len() {
+                image.chunks[index] = digest;
+            } else {
+                image.chunks.push(digest);
+            }
+        }
+        self.chunk += 1;
+        self.written += data.len() as u64;
+
+        let target_read = self.target_read.get();
+        let image = self.image();
+        image.size = image.size.max(offset + data.len() as u64);
+        image.target_written = target_read.min(image.target_length);
+        progress::store(self.progress_path, self.progress)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+impl Write for CheckpointWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let chunk_size = usize::try_from(self.image().chunk_size).unwrap_or(usize::MAX);
+        let take = buf.len().min(chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..take]);
+        if self.buf.len() == chunk_size {
+            let chunk = std::mem::take(&mut self.buf);
+            self.write_chunk(&chunk)?;
+            self.buf = chunk;
+            self.buf.clear();
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Checks whether every chunk recorded for a complete image is still on disk.
+pub(crate) fn image_on_disk(image: &ImageProgress) -> Result<bool> {
+    let file = File::open(&image.partition).context(error::OpenPartitionSnafu {
+        path: &image.partition,
+    })?;
+    for ((offset, length), digest) in image.chunk_ranges().zip(&image.chunks) {
+        let length = usize::try_from(length).unwrap_or(usize::MAX);
+        if !chunk_matches(&file, offset, length, digest).context(error::VerifyPartitionSnafu {
+            path: &image.partition,
+        })? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn chunk_matches(file: &File, offset: u64, length: usize, digest: &str) -> io::Result<bool> {
+    let mut data = vec![0; length];
+    match file.read_exact_at(&mut data, offset) {
+        Ok(()) => Ok(sha256_hex(&data) == digest),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::Version;
+
+    const CHUNK: u64 = 16;
+
+    /// Writes `data` to the partition through a `CheckpointWriter`, stopping after `limit` bytes
+    /// if given, like an interrupted update.
+    fn write(
+        data: &[u8],
+        partition: &Path,
+        progress: &mut WriteProgress,
+        progress_path: &Path,
+        limit: Option<usize>,
+    ) {
+        progress
+            .image_mut(partition, "root", data.len() as u64)
+            .chunk_size = CHUNK;
+        let reader = CountingReader::new(&data[..limit.unwrap_or(data.len())]);
+        let count = reader.count();
+        let mut writer = CheckpointWriter::new(partition, progress, progress_path, count).unwrap();
+        io::copy(&mut { reader }, &mut writer).unwrap();
+        if limit.is_none() {
+            writer.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let partition = dir.path().join("root-b");
+        let progress_path = dir.path().join("progress.json");
+        let data: Vec<u8> = (0..100).collect();
+        let mut progress = WriteProgress::new(Version::new(1, 2, 3));
+
+        // Interrupted partway through the fourth chunk.
+        write(&data, &partition, &mut progress, &progress_path, Some(60));
+        let stored = progress::load(&progress_path).unwrap().unwrap();
+        assert_eq!(stored, progress);
+        assert_eq!(stored.images[0].chunks.len(), 3);
+        assert_eq!(stored.images[0].target_written, 60);
+        assert!(!stored.images[0].complete);
+
+        // Corrupt the second chunk on disk; resuming rewrites it, and finishes the image.
+        let file = OpenOptions::new().write(true).open(&partition).unwrap();
+        file.write_all_at(&[0xff; 4], CHUNK + 2).unwrap();
+        let mut progress = stored;
+        write(&data, &partition, &mut progress, &progress_path, None);
+        assert_eq!(std::fs::read(&partition).unwrap(), data);
+
+        let image = &progress.images[0];
+        assert!(image.complete);
+        assert_eq!(image.size, 100);
+        assert_eq!(image.chunks.len(), 7);
+        assert_eq!(progress.bytes_written(), progress.bytes_total());
+        assert!(image_on_disk(image).unwrap());
+
+        file.write_all_at(&[0xff; 4], 96).unwrap();
+        assert!(!image_on_disk(image).unwrap());
+    }
+}
@@ -0,0 +1,349 @@
+//! Downloads update targets to persistent storage before they're written to disk, so an
+//! interrupted download can be resumed instead of starting over.
+//!
+//! Each target is stored under `DOWNLOAD_DIR`, named by its SHA-256 digest from the signed targets
+//! metadata.  If a partial download is there from an earlier attempt, we request only the rest of
+//! the target with an HTTP Range request.  Once the whole target is present, it's checked against
+//! the length and digest in the signed metadata before anything reads it, just as `tough` checks
+//! targets it reads itself.
+//!
+//! Only the download in progress is kept; others, like those left from an earlier update, are
+//! removed before it starts, and it's checked that there's space for the rest of it.  Once a
+//! download is verified, its file is unlinked and only the open handle refers to it, so its space
+//! is freed when the handle is dropped, however the update ends.
+
+use crate::error::{self, Result};
+use crate::transport::{self, QueryParams};
+use log::{debug, info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use tough::{Repository, TargetName};
+use url::Url;
+
+/// Where downloaded targets are kept until the update is written.  This is on persistent storage
+/// so a download survives a reboot.
+const DOWNLOAD_DIR: &str = "/var/lib/bottlerocket-updog/downloads";
+
+/// Downloads targets from the repository's targets URL, resuming earlier downloads.
+pub(crate) struct Downloader<'a> {
+    repository: &'a Repository,
+    targets_base_url: Url,
+    query_params: QueryParams,
+    client: Client,
+}
+
+impl<'a> Downloader<'a> {
+    pub(crate) fn new(
+        repository: &'a Repository,
+        targets_base_url: &str,
+        query_params: QueryParams,
+    ) -> Result<Self> {
+        // Target names are joined to the base URL, which only keeps the last path segment if
+        // the base ends with a slash.
+        let base = if targets_base_url.ends_with('/') {
+            targets_base_url.to_string()
+        } else {
+            format!("{targets_base_url}/")
+        };
+        let targets_base_url = Url::parse(&base).context(error::UrlParseSnafu { url: &base })?;
+        let client = transport::http_client().context(error::DownloadClientSnafu)?;
+        Ok(Self {
+            repository,
+            targets_base_url,
+            query_params,
+            client,
+        })
+    }
+
+    pub(crate) fn repository(&self) -> &'a Repository {
+        self.repository
+    }
+
+    /// Downloads the given target, or the rest of it if an earlier download was interrupted,
+    /// and returns its name and the verified file, ready to read from the start.
+    pub(crate) fn fetch(&self, target: &str) -> Result<(TargetName, File)> {
+        let target: TargetName = target
+            .try_into()
+            .context(error::TargetNameSnafu { target })?;
+        let info = self
+            .repository
+            .targets()
+            .signed
+            .find_target(&target)
+            .ok()
+            .context(error::TargetNotFoundSnafu {
+                target: target.raw(),
+            })?;
+        let sha256 = hex(&info.hashes.sha256);
+        let file_name = target_file_name(
+            &sha256,
+            target.resolved(),
+            self.repository.root().signed.consistent_snapshot,
+        );
+        let url = self
+            .targets_base_url
+            .join(&file_name)
+            .context(error::UrlParseSnafu { url: &file_name })?;
+        let url = self.query_params.add_params_to_url(url);
+
+        let dir = Path::new(DOWNLOAD_DIR);
+        fs::create_dir_all(dir).context(error::DirCreateSnafu { path: dir })?;
+        remove_others(dir, &sha256).context(error::DownloadFileSnafu { path: dir })?;
+        let path = dir.join(&sha256);
+        self.download(&url, &path, info.length)?;
+
+        let mut file = File::open(&path).context(error::DownloadFileSnafu { path: &path })?;
+        // We only need the open handle from here on, whether or not the target is good: a bad
+        // download is started over next time, rather than resumed when it can never succeed.
+        fs::remove_file(&path).context(error::DownloadFileSnafu { path: &path })?;
+        let verified = verify(&mut file, info.length, &sha256)
+            .context(error::DownloadFileSnafu { path: &path })?;
+        ensure!(
+            verified,
+            error::DownloadVerifySnafu {
+                target: target.raw(),
+            }
+        );
+        file.seek(SeekFrom::Start(0))
+            .context(error::DownloadFileSnafu { path: &path })?;
+        Ok((target, file))
+    }
+
+    /// Downloads the part of the target at `url` that isn't already at `path`.
+    fn download(&self, url: &Url, path: &Path, length: u64) -> Result<()> {
+        let (mut file, have) = resume(path, length).context(error::DownloadFileSnafu { path })?;
+        if have == length {
+            debug!("{} was already downloaded", url);
+            return Ok(());
+        }
+        let needed = length - have;
+        let available = fs2::available_space(path.parent().unwrap_or(path))
+            .context(error::DownloadFileSnafu { path })?;
+        ensure!(
+            available >= needed,
+            error::DownloadSpaceSnafu {
+                path,
+                needed,
+                available,
+            }
+        );
+
+        let mut request = self.client.get(url.clone());
+        if have > 0 {
+            info!("Resuming download of {} from byte {}", url, have);
+            request = request.header(RANGE, format!("bytes={have}-"));
+        }
+        let response = request
+            .send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .context(error::DownloadSnafu { url: url.as_str() })?;
+        let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+        if have > 0 && !partial {
+            warn!(
+                "Server doesn't support resuming downloads; downloading {} again",
+                url
+            );
+        }
+        store(&mut file, have, partial, response, length)
+            .context(error::DownloadStoreSnafu { url: url.as_str() })?;
+
+        // If the connection closed early, keep what we have for the next attempt.
+        let have = file
+            .metadata()
+            .context(error::DownloadFileSnafu { path })?
+            .len();
+        ensure!(
+            have == length,
+            error::DownloadIncompleteSnafu {
+                url: url.as_str(),
+                have,
+                length,
+            }
+        );
+        Ok(())
+    }
+}
+
+/// Removes the downloads in `dir` other than the one named `keep`, so they don't take space from
+/// it.
+fn remove_others(dir: &Path, keep: &str) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() != keep {
+            debug!("Removing stale download {}", entry.path().display());
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes any downloads left once the update has been written.
+pub(crate) fn remove_downloads() {
+    match fs::remove_dir_all(DOWNLOAD_DIR) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Unable to remove downloads in {}: {}", DOWNLOAD_DIR, e),
+    }
+}
+
+/// Returns the name of the file holding a target in the repository.  With consistent snapshots,
+/// targets are stored under names prefixed by their digest.
+fn target_file_name(sha256: &str, name: &str, consistent_snapshot: bool) -> String {
+    if consistent_snapshot {
+        format!("{sha256}.{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Opens the download at `path` for appending, returning the file and how many bytes of the
+/// target it holds.  A file longer than the target can't be part of it, so it's emptied.
+fn resume(path: &Path, length: u64) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut have = file.metadata()?.len();
+    if have > length {
+        file.set_len(0)?;
+        have = 0;
+    }
+    Ok((file, have))
+}
+
+/// Appends the response body to the download, which holds `have` bytes of the target.  If the
+/// response isn't `partial` content, it holds the whole target, so the download is started over.
+/// The file is synced before we return, even if reading fails, so what arrived is kept.
+fn store<R: Read>(
+    file: &mut File,
+    have: u64,
+    partial: bool,
+    body: R,
+    length: u64,
+) -> io::Result<()> {
+    let have = if partial {
+        have
+    } else {
+        file.set_len(0)?;
+        0
+    };
+    let mut body = body.take(length - have);
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let read = match body.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                // Keep what we have for the next attempt.
+                file.sync_data()?;
+                return Err(e);
+            }
+        };
+        io::Write::write_all(file, &buf[..read])?;
+    }
+    file.sync_data()
+}
+
+/// Checks that the file has the expected length and SHA-256 digest.
+fn verify(file: &mut File, length: u64, sha256: &str) -> io::Result<bool> {
+    if file.metadata()?.len() != length {
+        return Ok(false);
+    }
+    let mut hasher = Sha256::new();
+    io::copy(file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()).eq_ignore_ascii_case(sha256))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256_hex(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    #[test]
+    fn resumes_partial_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("target");
+        let data: Vec<u8> = (0..100).collect();
+
+        // Interrupted after 40 bytes.
+        let (mut file, have) = resume(&path, 100).unwrap();
+        assert_eq!(have, 0);
+        store(&mut file, have, false, &data[..40], 100).unwrap();
+
+        // The next attempt only receives the rest.
+        let (mut file, have) = resume(&path, 100).unwrap();
+        assert_eq!(have, 40);
+        store(&mut file, have, true, &data[40..], 100).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        assert!(verify(&mut file, 100, &sha256_hex(&data)).unwrap());
+    }
+
+    #[test]
+    fn restarts_without_range_support() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("target");
+        let data: Vec<u8> = (0..100).collect();
+        fs::write(&path, &data[..40]).unwrap();
+
+        // The server ignored the Range header and sent the whole target.
+        let (mut file, have) = resume(&path, 100).unwrap();
+        assert_eq!(have, 40);
+        store(&mut file, have, false, &data[..], 100).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_bad_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("target");
+        let data: Vec<u8> = (0..100).collect();
+
+        // Too long to be the target, so it's started over.
+        fs::write(&path, vec![0; 200]).unwrap();
+        let (_file, have) = resume(&path, 100).unwrap();
+        assert_eq!(have, 0);
+
+        fs::write(&path, vec![0; 100]).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert!(!verify(&mut file, 100, &sha256_hex(&data)).unwrap());
+    }
+
+    #[test]
+    fn removes_other_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("old"), b"stale").unwrap();
+        fs::write(dir.path().join("current"), b"partial").unwrap();
+
+        remove_others(dir.path(), "current").unwrap();
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["current"]);
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            target_file_name("abc", "root.ext4.lz4", false),
+            "root.ext4.lz4"
+        );
+        assert_eq!(
+            target_file_name("abc", "root.ext4.lz4", true),
+            "abc.root.ext4.lz4"
+        );
+    }
+}
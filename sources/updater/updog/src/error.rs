@@ -46,6 +46,39 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to create delta: {}", source))]
+    DeltaCreate {
+        source: update_metadata::error::Error,
+    },
+
+    #[snafu(display("No update matches the given variant, arch, and version"))]
+    DeltaNoUpdate { backtrace: Backtrace },
+
+    #[snafu(display("Failed to access delta input or output {}: {}", path.display(), source))]
+    DeltaFile {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to apply delta target {}: {}", target, source))]
+    DeltaApply {
+        target: String,
+        source: update_metadata::error::Error,
+    },
+
+    #[snafu(display(
+        "Image built from delta target {} has SHA-256 {}, expected {}",
+        target,
+        actual,
+        expected
+    ))]
+    DeltaHashMismatch {
+        target: String,
+        expected: String,
+        actual: String,
+    },
+
     #[snafu(display("Failed to create directory: {:?}", path))]
     DirCreate {
         backtrace: Backtrace,
@@ -53,12 +86,74 @@ pub(crate) enum Error {
         path: PathBuf,
     },
 
+    #[snafu(display("Failed to download {}: {}", url, source))]
+    Download {
+        url: String,
+        source: reqwest::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to create HTTP client for downloads: {}", source))]
+    DownloadClient {
+        source: reqwest::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to access download {}: {}", path.display(), source))]
+    DownloadFile {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to store download of {}: {}", url, source))]
+    DownloadStore {
+        url: String,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Download of {} ended after {} of {} bytes", url, have, length))]
+    DownloadIncomplete {
+        url: String,
+        have: u64,
+        length: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Not enough space in {} to download {} bytes; {} bytes are available",
+        path.display(),
+        needed,
+        available
+    ))]
+    DownloadSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Downloaded target {} doesn't match its signed length and hash",
+        target
+    ))]
+    DownloadVerify {
+        target: String,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Logger setup error: {}", source))]
     Logger { source: log::SetLoggerError },
 
     #[snafu(display("Could not mark inactive partition for boot: {}", source))]
     InactivePartitionUpgrade { source: signpost::Error },
 
+    #[snafu(display("Invalid maintenance window settings: {}", source))]
+    MaintenanceWindows {
+        source: update_metadata::error::Error,
+    },
+
     #[snafu(display("Failed to decode LZ4-compressed target {}: {}", target, source))]
     Lz4Decode {
         target: String,
@@ -110,6 +205,13 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to verify partition {}: {}", path.display(), source))]
+    VerifyPartition {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to open trusted root metadata file {}: {}", path.display(), source))]
     OpenRoot {
         path: PathBuf,
@@ -197,6 +299,12 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display(
+        "Update written, but not applied outside of maintenance window; {}",
+        next
+    ))]
+    OutsideMaintenanceWindow { next: String, backtrace: Backtrace },
+
     #[snafu(display("Failed to store manifest and migrations: {}", source))]
     RepoCacheMigrations {
         #[snafu(source(from(tough::error::Error, Box::new)))]
@@ -0,0 +1,372 @@
+//! Checks the health of the first boot of an update before it's marked successful.
+//!
+//! Until the active partitions are marked successful, a reboot falls back to the previous
+//! version, so `mark-successful-boot.service` leaves them alone on the first boot of an update
+//! that has health checks configured.  Here we wait for the configured systemd units to be active
+//! and HTTP probes to succeed, then mark the boot successful; if they don't pass in time, we roll
+//! back and reboot.  Either way, the result is recorded so `thar-be-updates` can report it.
+//!
+//! A first boot must never be left unmarked without a rollback, since the next reboot would then
+//! silently roll back an update that may be healthy.  If we're stopped while waiting, we decide
+//! with the results we have; if we can't roll back, we mark the boot successful instead; and if
+//! we exit without doing either, `finish_boot_health_check` marks the boot successful afterward.
+
+use crate::error::{self, Result};
+use chrono::Utc;
+use datastore::{Committed, DataStore, FilesystemDataStore, Key, KeyType};
+use log::{debug, error, info, warn};
+use reqwest::blocking::Client;
+use semver::Version;
+use serde::Deserialize;
+use signal_hook::consts::SIGTERM;
+use signpost::State;
+use snafu::ResultExt;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use update_metadata::health::{self, HealthCheckResult, HEALTH_CHECK_PATH};
+
+/// How long to wait for checks to pass if the config doesn't say.
+const DEFAULT_TIMEOUT_SECONDS: u64 = 300;
+/// How long to wait between rounds of checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to wait for a response to each HTTP probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The settings that list the health checks, which are only needed if they aren't empty.
+const CHECK_SETTINGS: &[&str] = &[
+    "settings.updates.health-check.units",
+    "settings.updates.health-check.http-probes",
+];
+/// The unit that runs `check-boot-health`; if it's masked, nothing will check the boot's health.
+const CHECK_UNIT: &str = "check-boot-health.service";
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct HealthCheck {
+    /// systemd units that must be active.
+    #[serde(default)]
+    units: Vec<String>,
+    /// URLs that must respond to a GET with a success status.
+    #[serde(default)]
+    http_probes: Vec<String>,
+    timeout_seconds: Option<u64>,
+}
+
+impl HealthCheck {
+    /// Runs the checks until they all pass, we time out, or `stop` is set, returning descriptions
+    /// of the checks that failed the last time they ran; an empty list means the boot is healthy.
+    fn wait(&self, stop: &AtomicBool) -> Vec<String> {
+        let timeout = Duration::from_secs(self.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS));
+        let start = Instant::now();
+        loop {
+            let failures = self.failures();
+            if failures.is_empty() || start.elapsed() >= timeout {
+                return failures;
+            }
+            if stop.load(Ordering::Relaxed) {
+                warn!("Stopped while waiting for health checks");
+                return failures;
+            }
+            debug!("Waiting for health checks: {}", failures.join("; "));
+            thread::sleep(CHECK_INTERVAL);
+        }
+    }
+
+    /// Runs each check once, returning descriptions of the ones that failed.
+    fn failures(&self) -> Vec<String> {
+        let mut failures = Vec::new();
+        for unit in &self.units {
+            match process::Command::new("systemctl")
+                .args(["is-active", unit])
+                .output()
+            {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => failures.push(format!(
+                    "unit {} is {}",
+                    unit,
+                    String::from_utf8_lossy(&output.stdout).trim()
+                )),
+                Err(e) => failures.push(format!("unable to check unit {unit}: {e}")),
+            }
+        }
+        let client = match Client::builder().timeout(PROBE_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                failures.extend(
+                    self.http_probes
+                        .iter()
+                        .map(|probe| format!("unable to run probe {probe}: {e}")),
+                );
+                return failures;
+            }
+        };
+        for probe in &self.http_probes {
+            // Settings allow URLs without a scheme, like other network addresses.
+            let url = if probe.contains("://") {
+                probe.clone()
+            } else {
+                format!("http://{probe}")
+            };
+            match client.get(&url).send() {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => {
+                    failures.push(format!("probe {} returned {}", probe, response.status()));
+                }
+                Err(e) => failures.push(format!("probe {probe} failed: {e}")),
+            }
+        }
+        failures
+    }
+}
+
+/// The part of updog's config file that holds the health checks.
+#[derive(Debug, Default, Deserialize)]
+struct HealthCheckConfig {
+    #[serde(default)]
+    health_check: HealthCheck,
+}
+
+/// Reads the health checks from updog's config file at `path`.  The rest of the file is only
+/// needed to find updates, so it isn't parsed here; marking a healthy boot successful mustn't fail
+/// because of it.  If the health checks themselves can't be read, we log why and check nothing,
+/// since leaving the boot unmarked would roll back an update that may be fine.
+pub(crate) fn load_health_check(path: &Path) -> HealthCheck {
+    let config = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| toml::from_str::<HealthCheckConfig>(&s).map_err(|e| e.to_string()));
+    match config {
+        Ok(config) => config.health_check,
+        Err(e) => {
+            warn!(
+                "Unable to read health checks from {}, checking nothing: {}",
+                path.display(),
+                e
+            );
+            HealthCheck::default()
+        }
+    }
+}
+
+/// Returns whether the settings in the datastore at `path` list any health checks.  This runs
+/// before updog's config file is rendered, so the settings are read directly.  If they can't be
+/// read, we say there are none, for the same reason as `load_health_check`.
+pub(crate) fn checks_configured(path: &Path) -> bool {
+    let datastore = FilesystemDataStore::new(path);
+    CHECK_SETTINGS.iter().any(|name| {
+        let value = Key::new(KeyType::Data, name)
+            .map_err(|e| e.to_string())
+            .and_then(|key| {
+                datastore
+                    .get_key(&key, &Committed::Live)
+                    .map_err(|e| e.to_string())
+            })
+            .and_then(|value| {
+                value
+                    .map(|value| serde_json::from_str::<Vec<String>>(&value))
+                    .transpose()
+                    .map_err(|e| e.to_string())
+            });
+        match value {
+            Ok(checks) => checks.map_or(false, |checks| !checks.is_empty()),
+            Err(e) => {
+                warn!("Unable to read {}, ignoring it: {}", name, e);
+                false
+            }
+        }
+    })
+}
+
+/// Returns whether this boot can be marked successful right away: it's not the first boot of an
+/// update, there are no health checks for it, or nothing will run them.  If the partition table
+/// can't be read, we say it can, so marking the boot reports the problem.
+pub(crate) fn skip_health_check(datastore_path: &Path) -> bool {
+    match State::load() {
+        Ok(gpt_state) if gpt_state.active_has_succeeded() => return true,
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Unable to read partition table: {}", e);
+            return true;
+        }
+    }
+    if !checks_configured(datastore_path) {
+        info!("No health checks are configured, the boot can be marked successful");
+        return true;
+    }
+    let masked = process::Command::new("systemctl")
+        .args(["is-enabled", CHECK_UNIT])
+        .output()
+        .map_or(false, |output| {
+            String::from_utf8_lossy(&output.stdout).trim() == "masked"
+        });
+    if masked {
+        warn!(
+            "{} is masked, the boot can't wait for health checks",
+            CHECK_UNIT
+        );
+    }
+    masked
+}
+
+/// Checks the health of this boot if it's the first boot of an update, then marks the boot
+/// successful, or rolls back to the previous version.  Returns whether the host should reboot
+/// to complete a rollback.
+pub(crate) fn check_boot_health(health_check: &HealthCheck, version: &Version) -> Result<bool> {
+    let mut gpt_state = State::load().context(error::PartitionTableReadSnafu)?;
+    if gpt_state.active_has_succeeded() {
+        debug!("Active partitions were already marked successful, not checking health");
+        return Ok(false);
+    }
+
+    // If the host is shutting down, decide before it reboots rather than leave the boot unmarked.
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Err(e) = signal_hook::flag::register(SIGTERM, Arc::clone(&stop)) {
+        warn!("Unable to handle termination signals: {}", e);
+    }
+    let failures = health_check.wait(&stop);
+    let healthy = failures.is_empty();
+    let mut rolled_back = false;
+    if healthy {
+        info!("Update {} is healthy, marking boot successful", version);
+        gpt_state.mark_successful_boot();
+    } else {
+        error!(
+            "Update {} failed health checks: {}",
+            version,
+            failures.join("; ")
+        );
+        match gpt_state.rollback_to_inactive() {
+            Ok(()) => rolled_back = true,
+            // Leaving the boot unmarked would only roll back on some later reboot, so we keep
+            // this version.
+            Err(e) => {
+                error!(
+                    "Unable to roll back to the previous version, marking boot successful: {}",
+                    e
+                );
+                gpt_state.mark_successful_boot();
+            }
+        }
+    }
+    gpt_state.write().context(error::PartitionTableWriteSnafu)?;
+
+    let result = HealthCheckResult {
+        version: version.clone(),
+        timestamp: Utc::now(),
+        healthy,
+        failures,
+        rolled_back,
+    };
+    // The boot was already marked or rolled back, so that's finished even if we can't say so.
+    if let Err(e) = health::store(Path::new(HEALTH_CHECK_PATH), &result) {
+        warn!("Unable to record the health check result: {}", e);
+    }
+    Ok(rolled_back)
+}
+
+/// Marks the boot successful if it's the first boot of an update and `check_boot_health` exited
+/// without marking it or rolling back, so the boot is never left unmarked.
+pub(crate) fn finish_boot_health_check(version: &Version) -> Result<()> {
+    let mut gpt_state = State::load().context(error::PartitionTableReadSnafu)?;
+    if gpt_state.active_has_succeeded() {
+        return Ok(());
+    }
+    // Marking the boot wouldn't undo a rollback, which only changes priorities, but it would make
+    // an unhealthy version a fallback for the previous one.
+    let rolled_back = health::load(Path::new(HEALTH_CHECK_PATH))
+        .ok()
+        .flatten()
+        .map_or(false, |result| {
+            &result.version == version && result.rolled_back
+        });
+    if rolled_back {
+        return Ok(());
+    }
+    warn!("Boot health check didn't finish, marking boot successful");
+    gpt_state.mark_successful_boot();
+    gpt_state.write().context(error::PartitionTableWriteSnafu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_check_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("updog.toml");
+
+        // Other settings being wrong or missing doesn't matter.
+        fs::write(
+            &path,
+            "seed = \"oops\"\n[health_check]\nunits = [\"kubelet.service\"]\n",
+        )
+        .unwrap();
+        assert_eq!(load_health_check(&path).units, vec!["kubelet.service"]);
+
+        // Unreadable health checks mean no checks, rather than an unmarked boot.
+        fs::write(&path, "[health_check]\nunits = 42\n").unwrap();
+        assert!(load_health_check(&path).units.is_empty());
+        assert!(load_health_check(&dir.path().join("missing"))
+            .units
+            .is_empty());
+    }
+
+    #[test]
+    fn configured_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!checks_configured(dir.path()));
+
+        let mut datastore = FilesystemDataStore::new(dir.path());
+        let set = |datastore: &mut FilesystemDataStore, name: &str, value: &str| {
+            let key = Key::new(KeyType::Data, name).unwrap();
+            datastore.set_key(&key, value, &Committed::Live).unwrap();
+        };
+        // A timeout alone, or empty lists, mean there's nothing to check.
+        set(
+            &mut datastore,
+            "settings.updates.health-check.timeout-seconds",
+            "60",
+        );
+        set(&mut datastore, "settings.updates.health-check.units", "[]");
+        assert!(!checks_configured(dir.path()));
+
+        set(
+            &mut datastore,
+            "settings.updates.health-check.http-probes",
+            "[\"127.0.0.1:8080/healthz\"]",
+        );
+        assert!(checks_configured(dir.path()));
+    }
+
+    #[test]
+    fn no_checks() {
+        let health_check = HealthCheck::default();
+        assert!(health_check.wait(&AtomicBool::new(false)).is_empty());
+    }
+
+    #[test]
+    fn stops_waiting() {
+        let health_check = HealthCheck {
+            http_probes: vec!["127.0.0.1:9/healthz".to_string()],
+            timeout_seconds: Some(3600),
+            ..HealthCheck::default()
+        };
+        assert_eq!(health_check.wait(&AtomicBool::new(true)).len(), 1);
+    }
+
+    #[test]
+    fn failing_probe() {
+        let health_check = HealthCheck {
+            // Nothing listens on the discard port.
+            http_probes: vec!["127.0.0.1:9/healthz".to_string()],
+            timeout_seconds: Some(0),
+            ..HealthCheck::default()
+        };
+        let failures = health_check.wait(&AtomicBool::new(false));
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("probe 127.0.0.1:9/healthz failed"));
+    }
+}
@@ -1,30 +1,38 @@
 #![warn(clippy::pedantic)]
 
+mod checkpoint;
+mod download;
 mod error;
+mod health;
 mod transport;
 
+use crate::checkpoint::{image_on_disk, CheckpointWriter, CountingReader};
+use crate::download::Downloader;
 use crate::error::Result;
 use crate::transport::{HttpQueryTransport, QueryParams};
 use bottlerocket_release::BottlerocketRelease;
 use chrono::Utc;
-use log::debug;
+use log::{debug, warn};
 use model::modeled_types::FriendlyVersion;
 use semver::Version;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use signal_hook::consts::SIGTERM;
 use signal_hook::iterator::Signals;
 use signpost::State;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
-use snafu::{ErrorCompat, OptionExt, ResultExt};
+use snafu::{ensure, ErrorCompat, OptionExt, ResultExt};
 use std::convert::{TryFrom, TryInto};
-use std::fs::{self, File, OpenOptions};
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::path::Path;
 use std::process;
 use std::str::FromStr;
 use std::thread;
-use tough::{Repository, RepositoryLoader};
-use update_metadata::{find_migrations, Manifest, Update};
+use tough::{Repository, RepositoryLoader, TargetName};
+use update_metadata::progress::{self, WriteProgress, PROGRESS_PATH};
+use update_metadata::window::MaintenanceWindows;
+use update_metadata::{find_migrations, DeltaImage, Manifest, Update};
 use url::Url;
 
 #[cfg(target_arch = "x86_64")]
@@ -32,6 +40,12 @@ const TARGET_ARCH: &str = "x86_64";
 #[cfg(target_arch = "aarch64")]
 const TARGET_ARCH: &str = "aarch64";
 
+/// updog's config file, written by settings-applier from the updates settings.
+const CONFIG_PATH: &str = "/etc/updog.toml";
+
+/// The API server's datastore, for settings needed before the config file is written.
+const DATASTORE_PATH: &str = "/var/lib/bottlerocket/datastore/current";
+
 /// The root.json file as required by TUF.
 const TRUSTED_ROOT_PATH: &str = "/usr/share/updog/root.json";
 
@@ -51,6 +65,9 @@ enum Command {
     UpdateImage,
     UpdateApply,
     UpdateRevert,
+    CheckBootHealth,
+    SkipBootHealthCheck,
+    FinishBootHealthCheck,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,11 +79,22 @@ struct Config {
     ignore_waves: bool,
     https_proxy: Option<String>,
     no_proxy: Option<Vec<String>>,
+    // The health_check table is read separately by health::load_health_check.
+    #[serde(default)]
+    maintenance: Maintenance,
     // TODO API sourced configuration, eg.
     // blacklist: Option<Vec<Version>>,
     // mode: Option<{Automatic, Managed, Disabled}>
 }
 
+/// Times when updates may be applied; see `update_metadata::window`.
+#[derive(Debug, Default, Deserialize)]
+struct Maintenance {
+    #[serde(default)]
+    windows: Vec<String>,
+    timezone: Option<String>,
+}
+
 /// Prints a more specific message before exiting through usage().
 fn usage_msg<S: AsRef<str>>(msg: S) -> ! {
     eprintln!("{}\n", msg.as_ref());
@@ -91,6 +119,7 @@ SUBCOMMANDS:
         [ -i | --image version ]      Update to a specific image version
         [ -n | --now ]                Update immediately, ignoring any release schedule
         [ -r | --reboot ]             Reboot into new update on success
+        [ --ignore-window ]           Apply the update outside of maintenance windows
 
     update-image            Download & write an update but do not update flags
         [ -i | --image version ]      Update to a specific image version
@@ -99,9 +128,20 @@ SUBCOMMANDS:
 
     update-apply            Update boot flags (after having called update-image)
         [ -r | --reboot ]             Reboot after updating boot flags
+        [ --ignore-window ]           Apply the update outside of maintenance windows
 
     update-revert           Revert actions done by 'update-apply'
 
+    check-boot-health       On the first boot of an update, wait for health checks, then
+                            mark the boot successful or roll back and reboot
+
+    skip-boot-health-check  Exits 0 if the boot can be marked successful without waiting for
+                            health checks, 1 if not
+
+    finish-boot-health-check
+                            Mark the boot successful if 'check-boot-health' didn't mark it
+                            or roll back
+
 GLOBAL OPTIONS:
     [ -j | --json ]               JSON-formatted output
     [ --log-level trace|debug|info|warn|error ]  Set logging verbosity");
@@ -109,7 +149,7 @@ GLOBAL OPTIONS:
 }
 
 fn load_config() -> Result<Config> {
-    let path = "/etc/updog.toml";
+    let path = CONFIG_PATH;
     let s = fs::read_to_string(path).context(error::ConfigReadSnafu { path })?;
     let config: Config = toml::from_str(&s).context(error::ConfigParseSnafu { path })?;
     Ok(config)
@@ -210,34 +250,37 @@ fn update_required<'a>(
     Ok(None)
 }
 
-fn write_target_to_disk<P: AsRef<Path>>(
-    repository: &Repository,
+/// Returns the length of a target in the repository, or 0 if it's unknown.
+fn target_length(repository: &Repository, target: &str) -> u64 {
+    TargetName::new(target)
+        .ok()
+        .and_then(|target| repository.targets().signed.find_target(&target).ok())
+        .map_or(0, |target| target.length)
+}
+
+fn write_target_to_disk(
+    downloader: &Downloader<'_>,
     target: &str,
-    disk_path: P,
+    disk_path: &Path,
+    progress: &mut WriteProgress,
 ) -> Result<()> {
-    let target = target
-        .try_into()
-        .context(error::TargetNameSnafu { target })?;
-    let reader = repository
-        .read_target(&target)
-        .context(error::MetadataSnafu)?
-        .context(error::TargetNotFoundSnafu {
-            target: target.raw(),
-        })?;
+    let (target, reader) = downloader.fetch(target)?;
+    progress.image_mut(
+        disk_path,
+        target.raw(),
+        target_length(downloader.repository(), target.raw()),
+    );
+    let reader = CountingReader::new(reader);
+    let target_read = reader.count();
     // Note: the file extension for the compression type we're using should be removed in
     // retrieve_migrations below.
     let mut reader = lz4::Decoder::new(reader).context(error::Lz4DecodeSnafu {
         target: target.raw(),
     })?;
-    let mut f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(disk_path.as_ref())
-        .context(error::OpenPartitionSnafu {
-            path: disk_path.as_ref(),
-        })?;
-    io::copy(&mut reader, &mut f).context(error::WriteUpdateSnafu)?;
-    Ok(())
+    let mut writer =
+        CheckpointWriter::new(disk_path, progress, Path::new(PROGRESS_PATH), target_read)?;
+    io::copy(&mut reader, &mut writer).context(error::WriteUpdateSnafu)?;
+    writer.finish()
 }
 
 /// Store required migrations for an update in persistent storage. All intermediate migrations
@@ -274,7 +317,79 @@ fn retrieve_migrations(
     Ok(())
 }
 
-fn update_image(update: &Update, repository: &Repository) -> Result<()> {
+/// Builds an image by applying a delta target to the image in the partition at `source_path`,
+/// writing it to the partition at `disk_path`.  The result is checked against the hash listed for
+/// the delta, so a delta applied to the wrong image is caught.
+fn write_delta_to_disk(
+    downloader: &Downloader<'_>,
+    delta: &DeltaImage,
+    source_path: &Path,
+    disk_path: &Path,
+    progress: &mut WriteProgress,
+) -> Result<()> {
+    let (target, reader) = downloader.fetch(&delta.target)?;
+    progress.image_mut(
+        disk_path,
+        target.raw(),
+        target_length(downloader.repository(), target.raw()),
+    );
+    let reader = CountingReader::new(reader);
+    let target_read = reader.count();
+    let mut reader = lz4::Decoder::new(reader).context(error::Lz4DecodeSnafu {
+        target: target.raw(),
+    })?;
+    let mut source = io::BufReader::new(
+        File::open(source_path).context(error::OpenPartitionSnafu { path: source_path })?,
+    );
+    let mut writer = HashingWriter {
+        inner: CheckpointWriter::new(disk_path, progress, Path::new(PROGRESS_PATH), target_read)?,
+        hasher: Sha256::new(),
+    };
+    update_metadata::delta::apply(&mut source, &mut reader, &mut writer).context(
+        error::DeltaApplySnafu {
+            target: target.raw(),
+        },
+    )?;
+
+    let actual = format!("{:x}", writer.hasher.finalize());
+    ensure!(
+        actual.eq_ignore_ascii_case(&delta.sha256),
+        error::DeltaHashMismatchSnafu {
+            target: target.raw(),
+            expected: &delta.sha256,
+            actual,
+        }
+    );
+    writer.inner.finish()
+}
+
+/// Passes writes through to `inner`, hashing the data along the way.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the update's images to the inactive partition set.  Targets are downloaded before
+/// they're written, so an interrupted attempt resumes both the download and the write; see the
+/// download and checkpoint modules.
+fn update_image(
+    update: &Update,
+    downloader: &Downloader<'_>,
+    current_version: &Version,
+) -> Result<()> {
+    let repository = downloader.repository();
     let mut gpt_state = State::load().context(error::PartitionTableReadSnafu)?;
     gpt_state.clear_inactive();
     // Write out the clearing of the inactive partition immediately, because we're about to
@@ -282,15 +397,76 @@ fn update_image(update: &Update, repository: &Repository) -> Result<()> {
     // know we're done with all components.
     gpt_state.write().context(error::PartitionTableWriteSnafu)?;
 
+    let active = gpt_state.active_set();
     let inactive = gpt_state.inactive_set();
+    let delta = update.delta_from(current_version);
+
+    // If an earlier attempt to write this update was interrupted, pick up where it left off.
+    // Progress for other partitions is from before we last switched partition sets.
+    let progress_path = Path::new(PROGRESS_PATH);
+    let mut progress = match progress::load(progress_path)? {
+        Some(progress) if progress.version == update.version => progress,
+        _ => WriteProgress::new(update.version.clone()),
+    };
+    progress.retain_partitions(&[&inactive.root, &inactive.boot, &inactive.hash]);
+
+    let images = [
+        (
+            &update.images.root,
+            delta.map(|d| &d.images.root),
+            &active.root,
+            &inactive.root,
+        ),
+        (
+            &update.images.boot,
+            delta.map(|d| &d.images.boot),
+            &active.boot,
+            &inactive.boot,
+        ),
+        (
+            &update.images.hash,
+            delta.map(|d| &d.images.hash),
+            &active.hash,
+            &inactive.hash,
+        ),
+    ];
+    // Start tracking every image up front, so the total size is known from the beginning.
+    for (full, delta, _, inactive_path) in &images {
+        let target = delta.map_or(full.as_str(), |delta| &delta.target);
+        progress.image_mut(inactive_path, target, target_length(repository, target));
+    }
+    progress::store(progress_path, &progress)?;
+
+    for (full, delta, active_path, inactive_path) in images {
+        let written = progress
+            .images
+            .iter()
+            .find(|image| &image.partition == inactive_path);
+        if let Some(image) = written {
+            if image.complete && image_on_disk(image)? {
+                debug!("{} was already written", inactive_path.display());
+                continue;
+            }
+        }
 
-    // TODO Do we want to recover the inactive side on an error?
-    write_target_to_disk(repository, &update.images.root, &inactive.root)?;
-    write_target_to_disk(repository, &update.images.boot, &inactive.boot)?;
-    write_target_to_disk(repository, &update.images.hash, &inactive.hash)?;
+        // Prefer the much smaller delta, but if it can't be applied to what we're running, we
+        // can still write the full image.
+        if let Some(delta) = delta {
+            match write_delta_to_disk(downloader, delta, active_path, inactive_path, &mut progress)
+            {
+                Ok(()) => continue,
+                Err(e) => warn!(
+                    "Unable to apply delta {}, writing full image {} instead: {}",
+                    delta.target, full, e
+                ),
+            }
+        }
+        write_target_to_disk(downloader, full, inactive_path, &mut progress)?;
+    }
 
     gpt_state.mark_inactive_valid();
     gpt_state.write().context(error::PartitionTableWriteSnafu)?;
+    download::remove_downloads();
     Ok(())
 }
 
@@ -351,6 +527,7 @@ struct Arguments {
     force_version: Option<Version>,
     all: bool,
     reboot: bool,
+    ignore_window: bool,
     variant: Option<String>,
 }
 
@@ -363,6 +540,7 @@ fn parse_args(args: std::env::Args) -> Arguments {
     let mut json = false;
     let mut all = false;
     let mut reboot = false;
+    let mut ignore_window = false;
     let mut variant = None;
 
     let mut iter = args.skip(1);
@@ -399,6 +577,9 @@ fn parse_args(args: std::env::Args) -> Arguments {
             "-r" | "--reboot" => {
                 reboot = true;
             }
+            "--ignore-window" => {
+                ignore_window = true;
+            }
             "-a" | "--all" => {
                 all = true;
             }
@@ -421,6 +602,7 @@ fn parse_args(args: std::env::Args) -> Arguments {
         force_version: update_version,
         all,
         reboot,
+        ignore_window,
         variant,
     }
 }
@@ -441,6 +623,24 @@ fn output<T: Serialize>(json: bool, object: T, string: &str) -> Result<()> {
     Ok(())
 }
 
+/// Makes sure we're in a maintenance window, if any are configured, before applying an update and
+/// rebooting into it.
+fn check_maintenance_window(maintenance: &Maintenance) -> Result<()> {
+    let windows = MaintenanceWindows::new(&maintenance.windows, maintenance.timezone.as_deref())
+        .context(error::MaintenanceWindowsSnafu)?;
+    let now = Utc::now();
+    ensure!(
+        windows.is_open(now),
+        error::OutsideMaintenanceWindowSnafu {
+            next: match windows.next(now) {
+                Some(window) => format!("next window starts at {}", window.start),
+                None => "no window is scheduled".to_string(),
+            }
+        }
+    );
+    Ok(())
+}
+
 fn initiate_reboot() -> Result<()> {
     // Set up signal handler for termination signals
     let mut signals = Signals::new([SIGTERM]).context(error::SignalSnafu)?;
@@ -499,6 +699,26 @@ fn main_inner() -> Result<()> {
     let command =
         serde_plain::from_str::<Command>(&arguments.subcommand).unwrap_or_else(|_| usage());
 
+    if command == Command::CheckBootHealth {
+        // This runs at boot, so it shouldn't depend on reaching the update repository, or on the
+        // parts of the config that are only needed to find updates.
+        let health_check = health::load_health_check(Path::new(CONFIG_PATH));
+        let current_release = BottlerocketRelease::new().context(error::ReleaseVersionSnafu)?;
+        if health::check_boot_health(&health_check, &current_release.version_id)? {
+            initiate_reboot()?;
+        }
+        return Ok(());
+    }
+    if command == Command::SkipBootHealthCheck {
+        process::exit(i32::from(!health::skip_health_check(Path::new(
+            DATASTORE_PATH,
+        ))));
+    }
+    if command == Command::FinishBootHealthCheck {
+        let current_release = BottlerocketRelease::new().context(error::ReleaseVersionSnafu)?;
+        return health::finish_boot_health_check(&current_release.version_id);
+    }
+
     let config = load_config()?;
     set_https_proxy_environment_variables(&config.https_proxy, &config.no_proxy);
     let current_release = BottlerocketRelease::new().context(error::ReleaseVersionSnafu)?;
@@ -555,8 +775,13 @@ fn main_inner() -> Result<()> {
                     u,
                     &current_release.version_id,
                 )?;
-                update_image(u, &repository)?;
+                let downloader =
+                    Downloader::new(&repository, &config.targets_base_url, query_params.clone())?;
+                update_image(u, &downloader, &current_release.version_id)?;
                 if command == Command::Update {
+                    if !arguments.ignore_window {
+                        check_maintenance_window(&config.maintenance)?;
+                    }
                     update_flags()?;
                     if arguments.reboot {
                         initiate_reboot()?;
@@ -572,6 +797,9 @@ fn main_inner() -> Result<()> {
             }
         }
         Command::UpdateApply => {
+            if !arguments.ignore_window {
+                check_maintenance_window(&config.maintenance)?;
+            }
             update_flags()?;
             if arguments.reboot {
                 initiate_reboot()?;
@@ -583,6 +811,11 @@ fn main_inner() -> Result<()> {
         Command::Prepare => {
             // TODO unimplemented
         }
+        Command::CheckBootHealth
+        | Command::SkipBootHealthCheck
+        | Command::FinishBootHealthCheck => {
+            unreachable!("handled before loading the repository")
+        }
     }
 
     Ok(())
@@ -679,6 +912,7 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            maintenance: Maintenance::default(),
         };
         let version = Version::parse("1.18.0").unwrap();
         let variant = String::from("bottlerocket-aws-eks");
@@ -713,6 +947,7 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            maintenance: Maintenance::default(),
         };
 
         let version = Version::parse("0.1.3").unwrap();
@@ -751,6 +986,7 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            maintenance: Maintenance::default(),
         };
 
         let version = Version::parse("1.10.0").unwrap();
@@ -793,6 +1029,7 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            maintenance: Maintenance::default(),
         };
 
         let version = Version::parse("1.10.0").unwrap();
@@ -863,6 +1100,7 @@ mod tests {
                 root: String::from("boot"),
                 hash: String::from("boot"),
             },
+            deltas: Vec::new(),
         };
 
         let current_version = Version::parse("1.0.0").unwrap();
@@ -876,6 +1114,7 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            maintenance: Maintenance::default(),
         };
 
         // Two waves; the 1st wave that starts immediately, and the final wave which starts in one hour
@@ -1,9 +1,18 @@
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use log::error;
-use tough::{HttpTransport, Transport, TransportError};
+use reqwest::blocking::Client;
+use tough::{HttpTransport, HttpTransportBuilder, Transport, TransportError};
 use url::Url;
 
+/// How long to wait for the server to respond, and for each read of the response, before giving
+/// up.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for a connection to the server before giving up.
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// A shared pointer to a list of query params that the transport will add to HTTP calls.
 #[derive(Debug, Clone, Default)]
 pub(crate) struct QueryParams(Arc<RwLock<Vec<(String, String)>>>);
@@ -56,7 +65,10 @@ impl QueryParams {
 impl HttpQueryTransport {
     pub fn new() -> Self {
         Self {
-            inner: HttpTransport::default(),
+            inner: HttpTransportBuilder::new()
+                .timeout(HTTP_TIMEOUT)
+                .connect_timeout(HTTP_CONNECT_TIMEOUT)
+                .build(),
             parameters: QueryParams::default(),
         }
     }
@@ -67,6 +79,17 @@ impl HttpQueryTransport {
     }
 }
 
+/// Builds an HTTP client for requests to the repository that `tough` doesn't make itself, like
+/// target downloads, with the same timeouts as `HttpQueryTransport`.  Like `tough`'s client, it
+/// uses the proxy set in the `HTTPS_PROXY` and `NO_PROXY` environment variables, so those must be
+/// set before it's built.
+pub(crate) fn http_client() -> reqwest::Result<Client> {
+    Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .build()
+}
+
 impl Transport for HttpQueryTransport {
     fn fetch(
         &self,